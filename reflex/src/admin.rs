@@ -0,0 +1,454 @@
+use crate::backend::Backend;
+use crate::lock::MutexExt;
+use crate::protocol::connection_stream::Credentials;
+use crate::protocol::error::ClientError;
+use crate::protocol::resource::Resource;
+use crate::protocol::session::Session;
+use crate::recording::{self, Recorder};
+use crate::screenshot::ScreenshotTarget;
+use crate::shutdown;
+use futures::future::Future;
+use futures::stream::Stream;
+use futures::sync::{mpsc, oneshot};
+use nix::sys::socket::{shutdown, Shutdown};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::io::{BufRead, BufReader};
+use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+use std::os::unix::io::RawFd;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tokio::io::AsyncRead;
+use tokio::net::{UnixListener, UnixStream};
+
+// Local admin/control socket.
+//
+// It speaks a line based protocol: one command per line, and each reply is
+// terminated by an empty line. Failures are reported as "error: ..." lines.
+//
+//   clients               list connected clients and their credentials
+//   resources <client>    dump the object map of a client
+//   surfaces [<client>]   show the surface tree and toplevel titles
+//   kill <client>         disconnect a client
+//   screenshot <path> [surface <client> <id> | app_id <app_id>]
+//                         save the output or one surface as .png or .ppm
+//   record <path> [fps <n>] [surface <client> <id> | app_id <app_id>]
+//                         record the output or one surface as .y4m or raw
+//   record stop           finish the recording
+//   help                  show this list
+
+const HELP: &str = "clients\nresources <client>\nsurfaces [<client>]\nkill <client>\n\
+                    screenshot <path> [surface <client> <id> | app_id <app_id>]\n\
+                    record <path> [fps <n>] [surface <client> <id> | app_id <app_id>]\n\
+                    record stop\nfocus <client> <surface>\nhelp\n";
+
+// Sent from the admin socket to a client's request loop, which is the only
+// place that owns the Session.
+pub enum Control {
+    Inspect(Box<dyn FnOnce(&Session) + Send>),
+    Kill(&'static str),
+    // Ends the session without closing the socket, which is left to the
+    // event sink once it has sent the client a wl_display.error.
+    Disconnect(&'static str),
+    Shutdown,
+}
+
+struct Client {
+    fd: RawFd,
+    credentials: Credentials,
+    connected_at: Instant,
+    control: mpsc::UnboundedSender<Control>,
+}
+
+#[derive(Clone, Default)]
+pub struct Clients {
+    clients: Arc<Mutex<BTreeMap<u32, Client>>>,
+}
+
+impl Clients {
+    pub fn register(
+        &self,
+        client_id: u32,
+        fd: RawFd,
+        credentials: Credentials,
+    ) -> mpsc::UnboundedReceiver<Control> {
+        let (control, rx) = mpsc::unbounded();
+        self.clients.locked().insert(
+            client_id,
+            Client {
+                fd,
+                credentials,
+                connected_at: Instant::now(),
+                control,
+            },
+        );
+        rx
+    }
+
+    pub fn unregister(&self, client_id: u32) {
+        self.clients.locked().remove(&client_id);
+    }
+
+    fn list(&self) -> String {
+        let mut reply = String::new();
+        for (client_id, client) in self.clients.locked().iter() {
+            let _ = writeln!(
+                reply,
+                "client#{} fd={} pid={} uid={} gid={} connected={}s",
+                client_id,
+                client.fd,
+                client.credentials.pid,
+                client.credentials.uid,
+                client.credentials.gid,
+                client.connected_at.elapsed().as_secs()
+            );
+        }
+        reply
+    }
+
+    pub fn kill(&self, client_id: u32, reason: &'static str) -> Result<(), String> {
+        self.send(client_id, Control::Kill(reason))
+    }
+
+    pub fn disconnect(&self, client_id: u32, reason: &'static str) -> Result<(), String> {
+        self.send(client_id, Control::Disconnect(reason))
+    }
+
+    pub fn shutdown_all(&self) {
+        for client in self.clients.locked().values() {
+            let _ = client.control.unbounded_send(Control::Shutdown);
+        }
+    }
+
+    fn send(&self, client_id: u32, control: Control) -> Result<(), String> {
+        let clients = self.clients.locked();
+        let client = clients
+            .get(&client_id)
+            .ok_or_else(|| format!("no such client: {}", client_id))?;
+        client
+            .control
+            .unbounded_send(control)
+            .map_err(|_| format!("client#{} is going away", client_id))
+    }
+
+    fn inspect(
+        &self,
+        client_ids: Vec<u32>,
+        f: fn(&Session) -> String,
+    ) -> Box<dyn Future<Item = String, Error = ()> + Send> {
+        let mut replies = Vec::new();
+        for client_id in client_ids {
+            let (tx, rx) = oneshot::channel();
+            let inspect = Control::Inspect(Box::new(move |session| {
+                let _ = tx.send(f(session));
+            }));
+            if let Err(err) = self.send(client_id, inspect) {
+                return Box::new(futures::future::ok(format!("error: {}\n", err)));
+            }
+            replies.push(rx.or_else(move |_| Ok(format!("client#{} went away\n", client_id))));
+        }
+        Box::new(futures::future::join_all(replies).map(|replies| replies.concat()))
+    }
+}
+
+pub fn handle_control(
+    session: Session,
+    fd: RawFd,
+    control: Control,
+) -> Box<dyn Future<Item = Session, Error = ClientError> + Send> {
+    match control {
+        Control::Inspect(f) => {
+            f(&session);
+            Box::new(futures::future::ok(session))
+        }
+        Control::Kill(reason) => {
+            let _ = shutdown(fd, Shutdown::Both);
+            Box::new(futures::future::err(ClientError::Killed(reason)))
+        }
+        Control::Disconnect(reason) => {
+            Box::new(futures::future::err(ClientError::Quota(reason.to_string())))
+        }
+        Control::Shutdown => {
+            let mut session = session;
+            session.backend.shutdown();
+            Box::new(futures::future::err(ClientError::Shutdown))
+        }
+    }
+}
+
+pub fn dump_resources(session: &Session) -> String {
+    let mut reply = String::new();
+    let _ = writeln!(reply, "client#{}", session.client_id);
+    let mut ids: Vec<&u32> = session.resources.keys().collect();
+    ids.sort();
+    for id in ids {
+        let resource = &session.resources[id];
+        let _ = write!(reply, "  {}@{}", resource.interface().name, id);
+        match resource {
+            Resource::WlBuffer(x) => {
+                let _ = write!(
+                    reply,
+                    " offset={} width={} height={} stride={} format={}",
+                    x.offset, x.width, x.height, x.stride, x.format
+                );
+            }
+            Resource::WlShmPool(x) => {
+                let _ = write!(reply, " fd={} size={}", x.fd, x.size);
+            }
+            Resource::XdgSurface(x) => {
+                let _ = write!(reply, " wl_surface@{}", x.wl_surface_id);
+            }
+            Resource::XdgToplevel(x) => {
+                let _ = write!(reply, " xdg_surface@{}", x.xdg_surface_id);
+            }
+            _ => {}
+        }
+        reply.push('\n');
+    }
+    reply
+}
+
+pub fn dump_surfaces(session: &Session) -> String {
+    let mut reply = String::new();
+    let _ = writeln!(reply, "client#{}", session.client_id);
+    let mut ids: Vec<&u32> = session.resources.keys().collect();
+    ids.sort();
+    for id in ids.iter().cloned() {
+        if let Resource::WlSurface(_) = session.resources[id] {
+            let _ = writeln!(reply, "  wl_surface@{}", id);
+        } else {
+            continue;
+        }
+        for xdg_surface_id in ids.iter().cloned() {
+            match &session.resources[xdg_surface_id] {
+                Resource::XdgSurface(x) if x.wl_surface_id == *id => {}
+                _ => continue,
+            }
+            let _ = writeln!(reply, "    xdg_surface@{}", xdg_surface_id);
+            for toplevel_id in ids.iter().cloned() {
+                match &session.resources[toplevel_id] {
+                    Resource::XdgToplevel(x) if x.xdg_surface_id == *xdg_surface_id => {
+                        let _ = writeln!(
+                            reply,
+                            "      xdg_toplevel@{} title={:?} app_id={:?}",
+                            toplevel_id,
+                            x.title.as_deref().unwrap_or(""),
+                            x.app_id.as_deref().unwrap_or("")
+                        );
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+    reply
+}
+
+fn parse_client_id(arg: Option<&str>) -> Result<u32, String> {
+    let arg = arg.ok_or_else(|| "missing client id".to_string())?;
+    arg.trim_start_matches("client#")
+        .parse()
+        .map_err(|_| format!("invalid client id: {}", arg))
+}
+
+fn parse_screenshot_target(words: &[&str]) -> Result<ScreenshotTarget, String> {
+    match words {
+        [] => Ok(ScreenshotTarget::Output),
+        ["surface", client_id, surface_id] => Ok(ScreenshotTarget::Surface {
+            client_id: parse_client_id(Some(client_id))?,
+            surface_id: surface_id
+                .parse()
+                .map_err(|_| format!("invalid surface id: {}", surface_id))?,
+        }),
+        ["app_id", app_id] => Ok(ScreenshotTarget::AppId(app_id.to_string())),
+        _ => Err("expected surface <client> <id> or app_id <app_id>".to_string()),
+    }
+}
+
+fn screenshot(
+    backend: &dyn Backend,
+    path: Option<&str>,
+    target: &[&str],
+) -> Result<String, String> {
+    let path = path.ok_or_else(|| "missing path".to_string())?;
+    let target = parse_screenshot_target(target)?;
+    let image = backend.screenshot(&target)?;
+    image
+        .save(Path::new(path))
+        .map_err(|err| format!("cannot write {}: {}", path, err))?;
+    Ok(format!(
+        "saved {}x{} to {}\n",
+        image.width, image.height, path
+    ))
+}
+
+fn record(backend: &dyn Backend, path: Option<&str>, words: &[&str]) -> Result<String, String> {
+    let path = path.ok_or_else(|| "missing path".to_string())?;
+    if path == "stop" {
+        let summary = backend.stop_recording()?;
+        return Ok(format!(
+            "recorded {} frames of {}x{} from {} commits\n",
+            summary.frames, summary.width, summary.height, summary.commits
+        ));
+    }
+    let (fps, target) = match words {
+        ["fps", fps, target @ ..] => (
+            fps.parse()
+                .map_err(|_| format!("invalid frame rate: {}", fps))?,
+            target,
+        ),
+        _ => (recording::DEFAULT_FPS, words),
+    };
+    let target = parse_screenshot_target(target)?;
+    let recorder = Recorder::create(Path::new(path), target, fps)
+        .map_err(|err| format!("cannot write {}: {}", path, err))?;
+    backend.start_recording(recorder)?;
+    Ok(format!("recording to {} at {} fps\n", path, fps))
+}
+
+fn focus(
+    backend: &dyn Backend,
+    client_id: Option<&str>,
+    surface_id: Option<&str>,
+) -> Result<String, String> {
+    let client_id = parse_client_id(client_id)?;
+    let surface_id = surface_id.ok_or_else(|| "missing surface id".to_string())?;
+    let surface_id = surface_id
+        .parse()
+        .map_err(|_| format!("invalid surface id: {}", surface_id))?;
+    backend.set_focus(client_id, surface_id)?;
+    Ok(format!(
+        "focused client#{} surface {}\n",
+        client_id, surface_id
+    ))
+}
+
+fn execute(
+    clients: &Clients,
+    backend: &dyn Backend,
+    line: &str,
+) -> Box<dyn Future<Item = String, Error = ()> + Send> {
+    let mut words = line.split_whitespace();
+    let command = words.next().unwrap_or("");
+    let arg = words.next();
+    let reply = match command {
+        "" => Ok(String::new()),
+        "help" => Ok(HELP.to_string()),
+        "clients" => Ok(clients.list()),
+        "resources" => match parse_client_id(arg) {
+            Ok(client_id) => return clients.inspect(vec![client_id], dump_resources),
+            Err(err) => Err(err),
+        },
+        "surfaces" => {
+            let client_ids = match arg {
+                Some(_) => parse_client_id(arg).map(|client_id| vec![client_id]),
+                None => Ok(clients.clients.locked().keys().cloned().collect()),
+            };
+            match client_ids {
+                Ok(client_ids) => return clients.inspect(client_ids, dump_surfaces),
+                Err(err) => Err(err),
+            }
+        }
+        "kill" => parse_client_id(arg).and_then(|client_id| {
+            clients
+                .kill(client_id, "requested via admin socket")
+                .map(|_| format!("killed client#{}\n", client_id))
+        }),
+        "screenshot" => screenshot(backend, arg, &words.collect::<Vec<_>>()),
+        "record" => record(backend, arg, &words.collect::<Vec<_>>()),
+        "focus" => focus(backend, arg, words.next()),
+        _ => Err(format!("unknown command: {}", command)),
+    };
+    Box::new(futures::future::ok(match reply {
+        Ok(reply) => reply,
+        Err(err) => format!("error: {}\n", err),
+    }))
+}
+
+fn handle_admin(
+    stream: UnixStream,
+    clients: Clients,
+    backend: Arc<dyn Backend>,
+) -> Box<dyn Future<Item = (), Error = ()> + Send> {
+    let (r, w) = stream.split();
+    Box::new(
+        tokio::io::lines(std::io::BufReader::new(r))
+            .map_err(|err| log::warn!("admin socket read failed: {:?}", err))
+            .fold(w, move |w, line| {
+                log::debug!("admin command: {}", line);
+                execute(&clients, backend.as_ref(), &line).and_then(|mut reply| {
+                    reply.push('\n');
+                    tokio::io::write_all(w, reply.into_bytes())
+                        .map(|(w, _)| w)
+                        .map_err(|err| log::warn!("admin socket write failed: {:?}", err))
+                })
+            })
+            .map(|_| ()),
+    )
+}
+
+// Anyone who can connect can kill clients, so the socket is kept to the
+// owner. It is bound in a directory only the owner can enter and moved into
+// place once it is 0600, so that nobody can connect in between.
+fn bind_private(path: &str) -> std::io::Result<UnixListener> {
+    let dir = format!("{}.{}.tmp", path, std::process::id());
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::DirBuilder::new().mode(0o700).create(&dir)?;
+    let bound = format!("{}/socket", dir);
+    let result = UnixListener::bind(&bound).and_then(|listener| {
+        std::fs::set_permissions(&bound, std::fs::Permissions::from_mode(0o600))?;
+        std::fs::rename(&bound, path)?;
+        Ok(listener)
+    });
+    let _ = std::fs::remove_dir_all(&dir);
+    result
+}
+
+pub fn serve(
+    path: &str,
+    clients: Clients,
+    backend: Arc<dyn Backend>,
+    shutdown: shutdown::Shutdown,
+) -> Box<dyn Future<Item = (), Error = ()> + Send> {
+    let _ = std::fs::remove_file(path);
+    let listener = match bind_private(path) {
+        Ok(x) => x,
+        Err(err) => {
+            log::error!("admin socket bind failed: path={} {:?}", path, err);
+            return Box::new(futures::future::err(()));
+        }
+    };
+    log::info!("admin socket listening on {}", path);
+    let until = shutdown.clone();
+    until.until(
+        listener
+            .incoming()
+            .map_err(|err| log::error!("admin socket accept failed: {:?}", err))
+            .for_each(move |stream| {
+                tokio::spawn(shutdown.until(handle_admin(
+                    stream,
+                    clients.clone(),
+                    backend.clone(),
+                )));
+                Ok(())
+            }),
+    )
+}
+
+// Sends one command to the admin socket of a running reflex and returns the
+// reply, for command line flags like --screenshot.
+pub fn request(path: &str, line: &str) -> std::io::Result<String> {
+    let mut stream = std::os::unix::net::UnixStream::connect(path)?;
+    std::io::Write::write_all(&mut stream, format!("{}\n", line).as_bytes())?;
+    let mut reply = String::new();
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        if line.is_empty() {
+            break;
+        }
+        reply.push_str(&line);
+        reply.push('\n');
+    }
+    Ok(reply)
+}
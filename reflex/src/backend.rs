@@ -0,0 +1,177 @@
+use crate::admin;
+use crate::compositor::Rect;
+use crate::frame_clock::FrameClock;
+use crate::protocol::connection_stream::Credentials;
+use crate::protocol::event::Event;
+use crate::protocol::event_sink::FatalError;
+use crate::protocol::wayland::wl_display;
+use crate::protocol::wayland::wl_surface::SurfaceState;
+use crate::protocol::xdg_shell::xdg_surface::ConfigureSerials;
+use crate::recording::{Recorder, RecordingSummary};
+use crate::screenshot::{Image, ScreenshotTarget};
+use futures::future::Future;
+use tokio::sync::mpsc::Sender;
+
+pub mod browser;
+pub mod frontend;
+pub mod headless;
+pub mod keymap;
+pub mod vision;
+pub mod vnc;
+
+// Backends.
+//
+// A backend is what reflex shows its clients' windows on. For each client it
+// opens a BackendSession, which is told about the requests reflex handles
+// itself and receives the requests reflex relays unchanged. Input and
+// configure events go the other way, through the client's ClientEvents.
+pub trait Backend: Send + Sync {
+    // Called once by Server::run() before any client connects, for backends
+    // that listen for something of their own.
+    fn start(&self) -> std::io::Result<()> {
+        Ok(())
+    }
+
+    fn connect(
+        &self,
+        client: BackendClient,
+    ) -> Box<dyn Future<Item = Box<dyn BackendSession>, Error = std::io::Error> + Send>;
+
+    // The index of the output a surface is shown on, whose refreshes run its
+    // frame callbacks, or None if it is hidden, minimized or occluded.
+    fn surface_output(&self, _client_id: u32, _surface_id: u32) -> Option<usize> {
+        Some(0)
+    }
+
+    // Backends that know when their outputs refresh keep the clock, call
+    // FrameClock::vblank() on each refresh and return true. Otherwise
+    // reflex times the refreshes itself.
+    fn drive_frame_clock(&self, _clock: &FrameClock) -> bool {
+        false
+    }
+
+    // What the output or a surface shows right now. Backends that do not keep
+    // buffer contents in reflex cannot take screenshots.
+    fn screenshot(&self, _target: &ScreenshotTarget) -> Result<Image, String> {
+        Err("this backend does not keep buffer contents".to_string())
+    }
+
+    // Starts feeding the recorder a frame at each commit, until
+    // stop_recording().
+    fn start_recording(&self, _recorder: Recorder) -> Result<(), String> {
+        Err("this backend does not keep buffer contents".to_string())
+    }
+
+    fn stop_recording(&self) -> Result<RecordingSummary, String> {
+        Err("not recording".to_string())
+    }
+
+    // Gives the keyboard focus to a client's surface, for the admin
+    // socket's focus command. Backends whose host decides the focus, like
+    // vision, refuse.
+    fn set_focus(&self, _client_id: u32, _surface_id: u32) -> Result<(), String> {
+        Err("this backend takes the input focus from its host".to_string())
+    }
+}
+
+pub trait BackendSession: Send {
+    // A request that reflex does not handle itself, in wire format. Backends
+    // that keep their own protocol state, like the vision relay, get every
+    // such request here; the others can ignore them.
+    fn relay(&mut self, buf: Vec<u8>) -> std::io::Result<()>;
+
+    // A client bound a global. Relayed binds only carry the name and the
+    // new id, so backends that answer binds themselves learn the version
+    // here, right before the bind is relayed.
+    fn global_bound(&mut self, _name: u32, _id: u32, _version: u32) {}
+
+    // wl_surface.commit, with the state it made current.
+    fn surface_commit(&mut self, _surface_id: u32, _state: &SurfaceState) {}
+
+    // Whether the backend has taken its own copy of a committed buffer once
+    // the commit has been relayed. Reflex then releases the buffer right
+    // away; otherwise it waits until another commit replaces the buffer or
+    // the surface is destroyed.
+    fn copies_buffers(&self) -> bool {
+        false
+    }
+
+    // A change to an xdg_toplevel. The request that made it is relayed
+    // afterwards, for backends like vision that keep their own window state.
+    fn window_changed(&mut self, _toplevel_id: u32, _change: &WindowChange) {}
+
+    // Reflex is shutting down; the session is dropped right after.
+    fn shutdown(&mut self) {}
+}
+
+#[derive(Clone, Debug)]
+pub enum WindowChange {
+    Created { surface_id: u32 },
+    Title(String),
+    AppId(String),
+    Minimized,
+    // The window geometry, in surface-local coordinates, changed.
+    Geometry(Rect),
+}
+
+pub struct BackendClient {
+    pub client_id: u32,
+    pub credentials: Credentials,
+    pub events: ClientEvents,
+}
+
+// Sends events to a client. A client that does not read its events gets a
+// wl_display.error and is disconnected once its queue is full, instead of
+// making reflex buffer them forever.
+#[derive(Clone)]
+pub struct ClientEvents {
+    client_id: u32,
+    tx: Sender<Box<dyn Event + Send>>,
+    clients: admin::Clients,
+    configure_serials: ConfigureSerials,
+    fatal: FatalError,
+}
+
+impl ClientEvents {
+    pub(crate) fn new(
+        client_id: u32,
+        tx: Sender<Box<dyn Event + Send>>,
+        clients: admin::Clients,
+        configure_serials: ConfigureSerials,
+        fatal: FatalError,
+    ) -> ClientEvents {
+        ClientEvents {
+            client_id,
+            tx,
+            clients,
+            configure_serials,
+            fatal,
+        }
+    }
+
+    // Records the serial of an xdg_surface.configure sent to the client, so
+    // that its ack_configure can be checked.
+    pub fn configure_sent(&self, xdg_surface_id: u32, serial: u32) {
+        self.configure_serials.sent(xdg_surface_id, serial);
+    }
+
+    // Returns false once the client is gone.
+    pub fn send(&mut self, event: Box<dyn Event + Send>) -> bool {
+        match self.tx.try_send(event) {
+            Ok(()) => true,
+            Err(err) => {
+                if err.is_full() {
+                    let message = "pending event quota exceeded";
+                    self.fatal.set(Box::new(wl_display::events::Error {
+                        sender_object_id: 1,
+                        object_id: 1,
+                        code: wl_display::enums::Error::NoMemory as u32,
+                        message: message.to_string(),
+                    }));
+                    let _ = self.clients.disconnect(self.client_id, message);
+                }
+                false
+            }
+        }
+    }
+}
@@ -0,0 +1,663 @@
+use super::frontend::HeadlessFrontend;
+use super::headless::HeadlessBackend;
+use super::keymap::{
+    char_to_evdev, dom_code_to_evdev, BTN_EXTRA, BTN_LEFT, BTN_MIDDLE, BTN_RIGHT, BTN_SIDE,
+    KEY_LEFTSHIFT,
+};
+use crate::compositor::{Compositor, Rect};
+use crate::config::Config;
+use crate::lock::RwLockExt;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::net::{IpAddr, SocketAddr, TcpStream};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+// Shows the headless backend's output in a web browser and feeds the
+// browser's mouse, keyboard and clipboard to the seat.
+//
+// Reflex serves the viewer page itself at http://<backend.listen>/, by
+// default http://127.0.0.1:8080/, and the page needs nothing from anywhere
+// else. The page opens a WebSocket to /ws, over which reflex sends the
+// damaged 64x64 tiles of the output as PNG, or as raw RGBA with
+// /?format=raw, and the page sends input back as lines of text:
+//
+//   pointer <x> <y>          in output pixels
+//   button <n> <0|1>         MouseEvent.button
+//   wheel <dx> <dy>          in pixels
+//   key <code> <0|1>         KeyboardEvent.code
+//   paste <text>             typed into the focused client
+//   blur                     lets go of every key and button
+//   ack                      the last frame is on screen
+//
+// There is no authentication, so keep it on loopback and tunnel it. Pages
+// from other origins cannot connect: the WebSocket handshake must come from
+// the page reflex served. Requests must name reflex by a loopback address,
+// localhost, or the address they came in on, so that DNS rebinding cannot
+// make another site's page look like reflex's own.
+pub fn backend(config: Arc<RwLock<Config>>) -> HeadlessFrontend {
+    HeadlessFrontend::new("browser", config, serve_connection)
+}
+
+const PAGE: &str = include_str!("browser.html");
+
+const TILE_SIZE: i32 = 64;
+
+// The largest message a page may send, which bounds a paste.
+const MAX_MESSAGE: u64 = 1 << 20;
+
+const OPCODE_CONTINUATION: u8 = 0;
+const OPCODE_TEXT: u8 = 1;
+const OPCODE_BINARY: u8 = 2;
+const OPCODE_CLOSE: u8 = 8;
+const OPCODE_PING: u8 = 9;
+const OPCODE_PONG: u8 = 10;
+
+const TILE_RAW: u8 = 0;
+const TILE_PNG: u8 = 1;
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+struct HttpRequest {
+    path: String,
+    query: String,
+    headers: Vec<(String, String)>,
+}
+
+impl HttpRequest {
+    fn read<R: BufRead>(r: &mut R) -> io::Result<HttpRequest> {
+        let mut lines = Vec::new();
+        let mut total = 0;
+        loop {
+            let mut line = String::new();
+            total += r.by_ref().take(8192).read_line(&mut line)?;
+            if total >= 8192 || line.is_empty() {
+                return Err(invalid_data("incomplete request"));
+            }
+            let line = line.trim_end().to_string();
+            if line.is_empty() {
+                break;
+            }
+            lines.push(line);
+        }
+        let request_line = lines.first().map(|x| x.as_str()).unwrap_or("");
+        let target = match request_line.split(' ').collect::<Vec<_>>().as_slice() {
+            ["GET", target, _] => target.to_string(),
+            _ => return Err(invalid_data(&format!("bad request {:?}", request_line))),
+        };
+        let (path, query) = match target.find('?') {
+            Some(i) => (target[..i].to_string(), target[i + 1..].to_string()),
+            None => (target, String::new()),
+        };
+        let headers = lines[1..]
+            .iter()
+            .filter_map(|line| {
+                let i = line.find(':')?;
+                Some((
+                    line[..i].trim().to_ascii_lowercase(),
+                    line[i + 1..].trim().to_string(),
+                ))
+            })
+            .collect();
+        Ok(HttpRequest {
+            path,
+            query,
+            headers,
+        })
+    }
+
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(x, _)| x == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+fn respond<W: Write>(w: &mut W, status: &str, content_type: &str, body: &[u8]) -> io::Result<()> {
+    write!(
+        w,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nCache-Control: no-store\r\nConnection: close\r\n\r\n",
+        status,
+        content_type,
+        body.len()
+    )?;
+    w.write_all(body)?;
+    w.flush()
+}
+
+fn serve_connection(
+    stream: TcpStream,
+    config: &Arc<RwLock<Config>>,
+    headless: &HeadlessBackend,
+) -> io::Result<()> {
+    let local = stream.local_addr()?;
+    let mut r = BufReader::new(stream.try_clone()?);
+    let mut w = BufWriter::new(stream);
+    let request = HttpRequest::read(&mut r)?;
+    let listen = config.read_locked().backend.listen_address();
+    let own_host = match request.header("host") {
+        Some(x) => is_own_host(x, local, &listen),
+        None => false,
+    };
+    if !own_host {
+        return respond(
+            &mut w,
+            "421 Misdirected Request",
+            "text/plain",
+            b"open reflex by its address or as localhost\n",
+        );
+    }
+    match request.path.as_str() {
+        "/" => respond(
+            &mut w,
+            "200 OK",
+            "text/html; charset=utf-8",
+            PAGE.as_bytes(),
+        ),
+        "/ws" => {
+            let key = match request.header("sec-websocket-key") {
+                Some(x) if is_same_origin(&request) => x.to_string(),
+                _ => {
+                    return respond(
+                        &mut w,
+                        "403 Forbidden",
+                        "text/plain",
+                        b"only the page served here can connect\n",
+                    )
+                }
+            };
+            let mut hash_input = key.into_bytes();
+            hash_input.extend_from_slice(b"258EAFA5-E914-47DA-95CA-C5AB0DC85B11");
+            write!(
+                w,
+                "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n",
+                base64(&sha1(&hash_input))
+            )?;
+            w.flush()?;
+            let png = !request.query.split('&').any(|x| x == "format=raw");
+            log::info!("browser: viewer {} connected", w.get_ref().peer_addr()?);
+            serve_viewer(r, w, config, headless, png)
+        }
+        _ => respond(&mut w, "404 Not Found", "text/plain", b"not found\n"),
+    }
+}
+
+// Browsers always send Origin with WebSocket handshakes, so a missing or
+// foreign one is another program or another site's page.
+fn is_same_origin(request: &HttpRequest) -> bool {
+    match (request.header("origin"), request.header("host")) {
+        (Some(origin), Some(host)) => origin == format!("http://{}", host),
+        _ => false,
+    }
+}
+
+// Whether a Host header names this server in a way no one else's DNS can:
+// loopback, the address the request came in on, or backend.listen.
+fn is_own_host(host: &str, local: SocketAddr, listen: &str) -> bool {
+    if host.eq_ignore_ascii_case(listen) {
+        return true;
+    }
+    let name = if host.starts_with('[') {
+        match host.find(']') {
+            Some(i) => &host[1..i],
+            None => return false,
+        }
+    } else {
+        host.split(':').next().unwrap_or("")
+    };
+    if name.eq_ignore_ascii_case("localhost") {
+        return true;
+    }
+    match name.parse::<IpAddr>() {
+        Ok(ip) => ip.is_loopback() || ip == local.ip(),
+        Err(_) => false,
+    }
+}
+
+// What the reader thread passes on to the thread that sends frames.
+enum Request {
+    Ack,
+    Pong(Vec<u8>),
+    Close,
+}
+
+fn serve_viewer(
+    mut r: BufReader<TcpStream>,
+    mut w: BufWriter<TcpStream>,
+    config: &Arc<RwLock<Config>>,
+    headless: &HeadlessBackend,
+    png: bool,
+) -> io::Result<()> {
+    let (width, height, scale, refresh) = {
+        let config = config.read_locked();
+        let output = config.outputs.first().cloned().unwrap_or_default();
+        (output.width, output.height, output.scale, output.refresh)
+    };
+    write_frame(
+        &mut w,
+        OPCODE_TEXT,
+        format!("init {} {}", width, height).as_bytes(),
+    )?;
+    w.flush()?;
+
+    let (tx, rx) = mpsc::channel();
+    let input = headless.clone();
+    std::thread::Builder::new()
+        .name("browser input".to_string())
+        .spawn(move || {
+            let mut seat = ViewerInput::new(input);
+            let result = seat.read_messages(&mut r, &tx);
+            seat.release_all();
+            if let Err(err) = &result {
+                log::info!("browser: viewer input ended: {}", err);
+            }
+            let _ = tx.send(Request::Close);
+        })?;
+
+    let interval = Duration::from_micros(1_000_000_000 / refresh.max(1) as u64);
+    let compositor = Compositor::new(width, height, scale);
+    let result = send_frames(&mut w, &rx, headless, compositor, png, interval);
+    // Ends the input thread too, if it is still reading.
+    let _ = w.get_ref().shutdown(std::net::Shutdown::Both);
+    log::info!("browser: viewer disconnected");
+    result
+}
+
+// Sends the damaged tiles whenever the output changed and the page has shown
+// the previous frame, checked once a frame.
+fn send_frames(
+    w: &mut BufWriter<TcpStream>,
+    rx: &Receiver<Request>,
+    headless: &HeadlessBackend,
+    mut compositor: Compositor,
+    png: bool,
+    interval: Duration,
+) -> io::Result<()> {
+    let mut awaiting_ack = false;
+    let mut first = true;
+    loop {
+        match rx.recv_timeout(interval) {
+            Ok(Request::Ack) => awaiting_ack = false,
+            Ok(Request::Pong(payload)) => {
+                write_frame(w, OPCODE_PONG, &payload)?;
+                w.flush()?;
+            }
+            Ok(Request::Close) | Err(RecvTimeoutError::Disconnected) => {
+                let _ = write_frame(w, OPCODE_CLOSE, &[]);
+                let _ = w.flush();
+                return Ok(());
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+        }
+        if awaiting_ack {
+            continue;
+        }
+        let output = Rect::new(0, 0, compositor.width(), compositor.height());
+        let mut damage = compositor.render(&headless.scene());
+        if first {
+            damage = vec![output];
+            first = false;
+        }
+        let tiles = dirty_tiles(&damage, &output);
+        if tiles.is_empty() {
+            continue;
+        }
+        for tile in &tiles {
+            let message = encode_tile(&compositor, tile, png)?;
+            write_frame(w, OPCODE_BINARY, &message)?;
+        }
+        write_frame(w, OPCODE_TEXT, b"frame")?;
+        w.flush()?;
+        awaiting_ack = true;
+    }
+}
+
+// The tiles of the output grid that any damage touches, clipped to the
+// output.
+fn dirty_tiles(damage: &[Rect], output: &Rect) -> Vec<Rect> {
+    let columns = (output.width + TILE_SIZE - 1) / TILE_SIZE;
+    let rows = (output.height + TILE_SIZE - 1) / TILE_SIZE;
+    let mut dirty = vec![false; (columns.max(0) * rows.max(0)) as usize];
+    for rect in damage {
+        let rect = rect.intersect(output);
+        if rect.is_empty() {
+            continue;
+        }
+        for row in rect.y / TILE_SIZE..=(rect.bottom() - 1) / TILE_SIZE {
+            for column in rect.x / TILE_SIZE..=(rect.right() - 1) / TILE_SIZE {
+                dirty[(row * columns + column) as usize] = true;
+            }
+        }
+    }
+    let mut tiles = Vec::new();
+    for (i, _) in dirty.iter().enumerate().filter(|(_, x)| **x) {
+        let (row, column) = (i as i32 / columns, i as i32 % columns);
+        let tile = Rect::new(column * TILE_SIZE, row * TILE_SIZE, TILE_SIZE, TILE_SIZE);
+        tiles.push(tile.intersect(output));
+    }
+    tiles
+}
+
+fn encode_tile(compositor: &Compositor, tile: &Rect, png: bool) -> io::Result<Vec<u8>> {
+    let mut rgba = Vec::with_capacity((tile.width * tile.height * 4) as usize);
+    for y in tile.y..tile.bottom() {
+        let row = (y * compositor.width()) as usize;
+        for pixel in &compositor.pixels()[row + tile.x as usize..row + tile.right() as usize] {
+            rgba.extend_from_slice(&[(pixel >> 16) as u8, (pixel >> 8) as u8, *pixel as u8, 0xff]);
+        }
+    }
+    let mut message = vec![if png { TILE_PNG } else { TILE_RAW }, 0];
+    for value in &[tile.x, tile.y, tile.width, tile.height] {
+        message.extend_from_slice(&(*value as u16).to_le_bytes());
+    }
+    if png {
+        let mut encoder = png::Encoder::new(&mut message, tile.width as u32, tile.height as u32);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder
+            .write_header()
+            .and_then(|mut writer| writer.write_image_data(&rgba))
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+    } else {
+        message.extend_from_slice(&rgba);
+    }
+    Ok(message)
+}
+
+// Server frames are never masked or fragmented.
+fn write_frame<W: Write>(w: &mut W, opcode: u8, payload: &[u8]) -> io::Result<()> {
+    w.write_all(&[0x80 | opcode])?;
+    match payload.len() {
+        len if len < 126 => w.write_all(&[len as u8])?,
+        len if len <= 0xffff => {
+            w.write_all(&[126])?;
+            w.write_all(&(len as u16).to_be_bytes())?;
+        }
+        len => {
+            w.write_all(&[127])?;
+            w.write_all(&(len as u64).to_be_bytes())?;
+        }
+    }
+    w.write_all(payload)
+}
+
+// Returns whether this is the last frame of a message, the opcode and the
+// unmasked payload.
+fn read_frame<R: Read>(r: &mut R) -> io::Result<(bool, u8, Vec<u8>)> {
+    let mut header = [0; 2];
+    r.read_exact(&mut header)?;
+    let fin = header[0] & 0x80 != 0;
+    let opcode = header[0] & 0x0f;
+    if header[1] & 0x80 == 0 {
+        return Err(invalid_data("unmasked frame from the page"));
+    }
+    let len = match header[1] & 0x7f {
+        126 => {
+            let mut buf = [0; 2];
+            r.read_exact(&mut buf)?;
+            u64::from(u16::from_be_bytes(buf))
+        }
+        127 => {
+            let mut buf = [0; 8];
+            r.read_exact(&mut buf)?;
+            u64::from_be_bytes(buf)
+        }
+        len => u64::from(len),
+    };
+    if len > MAX_MESSAGE {
+        return Err(invalid_data("message too large"));
+    }
+    let mut mask = [0; 4];
+    r.read_exact(&mut mask)?;
+    let mut payload = vec![0; len as usize];
+    r.read_exact(&mut payload)?;
+    for (i, byte) in payload.iter_mut().enumerate() {
+        *byte ^= mask[i % 4];
+    }
+    Ok((fin, opcode, payload))
+}
+
+// The keys and buttons one page holds, so that they can be let go when it
+// loses focus or disconnects.
+struct ViewerInput {
+    headless: HeadlessBackend,
+    keys: Vec<u32>,
+    buttons: Vec<u32>,
+}
+
+impl ViewerInput {
+    fn new(headless: HeadlessBackend) -> ViewerInput {
+        ViewerInput {
+            headless,
+            keys: Vec::new(),
+            buttons: Vec::new(),
+        }
+    }
+
+    fn read_messages<R: Read>(&mut self, r: &mut R, tx: &Sender<Request>) -> io::Result<()> {
+        let mut message = Vec::new();
+        loop {
+            let (fin, opcode, payload) = read_frame(r)?;
+            match opcode {
+                OPCODE_TEXT | OPCODE_BINARY | OPCODE_CONTINUATION => {
+                    if message.len() as u64 + payload.len() as u64 > MAX_MESSAGE {
+                        return Err(invalid_data("message too large"));
+                    }
+                    message.extend_from_slice(&payload);
+                    if fin {
+                        let text = String::from_utf8_lossy(&message).into_owned();
+                        message.clear();
+                        if let Some(request) = self.message(&text) {
+                            if tx.send(request).is_err() {
+                                return Ok(());
+                            }
+                        }
+                    }
+                }
+                OPCODE_PING => {
+                    let _ = tx.send(Request::Pong(payload));
+                }
+                OPCODE_CLOSE => return Ok(()),
+                _ => {}
+            }
+        }
+    }
+
+    fn message(&mut self, text: &str) -> Option<Request> {
+        let (command, rest) = match text.find(' ') {
+            Some(i) => (&text[..i], &text[i + 1..]),
+            None => (text, ""),
+        };
+        let args: Vec<&str> = rest.split(' ').collect();
+        let number = |i: usize| args.get(i).and_then(|x| x.parse::<f64>().ok());
+        match (command, number(0), number(1)) {
+            ("ack", _, _) => return Some(Request::Ack),
+            ("pointer", Some(x), Some(y)) => self.headless.pointer_motion(x, y),
+            ("button", Some(button), Some(pressed)) => self.button(button as u32, pressed != 0.0),
+            ("wheel", Some(dx), Some(dy)) => {
+                if dy != 0.0 {
+                    self.headless.pointer_axis(0, dy);
+                }
+                if dx != 0.0 {
+                    self.headless.pointer_axis(1, dx);
+                }
+            }
+            ("key", _, Some(pressed)) => match dom_code_to_evdev(args[0]) {
+                Some(keycode) => self.key(keycode, pressed != 0.0),
+                None => log::debug!("browser: no key for {:?}", args[0]),
+            },
+            ("paste", _, _) => self.paste(rest),
+            ("blur", _, _) => self.release_all(),
+            _ => log::debug!("browser: unknown message {:?}", text),
+        }
+        None
+    }
+
+    fn button(&mut self, button: u32, pressed: bool) {
+        let button = match button {
+            0 => BTN_LEFT,
+            1 => BTN_MIDDLE,
+            2 => BTN_RIGHT,
+            3 => BTN_SIDE,
+            4 => BTN_EXTRA,
+            _ => return,
+        };
+        if pressed {
+            self.buttons.push(button);
+        } else {
+            self.buttons.retain(|x| *x != button);
+        }
+        self.headless.pointer_button(button, pressed);
+    }
+
+    fn key(&mut self, keycode: u32, pressed: bool) {
+        if pressed {
+            self.keys.push(keycode);
+        } else {
+            self.keys.retain(|x| *x != keycode);
+        }
+        self.headless.key(keycode, pressed);
+    }
+
+    // Types the text, as if on a US keyboard. The modifiers of the paste
+    // shortcut are let go first so that they do not change what is typed.
+    fn paste(&mut self, text: &str) {
+        for keycode in std::mem::take(&mut self.keys) {
+            self.headless.key(keycode, false);
+        }
+        for c in text.chars() {
+            let (keycode, shift) = match char_to_evdev(c) {
+                Some(x) => x,
+                None => {
+                    log::debug!("browser: cannot type {:?}", c);
+                    continue;
+                }
+            };
+            if shift {
+                self.headless.key(KEY_LEFTSHIFT, true);
+            }
+            self.headless.key(keycode, true);
+            self.headless.key(keycode, false);
+            if shift {
+                self.headless.key(KEY_LEFTSHIFT, false);
+            }
+        }
+    }
+
+    fn release_all(&mut self) {
+        for keycode in std::mem::take(&mut self.keys) {
+            self.headless.key(keycode, false);
+        }
+        for button in std::mem::take(&mut self.buttons) {
+            self.headless.pointer_button(button, false);
+        }
+    }
+}
+
+fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut h: [u32; 5] = [
+        0x6745_2301,
+        0xefcd_ab89,
+        0x98ba_dcfe,
+        0x1032_5476,
+        0xc3d2_e1f0,
+    ];
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&(data.len() as u64 * 8).to_be_bytes());
+    for block in message.chunks(64) {
+        let mut w = [0u32; 80];
+        for (word, bytes) in w.iter_mut().zip(block.chunks(4)) {
+            *word = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        }
+        for i in 16..80 {
+            w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e] = h;
+        for (i, word) in w.iter().enumerate() {
+            let (f, k) = match i {
+                0..=19 => ((b & c) | (!b & d), 0x5a82_7999),
+                20..=39 => (b ^ c ^ d, 0x6ed9_eba1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8f1b_bcdc),
+                _ => (b ^ c ^ d, 0xca62_c1d6),
+            };
+            let temp = a
+                .rotate_left(5)
+                .wrapping_add(f)
+                .wrapping_add(e)
+                .wrapping_add(k)
+                .wrapping_add(*word);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = temp;
+        }
+        for (x, y) in h.iter_mut().zip(&[a, b, c, d, e]) {
+            *x = x.wrapping_add(*y);
+        }
+    }
+    let mut out = [0; 20];
+    for (bytes, x) in out.chunks_mut(4).zip(&h) {
+        bytes.copy_from_slice(&x.to_be_bytes());
+    }
+    out
+}
+
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::new();
+    for chunk in data.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, x)| n | u32::from(*x) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_own_hosts_are_served() {
+        let local: SocketAddr = "192.168.1.5:8080".parse().unwrap();
+        let listen = "desktop.lan:8080";
+        for host in &[
+            "127.0.0.1:8080",
+            "127.1.2.3",
+            "[::1]:8080",
+            "localhost:8080",
+            "LOCALHOST",
+            "192.168.1.5:8080",
+            "desktop.lan:8080",
+        ] {
+            assert!(is_own_host(host, local, listen), "{}", host);
+        }
+        for host in &[
+            "evil.example:8080",
+            "localhost.evil.example:8080",
+            "127.0.0.1.evil.example",
+            "192.168.1.6:8080",
+            "desktop.lan:9090",
+            "[::1",
+            "",
+        ] {
+            assert!(!is_own_host(host, local, listen), "{}", host);
+        }
+    }
+}
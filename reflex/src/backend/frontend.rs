@@ -0,0 +1,126 @@
+use super::headless::HeadlessBackend;
+use super::{Backend, BackendClient, BackendSession};
+use crate::config::Config;
+use crate::frame_clock::FrameClock;
+use crate::lock::RwLockExt;
+use crate::recording::{Recorder, RecordingSummary};
+use crate::screenshot::{Image, ScreenshotTarget};
+use futures::future::Future;
+use std::io;
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, RwLock};
+
+// Serves one connection to a frontend until it closes, on a thread of its
+// own.
+pub type Serve = fn(TcpStream, &Arc<RwLock<Config>>, &HeadlessBackend) -> io::Result<()>;
+
+// The headless backend shown to viewers that connect over TCP, like the VNC
+// and browser backends. Clients are handled by the headless backend; the
+// frontend listens on backend.listen and serves each connection there.
+pub struct HeadlessFrontend {
+    name: &'static str,
+    config: Arc<RwLock<Config>>,
+    headless: HeadlessBackend,
+    serve: Serve,
+}
+
+impl HeadlessFrontend {
+    pub fn new(name: &'static str, config: Arc<RwLock<Config>>, serve: Serve) -> HeadlessFrontend {
+        HeadlessFrontend {
+            name,
+            headless: HeadlessBackend::new(config.clone()),
+            config,
+            serve,
+        }
+    }
+}
+
+impl Backend for HeadlessFrontend {
+    fn start(&self) -> io::Result<()> {
+        let address = self.config.read_locked().backend.listen_address();
+        let listener = TcpListener::bind(&address)?;
+        let local = listener.local_addr()?;
+        if !local.ip().is_loopback() {
+            log::warn!(
+                "{}: listening on {} without authentication; anyone who can reach it controls the clients",
+                self.name,
+                local
+            );
+        } else {
+            log::info!("{}: listening on {}", self.name, local);
+        }
+        let (name, serve) = (self.name, self.serve);
+        let config = self.config.clone();
+        let headless = self.headless.clone();
+        std::thread::Builder::new()
+            .name(name.to_string())
+            .spawn(move || {
+                for stream in listener.incoming() {
+                    match stream {
+                        Ok(stream) => {
+                            spawn_connection(name, serve, stream, config.clone(), headless.clone())
+                        }
+                        Err(err) => log::warn!("{}: cannot accept a connection: {}", name, err),
+                    }
+                }
+            })?;
+        Ok(())
+    }
+
+    fn connect(
+        &self,
+        client: BackendClient,
+    ) -> Box<dyn Future<Item = Box<dyn BackendSession>, Error = io::Error> + Send> {
+        self.headless.connect(client)
+    }
+
+    fn surface_output(&self, client_id: u32, surface_id: u32) -> Option<usize> {
+        self.headless.surface_output(client_id, surface_id)
+    }
+
+    fn drive_frame_clock(&self, clock: &FrameClock) -> bool {
+        self.headless.drive_frame_clock(clock)
+    }
+
+    fn screenshot(&self, target: &ScreenshotTarget) -> Result<Image, String> {
+        self.headless.screenshot(target)
+    }
+
+    fn start_recording(&self, recorder: Recorder) -> Result<(), String> {
+        self.headless.start_recording(recorder)
+    }
+
+    fn stop_recording(&self) -> Result<RecordingSummary, String> {
+        self.headless.stop_recording()
+    }
+
+    fn set_focus(&self, client_id: u32, surface_id: u32) -> Result<(), String> {
+        self.headless.set_focus(client_id, surface_id)
+    }
+}
+
+fn spawn_connection(
+    name: &'static str,
+    serve: Serve,
+    stream: TcpStream,
+    config: Arc<RwLock<Config>>,
+    headless: HeadlessBackend,
+) {
+    let peer = match stream.peer_addr() {
+        Ok(x) => x,
+        Err(err) => {
+            log::warn!("{}: connection went away: {}", name, err);
+            return;
+        }
+    };
+    let result = std::thread::Builder::new()
+        .name(format!("{} {}", name, peer))
+        .spawn(move || {
+            if let Err(err) = serve(stream, &config, &headless) {
+                log::info!("{}: {}: {}", name, peer, err);
+            }
+        });
+    if let Err(err) = result {
+        log::error!("{}: cannot start a thread for {}: {}", name, peer, err);
+    }
+}
@@ -0,0 +1,1347 @@
+use super::{Backend, BackendClient, BackendSession, ClientEvents, WindowChange};
+use crate::compositor::region::Region;
+use crate::compositor::{Buffer, Compositor, Rect, SceneSurface, FORMAT_XRGB8888};
+use crate::config::Config;
+use crate::frame_clock::FrameClock;
+use crate::lock::{MutexExt, RwLockExt};
+use crate::protocol::event::Event;
+use crate::protocol::interface::{find_interface, ArgKind, Interface, Message};
+use crate::protocol::trace;
+use crate::protocol::wayland::wl_surface::Placement;
+use crate::protocol::wayland::{
+    wl_buffer, wl_callback, wl_compositor, wl_data_device_manager, wl_display, wl_registry,
+    wl_seat, wl_shm, wl_shm_pool, wl_subcompositor, wl_subsurface, wl_surface,
+};
+use crate::protocol::xdg_shell::xdg_positioner::XdgPositioner;
+use crate::protocol::xdg_shell::{
+    xdg_popup, xdg_positioner, xdg_surface, xdg_toplevel, xdg_wm_base,
+};
+use crate::recording::{Recorder, RecordingSummary};
+use crate::screenshot::{Image, ScreenshotTarget};
+use byteorder::{ByteOrder, NativeEndian, ReadBytesExt};
+use futures::future::Future;
+use std::collections::{BTreeMap, HashMap};
+use std::io::Cursor;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Instant;
+
+mod seat;
+
+// Keeps every client's surfaces in memory instead of showing them anywhere,
+// so that reflex runs on machines without a Windows host. It answers the
+// relayed requests the way vision would: it advertises the globals, copies
+// each committed shm buffer so that reflex can release it, and configures
+// new toplevels and popups without waiting for a window manager.
+//
+// With clock = "manual" the frame clock only moves on advance(), which makes
+// frame timing deterministic in tests. Tests drive clients with configure() and
+// close(), type and click with key() and the pointer_*() functions, and
+// read back what they drew with surfaces().
+#[derive(Clone)]
+pub struct HeadlessBackend {
+    config: Arc<RwLock<Config>>,
+    state: Arc<Mutex<State>>,
+}
+
+// What a surface showed after its last commit.
+#[derive(Clone, Debug)]
+pub struct SurfaceSnapshot {
+    pub client_id: u32,
+    pub surface_id: u32,
+    pub title: Option<String>,
+    pub app_id: Option<String>,
+    pub width: i32,
+    pub height: i32,
+    pub stride: i32,
+    pub format: u32,
+    pub scale: i32,
+    pub transform: i32,
+    pub pixels: Arc<Vec<u8>>,
+}
+
+// A rendered output, as 0xXXRRGGBB pixels row by row.
+#[derive(Clone, Debug)]
+pub struct Frame {
+    pub width: i32,
+    pub height: i32,
+    pub pixels: Vec<u32>,
+    pub damage: Vec<Rect>,
+}
+
+struct State {
+    epoch: Instant,
+    manual_time: u32,
+    manual_clock: bool,
+    frame_clock: Option<FrameClock>,
+    compositor: Option<Compositor>,
+    recorder: Option<Recorder>,
+    seat: seat::Seat,
+    next_serial: u32,
+    // Toplevels stack by the value they took from here when they were
+    // created or last raised.
+    next_stacking: u64,
+    clients: BTreeMap<u32, ClientState>,
+}
+
+struct ClientState {
+    events: ClientEvents,
+    objects: HashMap<u32, Object>,
+    // Versions of the bound globals and the objects created from them.
+    versions: HashMap<u32, u32>,
+}
+
+enum Object {
+    Plain(&'static Interface),
+    ShmPool {
+        fd: i32,
+        size: i32,
+    },
+    Buffer {
+        fd: i32,
+        offset: i32,
+        width: i32,
+        height: i32,
+        stride: i32,
+        format: u32,
+    },
+    Surface(Surface),
+    Subsurface {
+        surface_id: u32,
+    },
+    XdgSurface {
+        surface_id: u32,
+        configured: bool,
+    },
+    XdgToplevel {
+        xdg_surface_id: u32,
+        stacking: u64,
+        x: i32,
+        y: i32,
+        title: Option<String>,
+        app_id: Option<String>,
+        // Until the next configure.
+        minimized: bool,
+        // The window geometry, None for the whole surface.
+        geometry: Option<Rect>,
+    },
+    Positioner(XdgPositioner),
+    XdgPopup {
+        xdg_surface_id: u32,
+        // The xdg_surface of the parent.
+        parent_id: u32,
+        // Relative to the parent's window geometry.
+        geometry: Rect,
+    },
+}
+
+impl Object {
+    fn interface(&self) -> &'static Interface {
+        match self {
+            Object::Plain(interface) => interface,
+            Object::ShmPool { .. } => &wl_shm_pool::INTERFACE,
+            Object::Buffer { .. } => &wl_buffer::INTERFACE,
+            Object::Surface(_) => &wl_surface::INTERFACE,
+            Object::Subsurface { .. } => &wl_subsurface::INTERFACE,
+            Object::XdgSurface { .. } => &xdg_surface::INTERFACE,
+            Object::XdgToplevel { .. } => &xdg_toplevel::INTERFACE,
+            Object::Positioner(_) => &xdg_positioner::INTERFACE,
+            Object::XdgPopup { .. } => &xdg_popup::INTERFACE,
+        }
+    }
+}
+
+// The state reflex last applied, which for synchronized sub-surfaces is not
+// that of their last commit.
+#[derive(Default)]
+struct Surface {
+    contents: Option<Buffer>,
+    generation: u64,
+    // What the commit that made generation current damaged, and on top of
+    // which generation.
+    damage: Option<(u64, Vec<Rect>)>,
+    scale: i32,
+    transform: i32,
+    input_region: Option<Region>,
+    // Bottom to top, with the surface itself as 0.
+    subsurfaces: Vec<Placement>,
+}
+
+// Globals in the order they are advertised, by their reflex names.
+const GLOBALS: &[(u32, &Interface, u32)] = &[
+    (
+        wl_compositor::GLOBAL_SINGLETON_NAME,
+        &wl_compositor::INTERFACE,
+        wl_compositor::VERSION,
+    ),
+    (
+        wl_shm::GLOBAL_SINGLETON_NAME,
+        &wl_shm::INTERFACE,
+        wl_shm::VERSION,
+    ),
+    (
+        wl_data_device_manager::GLOBAL_SINGLETON_NAME,
+        &wl_data_device_manager::INTERFACE,
+        wl_data_device_manager::VERSION,
+    ),
+    (
+        xdg_wm_base::GLOBAL_SINGLETON_NAME,
+        &xdg_wm_base::INTERFACE,
+        xdg_wm_base::VERSION,
+    ),
+    (wl_seat::GLOBAL_NAME, &wl_seat::INTERFACE, seat::VERSION),
+    (
+        wl_subcompositor::GLOBAL_NAME,
+        &wl_subcompositor::INTERFACE,
+        wl_subcompositor::VERSION,
+    ),
+];
+
+impl HeadlessBackend {
+    pub fn new(config: Arc<RwLock<Config>>) -> HeadlessBackend {
+        HeadlessBackend {
+            config: config.clone(),
+            state: Arc::new(Mutex::new(State {
+                epoch: Instant::now(),
+                manual_time: 0,
+                manual_clock: config.read_locked().backend.clock == "manual",
+                frame_clock: None,
+                compositor: None,
+                recorder: None,
+                seat: seat::Seat::default(),
+                next_serial: 0,
+                next_stacking: 0,
+                clients: BTreeMap::new(),
+            })),
+        }
+    }
+
+    // Moves the manual clock forward and refreshes every output, which runs
+    // the pending frame callbacks.
+    pub fn advance(&self, ms: u32) {
+        let (time, frame_clock) = {
+            let mut state = self.state.locked();
+            state.manual_time = state.manual_time.wrapping_add(ms);
+            (state.manual_time, state.frame_clock.clone())
+        };
+        // The clock asks surface_output(), so the state must not be locked.
+        if let Some(frame_clock) = frame_clock {
+            let outputs = self.config.read_locked().outputs.len();
+            for output in 0..outputs {
+                frame_clock.vblank(output, time);
+            }
+        }
+    }
+
+    // Asks a toplevel to take a new size and states, as a window manager
+    // would. Returns false if there is no such toplevel.
+    pub fn configure(
+        &self,
+        client_id: u32,
+        toplevel_id: u32,
+        width: i32,
+        height: i32,
+        states: &[u32],
+    ) -> bool {
+        let mut state = self.state.locked();
+        let serial = state.serial();
+        match state.clients.get_mut(&client_id) {
+            Some(client) => client.configure(toplevel_id, width, height, states, serial),
+            None => false,
+        }
+    }
+
+    // Asks a toplevel to close, as if its close button had been clicked.
+    pub fn close(&self, client_id: u32, toplevel_id: u32) -> bool {
+        let mut state = self.state.locked();
+        let client = match state.clients.get_mut(&client_id) {
+            Some(x) => x,
+            None => return false,
+        };
+        match client.objects.get(&toplevel_id) {
+            Some(Object::XdgToplevel { .. }) => {
+                client.events.send(Box::new(xdg_toplevel::events::Close {
+                    sender_object_id: toplevel_id,
+                }))
+            }
+            _ => false,
+        }
+    }
+
+    // Places a toplevel on the output: the top left corner of its window
+    // geometry goes to x, y. New toplevels are at the origin.
+    pub fn move_window(&self, client_id: u32, toplevel_id: u32, x: i32, y: i32) -> bool {
+        let mut state = self.state.locked();
+        let client = match state.clients.get_mut(&client_id) {
+            Some(x) => x,
+            None => return false,
+        };
+        match client.objects.get_mut(&toplevel_id) {
+            Some(Object::XdgToplevel {
+                x: toplevel_x,
+                y: toplevel_y,
+                ..
+            }) => {
+                *toplevel_x = x;
+                *toplevel_y = y;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    // Sends any event to a client.
+    pub fn send_event(&self, client_id: u32, event: Box<dyn Event + Send>) -> bool {
+        let mut state = self.state.locked();
+        match state.clients.get_mut(&client_id) {
+            Some(client) => client.events.send(event),
+            None => false,
+        }
+    }
+
+    // Moves the pointer to x, y in pixels of the first output.
+    pub fn pointer_motion(&self, x: f64, y: f64) {
+        let scale = self.output_scale();
+        self.state.locked().pointer_motion(x / scale, y / scale);
+    }
+
+    // Presses or releases a button, a Linux input event code like BTN_LEFT.
+    // A press gives the keyboard focus to the surface under the pointer.
+    pub fn pointer_button(&self, button: u32, pressed: bool) {
+        self.state.locked().pointer_button(button, pressed);
+    }
+
+    // Scrolls by value on axis 0, vertical, or 1, horizontal.
+    pub fn pointer_axis(&self, axis: u32, value: f64) {
+        self.state.locked().pointer_axis(axis, value);
+    }
+
+    // Presses or releases a key, a Linux input event code like KEY_A.
+    pub fn key(&self, keycode: u32, pressed: bool) {
+        let config = self.config.read_locked();
+        self.state.locked().key(&config, keycode, pressed);
+    }
+
+    // Where the pointer is in global coordinates, and the buttons it holds.
+    pub fn pointer(&self) -> (f64, f64, Vec<u32>) {
+        self.state.locked().pointer()
+    }
+
+    // Every surface that has committed a buffer, by client and object id.
+    pub fn surfaces(&self) -> Vec<SurfaceSnapshot> {
+        self.state.locked().surfaces()
+    }
+
+    // Every mapped toplevel that is not minimized, bottom to top. New
+    // toplevels go on top, and so does one that gets the keyboard focus.
+    // Popups are drawn above their parent.
+    pub fn scene(&self) -> Vec<SceneSurface> {
+        self.state.locked().scene()
+    }
+
+    // Renders the first output and returns it with the damage since the
+    // previous call. The first call damages the whole output.
+    pub fn composite(&self) -> Frame {
+        let config = self.config.read_locked();
+        self.state.locked().composite(&config)
+    }
+
+    fn output_scale(&self) -> f64 {
+        let config = self.config.read_locked();
+        f64::from(config.outputs.first().map(|x| x.scale).unwrap_or(1).max(1))
+    }
+}
+
+impl Backend for HeadlessBackend {
+    fn connect(
+        &self,
+        client: BackendClient,
+    ) -> Box<dyn Future<Item = Box<dyn BackendSession>, Error = std::io::Error> + Send> {
+        let client_id = client.client_id;
+        let mut objects = HashMap::new();
+        objects.insert(1, Object::Plain(&wl_display::INTERFACE));
+        self.state.locked().clients.insert(
+            client_id,
+            ClientState {
+                events: client.events,
+                objects,
+                versions: HashMap::new(),
+            },
+        );
+
+        let session: Box<dyn BackendSession> = Box::new(HeadlessSession {
+            client_id,
+            config: self.config.clone(),
+            state: self.state.clone(),
+        });
+        Box::new(futures::future::ok(session))
+    }
+
+    fn surface_output(&self, client_id: u32, surface_id: u32) -> Option<usize> {
+        let config = self.config.read_locked();
+        self.state
+            .locked()
+            .surface_output(&config, client_id, surface_id)
+    }
+
+    fn drive_frame_clock(&self, clock: &FrameClock) -> bool {
+        let mut state = self.state.locked();
+        if !state.manual_clock {
+            return false;
+        }
+        state.frame_clock = Some(clock.clone());
+        true
+    }
+
+    fn screenshot(&self, target: &ScreenshotTarget) -> Result<Image, String> {
+        let config = self.config.read_locked();
+        self.state.locked().screenshot(&config, target)
+    }
+
+    fn start_recording(&self, recorder: Recorder) -> Result<(), String> {
+        let config = self.config.read_locked();
+        let mut state = self.state.locked();
+        if state.recorder.is_some() {
+            return Err("already recording".to_string());
+        }
+        state.recorder = Some(recorder);
+        // The recording starts with what is shown now.
+        state.record_frame(&config);
+        Ok(())
+    }
+
+    fn set_focus(&self, client_id: u32, surface_id: u32) -> Result<(), String> {
+        self.state.locked().focus(client_id, surface_id)
+    }
+
+    fn stop_recording(&self) -> Result<RecordingSummary, String> {
+        let (recorder, now) = {
+            let mut state = self.state.locked();
+            (state.recorder.take(), state.now())
+        };
+        // Waits for the queued frames to be written, without the lock.
+        match recorder {
+            Some(recorder) => recorder
+                .finish(now)
+                .map_err(|err| format!("cannot write the recording: {}", err)),
+            None => Err("not recording".to_string()),
+        }
+    }
+}
+
+struct HeadlessSession {
+    client_id: u32,
+    config: Arc<RwLock<Config>>,
+    state: Arc<Mutex<State>>,
+}
+
+impl BackendSession for HeadlessSession {
+    fn relay(&mut self, buf: Vec<u8>) -> std::io::Result<()> {
+        // The config before the state, as everywhere else, so that a reload
+        // waiting for the config cannot deadlock with another lock holder.
+        let config = self.config.read_locked();
+        let mut state = self.state.locked();
+        let serial = state.serial();
+        let outcome = match state.clients.get_mut(&self.client_id) {
+            Some(client) => match client.request(&config, &buf, serial) {
+                Some(outcome) => outcome,
+                None => {
+                    log::warn!(
+                        client_id = self.client_id;
+                        "headless: cannot decode relayed request {:?}",
+                        buf
+                    );
+                    Outcome::None
+                }
+            },
+            None => Outcome::None,
+        };
+        match outcome {
+            Outcome::None => {}
+            Outcome::Committed => state.record_frame(&config),
+            Outcome::KeyboardCreated(id) => state.keyboard_created(&config, self.client_id, id),
+            Outcome::ToplevelCreated(id) => state.raise(self.client_id, id),
+        }
+        Ok(())
+    }
+
+    // Surfaces take their state from reflex rather than from the relayed
+    // requests: reflex keeps the wl_region objects, and decides when the
+    // commits of synchronized sub-surfaces are applied.
+    fn surface_commit(&mut self, surface_id: u32, committed: &wl_surface::SurfaceState) {
+        let mut state = self.state.locked();
+        let serial = state.serial();
+        if let Some(client) = state.clients.get_mut(&self.client_id) {
+            client.apply(surface_id, committed, serial);
+        }
+    }
+
+    fn global_bound(&mut self, name: u32, id: u32, version: u32) {
+        let mut state = self.state.locked();
+        if let Some(client) = state.clients.get_mut(&self.client_id) {
+            log::debug!(client_id = self.client_id; "headless: global {} bound as {} v{}", name, id, version);
+            client.versions.insert(id, version);
+        }
+    }
+
+    fn copies_buffers(&self) -> bool {
+        true
+    }
+
+    fn window_changed(&mut self, toplevel_id: u32, change: &WindowChange) {
+        let mut state = self.state.locked();
+        let client = match state.clients.get_mut(&self.client_id) {
+            Some(x) => x,
+            None => return,
+        };
+        if let Some(Object::XdgToplevel {
+            title,
+            app_id,
+            minimized,
+            geometry,
+            ..
+        }) = client.objects.get_mut(&toplevel_id)
+        {
+            match change {
+                WindowChange::Title(x) => *title = Some(x.clone()),
+                WindowChange::AppId(x) => *app_id = Some(x.clone()),
+                WindowChange::Minimized => *minimized = true,
+                WindowChange::Geometry(x) => *geometry = Some(*x),
+                WindowChange::Created { .. } => {}
+            }
+        }
+    }
+}
+
+impl Drop for HeadlessSession {
+    fn drop(&mut self) {
+        self.state.locked().clients.remove(&self.client_id);
+    }
+}
+
+impl State {
+    fn serial(&mut self) -> u32 {
+        self.next_serial = self.next_serial.wrapping_add(1);
+        self.next_serial
+    }
+
+    // Milliseconds on the clock that frame callbacks use.
+    fn now(&self) -> u64 {
+        if self.manual_clock {
+            u64::from(self.manual_time)
+        } else {
+            self.epoch.elapsed().as_millis() as u64
+        }
+    }
+
+    fn surfaces(&self) -> Vec<SurfaceSnapshot> {
+        let mut surfaces = Vec::new();
+        for (client_id, client) in &self.clients {
+            let mut ids: Vec<&u32> = client.objects.keys().collect();
+            ids.sort();
+            for id in ids {
+                let surface = match &client.objects[id] {
+                    Object::Surface(x) => x,
+                    _ => continue,
+                };
+                let contents = match &surface.contents {
+                    Some(x) => x,
+                    None => continue,
+                };
+                let (title, app_id) = client.toplevel_names(*id);
+                surfaces.push(SurfaceSnapshot {
+                    client_id: *client_id,
+                    surface_id: *id,
+                    title,
+                    app_id,
+                    width: contents.width,
+                    height: contents.height,
+                    stride: contents.stride,
+                    format: contents.format,
+                    scale: surface.scale,
+                    transform: surface.transform,
+                    pixels: contents.pixels.clone(),
+                });
+            }
+        }
+        surfaces
+    }
+
+    fn scene(&self) -> Vec<SceneSurface> {
+        let mut toplevels: Vec<(u64, SceneSurface)> = self
+            .clients
+            .iter()
+            .flat_map(|(client_id, client)| client.scene(*client_id))
+            .collect();
+        toplevels.sort_by_key(|(stacking, _)| *stacking);
+        toplevels.into_iter().map(|(_, x)| x).collect()
+    }
+
+    // Puts a toplevel on top of the others.
+    fn raise(&mut self, client_id: u32, toplevel_id: u32) {
+        self.next_stacking += 1;
+        let next = self.next_stacking;
+        if let Some(client) = self.clients.get_mut(&client_id) {
+            if let Some(Object::XdgToplevel { stacking, .. }) = client.objects.get_mut(&toplevel_id)
+            {
+                *stacking = next;
+            }
+        }
+    }
+
+    fn composite(&mut self, config: &Config) -> Frame {
+        let scene = self.scene();
+        let mut damage = Vec::new();
+        if self.compositor.is_none() {
+            let output = config.outputs.first().cloned().unwrap_or_default();
+            damage.push(Rect::new(0, 0, output.width, output.height));
+            self.compositor = Some(Compositor::new(output.width, output.height, output.scale));
+        }
+        let compositor = self.compositor.as_mut().unwrap();
+        damage.extend(compositor.render(&scene));
+        Frame {
+            width: compositor.width(),
+            height: compositor.height(),
+            pixels: compositor.pixels().to_vec(),
+            damage,
+        }
+    }
+
+    // Toplevels are on the output under their centre, unless they are
+    // minimized or covered by an opaque toplevel above them. Other surfaces,
+    // like cursors and the ones not mapped yet, count as on the first
+    // output.
+    fn surface_output(&self, config: &Config, client_id: u32, surface_id: u32) -> Option<usize> {
+        if let Some(client) = self.clients.get(&client_id) {
+            if client.minimized(surface_id) {
+                return None;
+            }
+        }
+        let scene = self.scene();
+        let index = match scene
+            .iter()
+            .position(|x| x.client_id == client_id && x.surface_id == surface_id)
+        {
+            Some(x) => x,
+            None => return Some(0),
+        };
+        let bounds = |x: &SceneSurface| {
+            let (width, height) = x.size();
+            Rect::new(x.x, x.y, width, height)
+        };
+        let rect = bounds(&scene[index]);
+        let occluded = scene[index + 1..].iter().any(|x| {
+            let opaque = x.buffer.as_ref().map(|x| x.format) == Some(FORMAT_XRGB8888);
+            opaque && bounds(x).intersect(&rect) == rect
+        });
+        if occluded {
+            return None;
+        }
+        let (x, y) = (rect.x + rect.width / 2, rect.y + rect.height / 2);
+        config.outputs.iter().position(|output| {
+            let scale = output.scale.max(1);
+            Rect::new(
+                output.x,
+                output.y,
+                output.width / scale,
+                output.height / scale,
+            )
+            .contains(x, y)
+        })
+    }
+
+    fn screenshot(&mut self, config: &Config, target: &ScreenshotTarget) -> Result<Image, String> {
+        let surface = match target {
+            ScreenshotTarget::Output => {
+                let frame = self.composite(config);
+                return Ok(Image::from_frame(frame.width, frame.height, &frame.pixels));
+            }
+            ScreenshotTarget::Surface {
+                client_id,
+                surface_id,
+            } => self
+                .surfaces()
+                .into_iter()
+                .find(|x| x.client_id == *client_id && x.surface_id == *surface_id),
+            ScreenshotTarget::AppId(app_id) => self
+                .surfaces()
+                .into_iter()
+                .find(|x| x.app_id.as_ref() == Some(app_id)),
+        };
+        match surface {
+            Some(x) => Image::from_buffer(&Buffer {
+                width: x.width,
+                height: x.height,
+                stride: x.stride,
+                format: x.format,
+                pixels: x.pixels,
+            }),
+            None => Err("no such surface, or it has no buffer".to_string()),
+        }
+    }
+
+    fn record_frame(&mut self, config: &Config) {
+        let target = match &self.recorder {
+            Some(x) => x.target().clone(),
+            None => return,
+        };
+        // The recorded toplevel may not be mapped yet, or not any more.
+        let image = match self.screenshot(config, &target) {
+            Ok(x) => x,
+            Err(_) => return,
+        };
+        let now = self.now();
+        if let Some(recorder) = &mut self.recorder {
+            if let Err(err) = recorder.frame(image, now) {
+                log::error!("recording stopped: {}", err);
+                self.recorder = None;
+            }
+        }
+    }
+}
+
+enum Value {
+    Int(i32),
+    Uint(u32),
+    Object(u32),
+    NewId(u32),
+    Fd(i32),
+    // Strings and arrays, which no request handled here looks at.
+    Skipped,
+}
+
+impl Value {
+    fn id(&self) -> u32 {
+        match self {
+            Value::Object(x) | Value::NewId(x) | Value::Uint(x) => *x,
+            _ => 0,
+        }
+    }
+
+    fn int(&self) -> i32 {
+        match self {
+            Value::Int(x) | Value::Fd(x) => *x,
+            Value::Uint(x) => *x as i32,
+            _ => 0,
+        }
+    }
+}
+
+// Relayed requests carry their fds inline, as the fd number in reflex.
+fn decode_args(message: &Message, body: &[u8]) -> Option<Vec<Value>> {
+    let mut cursor = Cursor::new(body);
+    let mut values = Vec::new();
+    for arg in message.args {
+        values.push(match arg.kind {
+            ArgKind::Int | ArgKind::Fixed => Value::Int(cursor.read_i32::<NativeEndian>().ok()?),
+            ArgKind::Uint => Value::Uint(cursor.read_u32::<NativeEndian>().ok()?),
+            ArgKind::Object => Value::Object(cursor.read_u32::<NativeEndian>().ok()?),
+            ArgKind::NewId => Value::NewId(cursor.read_u32::<NativeEndian>().ok()?),
+            ArgKind::Fd => Value::Fd(cursor.read_i32::<NativeEndian>().ok()?),
+            ArgKind::String | ArgKind::Array => {
+                let len = cursor.read_u32::<NativeEndian>().ok()?;
+                let padded = trace::padded_len(&cursor, len)?;
+                cursor.set_position(cursor.position() + padded as u64);
+                Value::Skipped
+            }
+        });
+    }
+    Some(values)
+}
+
+// What a relayed request needs done beyond the client's own state.
+enum Outcome {
+    None,
+    Committed,
+    KeyboardCreated(u32),
+    ToplevelCreated(u32),
+}
+
+impl ClientState {
+    fn send<E: Event + Send + 'static>(&mut self, event: E) {
+        self.events.send(Box::new(event));
+    }
+
+    fn delete(&mut self, id: u32) {
+        self.objects.remove(&id);
+        self.versions.remove(&id);
+        self.send(wl_display::events::DeleteId {
+            sender_object_id: 1,
+            id,
+        });
+    }
+
+    // Returns what the state outside the client has to follow up on.
+    fn request(&mut self, config: &Config, buf: &[u8], serial: u32) -> Option<Outcome> {
+        if buf.len() < 8 {
+            return None;
+        }
+        let object_id = NativeEndian::read_u32(&buf[0..4]);
+        let opcode = (NativeEndian::read_u32(&buf[4..8]) & 0xffff) as usize;
+        // Requests to objects that reflex knows but this backend does not
+        // are not answered.
+        let interface = match self.objects.get(&object_id) {
+            Some(x) => x.interface(),
+            None => return Some(Outcome::None),
+        };
+        let body = &buf[8..];
+
+        // wl_registry.bind is relayed with the global's name and the new id
+        // only.
+        if interface.name == wl_registry::INTERFACE.name && opcode == 0 {
+            let mut cursor = Cursor::new(body);
+            let name = cursor.read_u32::<NativeEndian>().ok()?;
+            let id = cursor.read_u32::<NativeEndian>().ok()?;
+            self.bind(config, name, id);
+            return Some(Outcome::None);
+        }
+
+        let message = interface.requests.get(opcode)?;
+        let args = decode_args(message, body)?;
+        for (arg, value) in message.args.iter().zip(&args) {
+            if let (Value::NewId(id), Some(name)) = (value, arg.interface) {
+                if let Some(interface) = find_interface(name) {
+                    self.objects.insert(*id, Object::Plain(interface));
+                }
+                // Objects share the version of the object that made them.
+                if let Some(version) = self.versions.get(&object_id).cloned() {
+                    self.versions.insert(*id, version);
+                }
+            }
+        }
+
+        match (interface.name, message.name) {
+            ("wl_display", "sync") => {
+                let callback = args[0].id();
+                self.send(wl_callback::events::Done {
+                    sender_object_id: callback,
+                    callback_data: serial,
+                });
+                self.delete(callback);
+            }
+            ("wl_display", "get_registry") => {
+                let registry = args[0].id();
+                let disabled = config.protocols.disabled_globals();
+                for (name, interface, version) in GLOBALS {
+                    if !disabled.contains(name) {
+                        self.send(wl_registry::events::Global {
+                            sender_object_id: registry,
+                            name: *name,
+                            interface: interface.name.to_string(),
+                            version: *version,
+                        });
+                    }
+                }
+            }
+            ("wl_compositor", "create_surface") => {
+                self.objects.insert(
+                    args[0].id(),
+                    Object::Surface(Surface {
+                        scale: 1,
+                        ..Surface::default()
+                    }),
+                );
+            }
+            ("wl_shm", "create_pool") => {
+                self.objects.insert(
+                    args[0].id(),
+                    Object::ShmPool {
+                        fd: args[1].int(),
+                        size: args[2].int(),
+                    },
+                );
+            }
+            ("wl_shm_pool", "resize") => {
+                if let Some(Object::ShmPool { size, .. }) = self.objects.get_mut(&object_id) {
+                    *size = args[0].int();
+                }
+            }
+            ("wl_shm_pool", "create_buffer") => {
+                if let Some(Object::ShmPool { fd, size }) = self.objects.get(&object_id) {
+                    let (fd, size) = (*fd, *size);
+                    let (offset, width, height, stride) =
+                        (args[1].int(), args[2].int(), args[3].int(), args[4].int());
+                    let end = i64::from(offset) + i64::from(stride) * i64::from(height);
+                    if offset < 0
+                        || width <= 0
+                        || height <= 0
+                        || i64::from(stride) < i64::from(width) * 4
+                        || end > size.into()
+                    {
+                        log::warn!(
+                            "headless: buffer {}x{} stride {} at {} does not fit in a pool of {} bytes",
+                            width,
+                            height,
+                            stride,
+                            offset,
+                            size
+                        );
+                    } else {
+                        self.objects.insert(
+                            args[0].id(),
+                            Object::Buffer {
+                                fd,
+                                offset,
+                                width,
+                                height,
+                                stride,
+                                format: args[5].id(),
+                            },
+                        );
+                    }
+                }
+            }
+            ("wl_surface", "destroy") => {
+                self.delete(object_id);
+                self.unstack(object_id);
+                // Its wl_subsurface is inert, even if the ID gets reused.
+                for object in self.objects.values_mut() {
+                    if let Object::Subsurface { surface_id } = object {
+                        if *surface_id == object_id {
+                            *surface_id = 0;
+                        }
+                    }
+                }
+            }
+            ("wl_surface", "commit") => {
+                self.commit(object_id, serial);
+                return Some(Outcome::Committed);
+            }
+            ("wl_subcompositor", "get_subsurface") => {
+                self.objects.insert(
+                    args[0].id(),
+                    Object::Subsurface {
+                        surface_id: args[1].id(),
+                    },
+                );
+            }
+            ("wl_subsurface", "destroy") => {
+                if let Some(Object::Subsurface { surface_id }) = self.objects.get(&object_id) {
+                    let surface_id = *surface_id;
+                    self.unstack(surface_id);
+                }
+                self.delete(object_id);
+            }
+            ("wl_seat", "get_keyboard") => return Some(Outcome::KeyboardCreated(args[0].id())),
+            ("xdg_wm_base", "get_xdg_surface") => {
+                self.objects.insert(
+                    args[0].id(),
+                    Object::XdgSurface {
+                        surface_id: args[1].id(),
+                        configured: false,
+                    },
+                );
+            }
+            ("xdg_wm_base", "create_positioner") => {
+                self.objects
+                    .insert(args[0].id(), Object::Positioner(XdgPositioner::default()));
+            }
+            ("xdg_positioner", _) => {
+                // reflex has checked the values already.
+                if let Some(Object::Positioner(positioner)) = self.objects.get_mut(&object_id) {
+                    match message.name {
+                        "set_size" => positioner.size = Some((args[0].int(), args[1].int())),
+                        "set_anchor_rect" => {
+                            positioner.anchor_rect = Some(Rect::new(
+                                args[0].int(),
+                                args[1].int(),
+                                args[2].int(),
+                                args[3].int(),
+                            ))
+                        }
+                        "set_anchor" => positioner.anchor = args[0].id(),
+                        "set_gravity" => positioner.gravity = args[0].id(),
+                        "set_offset" => positioner.offset = (args[0].int(), args[1].int()),
+                        "destroy" => self.delete(object_id),
+                        _ => {}
+                    }
+                }
+            }
+            ("xdg_surface", "get_popup") => {
+                let geometry = match self.objects.get(&args[2].id()) {
+                    Some(Object::Positioner(x)) => x.place(),
+                    _ => None,
+                };
+                if let Some(geometry) = geometry {
+                    self.objects.insert(
+                        args[0].id(),
+                        Object::XdgPopup {
+                            xdg_surface_id: object_id,
+                            parent_id: args[1].id(),
+                            geometry,
+                        },
+                    );
+                }
+            }
+            ("xdg_surface", "get_toplevel") => {
+                self.objects.insert(
+                    args[0].id(),
+                    Object::XdgToplevel {
+                        xdg_surface_id: object_id,
+                        stacking: 0,
+                        x: 0,
+                        y: 0,
+                        title: None,
+                        app_id: None,
+                        minimized: false,
+                        geometry: None,
+                    },
+                );
+                return Some(Outcome::ToplevelCreated(args[0].id()));
+            }
+            (_, "destroy") | (_, "release") => self.delete(object_id),
+            _ => {}
+        }
+        Some(Outcome::None)
+    }
+
+    fn bind(&mut self, config: &Config, name: u32, id: u32) {
+        let interface = match GLOBALS.iter().find(|(x, _, _)| *x == name) {
+            Some((_, interface, _)) => *interface,
+            None => return,
+        };
+        self.objects.insert(id, Object::Plain(interface));
+        if interface.name == wl_shm::INTERFACE.name {
+            for format in config.shm.format_codes() {
+                self.send(wl_shm::events::Format {
+                    sender_object_id: id,
+                    format,
+                });
+            }
+        } else if interface.name == wl_seat::INTERFACE.name {
+            self.bind_seat(config, id);
+        }
+    }
+
+    fn apply(&mut self, surface_id: u32, committed: &wl_surface::SurfaceState, serial: u32) {
+        let contents = match committed.buffer {
+            Some(x) if committed.attached => self.read_buffer(x),
+            _ => None,
+        };
+        if let Some(Object::Surface(surface)) = self.objects.get_mut(&surface_id) {
+            // The client's damage only describes new contents drawn the same
+            // way; a new scale or transform redraws everything.
+            let redrawn =
+                committed.scale != surface.scale || committed.transform != surface.transform;
+            if committed.attached || redrawn {
+                surface.damage = if redrawn {
+                    None
+                } else {
+                    Some((surface.generation, committed.damage.clone()))
+                };
+                surface.generation = u64::from(serial);
+            }
+            if committed.attached {
+                surface.contents = contents;
+            }
+            surface.scale = committed.scale;
+            surface.transform = committed.transform;
+            surface.input_region = committed.input_region.clone();
+            surface.subsurfaces = committed.subsurfaces.clone();
+        }
+    }
+
+    // Unmaps a sub-surface right away, when it or its wl_subsurface is
+    // destroyed.
+    fn unstack(&mut self, surface_id: u32) {
+        for object in self.objects.values_mut() {
+            if let Object::Surface(surface) = object {
+                surface.subsurfaces.retain(|x| x.surface_id != surface_id);
+            }
+        }
+    }
+
+    fn commit(&mut self, surface_id: u32, serial: u32) {
+        // The first commit of an xdg_surface asks for its initial configure.
+        let xdg_surface_id = self.objects.iter().find_map(|(id, object)| match object {
+            Object::XdgSurface {
+                surface_id: x,
+                configured: false,
+            } if *x == surface_id => Some(*id),
+            _ => None,
+        });
+        if let Some(xdg_surface_id) = xdg_surface_id {
+            let toplevel_id = self.objects.iter().find_map(|(id, object)| match object {
+                Object::XdgToplevel {
+                    xdg_surface_id: x, ..
+                } if *x == xdg_surface_id => Some(*id),
+                _ => None,
+            });
+            if let Some(toplevel_id) = toplevel_id {
+                self.configure(toplevel_id, 0, 0, &[], serial);
+            }
+            let popup = self.objects.iter().find_map(|(id, object)| match object {
+                Object::XdgPopup {
+                    xdg_surface_id: x,
+                    geometry,
+                    ..
+                } if *x == xdg_surface_id => Some((*id, *geometry)),
+                _ => None,
+            });
+            if let Some((popup_id, geometry)) = popup {
+                self.send(xdg_popup::events::Configure {
+                    sender_object_id: popup_id,
+                    x: geometry.x,
+                    y: geometry.y,
+                    width: geometry.width,
+                    height: geometry.height,
+                });
+                self.configure_surface(xdg_surface_id, serial);
+            }
+        }
+    }
+
+    fn read_buffer(&self, buffer_id: u32) -> Option<Buffer> {
+        let (fd, offset, width, height, stride, format) = match self.objects.get(&buffer_id) {
+            Some(Object::Buffer {
+                fd,
+                offset,
+                width,
+                height,
+                stride,
+                format,
+            }) => (*fd, *offset, *width, *height, *stride, *format),
+            _ => return None,
+        };
+        // pread rather than mmap: a client that truncates the file under us
+        // only gets a short read instead of a SIGBUS.
+        let mut pixels = vec![0; (stride * height) as usize];
+        let mut done = 0;
+        while done < pixels.len() {
+            match nix::sys::uio::pread(fd, &mut pixels[done..], (offset as usize + done) as i64) {
+                Ok(0) => break,
+                Ok(n) => done += n,
+                Err(err) => {
+                    log::warn!("headless: cannot read buffer {}: {}", buffer_id, err);
+                    return None;
+                }
+            }
+        }
+        Some(Buffer {
+            width,
+            height,
+            stride,
+            format,
+            pixels: Arc::new(pixels),
+        })
+    }
+
+    fn configure(
+        &mut self,
+        toplevel_id: u32,
+        width: i32,
+        height: i32,
+        states: &[u32],
+        serial: u32,
+    ) -> bool {
+        let xdg_surface_id = match self.objects.get_mut(&toplevel_id) {
+            Some(Object::XdgToplevel {
+                xdg_surface_id,
+                minimized,
+                ..
+            }) => {
+                *minimized = false;
+                *xdg_surface_id
+            }
+            _ => return false,
+        };
+        let mut array = vec![0; states.len() * 4];
+        NativeEndian::write_u32_into(states, &mut array);
+        self.send(xdg_toplevel::events::Configure {
+            sender_object_id: toplevel_id,
+            width,
+            height,
+            states: array,
+        });
+        self.configure_surface(xdg_surface_id, serial);
+        true
+    }
+
+    // xdg_surface.configure, which ends the configure of its role object.
+    fn configure_surface(&mut self, xdg_surface_id: u32, serial: u32) {
+        if let Some(Object::XdgSurface { configured, .. }) = self.objects.get_mut(&xdg_surface_id) {
+            *configured = true;
+        }
+        self.events.configure_sent(xdg_surface_id, serial);
+        self.send(xdg_surface::events::Configure {
+            sender_object_id: xdg_surface_id,
+            serial,
+        });
+    }
+
+    // Whether the surface is that of a minimized toplevel.
+    fn minimized(&self, surface_id: u32) -> bool {
+        self.objects.values().any(|object| match object {
+            Object::XdgToplevel {
+                xdg_surface_id,
+                minimized: true,
+                ..
+            } => match self.objects.get(xdg_surface_id) {
+                Some(Object::XdgSurface { surface_id: x, .. }) => *x == surface_id,
+                _ => false,
+            },
+            _ => false,
+        })
+    }
+
+    // The toplevel whose xdg_surface is that of a surface.
+    fn toplevel_of(&self, surface_id: u32) -> Option<u32> {
+        self.objects.iter().find_map(|(id, object)| match object {
+            Object::XdgToplevel { xdg_surface_id, .. } => match self.objects.get(xdg_surface_id) {
+                Some(Object::XdgSurface { surface_id: x, .. }) if *x == surface_id => Some(*id),
+                _ => None,
+            },
+            _ => None,
+        })
+    }
+
+    // The client's toplevels, each with its place in the stack.
+    fn scene(&self, client_id: u32) -> Vec<(u64, SceneSurface)> {
+        self.objects
+            .values()
+            .filter_map(|object| match object {
+                Object::XdgToplevel {
+                    xdg_surface_id,
+                    stacking,
+                    x,
+                    y,
+                    minimized: false,
+                    geometry,
+                    ..
+                } => {
+                    let (dx, dy) = geometry.map_or((0, 0), |g| (g.x, g.y));
+                    Some((*stacking, *xdg_surface_id, *x - dx, *y - dy))
+                }
+                _ => None,
+            })
+            .filter_map(|(stacking, xdg_surface_id, x, y)| {
+                let surface_id = match self.objects.get(&xdg_surface_id) {
+                    Some(Object::XdgSurface {
+                        surface_id,
+                        configured: true,
+                    }) => *surface_id,
+                    _ => return None,
+                };
+                self.scene_surface(client_id, surface_id, x, y)
+                    .map(|surface| (stacking, surface))
+            })
+            .collect()
+    }
+
+    // The surfaces of the configured popups of a surface, with their
+    // positions relative to it, oldest first.
+    fn popups(&self, surface_id: u32) -> Vec<(u32, i32, i32)> {
+        let parent = match self.objects.iter().find_map(|(id, object)| match object {
+            Object::XdgSurface { surface_id: x, .. } if *x == surface_id => Some(*id),
+            _ => None,
+        }) {
+            Some(x) => x,
+            None => return Vec::new(),
+        };
+        // Popups are placed relative to the window geometry of a toplevel
+        // parent. That of popups is not tracked.
+        let (dx, dy) = self
+            .objects
+            .values()
+            .find_map(|object| match object {
+                Object::XdgToplevel {
+                    xdg_surface_id: x,
+                    geometry: Some(g),
+                    ..
+                } if *x == parent => Some((g.x, g.y)),
+                _ => None,
+            })
+            .unwrap_or((0, 0));
+        let mut popups: Vec<(u32, u32, i32, i32)> = self
+            .objects
+            .iter()
+            .filter_map(|(id, object)| match object {
+                Object::XdgPopup {
+                    xdg_surface_id,
+                    parent_id,
+                    geometry,
+                } if *parent_id == parent => match self.objects.get(xdg_surface_id) {
+                    Some(Object::XdgSurface {
+                        surface_id,
+                        configured: true,
+                    }) => Some((*id, *surface_id, dx + geometry.x, dy + geometry.y)),
+                    _ => None,
+                },
+                _ => None,
+            })
+            .collect();
+        popups.sort();
+        popups
+            .into_iter()
+            .map(|(_, surface_id, x, y)| (surface_id, x, y))
+            .collect()
+    }
+
+    // A surface with its sub-surfaces, or None if it has no contents.
+    fn scene_surface(
+        &self,
+        client_id: u32,
+        surface_id: u32,
+        x: i32,
+        y: i32,
+    ) -> Option<SceneSurface> {
+        let surface = match self.objects.get(&surface_id) {
+            Some(Object::Surface(x)) => x,
+            _ => return None,
+        };
+        let buffer = Some(surface.contents.clone()?);
+        let mut below = Vec::new();
+        let mut above = Vec::new();
+        let mut stack = &mut below;
+        for placement in &surface.subsurfaces {
+            if placement.surface_id == 0 {
+                stack = &mut above;
+                continue;
+            }
+            if let Some(x) =
+                self.scene_surface(client_id, placement.surface_id, placement.x, placement.y)
+            {
+                stack.push(x);
+            }
+        }
+        for (popup_surface_id, x, y) in self.popups(surface_id) {
+            if let Some(x) = self.scene_surface(client_id, popup_surface_id, x, y) {
+                above.push(x);
+            }
+        }
+        Some(SceneSurface {
+            client_id,
+            surface_id,
+            x,
+            y,
+            buffer,
+            scale: surface.scale,
+            transform: surface.transform,
+            input_region: surface.input_region.clone(),
+            generation: surface.generation,
+            damage: surface.damage.clone(),
+            below,
+            above,
+        })
+    }
+
+    fn toplevel_names(&self, surface_id: u32) -> (Option<String>, Option<String>) {
+        for object in self.objects.values() {
+            if let Object::XdgToplevel {
+                xdg_surface_id,
+                title,
+                app_id,
+                ..
+            } = object
+            {
+                if let Some(Object::XdgSurface { surface_id: x, .. }) =
+                    self.objects.get(xdg_surface_id)
+                {
+                    if *x == surface_id {
+                        return (title.clone(), app_id.clone());
+                    }
+                }
+            }
+        }
+        (None, None)
+    }
+}
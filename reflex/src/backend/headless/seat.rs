@@ -0,0 +1,666 @@
+use super::{ClientState, Object, State};
+use crate::backend::keymap::KeymapFile;
+use crate::compositor::hit_test::{self, hit_test};
+use crate::config::Config;
+use crate::protocol::event::Event;
+use crate::protocol::wayland::{wl_keyboard, wl_pointer, wl_seat};
+use byteorder::{ByteOrder, NativeEndian};
+use bytes::BytesMut;
+use std::os::unix::io::RawFd;
+
+// The seat of the headless backend: one pointer and one keyboard, driven by
+// whatever embeds the backend, like the VNC server or a test.
+//
+// The pointer focus is the topmost toplevel under the pointer. The keyboard
+// focus follows clicks, and goes to the topmost toplevel when a key is
+// pressed without one.
+#[derive(Default)]
+pub(super) struct Seat {
+    keymap: Option<KeymapFile>,
+    x: f64,
+    y: f64,
+    pointer_focus: Option<Focus>,
+    keyboard_focus: Option<Focus>,
+    buttons: Vec<u32>,
+    keys: Vec<u32>,
+    mods_depressed: u32,
+    mods_locked: u32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Focus {
+    client_id: u32,
+    surface_id: u32,
+}
+
+pub const CAPABILITY_POINTER: u32 = 1;
+pub const CAPABILITY_KEYBOARD: u32 = 2;
+
+// The highest wl_seat version the headless seat implements.
+pub const VERSION: u32 = 5;
+
+const KEYMAP_FORMAT_XKB_V1: u32 = 1;
+
+// wl_keyboard.keymap. The generated event cannot send its fd.
+struct Keymap {
+    sender_object_id: u32,
+    fd: RawFd,
+    size: u32,
+}
+
+impl Event for Keymap {
+    fn encode(&self, dst: &mut BytesMut) -> Result<(), std::io::Error> {
+        let offset = dst.len();
+        dst.resize(offset + 16, 0);
+        NativeEndian::write_u32(&mut dst[offset..], self.sender_object_id);
+        NativeEndian::write_u32(&mut dst[offset + 4..], 16 << 16);
+        NativeEndian::write_u32(&mut dst[offset + 8..], KEYMAP_FORMAT_XKB_V1);
+        NativeEndian::write_u32(&mut dst[offset + 12..], self.size);
+        Ok(())
+    }
+
+    fn fds(&self) -> Vec<RawFd> {
+        vec![self.fd]
+    }
+}
+
+fn fixed(x: f64) -> u32 {
+    (x * 256.0).round() as i32 as u32
+}
+
+impl ClientState {
+    fn objects_of(&self, interface: &str) -> Vec<u32> {
+        let mut ids: Vec<u32> = self
+            .objects
+            .iter()
+            .filter(|(_, object)| object.interface().name == interface)
+            .map(|(id, _)| *id)
+            .collect();
+        ids.sort();
+        ids
+    }
+
+    fn version(&self, id: u32) -> u32 {
+        self.versions.get(&id).cloned().unwrap_or(1)
+    }
+
+    // Sends events to every wl_pointer of the client, each followed by
+    // wl_pointer.frame where the version has it.
+    fn pointer_events<F: Fn(u32) -> Vec<Box<dyn Event + Send>>>(&mut self, events: F) {
+        for pointer in self.objects_of(wl_pointer::INTERFACE.name) {
+            for event in events(pointer) {
+                self.events.send(event);
+            }
+            if self.version(pointer) >= 5 {
+                self.send(wl_pointer::events::Frame {
+                    sender_object_id: pointer,
+                });
+            }
+        }
+    }
+
+    fn keyboard_events<F: Fn(u32) -> Vec<Box<dyn Event + Send>>>(&mut self, events: F) {
+        for keyboard in self.objects_of(wl_keyboard::INTERFACE.name) {
+            for event in events(keyboard) {
+                self.events.send(event);
+            }
+        }
+    }
+
+    pub(super) fn bind_seat(&mut self, config: &Config, id: u32) {
+        let mut capabilities = 0;
+        if config.seat.pointer {
+            capabilities |= CAPABILITY_POINTER;
+        }
+        if config.seat.keyboard {
+            capabilities |= CAPABILITY_KEYBOARD;
+        }
+        self.send(wl_seat::events::Capabilities {
+            sender_object_id: id,
+            capabilities,
+        });
+        if self.version(id) >= 2 {
+            self.send(wl_seat::events::Name {
+                sender_object_id: id,
+                name: "seat0".to_string(),
+            });
+        }
+    }
+}
+
+impl State {
+    fn keymap(&mut self, config: &Config) -> Option<&KeymapFile> {
+        if self.seat.keymap.is_none() {
+            match KeymapFile::new(&config.keyboard.layout) {
+                Ok(x) => self.seat.keymap = Some(x),
+                Err(err) => log::error!("cannot create the keymap: {}", err),
+            }
+        }
+        self.seat.keymap.as_ref()
+    }
+
+    // A surface that still exists, or None.
+    fn alive(&self, focus: Option<Focus>) -> Option<Focus> {
+        let focus = focus?;
+        match self
+            .clients
+            .get(&focus.client_id)?
+            .objects
+            .get(&focus.surface_id)
+        {
+            Some(Object::Surface(_)) => Some(focus),
+            _ => None,
+        }
+    }
+
+    // The topmost toplevel at x, y in global coordinates, and the point in
+    // its surface-local coordinates.
+    fn surface_at(&self, x: f64, y: f64) -> Option<(Focus, f64, f64)> {
+        hit_test(&self.scene(), x, y).map(|hit| {
+            let focus = Focus {
+                client_id: hit.client_id,
+                surface_id: hit.surface_id,
+            };
+            (focus, hit.x, hit.y)
+        })
+    }
+
+    pub(super) fn keyboard_created(&mut self, config: &Config, client_id: u32, id: u32) {
+        let (fd, size) = match self.keymap(config) {
+            Some(x) => (x.fd(), x.size()),
+            None => return,
+        };
+        let focus = self.alive(self.seat.keyboard_focus);
+        let serial = self.serial();
+        let keys = self.pressed_keys();
+        let (mods_depressed, mods_locked) = (self.seat.mods_depressed, self.seat.mods_locked);
+        let client = match self.clients.get_mut(&client_id) {
+            Some(x) => x,
+            None => return,
+        };
+        client.send(Keymap {
+            sender_object_id: id,
+            fd,
+            size,
+        });
+        if client.version(id) >= 4 {
+            client.send(wl_keyboard::events::RepeatInfo {
+                sender_object_id: id,
+                rate: config.keyboard.repeat_rate,
+                delay: config.keyboard.repeat_delay,
+            });
+        }
+        if let Some(focus) = focus.filter(|x| x.client_id == client_id) {
+            client.send(wl_keyboard::events::Enter {
+                sender_object_id: id,
+                serial,
+                surface: focus.surface_id,
+                keys,
+            });
+            client.send(wl_keyboard::events::Modifiers {
+                sender_object_id: id,
+                serial,
+                mods_depressed,
+                mods_latched: 0,
+                mods_locked,
+                group: 0,
+            });
+        }
+    }
+
+    fn pressed_keys(&self) -> Vec<u8> {
+        let mut keys = Vec::new();
+        for key in &self.seat.keys {
+            keys.extend_from_slice(&key.to_ne_bytes());
+        }
+        keys
+    }
+
+    // Where the pointer is in global coordinates, and the buttons it holds.
+    pub(super) fn pointer(&self) -> (f64, f64, Vec<u32>) {
+        (self.seat.x, self.seat.y, self.seat.buttons.clone())
+    }
+
+    // Moves the pointer to x, y in global coordinates.
+    pub(super) fn pointer_motion(&mut self, x: f64, y: f64) {
+        self.seat.x = x;
+        self.seat.y = y;
+        let time = self.now() as u32;
+        let hit = self.surface_at(x, y);
+        // While a button is held the pointer stays with the surface that
+        // got the press, like an implicit grab.
+        let old = self.alive(self.seat.pointer_focus);
+        let new = if self.seat.buttons.is_empty() {
+            hit.map(|(focus, _, _)| focus)
+        } else {
+            old
+        };
+        if new != old {
+            self.set_pointer_focus(old, new);
+        }
+        let focus = match new {
+            Some(x) => x,
+            None => return,
+        };
+        if let Some((sx, sy)) = self.surface_local(focus, x, y) {
+            if let Some(client) = self.clients.get_mut(&focus.client_id) {
+                client.pointer_events(|pointer| {
+                    vec![Box::new(wl_pointer::events::Motion {
+                        sender_object_id: pointer,
+                        time,
+                        surface_x: fixed(sx),
+                        surface_y: fixed(sy),
+                    })]
+                });
+            }
+        }
+    }
+
+    fn surface_local(&self, focus: Focus, x: f64, y: f64) -> Option<(f64, f64)> {
+        hit_test::surface_local(&self.scene(), focus.client_id, focus.surface_id, x, y)
+    }
+
+    fn set_pointer_focus(&mut self, old: Option<Focus>, new: Option<Focus>) {
+        if let Some(old) = old {
+            let serial = self.serial();
+            if let Some(client) = self.clients.get_mut(&old.client_id) {
+                client.pointer_events(|pointer| {
+                    vec![Box::new(wl_pointer::events::Leave {
+                        sender_object_id: pointer,
+                        serial,
+                        surface: old.surface_id,
+                    })]
+                });
+            }
+        }
+        self.seat.pointer_focus = new;
+        if let Some(new) = new {
+            let (x, y) = (self.seat.x, self.seat.y);
+            let (sx, sy) = self.surface_local(new, x, y).unwrap_or((0.0, 0.0));
+            let serial = self.serial();
+            if let Some(client) = self.clients.get_mut(&new.client_id) {
+                client.pointer_events(|pointer| {
+                    vec![Box::new(wl_pointer::events::Enter {
+                        sender_object_id: pointer,
+                        serial,
+                        surface: new.surface_id,
+                        surface_x: fixed(sx),
+                        surface_y: fixed(sy),
+                    })]
+                });
+            }
+        }
+    }
+
+    // button is a Linux input event code, like BTN_LEFT.
+    pub(super) fn pointer_button(&mut self, button: u32, pressed: bool) {
+        if pressed == self.seat.buttons.contains(&button) {
+            return;
+        }
+        if pressed {
+            self.seat.buttons.push(button);
+        } else {
+            self.seat.buttons.retain(|x| *x != button);
+        }
+        let focus = match self.alive(self.seat.pointer_focus) {
+            Some(x) => x,
+            None => return,
+        };
+        if pressed && self.alive(self.seat.keyboard_focus) != Some(focus) {
+            self.set_keyboard_focus(Some(focus));
+        }
+        let serial = self.serial();
+        let time = self.now() as u32;
+        if let Some(client) = self.clients.get_mut(&focus.client_id) {
+            client.pointer_events(|pointer| {
+                vec![Box::new(wl_pointer::events::Button {
+                    sender_object_id: pointer,
+                    serial,
+                    time,
+                    button,
+                    state: pressed as u32,
+                })]
+            });
+        }
+        if !pressed && self.seat.buttons.is_empty() {
+            // The implicit grab ends; the pointer may be over something else.
+            let (x, y) = (self.seat.x, self.seat.y);
+            self.pointer_motion(x, y);
+        }
+    }
+
+    // axis is 0 for vertical and 1 for horizontal scrolling, value is in
+    // surface-local coordinates.
+    pub(super) fn pointer_axis(&mut self, axis: u32, value: f64) {
+        let focus = match self.alive(self.seat.pointer_focus) {
+            Some(x) => x,
+            None => return,
+        };
+        let time = self.now() as u32;
+        if let Some(client) = self.clients.get_mut(&focus.client_id) {
+            client.pointer_events(|pointer| {
+                vec![Box::new(wl_pointer::events::Axis {
+                    sender_object_id: pointer,
+                    time,
+                    axis,
+                    value: fixed(value),
+                })]
+            });
+        }
+    }
+
+    pub(super) fn focus(&mut self, client_id: u32, surface_id: u32) -> Result<(), String> {
+        let focus = Focus {
+            client_id,
+            surface_id,
+        };
+        if self.alive(Some(focus)).is_none() {
+            return Err(format!(
+                "client#{} has no surface {}",
+                client_id, surface_id
+            ));
+        }
+        if self.alive(self.seat.keyboard_focus) != Some(focus) {
+            self.set_keyboard_focus(Some(focus));
+        }
+        Ok(())
+    }
+
+    // Raises the toplevel that shows the surface, which may be one of its
+    // sub-surfaces or popups.
+    fn raise_focus(&mut self, focus: Focus) {
+        let root = self.scene().into_iter().find(|root| {
+            let tree = std::slice::from_ref(root);
+            hit_test::surface_local(tree, focus.client_id, focus.surface_id, 0.0, 0.0).is_some()
+        });
+        let root = match root {
+            Some(x) => x,
+            None => return,
+        };
+        let toplevel_id = self
+            .clients
+            .get(&root.client_id)
+            .and_then(|client| client.toplevel_of(root.surface_id));
+        if let Some(toplevel_id) = toplevel_id {
+            self.raise(root.client_id, toplevel_id);
+        }
+    }
+
+    fn set_keyboard_focus(&mut self, new: Option<Focus>) {
+        if let Some(old) = self.alive(self.seat.keyboard_focus) {
+            let serial = self.serial();
+            if let Some(client) = self.clients.get_mut(&old.client_id) {
+                client.keyboard_events(|keyboard| {
+                    vec![Box::new(wl_keyboard::events::Leave {
+                        sender_object_id: keyboard,
+                        serial,
+                        surface: old.surface_id,
+                    })]
+                });
+            }
+        }
+        self.seat.keyboard_focus = new;
+        if let Some(new) = new {
+            self.raise_focus(new);
+            let serial = self.serial();
+            let keys = self.pressed_keys();
+            let (mods_depressed, mods_locked) = (self.seat.mods_depressed, self.seat.mods_locked);
+            if let Some(client) = self.clients.get_mut(&new.client_id) {
+                client.keyboard_events(|keyboard| {
+                    vec![
+                        Box::new(wl_keyboard::events::Enter {
+                            sender_object_id: keyboard,
+                            serial,
+                            surface: new.surface_id,
+                            keys: keys.clone(),
+                        }),
+                        Box::new(wl_keyboard::events::Modifiers {
+                            sender_object_id: keyboard,
+                            serial,
+                            mods_depressed,
+                            mods_latched: 0,
+                            mods_locked,
+                            group: 0,
+                        }),
+                    ]
+                });
+            }
+        }
+    }
+
+    // keycode is a Linux input event code, like wl_keyboard.key has.
+    pub(super) fn key(&mut self, config: &Config, keycode: u32, pressed: bool) {
+        if pressed == self.seat.keys.contains(&keycode) {
+            return;
+        }
+        if pressed {
+            self.seat.keys.push(keycode);
+        } else {
+            self.seat.keys.retain(|x| *x != keycode);
+        }
+
+        let modifier = self.keymap(config).and_then(|x| x.modifier(keycode));
+        let mut modifiers_changed = false;
+        if let Some((mask, toggles)) = modifier {
+            if toggles {
+                if pressed {
+                    self.seat.mods_locked ^= mask;
+                    modifiers_changed = true;
+                }
+            } else {
+                if pressed {
+                    self.seat.mods_depressed |= mask;
+                } else {
+                    self.seat.mods_depressed &= !mask;
+                }
+                modifiers_changed = true;
+            }
+        }
+
+        if self.alive(self.seat.keyboard_focus).is_none() {
+            let top = self.scene().last().map(|s| Focus {
+                client_id: s.client_id,
+                surface_id: s.surface_id,
+            });
+            if top.is_none() {
+                return;
+            }
+            self.set_keyboard_focus(top);
+        }
+        let focus = match self.seat.keyboard_focus {
+            Some(x) => x,
+            None => return,
+        };
+        let serial = self.serial();
+        let time = self.now() as u32;
+        let (mods_depressed, mods_locked) = (self.seat.mods_depressed, self.seat.mods_locked);
+        if let Some(client) = self.clients.get_mut(&focus.client_id) {
+            client.keyboard_events(|keyboard| {
+                let mut events: Vec<Box<dyn Event + Send>> =
+                    vec![Box::new(wl_keyboard::events::Key {
+                        sender_object_id: keyboard,
+                        serial,
+                        time,
+                        key: keycode,
+                        state: pressed as u32,
+                    })];
+                if modifiers_changed {
+                    events.push(Box::new(wl_keyboard::events::Modifiers {
+                        sender_object_id: keyboard,
+                        serial,
+                        mods_depressed,
+                        mods_latched: 0,
+                        mods_locked,
+                        group: 0,
+                    }));
+                }
+                events
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{HeadlessBackend, Surface};
+    use super::*;
+    use crate::admin::Clients;
+    use crate::backend::keymap::BTN_LEFT;
+    use crate::backend::ClientEvents;
+    use crate::compositor::{Buffer, FORMAT_XRGB8888};
+    use crate::lock::MutexExt;
+    use crate::protocol::event_sink::FatalError;
+    use crate::protocol::wayland::wl_surface::Placement;
+    use crate::protocol::xdg_shell::xdg_surface::ConfigureSerials;
+    use futures::stream::Stream;
+    use std::collections::HashMap;
+    use std::sync::{Arc, RwLock};
+    use tokio::sync::mpsc::Receiver;
+
+    fn surface(width: i32, height: i32, subsurfaces: Vec<Placement>) -> Object {
+        Object::Surface(Surface {
+            contents: Some(Buffer {
+                width,
+                height,
+                stride: width * 4,
+                format: FORMAT_XRGB8888,
+                pixels: Arc::new(vec![0; (width * height * 4) as usize]),
+            }),
+            scale: 1,
+            subsurfaces,
+            ..Surface::default()
+        })
+    }
+
+    // A client with a wl_pointer and a 4x4 toplevel, surface 10, at x, 0,
+    // on top of the others. Its sub-surfaces are 2x2 and above it.
+    fn client(
+        state: &mut State,
+        client_id: u32,
+        x: i32,
+        subsurfaces: &[Placement],
+    ) -> Receiver<Box<dyn Event + Send>> {
+        let (tx, rx) = tokio::sync::mpsc::channel(64);
+        let mut objects = HashMap::new();
+        objects.insert(5, Object::Plain(&wl_pointer::INTERFACE));
+        let mut placements = vec![Placement {
+            surface_id: 0,
+            x: 0,
+            y: 0,
+        }];
+        for placement in subsurfaces {
+            objects.insert(placement.surface_id, surface(2, 2, Vec::new()));
+            placements.push(*placement);
+        }
+        objects.insert(10, surface(4, 4, placements));
+        objects.insert(
+            11,
+            Object::XdgSurface {
+                surface_id: 10,
+                configured: true,
+            },
+        );
+        state.next_stacking += 1;
+        objects.insert(
+            12,
+            Object::XdgToplevel {
+                xdg_surface_id: 11,
+                stacking: state.next_stacking,
+                x,
+                y: 0,
+                title: None,
+                app_id: None,
+                minimized: false,
+                geometry: None,
+            },
+        );
+        let events = ClientEvents::new(
+            client_id,
+            tx,
+            Clients::default(),
+            ConfigureSerials::default(),
+            FatalError::default(),
+        );
+        state.clients.insert(
+            client_id,
+            ClientState {
+                events,
+                objects,
+                versions: HashMap::new(),
+            },
+        );
+        rx
+    }
+
+    // The wl_pointer events a client got, without serials and times.
+    fn pointer_events(rx: Receiver<Box<dyn Event + Send>>) -> Vec<String> {
+        let unfixed = |x: u32| f64::from(x as i32) / 256.0;
+        rx.wait()
+            .map(|event| {
+                let mut buf = BytesMut::new();
+                event.unwrap().encode(&mut buf).unwrap();
+                let opcode = NativeEndian::read_u32(&buf[4..8]) & 0xffff;
+                let mut args = vec![0; (buf.len() - 8) / 4];
+                NativeEndian::read_u32_into(&buf[8..], &mut args);
+                match opcode {
+                    0 => format!(
+                        "enter {} {} {}",
+                        args[1],
+                        unfixed(args[2]),
+                        unfixed(args[3])
+                    ),
+                    1 => format!("leave {}", args[1]),
+                    2 => format!("motion {} {}", unfixed(args[1]), unfixed(args[2])),
+                    3 => format!("button {} {}", args[2], args[3]),
+                    x => format!("opcode {}", x),
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn pointer_focus_follows_the_scene() {
+        let backend = HeadlessBackend::new(Arc::new(RwLock::new(Config::default())));
+        let mut state = backend.state.locked();
+        // The sub-surface of the first client sticks out below the second.
+        let subsurface = Placement {
+            surface_id: 20,
+            x: 3,
+            y: 3,
+        };
+        let first = client(&mut state, 1, 0, &[subsurface]);
+        let second = client(&mut state, 2, 4, &[]);
+
+        state.pointer_motion(1.5, 1.5);
+        state.pointer_motion(4.5, 3.5);
+        state.pointer_motion(3.5, 4.5);
+        // The press raises the first client, and the pointer stays with the
+        // sub-surface until the button is released.
+        state.pointer_button(BTN_LEFT, true);
+        state.pointer_motion(10.5, 10.5);
+        state.pointer_button(BTN_LEFT, false);
+        state.pointer_motion(4.5, 3.5);
+        state.clients.clear();
+
+        assert_eq!(
+            pointer_events(first),
+            vec![
+                "enter 10 1.5 1.5",
+                "motion 1.5 1.5",
+                "leave 10",
+                "enter 20 0.5 1.5",
+                "motion 0.5 1.5",
+                "button 272 1",
+                "motion 7.5 7.5",
+                "button 272 0",
+                "leave 20",
+                "enter 20 1.5 0.5",
+                "motion 1.5 0.5",
+            ]
+        );
+        assert_eq!(
+            pointer_events(second),
+            vec!["enter 10 0.5 3.5", "motion 0.5 3.5", "leave 10"]
+        );
+    }
+}
@@ -0,0 +1,266 @@
+use std::fs::File;
+use std::io::{self, Write};
+use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+
+// The keymap handed to clients in wl_keyboard.keymap, and the translation
+// of X11 keysyms, which is what VNC reports, and of KeyboardEvent.code,
+// which is what browsers report, to the evdev key codes of wl_keyboard.key.
+//
+// The keymap only names the XKB components; the client's libxkbcommon
+// resolves them from its own XKB data, so any layout it knows works. The
+// keysym table goes by key position on a US keyboard, which is also the
+// position on most other layouts.
+pub struct KeymapFile {
+    file: File,
+    size: u32,
+    layout: String,
+}
+
+impl KeymapFile {
+    pub fn new(layout: &str) -> io::Result<KeymapFile> {
+        let text = format!(
+            "xkb_keymap {{\n\
+             \txkb_keycodes {{ include \"evdev+aliases(qwerty)\" }};\n\
+             \txkb_types {{ include \"complete\" }};\n\
+             \txkb_compat {{ include \"complete\" }};\n\
+             \txkb_symbols {{ include \"pc+{}+inet(evdev)\" }};\n\
+             }};\n\0",
+            layout
+        );
+        let fd = unsafe { libc::memfd_create(b"reflex-keymap\0".as_ptr() as _, libc::MFD_CLOEXEC) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let mut file = unsafe { File::from_raw_fd(fd) };
+        file.write_all(text.as_bytes())?;
+        Ok(KeymapFile {
+            file,
+            size: text.len() as u32,
+            layout: layout.to_string(),
+        })
+    }
+
+    pub fn fd(&self) -> RawFd {
+        self.file.as_raw_fd()
+    }
+
+    // In bytes, including the terminating NUL.
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+
+    // The wl_keyboard.modifiers bit of a modifier key, and whether the key
+    // toggles the modifier instead of holding it.
+    pub fn modifier(&self, keycode: u32) -> Option<(u32, bool)> {
+        match keycode {
+            KEY_LEFTSHIFT | KEY_RIGHTSHIFT => Some((MOD_SHIFT, false)),
+            KEY_CAPSLOCK => Some((MOD_LOCK, true)),
+            KEY_LEFTCTRL | KEY_RIGHTCTRL => Some((MOD_CONTROL, false)),
+            KEY_LEFTALT => Some((MOD_ALT, false)),
+            // Right Alt is AltGr everywhere but in the US layout.
+            KEY_RIGHTALT if self.layout == "us" => Some((MOD_ALT, false)),
+            KEY_RIGHTALT => Some((MOD_LEVEL3, false)),
+            KEY_LEFTMETA | KEY_RIGHTMETA => Some((MOD_SUPER, false)),
+            KEY_NUMLOCK => Some((MOD_NUM, true)),
+            _ => None,
+        }
+    }
+}
+
+// Modifier masks of the "complete" XKB types.
+const MOD_SHIFT: u32 = 1;
+const MOD_LOCK: u32 = 2;
+const MOD_CONTROL: u32 = 4;
+const MOD_ALT: u32 = 8;
+const MOD_NUM: u32 = 16;
+const MOD_SUPER: u32 = 64;
+const MOD_LEVEL3: u32 = 128;
+
+const KEY_LEFTCTRL: u32 = 29;
+pub const KEY_LEFTSHIFT: u32 = 42;
+const KEY_RIGHTSHIFT: u32 = 54;
+const KEY_LEFTALT: u32 = 56;
+const KEY_CAPSLOCK: u32 = 58;
+const KEY_NUMLOCK: u32 = 69;
+const KEY_RIGHTCTRL: u32 = 97;
+const KEY_RIGHTALT: u32 = 100;
+const KEY_LEFTMETA: u32 = 125;
+const KEY_RIGHTMETA: u32 = 126;
+
+pub const BTN_LEFT: u32 = 0x110;
+pub const BTN_RIGHT: u32 = 0x111;
+pub const BTN_MIDDLE: u32 = 0x112;
+pub const BTN_SIDE: u32 = 0x113;
+pub const BTN_EXTRA: u32 = 0x114;
+
+// Keysyms that are not printable characters, and their evdev codes.
+const SPECIAL_KEYS: &[(u32, u32)] = &[
+    (0xff08, 14),  // BackSpace
+    (0xff09, 15),  // Tab
+    (0xff0d, 28),  // Return
+    (0xff13, 119), // Pause
+    (0xff14, 70),  // Scroll_Lock
+    (0xff1b, 1),   // Escape
+    (0xff50, 102), // Home
+    (0xff51, 105), // Left
+    (0xff52, 103), // Up
+    (0xff53, 106), // Right
+    (0xff54, 108), // Down
+    (0xff55, 104), // Page_Up
+    (0xff56, 109), // Page_Down
+    (0xff57, 107), // End
+    (0xff61, 99),  // Print
+    (0xff63, 110), // Insert
+    (0xff67, 127), // Menu
+    (0xff7f, KEY_NUMLOCK),
+    (0xff8d, 96), // KP_Enter
+    (0xffaa, 55), // KP_Multiply
+    (0xffab, 78), // KP_Add
+    (0xffad, 74), // KP_Subtract
+    (0xffae, 83), // KP_Decimal
+    (0xffaf, 98), // KP_Divide
+    (0xffb0, 82), // KP_0
+    (0xffb1, 79),
+    (0xffb2, 80),
+    (0xffb3, 81),
+    (0xffb4, 75),
+    (0xffb5, 76),
+    (0xffb6, 77),
+    (0xffb7, 71),
+    (0xffb8, 72),
+    (0xffb9, 73), // KP_9
+    (0xffc8, 87), // F11
+    (0xffc9, 88), // F12
+    (0xffe1, KEY_LEFTSHIFT),
+    (0xffe2, KEY_RIGHTSHIFT),
+    (0xffe3, KEY_LEFTCTRL),
+    (0xffe4, KEY_RIGHTCTRL),
+    (0xffe5, KEY_CAPSLOCK),
+    (0xffe7, KEY_LEFTALT), // Meta_L
+    (0xffe8, KEY_RIGHTALT),
+    (0xffe9, KEY_LEFTALT),
+    (0xffea, KEY_RIGHTALT),
+    (0xffeb, KEY_LEFTMETA),
+    (0xffec, KEY_RIGHTMETA),
+    (0xfe03, KEY_RIGHTALT), // ISO_Level3_Shift
+    (0xffff, 111),          // Delete
+];
+
+// Printable ASCII from 0x20, by position on a US keyboard. Shifted
+// characters share the key of their unshifted one.
+const ASCII_KEYS: &[u8; 95] = &[
+    57, 2, 40, 4, 5, 6, 8, 40, 10, 11, 9, 13, 51, 12, 52, 53, // ' ' to '/'
+    11, 2, 3, 4, 5, 6, 7, 8, 9, 10, // '0' to '9'
+    39, 39, 51, 13, 52, 53, 3, // ':' to '@'
+    30, 48, 46, 32, 18, 33, 34, 35, 23, 36, 37, 38, 50, 49, 24, 25, 16, 19, 31, 20, 22, 47, 17, 45,
+    21, 44, // 'A' to 'Z'
+    26, 43, 27, 7, 12, 41, // '[' to '`'
+    30, 48, 46, 32, 18, 33, 34, 35, 23, 36, 37, 38, 50, 49, 24, 25, 16, 19, 31, 20, 22, 47, 17, 45,
+    21, 44, // 'a' to 'z'
+    26, 43, 27, 41, // '{' to '~'
+];
+
+// Characters typed with Shift held on a US keyboard, besides A to Z.
+const SHIFTED: &str = "~!@#$%^&*()_+{}|:\"<>?";
+
+// KeyboardEvent.code values that are not a letter or digit, and their evdev
+// codes.
+const DOM_CODES: &[(&str, u32)] = &[
+    ("Escape", 1),
+    ("Minus", 12),
+    ("Equal", 13),
+    ("Backspace", 14),
+    ("Tab", 15),
+    ("BracketLeft", 26),
+    ("BracketRight", 27),
+    ("Enter", 28),
+    ("ControlLeft", KEY_LEFTCTRL),
+    ("Semicolon", 39),
+    ("Quote", 40),
+    ("Backquote", 41),
+    ("ShiftLeft", KEY_LEFTSHIFT),
+    ("Backslash", 43),
+    ("Comma", 51),
+    ("Period", 52),
+    ("Slash", 53),
+    ("ShiftRight", KEY_RIGHTSHIFT),
+    ("NumpadMultiply", 55),
+    ("AltLeft", KEY_LEFTALT),
+    ("Space", 57),
+    ("CapsLock", KEY_CAPSLOCK),
+    ("NumLock", KEY_NUMLOCK),
+    ("ScrollLock", 70),
+    ("Numpad7", 71),
+    ("Numpad8", 72),
+    ("Numpad9", 73),
+    ("NumpadSubtract", 74),
+    ("Numpad4", 75),
+    ("Numpad5", 76),
+    ("Numpad6", 77),
+    ("NumpadAdd", 78),
+    ("Numpad1", 79),
+    ("Numpad2", 80),
+    ("Numpad3", 81),
+    ("Numpad0", 82),
+    ("NumpadDecimal", 83),
+    ("IntlBackslash", 86),
+    ("F11", 87),
+    ("F12", 88),
+    ("NumpadEnter", 96),
+    ("ControlRight", KEY_RIGHTCTRL),
+    ("NumpadDivide", 98),
+    ("PrintScreen", 99),
+    ("AltRight", KEY_RIGHTALT),
+    ("Home", 102),
+    ("ArrowUp", 103),
+    ("PageUp", 104),
+    ("ArrowLeft", 105),
+    ("ArrowRight", 106),
+    ("End", 107),
+    ("ArrowDown", 108),
+    ("PageDown", 109),
+    ("Insert", 110),
+    ("Delete", 111),
+    ("Pause", 119),
+    ("MetaLeft", KEY_LEFTMETA),
+    ("MetaRight", KEY_RIGHTMETA),
+    ("ContextMenu", 127),
+];
+
+pub fn keysym_to_evdev(keysym: u32) -> Option<u32> {
+    match keysym {
+        0x20..=0x7e => Some(u32::from(ASCII_KEYS[(keysym - 0x20) as usize])),
+        // F1 to F10
+        0xffbe..=0xffc7 => Some(59 + keysym - 0xffbe),
+        _ => SPECIAL_KEYS
+            .iter()
+            .find(|(x, _)| *x == keysym)
+            .map(|(_, code)| *code),
+    }
+}
+
+// The key that types c on a US keyboard, and whether Shift has to be held.
+pub fn char_to_evdev(c: char) -> Option<(u32, bool)> {
+    match c {
+        '\n' => Some((28, false)),
+        '\t' => Some((15, false)),
+        ' '..='~' => Some((
+            u32::from(ASCII_KEYS[c as usize - 0x20]),
+            c.is_ascii_uppercase() || SHIFTED.contains(c),
+        )),
+        _ => None,
+    }
+}
+
+pub fn dom_code_to_evdev(code: &str) -> Option<u32> {
+    match code.as_bytes() {
+        [b'K', b'e', b'y', c @ b'A'..=b'Z'] => keysym_to_evdev(u32::from(*c)),
+        [b'D', b'i', b'g', b'i', b't', c @ b'0'..=b'9'] => keysym_to_evdev(u32::from(*c)),
+        [b'F', c @ b'1'..=b'9'] => Some(59 + u32::from(c - b'1')),
+        [b'F', b'1', b'0'] => Some(68),
+        _ => DOM_CODES
+            .iter()
+            .find(|(x, _)| *x == code)
+            .map(|(_, keycode)| *keycode),
+    }
+}
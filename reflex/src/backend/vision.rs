@@ -0,0 +1,332 @@
+use super::{Backend, BackendClient, BackendSession, ClientEvents, WindowChange};
+use crate::config::Config;
+use crate::lock::{MutexExt, RwLockExt};
+use crate::protocol::connection_stream::Credentials;
+use crate::protocol::error::ClientError;
+use crate::protocol::raw_event::RawEvent;
+use byteorder::{ByteOrder, NativeEndian, ReadBytesExt};
+use futures::future::{loop_fn, Either, Future, Loop};
+use futures::stream::Stream;
+use futures::sync::{mpsc, oneshot};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex, RwLock};
+use tokio::io::{AsyncRead, ReadHalf};
+use tokio::net::UnixStream;
+
+// Relays to vision, the Windows side of reflex, over one unix socket per
+// client. Requests that reflex does not handle itself are forwarded as they
+// are, and the events vision sends back are queued to the client unchanged.
+//
+// Vision does not say which windows the host shows, so every surface counts
+// as on the first output, except those of a toplevel the client minimized.
+// The host restoring the window configures it again, which shows it.
+pub struct VisionBackend {
+    config: Arc<RwLock<Config>>,
+    windows: Windows,
+}
+
+impl VisionBackend {
+    // The address is read from the config for every client, so that a
+    // reloaded config applies to the next one.
+    pub fn new(config: Arc<RwLock<Config>>) -> VisionBackend {
+        VisionBackend {
+            config,
+            windows: Windows::default(),
+        }
+    }
+}
+
+impl Backend for VisionBackend {
+    fn connect(
+        &self,
+        client: BackendClient,
+    ) -> Box<dyn Future<Item = Box<dyn BackendSession>, Error = std::io::Error> + Send> {
+        let (address, disabled_globals) = {
+            let config = self.config.read_locked();
+            (
+                config.backend.address.clone(),
+                config.protocols.disabled_globals(),
+            )
+        };
+        let hello = encode_hello(&client.credentials);
+        let windows = self.windows.clone();
+        Box::new(
+            UnixStream::connect(address)
+                .and_then(move |stream| tokio::io::write_all(stream, hello))
+                .map(move |(stream, _)| {
+                    let client_id = client.client_id;
+                    let (r, w) = stream.split();
+                    let (requests, rx) = mpsc::unbounded::<Vec<u8>>();
+                    tokio::spawn(
+                        rx.fold(w, move |w, buf| {
+                            tokio::io::write_all(w, buf)
+                                .map(|(w, _)| w)
+                                .map_err(move |err| {
+                                    log::error!(
+                                        client_id = client_id;
+                                        "{}",
+                                        ClientError::Backend(err)
+                                    );
+                                })
+                        })
+                        .map(|_| ()),
+                    );
+                    // The connection lives as long as the client's session.
+                    // Once both are gone the client's event queue ends, and
+                    // its socket is closed after the remaining events have
+                    // been flushed.
+                    let (closed, closed_rx) = oneshot::channel::<()>();
+                    let registries = Registries {
+                        ids: Arc::new(Mutex::new(HashSet::new())),
+                        disabled_globals,
+                    };
+                    tokio::spawn(
+                        read_events(
+                            r,
+                            client_id,
+                            client.events,
+                            registries.clone(),
+                            windows.clone(),
+                        )
+                        .select2(closed_rx)
+                        .then(|_| Ok(())),
+                    );
+                    let session: Box<dyn BackendSession> = Box::new(VisionSession {
+                        client_id,
+                        requests,
+                        registries,
+                        windows,
+                        _closed: closed,
+                    });
+                    session
+                }),
+        )
+    }
+
+    fn surface_output(&self, client_id: u32, surface_id: u32) -> Option<usize> {
+        if self.windows.minimized(client_id, surface_id) {
+            None
+        } else {
+            Some(0)
+        }
+    }
+}
+
+// The toplevels of every client, by client and toplevel id.
+#[derive(Clone, Default)]
+struct Windows(Arc<Mutex<HashMap<(u32, u32), Window>>>);
+
+struct Window {
+    surface_id: u32,
+    minimized: bool,
+}
+
+impl Windows {
+    fn changed(&self, client_id: u32, toplevel_id: u32, change: &WindowChange) {
+        let mut windows = self.0.locked();
+        match change {
+            WindowChange::Created { surface_id } => {
+                // The surface may have had another role object before.
+                windows
+                    .retain(|(x, _), window| (*x, window.surface_id) != (client_id, *surface_id));
+                windows.insert(
+                    (client_id, toplevel_id),
+                    Window {
+                        surface_id: *surface_id,
+                        minimized: false,
+                    },
+                );
+            }
+            WindowChange::Minimized => {
+                if let Some(window) = windows.get_mut(&(client_id, toplevel_id)) {
+                    window.minimized = true;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // The host sent an event to object_id; a toplevel it configures is
+    // shown again.
+    fn event(&self, client_id: u32, object_id: u32, opcode: u32) {
+        // Opcode 0 of xdg_toplevel is configure.
+        if opcode == 0 {
+            if let Some(window) = self.0.locked().get_mut(&(client_id, object_id)) {
+                window.minimized = false;
+            }
+        }
+    }
+
+    fn minimized(&self, client_id: u32, surface_id: u32) -> bool {
+        self.0.locked().iter().any(|((x, _), window)| {
+            *x == client_id && window.surface_id == surface_id && window.minimized
+        })
+    }
+
+    fn remove_client(&self, client_id: u32) {
+        self.0.locked().retain(|(x, _), _| *x != client_id);
+    }
+}
+
+// The client's wl_registry objects, whose global events vision sends for
+// every global it has. Those of globals disabled in the config when the
+// client connected are dropped.
+#[derive(Clone)]
+struct Registries {
+    ids: Arc<Mutex<HashSet<u32>>>,
+    disabled_globals: Vec<u32>,
+}
+
+impl Registries {
+    // Notes the registry of a relayed wl_display.get_registry.
+    fn request(&self, buf: &[u8]) {
+        if buf.len() >= 12
+            && NativeEndian::read_u32(&buf[0..4]) == 1
+            && NativeEndian::read_u32(&buf[4..8]) & 0xffff == 1
+        {
+            self.ids
+                .locked()
+                .insert(NativeEndian::read_u32(&buf[8..12]));
+        }
+    }
+
+    // Whether data, an event without its fds, is wl_registry.global for a
+    // disabled global.
+    fn hides(&self, data: &[u8]) -> bool {
+        data.len() >= 12
+            && NativeEndian::read_u32(&data[4..8]) & 0xffff == 0
+            && self
+                .ids
+                .locked()
+                .contains(&NativeEndian::read_u32(&data[0..4]))
+            && self
+                .disabled_globals
+                .contains(&NativeEndian::read_u32(&data[8..12]))
+    }
+}
+
+struct VisionSession {
+    client_id: u32,
+    requests: mpsc::UnboundedSender<Vec<u8>>,
+    registries: Registries,
+    windows: Windows,
+    _closed: oneshot::Sender<()>,
+}
+
+impl BackendSession for VisionSession {
+    fn relay(&mut self, buf: Vec<u8>) -> std::io::Result<()> {
+        self.registries.request(&buf);
+        self.requests.unbounded_send(buf).map_err(|_| {
+            std::io::Error::new(std::io::ErrorKind::BrokenPipe, "vision connection closed")
+        })
+    }
+
+    fn window_changed(&mut self, toplevel_id: u32, change: &WindowChange) {
+        self.windows.changed(self.client_id, toplevel_id, change);
+    }
+
+    fn shutdown(&mut self) {
+        let _ = self.requests.unbounded_send(encode_goodbye());
+    }
+}
+
+impl Drop for VisionSession {
+    fn drop(&mut self) {
+        self.windows.remove_client(self.client_id);
+    }
+}
+
+fn read_events(
+    r: ReadHalf<UnixStream>,
+    client_id: u32,
+    events: ClientEvents,
+    registries: Registries,
+    windows: Windows,
+) -> impl Future<Item = (), Error = ()> {
+    loop_fn((events, r), move |(mut events, r)| {
+        let registries = registries.clone();
+        let windows = windows.clone();
+        let mut header_buf = Vec::new();
+        header_buf.resize(12, 0);
+        tokio::io::read_exact(r, header_buf)
+            .map_err(move |err| log::warn!(client_id = client_id; "{}", ClientError::Backend(err)))
+            .and_then(move |(r1, buf1)| {
+                let mut cursor = std::io::Cursor::new(&buf1);
+                let response_type = cursor.read_u32::<NativeEndian>().unwrap();
+                let message_size = if response_type == 0 {
+                    let _sender_object_id = cursor.read_u32::<NativeEndian>().unwrap();
+                    let message_size_and_opcode = cursor.read_u32::<NativeEndian>().unwrap();
+                    (message_size_and_opcode >> 16) as usize
+                } else {
+                    cursor.read_u32::<NativeEndian>().unwrap() as usize
+                };
+                if message_size < 8 {
+                    let err = ClientError::MalformedMessage(format!(
+                        "backend message type={} size={}",
+                        response_type, message_size
+                    ));
+                    log::error!(client_id = client_id; "{}", err);
+                    return Either::A(futures::future::err(()));
+                }
+                let mut buf2: Vec<u8> = Vec::new();
+                buf2.resize(message_size - 8, 0);
+                Either::B(
+                    tokio::io::read_exact(r1, buf2)
+                        .map_err(move |err| {
+                            log::warn!(client_id = client_id; "{}", ClientError::Backend(err))
+                        })
+                        .and_then(|(r2, buf3)| futures::future::ok((r2, buf1, buf3))),
+                )
+            })
+            .map(move |(r1, buf1, buf2)| {
+                let mut cursor = std::io::Cursor::new(&buf1);
+                let response_type = cursor.read_u32::<NativeEndian>().unwrap();
+                if response_type != 0 {
+                    return Loop::Continue((events, r1));
+                }
+                let mut data = Vec::new();
+                data.extend_from_slice(&buf1[4..]);
+                data.extend_from_slice(&buf2[..]);
+                if registries.hides(&data) {
+                    return Loop::Continue((events, r1));
+                }
+                let opcode = NativeEndian::read_u32(&data[4..]) & 0xffff;
+                windows.event(client_id, NativeEndian::read_u32(&data), opcode);
+                // Opcode 0 of xdg_surface is configure, whose only argument
+                // is the serial. Events to other objects are ignored.
+                if opcode == 0 && buf2.len() >= 4 {
+                    events.configure_sent(
+                        NativeEndian::read_u32(&data),
+                        NativeEndian::read_u32(&buf2),
+                    );
+                }
+                if events.send(Box::new(RawEvent { data })) {
+                    Loop::Continue((events, r1))
+                } else {
+                    Loop::Break(())
+                }
+            })
+    })
+}
+
+// Object id 0 is never used by Wayland clients, so reflex uses it for its
+// own messages to vision. Opcode 0 announces the client's credentials and is
+// sent before any relayed request.
+fn encode_hello(credentials: &Credentials) -> Vec<u8> {
+    let mut buf = vec![0; 20];
+    NativeEndian::write_u32(&mut buf[0..4], 0);
+    NativeEndian::write_u32(&mut buf[4..8], (20 << 16) as u32);
+    NativeEndian::write_i32(&mut buf[8..12], credentials.pid);
+    NativeEndian::write_u32(&mut buf[12..16], credentials.uid);
+    NativeEndian::write_u32(&mut buf[16..20], credentials.gid);
+    buf
+}
+
+// Opcode 1 tells vision that the client is going away, so that it can drop
+// the client's windows right away.
+fn encode_goodbye() -> Vec<u8> {
+    let mut buf = vec![0; 8];
+    NativeEndian::write_u32(&mut buf[0..4], 0);
+    NativeEndian::write_u32(&mut buf[4..8], (8 << 16) | 1);
+    buf
+}
@@ -0,0 +1,641 @@
+use super::frontend::HeadlessFrontend;
+use super::headless::HeadlessBackend;
+use super::keymap::{keysym_to_evdev, BTN_LEFT, BTN_MIDDLE, BTN_RIGHT};
+use crate::compositor::{Compositor, Rect};
+use crate::config::Config;
+use crate::lock::RwLockExt;
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use flate2::{Compress, Compression, FlushCompress};
+use std::io::{self, BufWriter, Read, Write};
+use std::net::TcpStream;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+// Shows the headless backend's output to VNC viewers and feeds their
+// keyboard and pointer to the seat, so that clients can be watched and used
+// from another machine without a Windows host.
+//
+// Viewers connect to backend.listen, 127.0.0.1:5900 unless set, with RFB
+// 3.3, 3.7 or 3.8 and no authentication. Anyone who can reach the port can
+// type into the clients, so keep it on loopback and tunnel it, for example
+//
+//   ssh -L 5900:127.0.0.1:5900 host
+//
+// Each viewer gets only the parts of the output that changed since its last
+// update, in the Raw or Zlib encoding and any true-colour pixel format.
+// Keysyms are translated to evdev key codes by position on a US keyboard,
+// and the keymap from keyboard.layout gives them their meaning.
+pub fn backend(config: Arc<RwLock<Config>>) -> HeadlessFrontend {
+    HeadlessFrontend::new("vnc", config, serve_viewer)
+}
+
+// Client-to-server messages.
+const SET_PIXEL_FORMAT: u8 = 0;
+const SET_ENCODINGS: u8 = 2;
+const FRAMEBUFFER_UPDATE_REQUEST: u8 = 3;
+const KEY_EVENT: u8 = 4;
+const POINTER_EVENT: u8 = 5;
+const CLIENT_CUT_TEXT: u8 = 6;
+
+const ENCODING_RAW: i32 = 0;
+const ENCODING_ZLIB: i32 = 6;
+
+const SECURITY_NONE: u8 = 1;
+
+// How far one step of a scroll wheel scrolls, like most compositors.
+const SCROLL_STEP: f64 = 10.0;
+
+#[derive(Clone, Copy, Debug)]
+struct PixelFormat {
+    bits_per_pixel: u8,
+    depth: u8,
+    big_endian: bool,
+    true_colour: bool,
+    red_max: u16,
+    green_max: u16,
+    blue_max: u16,
+    red_shift: u8,
+    green_shift: u8,
+    blue_shift: u8,
+}
+
+impl PixelFormat {
+    // What the server offers: the compositor's own XRGB8888.
+    const NATIVE: PixelFormat = PixelFormat {
+        bits_per_pixel: 32,
+        depth: 24,
+        big_endian: false,
+        true_colour: true,
+        red_max: 255,
+        green_max: 255,
+        blue_max: 255,
+        red_shift: 16,
+        green_shift: 8,
+        blue_shift: 0,
+    };
+
+    fn read<R: Read>(r: &mut R) -> io::Result<PixelFormat> {
+        let mut buf = [0; 16];
+        r.read_exact(&mut buf)?;
+        let max = |i: usize| u16::from_be_bytes([buf[i], buf[i + 1]]);
+        Ok(PixelFormat {
+            bits_per_pixel: buf[0],
+            depth: buf[1],
+            big_endian: buf[2] != 0,
+            true_colour: buf[3] != 0,
+            red_max: max(4),
+            green_max: max(6),
+            blue_max: max(8),
+            red_shift: buf[10],
+            green_shift: buf[11],
+            blue_shift: buf[12],
+        })
+    }
+
+    fn write<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        w.write_all(&[
+            self.bits_per_pixel,
+            self.depth,
+            self.big_endian as u8,
+            self.true_colour as u8,
+        ])?;
+        w.write_u16::<BigEndian>(self.red_max)?;
+        w.write_u16::<BigEndian>(self.green_max)?;
+        w.write_u16::<BigEndian>(self.blue_max)?;
+        w.write_all(&[self.red_shift, self.green_shift, self.blue_shift, 0, 0, 0])
+    }
+
+    fn validate(&self) -> io::Result<()> {
+        if !self.true_colour {
+            return Err(invalid_data("colour maps are not supported"));
+        }
+        let bits = self.bits_per_pixel;
+        if bits != 8 && bits != 16 && bits != 32 {
+            return Err(invalid_data(&format!("{} bits per pixel", bits)));
+        }
+        // Every value of every channel must fit in the pixel.
+        for (name, max, shift) in &[
+            ("red", self.red_max, self.red_shift),
+            ("green", self.green_max, self.green_shift),
+            ("blue", self.blue_max, self.blue_shift),
+        ] {
+            if *shift >= bits || (u64::from(*max) << shift) >> bits != 0 {
+                return Err(invalid_data(&format!(
+                    "the {} channel does not fit in {} bits per pixel",
+                    name, bits
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    // Appends one 0xXXRRGGBB pixel.
+    fn push(&self, pixel: u32, out: &mut Vec<u8>) {
+        let channel =
+            |shift: u32, max: u16, to: u8| (((pixel >> shift) & 0xff) * u32::from(max) / 255) << to;
+        let value = channel(16, self.red_max, self.red_shift)
+            | channel(8, self.green_max, self.green_shift)
+            | channel(0, self.blue_max, self.blue_shift);
+        match (self.bits_per_pixel, self.big_endian) {
+            (8, _) => out.push(value as u8),
+            (16, false) => out.extend_from_slice(&(value as u16).to_le_bytes()),
+            (16, true) => out.extend_from_slice(&(value as u16).to_be_bytes()),
+            (_, false) => out.extend_from_slice(&value.to_le_bytes()),
+            (_, true) => out.extend_from_slice(&value.to_be_bytes()),
+        }
+    }
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+// What the reader thread passes on to the thread that sends updates.
+enum Request {
+    PixelFormat(PixelFormat),
+    Encodings(Vec<i32>),
+    Update { incremental: bool },
+}
+
+fn serve_viewer(
+    stream: TcpStream,
+    config: &Arc<RwLock<Config>>,
+    headless: &HeadlessBackend,
+) -> io::Result<()> {
+    let peer = stream.peer_addr()?;
+    log::info!("vnc: viewer {} connected", peer);
+    stream.set_nodelay(true)?;
+    let mut reader = stream.try_clone()?;
+    let mut w = BufWriter::new(stream);
+
+    let (width, height, scale, refresh) = {
+        let config = config.read_locked();
+        let output = config.outputs.first().cloned().unwrap_or_default();
+        (output.width, output.height, output.scale, output.refresh)
+    };
+    handshake(&mut reader, &mut w, width, height)?;
+
+    let (tx, rx) = mpsc::channel();
+    let input = headless.clone();
+    std::thread::Builder::new()
+        .name("vnc input".to_string())
+        .spawn(move || {
+            let mut seat = ViewerInput::new(input);
+            let result = seat.read_messages(&mut reader, &tx);
+            seat.release_all();
+            // Wakes the update loop, which then finds the socket closed.
+            drop(tx);
+            let _ = reader.shutdown(std::net::Shutdown::Both);
+            result
+        })?;
+
+    let interval = Duration::from_micros(1_000_000_000 / refresh.max(1) as u64);
+    let mut updates = Updates {
+        compositor: Compositor::new(width, height, scale),
+        format: PixelFormat::NATIVE,
+        zlib: None,
+        first: true,
+    };
+    send_updates(&mut w, &rx, headless, &mut updates, interval)?;
+    log::info!("vnc: viewer {} disconnected", peer);
+    Ok(())
+}
+
+fn handshake(
+    r: &mut TcpStream,
+    w: &mut BufWriter<TcpStream>,
+    width: i32,
+    height: i32,
+) -> io::Result<()> {
+    w.write_all(b"RFB 003.008\n")?;
+    w.flush()?;
+    let mut version = [0; 12];
+    r.read_exact(&mut version)?;
+    let minor = match &version {
+        b"RFB 003.008\n" => 8,
+        b"RFB 003.007\n" => 7,
+        // Anything else, including the 3.5 and 3.889 some viewers send,
+        // gets 3.3 as the RFC asks.
+        _ => 3,
+    };
+    if minor >= 7 {
+        w.write_all(&[1, SECURITY_NONE])?;
+        w.flush()?;
+        let security = r.read_u8()?;
+        if security != SECURITY_NONE {
+            return Err(invalid_data(&format!(
+                "unsupported security type {}",
+                security
+            )));
+        }
+        if minor == 8 {
+            w.write_u32::<BigEndian>(0)?;
+        }
+    } else {
+        w.write_u32::<BigEndian>(u32::from(SECURITY_NONE))?;
+    }
+    w.flush()?;
+
+    // ClientInit, whose shared flag does not matter: every viewer sees the
+    // same output.
+    r.read_u8()?;
+    let name = b"reflex";
+    w.write_u16::<BigEndian>(width as u16)?;
+    w.write_u16::<BigEndian>(height as u16)?;
+    PixelFormat::NATIVE.write(w)?;
+    w.write_u32::<BigEndian>(name.len() as u32)?;
+    w.write_all(name)?;
+    w.flush()
+}
+
+struct Updates {
+    compositor: Compositor,
+    format: PixelFormat,
+    // The viewer's zlib stream, which lasts as long as the connection.
+    zlib: Option<Compress>,
+    first: bool,
+}
+
+// Waits for update requests and answers each with the damage since the
+// last update. An incremental request with no damage is held until the
+// output changes, checked once a frame.
+fn send_updates(
+    w: &mut BufWriter<TcpStream>,
+    rx: &Receiver<Request>,
+    headless: &HeadlessBackend,
+    updates: &mut Updates,
+    interval: Duration,
+) -> io::Result<()> {
+    let mut pending: Option<bool> = None;
+    loop {
+        let request = match pending {
+            Some(_) => match rx.recv_timeout(interval) {
+                Ok(x) => Some(x),
+                Err(RecvTimeoutError::Timeout) => None,
+                Err(RecvTimeoutError::Disconnected) => return Ok(()),
+            },
+            None => match rx.recv() {
+                Ok(x) => Some(x),
+                Err(_) => return Ok(()),
+            },
+        };
+        match request {
+            Some(Request::PixelFormat(format)) => {
+                updates.format = format;
+                // Everything sent before is in the old format.
+                pending = pending.map(|_| false);
+                updates.first = true;
+            }
+            Some(Request::Encodings(encodings)) => {
+                let zlib = encodings
+                    .iter()
+                    .take_while(|x| **x != ENCODING_RAW)
+                    .any(|x| *x == ENCODING_ZLIB);
+                if !zlib {
+                    updates.zlib = None;
+                } else if updates.zlib.is_none() {
+                    updates.zlib = Some(Compress::new(Compression::fast(), true));
+                }
+            }
+            Some(Request::Update { incremental }) => {
+                // A full request wins over an incremental one.
+                pending = Some(pending.map_or(incremental, |x| x && incremental));
+            }
+            None => {}
+        }
+        if let Some(incremental) = pending {
+            if send_update(w, headless, updates, incremental)? {
+                pending = None;
+            }
+        }
+    }
+}
+
+// Returns false if there was nothing to send yet.
+fn send_update(
+    w: &mut BufWriter<TcpStream>,
+    headless: &HeadlessBackend,
+    updates: &mut Updates,
+    incremental: bool,
+) -> io::Result<bool> {
+    let mut damage = updates.compositor.render(&headless.scene());
+    let output = Rect::new(
+        0,
+        0,
+        updates.compositor.width(),
+        updates.compositor.height(),
+    );
+    if !incremental || updates.first {
+        damage = vec![output];
+        updates.first = false;
+    }
+    if damage.is_empty() {
+        return Ok(false);
+    }
+    w.write_all(&[0, 0])?;
+    w.write_u16::<BigEndian>(damage.len() as u16)?;
+    for rect in &damage {
+        let mut data = Vec::with_capacity((rect.width * rect.height * 4) as usize);
+        let pixels = updates.compositor.pixels();
+        for y in rect.y..rect.bottom() {
+            let row = (y * output.width) as usize;
+            for pixel in &pixels[row + rect.x as usize..row + rect.right() as usize] {
+                updates.format.push(*pixel, &mut data);
+            }
+        }
+        w.write_u16::<BigEndian>(rect.x as u16)?;
+        w.write_u16::<BigEndian>(rect.y as u16)?;
+        w.write_u16::<BigEndian>(rect.width as u16)?;
+        w.write_u16::<BigEndian>(rect.height as u16)?;
+        match &mut updates.zlib {
+            Some(zlib) => {
+                let compressed = deflate(zlib, &data)?;
+                w.write_i32::<BigEndian>(ENCODING_ZLIB)?;
+                w.write_u32::<BigEndian>(compressed.len() as u32)?;
+                w.write_all(&compressed)?;
+            }
+            None => {
+                w.write_i32::<BigEndian>(ENCODING_RAW)?;
+                w.write_all(&data)?;
+            }
+        }
+    }
+    w.flush()?;
+    Ok(true)
+}
+
+fn deflate(zlib: &mut Compress, data: &[u8]) -> io::Result<Vec<u8>> {
+    let start = zlib.total_in();
+    // Room for incompressible data and the sync flush.
+    let mut out = Vec::with_capacity(data.len() + data.len() / 1000 + 64);
+    loop {
+        let consumed = (zlib.total_in() - start) as usize;
+        zlib.compress_vec(&data[consumed..], &mut out, FlushCompress::Sync)
+            .map_err(|err| invalid_data(&err.to_string()))?;
+        // The flush is complete once it did not fill the output.
+        if (zlib.total_in() - start) as usize == data.len() && out.len() < out.capacity() {
+            return Ok(out);
+        }
+        out.reserve(out.capacity());
+    }
+}
+
+// The keys and buttons one viewer holds, so that they can be let go when it
+// disconnects.
+struct ViewerInput {
+    headless: HeadlessBackend,
+    keys: Vec<u32>,
+    buttons: u8,
+    position: Option<(u16, u16)>,
+}
+
+impl ViewerInput {
+    fn new(headless: HeadlessBackend) -> ViewerInput {
+        ViewerInput {
+            headless,
+            keys: Vec::new(),
+            buttons: 0,
+            position: None,
+        }
+    }
+
+    fn read_messages(&mut self, r: &mut TcpStream, tx: &Sender<Request>) -> io::Result<()> {
+        loop {
+            let request = match r.read_u8()? {
+                SET_PIXEL_FORMAT => {
+                    let mut padding = [0; 3];
+                    r.read_exact(&mut padding)?;
+                    let format = PixelFormat::read(r)?;
+                    format.validate()?;
+                    Request::PixelFormat(format)
+                }
+                SET_ENCODINGS => {
+                    r.read_u8()?;
+                    let count = r.read_u16::<BigEndian>()?;
+                    let mut encodings = Vec::with_capacity(count.into());
+                    for _ in 0..count {
+                        encodings.push(r.read_i32::<BigEndian>()?);
+                    }
+                    Request::Encodings(encodings)
+                }
+                FRAMEBUFFER_UPDATE_REQUEST => {
+                    let incremental = r.read_u8()? != 0;
+                    // The area is ignored; the whole damage is sent.
+                    let mut area = [0; 8];
+                    r.read_exact(&mut area)?;
+                    Request::Update { incremental }
+                }
+                KEY_EVENT => {
+                    let down = r.read_u8()? != 0;
+                    r.read_u16::<BigEndian>()?;
+                    let keysym = r.read_u32::<BigEndian>()?;
+                    self.key(keysym, down);
+                    continue;
+                }
+                POINTER_EVENT => {
+                    let mask = r.read_u8()?;
+                    let x = r.read_u16::<BigEndian>()?;
+                    let y = r.read_u16::<BigEndian>()?;
+                    self.pointer(mask, x, y);
+                    continue;
+                }
+                CLIENT_CUT_TEXT => {
+                    let mut padding = [0; 3];
+                    r.read_exact(&mut padding)?;
+                    let len = r.read_u32::<BigEndian>()?;
+                    io::copy(&mut r.take(u64::from(len)), &mut io::sink())?;
+                    log::debug!("vnc: ignoring {} bytes of cut text", len);
+                    continue;
+                }
+                x => return Err(invalid_data(&format!("unknown message type {}", x))),
+            };
+            if tx.send(request).is_err() {
+                return Ok(());
+            }
+        }
+    }
+
+    fn key(&mut self, keysym: u32, down: bool) {
+        let keycode = match keysym_to_evdev(keysym) {
+            Some(x) => x,
+            None => {
+                log::debug!("vnc: no key for keysym {:#x}", keysym);
+                return;
+            }
+        };
+        if down {
+            self.keys.push(keycode);
+        } else {
+            self.keys.retain(|x| *x != keycode);
+        }
+        self.headless.key(keycode, down);
+    }
+
+    fn pointer(&mut self, mask: u8, x: u16, y: u16) {
+        if self.position != Some((x, y)) {
+            self.position = Some((x, y));
+            self.headless.pointer_motion(f64::from(x), f64::from(y));
+        }
+        let changed = mask ^ self.buttons;
+        for (bit, button) in &[(1, BTN_LEFT), (2, BTN_MIDDLE), (4, BTN_RIGHT)] {
+            if changed & bit != 0 {
+                self.headless.pointer_button(*button, mask & bit != 0);
+            }
+        }
+        // The wheel is buttons 4 to 7, each pressed for one step.
+        for (bit, axis, value) in &[
+            (8, 0, -SCROLL_STEP),
+            (16, 0, SCROLL_STEP),
+            (32, 1, -SCROLL_STEP),
+            (64, 1, SCROLL_STEP),
+        ] {
+            if changed & mask & bit != 0 {
+                self.headless.pointer_axis(*axis, *value);
+            }
+        }
+        self.buttons = mask;
+    }
+
+    fn release_all(&mut self) {
+        for keycode in std::mem::take(&mut self.keys) {
+            self.headless.key(keycode, false);
+        }
+        if let Some((x, y)) = self.position {
+            self.pointer(0, x, y);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pixel_formats_must_fit() {
+        assert!(PixelFormat::NATIVE.validate().is_ok());
+        let rgb565 = PixelFormat {
+            bits_per_pixel: 16,
+            depth: 16,
+            red_max: 31,
+            green_max: 63,
+            blue_max: 31,
+            red_shift: 11,
+            green_shift: 5,
+            blue_shift: 0,
+            ..PixelFormat::NATIVE
+        };
+        assert!(rgb565.validate().is_ok());
+        for format in &[
+            PixelFormat {
+                red_shift: 32,
+                ..PixelFormat::NATIVE
+            },
+            PixelFormat {
+                blue_shift: 255,
+                ..PixelFormat::NATIVE
+            },
+            PixelFormat {
+                red_shift: 12,
+                ..rgb565
+            },
+            PixelFormat {
+                green_max: 0xfff,
+                ..rgb565
+            },
+            PixelFormat {
+                red_max: 0xffff,
+                red_shift: 17,
+                ..PixelFormat::NATIVE
+            },
+            PixelFormat {
+                bits_per_pixel: 24,
+                ..PixelFormat::NATIVE
+            },
+            PixelFormat {
+                true_colour: false,
+                ..PixelFormat::NATIVE
+            },
+        ] {
+            assert!(format.validate().is_err(), "{:?}", format);
+        }
+    }
+
+    // Plays a viewer against serve_viewer over loopback.
+    #[test]
+    fn viewer_session() {
+        use std::net::TcpListener;
+
+        let mut config = Config::default();
+        config.backend.kind = "vnc".to_string();
+        config.outputs[0].width = 4;
+        config.outputs[0].height = 2;
+        let config = Arc::new(RwLock::new(config));
+        let headless = HeadlessBackend::new(config.clone());
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let served = headless.clone();
+        std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            serve_viewer(stream, &config, &served)
+        });
+        let mut viewer = TcpStream::connect(address).unwrap();
+        viewer
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let mut reader = viewer.try_clone().unwrap();
+        let mut read = |len: usize| {
+            let mut buf = vec![0; len];
+            reader.read_exact(&mut buf).unwrap();
+            buf
+        };
+
+        // ProtocolVersion, security None and ServerInit.
+        assert_eq!(read(12), b"RFB 003.008\n");
+        let mut script = b"RFB 003.008\n".to_vec();
+        viewer.write_all(&script).unwrap();
+        assert_eq!(read(2), [1, SECURITY_NONE]);
+        viewer.write_all(&[SECURITY_NONE]).unwrap();
+        assert_eq!(read(4), [0, 0, 0, 0]);
+        viewer.write_all(&[1]).unwrap();
+        assert_eq!(read(4), [0, 4, 0, 2]);
+        let mut native = Vec::new();
+        PixelFormat::NATIVE.write(&mut native).unwrap();
+        assert_eq!(read(16), native);
+        assert_eq!(read(4), [0, 0, 0, 6]);
+        assert_eq!(read(6), b"reflex");
+
+        // A full update in 16 bits per pixel, after a click at 3, 1.
+        script = vec![SET_PIXEL_FORMAT, 0, 0, 0];
+        PixelFormat {
+            bits_per_pixel: 16,
+            depth: 16,
+            red_max: 31,
+            green_max: 63,
+            blue_max: 31,
+            red_shift: 11,
+            green_shift: 5,
+            blue_shift: 0,
+            ..PixelFormat::NATIVE
+        }
+        .write(&mut script)
+        .unwrap();
+        script.extend_from_slice(&[POINTER_EVENT, 1, 0, 3, 0, 1]);
+        script.extend_from_slice(&[FRAMEBUFFER_UPDATE_REQUEST, 0, 0, 0, 0, 0, 0, 4, 0, 2]);
+        viewer.write_all(&script).unwrap();
+        assert_eq!(read(4), [0, 0, 0, 1]);
+        assert_eq!(read(12), [0, 0, 0, 0, 0, 4, 0, 2, 0, 0, 0, 0]);
+        assert_eq!(read(4 * 2 * 2), vec![0; 16]);
+        assert_eq!(headless.pointer(), (3.0, 1.0, vec![BTN_LEFT]));
+
+        // Letting go of the connection lets go of the button.
+        viewer.shutdown(std::net::Shutdown::Both).unwrap();
+        for _ in 0..500 {
+            if headless.pointer().2.is_empty() {
+                return;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        panic!("the button is still held");
+    }
+}
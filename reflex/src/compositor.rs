@@ -0,0 +1,675 @@
+// A software compositor that draws the surface tree into an output-sized
+// XRGB8888 framebuffer on the CPU.
+//
+// Backends that keep buffer contents describe what is mapped as a list of
+// SceneSurface trees, bottom to top, and call Compositor::render() each
+// frame. Only the parts of the output that changed since the previous frame
+// are redrawn, and render() returns them so that remote-desktop backends can
+// send just those. A surface that only committed new contents is redrawn
+// where the client damaged it.
+//
+// Buffers are wl_shm ARGB8888, which is blended as premultiplied alpha, or
+// XRGB8888, which is opaque. Other formats are not drawn.
+
+use region::Region;
+use std::sync::Arc;
+
+pub mod hit_test;
+pub mod region;
+
+pub const FORMAT_ARGB8888: u32 = 0;
+pub const FORMAT_XRGB8888: u32 = 1;
+
+// The color of the output where no surface is mapped.
+const BACKGROUND: u32 = 0xff00_0000;
+
+// The largest output width and height, which keeps pixel offsets within i32.
+pub const MAX_OUTPUT_SIZE: i32 = 16384;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Rect {
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+}
+
+impl Rect {
+    pub fn new(x: i32, y: i32, width: i32, height: i32) -> Rect {
+        Rect {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.width <= 0 || self.height <= 0
+    }
+
+    pub fn right(&self) -> i32 {
+        self.x + self.width
+    }
+
+    pub fn bottom(&self) -> i32 {
+        self.y + self.height
+    }
+
+    pub fn intersect(&self, other: &Rect) -> Rect {
+        let x = self.x.max(other.x);
+        let y = self.y.max(other.y);
+        Rect::new(
+            x,
+            y,
+            (self.right().min(other.right()) - x).max(0),
+            (self.bottom().min(other.bottom()) - y).max(0),
+        )
+    }
+
+    pub fn contains(&self, x: i32, y: i32) -> bool {
+        x >= self.x && y >= self.y && x < self.right() && y < self.bottom()
+    }
+}
+
+// The size of a surface in surface-local coordinates: the size of its buffer
+// with the buffer scale and wl_output.transform undone.
+pub fn surface_size(
+    buffer_width: i32,
+    buffer_height: i32,
+    scale: i32,
+    transform: i32,
+) -> (i32, i32) {
+    let scale = scale.max(1);
+    let (width, height) = (buffer_width / scale, buffer_height / scale);
+    if transform & 1 == 1 {
+        (height, width)
+    } else {
+        (width, height)
+    }
+}
+
+// Maps a rectangle in buffer pixels to surface-local coordinates, the
+// inverse of SceneSurface::buffer_coord(). size is the surface size. Partly
+// covered surface pixels are included.
+pub fn buffer_rect_to_surface(rect: &Rect, scale: i32, transform: i32, size: (i32, i32)) -> Rect {
+    let scale = scale.max(1);
+    let (width, height) = size;
+    let x1 = rect.x.div_euclid(scale);
+    let y1 = rect.y.div_euclid(scale);
+    let x2 = (rect.right() + scale - 1).div_euclid(scale);
+    let y2 = (rect.bottom() + scale - 1).div_euclid(scale);
+    let map = |bx: i32, by: i32| match transform {
+        1 => (width - by, bx),
+        2 => (width - bx, height - by),
+        3 => (by, height - bx),
+        4 => (width - bx, by),
+        5 => (by, bx),
+        6 => (bx, height - by),
+        7 => (width - by, height - bx),
+        _ => (bx, by),
+    };
+    let (ax, ay) = map(x1, y1);
+    let (bx, by) = map(x2, y2);
+    Rect::new(ax.min(bx), ay.min(by), (ax - bx).abs(), (ay - by).abs())
+}
+
+// The contents of a wl_buffer as last committed.
+#[derive(Clone, Debug)]
+pub struct Buffer {
+    pub width: i32,
+    pub height: i32,
+    pub stride: i32,
+    pub format: u32,
+    pub pixels: Arc<Vec<u8>>,
+}
+
+impl Buffer {
+    fn pixel(&self, x: i32, y: i32) -> u32 {
+        let x = x.max(0).min(self.width - 1) as usize;
+        let y = y.max(0).min(self.height - 1) as usize;
+        let offset = y * self.stride as usize + x * 4;
+        match self.pixels.get(offset..offset + 4) {
+            Some(x) => u32::from_le_bytes([x[0], x[1], x[2], x[3]]),
+            None => 0,
+        }
+    }
+}
+
+// A mapped wl_surface and its subsurfaces.
+//
+// x and y are in the surface-local coordinates of the parent, or in global
+// coordinates for the root of a tree. generation must change whenever the
+// surface commits new contents, scale or transform, which is how damage is
+// found.
+#[derive(Clone, Debug)]
+pub struct SceneSurface {
+    pub client_id: u32,
+    pub surface_id: u32,
+    pub x: i32,
+    pub y: i32,
+    pub buffer: Option<Buffer>,
+    pub scale: i32,
+    pub transform: i32,
+    // Surface-local; None for the whole surface.
+    pub input_region: Option<Region>,
+    pub generation: u64,
+    // The surface-local damage of the commit that made generation current,
+    // with the generation it was made on top of. Without it, or when the
+    // compositor last drew another generation, the whole surface is redrawn.
+    pub damage: Option<(u64, Vec<Rect>)>,
+    pub below: Vec<SceneSurface>,
+    pub above: Vec<SceneSurface>,
+}
+
+impl SceneSurface {
+    // The size of the surface in surface-local coordinates, after the
+    // buffer scale and transform are undone.
+    pub fn size(&self) -> (i32, i32) {
+        match &self.buffer {
+            Some(x) => surface_size(x.width, x.height, self.scale, self.transform),
+            None => (0, 0),
+        }
+    }
+
+    // Maps a surface-local point to buffer pixel coordinates, as in
+    // wl_output.transform.
+    pub fn buffer_coord(&self, sx: f64, sy: f64) -> (f64, f64) {
+        let (width, height) = self.size();
+        let (width, height) = (f64::from(width), f64::from(height));
+        let (bx, by) = match self.transform {
+            1 => (sy, width - sx),
+            2 => (width - sx, height - sy),
+            3 => (height - sy, sx),
+            4 => (width - sx, sy),
+            5 => (sy, sx),
+            6 => (sx, height - sy),
+            7 => (height - sy, width - sx),
+            _ => (sx, sy),
+        };
+        let scale = f64::from(self.scale.max(1));
+        (bx * scale, by * scale)
+    }
+}
+
+// One surface of the flattened tree, in output pixels.
+#[derive(Clone, Debug)]
+struct Placed {
+    client_id: u32,
+    surface_id: u32,
+    rect: Rect,
+    generation: u64,
+    // What changed since generation base, if the surface says.
+    damage: Option<(u64, Vec<Rect>)>,
+}
+
+impl Placed {
+    fn same_place(&self, other: &Placed) -> bool {
+        self.client_id == other.client_id
+            && self.surface_id == other.surface_id
+            && self.rect == other.rect
+    }
+
+    // Where the surface changed since the old generation.
+    fn damage_since(&self, generation: u64) -> Vec<Rect> {
+        match &self.damage {
+            Some((base, rects)) if *base == generation => rects.clone(),
+            _ => vec![self.rect],
+        }
+    }
+}
+
+// Surface-local damage in output pixels, clipped to the surface. Clients may
+// damage far outside their surface, so the math saturates.
+fn damage_to_output(damage: &Rect, rect: &Rect, scale: i32) -> Rect {
+    let to_output = |x: i32, origin: i32, size: i32| {
+        x.saturating_mul(scale)
+            .saturating_add(origin)
+            .max(origin)
+            .min(origin + size)
+    };
+    let x1 = to_output(damage.x, rect.x, rect.width);
+    let y1 = to_output(damage.y, rect.y, rect.height);
+    let x2 = to_output(damage.x.saturating_add(damage.width), rect.x, rect.width);
+    let y2 = to_output(damage.y.saturating_add(damage.height), rect.y, rect.height);
+    Rect::new(x1, y1, x2 - x1, y2 - y1)
+}
+
+pub struct Compositor {
+    width: i32,
+    height: i32,
+    scale: i32,
+    pixels: Vec<u32>,
+    placed: Vec<Placed>,
+}
+
+impl Compositor {
+    // An output of width x height pixels, each at most MAX_OUTPUT_SIZE.
+    // Surfaces are drawn scale times as large as their surface-local size.
+    pub fn new(width: i32, height: i32, scale: i32) -> Compositor {
+        let bound = |x: i32| if x > 0 { x.min(MAX_OUTPUT_SIZE) } else { 0 };
+        let (width, height) = (bound(width), bound(height));
+        Compositor {
+            width,
+            height,
+            scale: scale.max(1),
+            pixels: vec![BACKGROUND; width as usize * height as usize],
+            placed: Vec::new(),
+        }
+    }
+
+    pub fn width(&self) -> i32 {
+        self.width
+    }
+
+    pub fn height(&self) -> i32 {
+        self.height
+    }
+
+    // The framebuffer, row by row, as 0xXXRRGGBB.
+    pub fn pixels(&self) -> &[u32] {
+        &self.pixels
+    }
+
+    // Redraws the parts of the framebuffer that changed since the last call
+    // and returns them.
+    pub fn render(&mut self, scene: &[SceneSurface]) -> Vec<Rect> {
+        let mut flat = Vec::new();
+        for surface in scene {
+            flatten(surface, 0, 0, &mut flat);
+        }
+        let placed: Vec<Placed> = flat
+            .iter()
+            .map(|(surface, x, y)| {
+                let (width, height) = surface.size();
+                let rect = Rect::new(
+                    x * self.scale,
+                    y * self.scale,
+                    width * self.scale,
+                    height * self.scale,
+                );
+                let damage = surface.damage.as_ref().map(|(base, rects)| {
+                    let rects = rects
+                        .iter()
+                        .map(|x| damage_to_output(x, &rect, self.scale))
+                        .collect();
+                    (*base, rects)
+                });
+                Placed {
+                    client_id: surface.client_id,
+                    surface_id: surface.surface_id,
+                    rect,
+                    generation: surface.generation,
+                    damage,
+                }
+            })
+            .collect();
+
+        // A surface is damaged where it is now and where it was before when
+        // it is new, gone, moved, resized or restacked. One that only
+        // committed new contents is damaged where the client said.
+        let mut damage = Vec::new();
+        for (i, old) in self.placed.iter().enumerate() {
+            let kept = match placed.get(i) {
+                Some(new) => new.same_place(old),
+                None => false,
+            };
+            if !kept {
+                damage.push(old.rect);
+            }
+        }
+        for (i, new) in placed.iter().enumerate() {
+            match self.placed.get(i) {
+                Some(old) if new.same_place(old) => {
+                    if new.generation != old.generation {
+                        damage.extend(new.damage_since(old.generation));
+                    }
+                }
+                _ => damage.push(new.rect),
+            }
+        }
+        let output = Rect::new(0, 0, self.width, self.height);
+        let damage: Vec<Rect> = damage
+            .iter()
+            .map(|x| x.intersect(&output))
+            .filter(|x| !x.is_empty())
+            .collect();
+
+        for clip in &damage {
+            self.fill(clip, BACKGROUND);
+            for ((surface, x, y), placed) in flat.iter().zip(&placed) {
+                let area = placed.rect.intersect(clip);
+                if !area.is_empty() {
+                    self.draw(surface, *x, *y, &area);
+                }
+            }
+        }
+        self.placed = placed;
+        damage
+    }
+
+    fn fill(&mut self, rect: &Rect, color: u32) {
+        for y in rect.y..rect.bottom() {
+            let row = (y * self.width) as usize;
+            for pixel in &mut self.pixels[row + rect.x as usize..row + rect.right() as usize] {
+                *pixel = color;
+            }
+        }
+    }
+
+    fn draw(&mut self, surface: &SceneSurface, x: i32, y: i32, area: &Rect) {
+        let buffer = match &surface.buffer {
+            Some(x) => x,
+            None => return,
+        };
+        let opaque = match buffer.format {
+            FORMAT_ARGB8888 => false,
+            FORMAT_XRGB8888 => true,
+            _ => return,
+        };
+        let scale = f64::from(self.scale);
+        for oy in area.y..area.bottom() {
+            let row = (oy * self.width) as usize;
+            for ox in area.x..area.right() {
+                // Sample at the center of the output pixel.
+                let sx = (f64::from(ox) + 0.5) / scale - f64::from(x);
+                let sy = (f64::from(oy) + 0.5) / scale - f64::from(y);
+                let (bx, by) = surface.buffer_coord(sx, sy);
+                let src = buffer.pixel(bx.floor() as i32, by.floor() as i32);
+                let dst = &mut self.pixels[row + ox as usize];
+                *dst = if opaque {
+                    src | 0xff00_0000
+                } else {
+                    over(src, *dst)
+                };
+            }
+        }
+    }
+}
+
+// Collects the surfaces of a tree bottom to top with their global positions.
+fn flatten<'a>(
+    surface: &'a SceneSurface,
+    parent_x: i32,
+    parent_y: i32,
+    out: &mut Vec<(&'a SceneSurface, i32, i32)>,
+) {
+    let x = parent_x + surface.x;
+    let y = parent_y + surface.y;
+    for child in &surface.below {
+        flatten(child, x, y, out);
+    }
+    if surface.buffer.is_some() {
+        out.push((surface, x, y));
+    }
+    for child in &surface.above {
+        flatten(child, x, y, out);
+    }
+}
+
+// Porter-Duff "over" with premultiplied alpha, as wl_shm ARGB8888 is.
+fn over(src: u32, dst: u32) -> u32 {
+    let alpha = src >> 24;
+    if alpha == 0xff {
+        return src;
+    }
+    let inverse = 0xff - alpha;
+    let mut out = 0xff00_0000;
+    for shift in &[0, 8, 16] {
+        let s = (src >> shift) & 0xff;
+        let d = (dst >> shift) & 0xff;
+        let c = (s + (d * inverse + 127) / 255).min(0xff);
+        out |= c << shift;
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A width x height ARGB8888 buffer whose pixel i is 0xff000000 | i, in
+    // row-major order.
+    fn numbered_buffer(width: i32, height: i32) -> Buffer {
+        let pixels = (0..width * height)
+            .flat_map(|i| (0xff00_0000 | i as u32).to_le_bytes().to_vec())
+            .collect();
+        Buffer {
+            width,
+            height,
+            stride: width * 4,
+            format: FORMAT_ARGB8888,
+            pixels: Arc::new(pixels),
+        }
+    }
+
+    fn scene_surface(surface_id: u32, buffer: Buffer, scale: i32, transform: i32) -> SceneSurface {
+        SceneSurface {
+            client_id: 1,
+            surface_id,
+            x: 0,
+            y: 0,
+            buffer: Some(buffer),
+            scale,
+            transform,
+            input_region: None,
+            generation: 1,
+            damage: None,
+            below: Vec::new(),
+            above: Vec::new(),
+        }
+    }
+
+    // The buffer pixel index sampled at the center of each surface pixel.
+    fn sampled(surface: &SceneSurface) -> Vec<Vec<i32>> {
+        let buffer = surface.buffer.as_ref().unwrap();
+        let (width, height) = surface.size();
+        (0..height)
+            .map(|sy| {
+                (0..width)
+                    .map(|sx| {
+                        let (bx, by) =
+                            surface.buffer_coord(f64::from(sx) + 0.5, f64::from(sy) + 0.5);
+                        by.floor() as i32 * buffer.width + bx.floor() as i32
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    // The 4x2 buffer
+    //   0 1 2 3
+    //   4 5 6 7
+    // as shown for each wl_output.transform.
+    fn transformed() -> Vec<Vec<Vec<i32>>> {
+        vec![
+            vec![vec![0, 1, 2, 3], vec![4, 5, 6, 7]],
+            vec![vec![4, 0], vec![5, 1], vec![6, 2], vec![7, 3]],
+            vec![vec![7, 6, 5, 4], vec![3, 2, 1, 0]],
+            vec![vec![3, 7], vec![2, 6], vec![1, 5], vec![0, 4]],
+            vec![vec![3, 2, 1, 0], vec![7, 6, 5, 4]],
+            vec![vec![0, 4], vec![1, 5], vec![2, 6], vec![3, 7]],
+            vec![vec![4, 5, 6, 7], vec![0, 1, 2, 3]],
+            vec![vec![7, 3], vec![6, 2], vec![5, 1], vec![4, 0]],
+        ]
+    }
+
+    #[test]
+    fn surface_size_undoes_scale_and_transform() {
+        for transform in 0..8 {
+            let expected = if transform & 1 == 1 { (2, 4) } else { (4, 2) };
+            assert_eq!(surface_size(8, 4, 2, transform), expected);
+            assert_eq!(surface_size(4, 2, 1, transform), expected);
+        }
+        // Buffers that are not a multiple of the scale lose the remainder.
+        assert_eq!(surface_size(9, 5, 2, 0), (4, 2));
+        assert_eq!(surface_size(8, 4, 0, 0), (8, 4));
+    }
+
+    #[test]
+    fn buffer_coord_undoes_each_transform() {
+        for (transform, expected) in transformed().into_iter().enumerate() {
+            let surface = scene_surface(1, numbered_buffer(4, 2), 1, transform as i32);
+            assert_eq!(sampled(&surface), expected, "transform {}", transform);
+        }
+    }
+
+    #[test]
+    fn buffer_coord_scales() {
+        let surface = scene_surface(1, numbered_buffer(8, 4), 2, 1);
+        assert_eq!(surface.size(), (2, 4));
+        assert_eq!(surface.buffer_coord(0.5, 0.5), (1.0, 3.0));
+        assert_eq!(surface.buffer_coord(2.0, 4.0), (8.0, 0.0));
+    }
+
+    #[test]
+    fn buffer_rect_to_surface_inverts_buffer_coord() {
+        for (transform, expected) in transformed().into_iter().enumerate() {
+            let transform = transform as i32;
+            let size = surface_size(4, 2, 1, transform);
+            for (sy, row) in expected.iter().enumerate() {
+                for (sx, index) in row.iter().enumerate() {
+                    let pixel = Rect::new(index % 4, index / 4, 1, 1);
+                    assert_eq!(
+                        buffer_rect_to_surface(&pixel, 1, transform, size),
+                        Rect::new(sx as i32, sy as i32, 1, 1),
+                        "transform {} pixel {}",
+                        transform,
+                        index
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn buffer_rect_to_surface_covers_partial_pixels() {
+        let rect = Rect::new(1, 1, 2, 2);
+        assert_eq!(
+            buffer_rect_to_surface(&rect, 2, 0, (4, 2)),
+            Rect::new(0, 0, 2, 2)
+        );
+        // 90 degrees: buffer x runs down the surface, buffer y right to left.
+        assert_eq!(
+            buffer_rect_to_surface(&rect, 2, 1, (2, 4)),
+            Rect::new(0, 0, 2, 2)
+        );
+        let rect = Rect::new(4, 0, 4, 2);
+        assert_eq!(
+            buffer_rect_to_surface(&rect, 2, 1, (2, 4)),
+            Rect::new(1, 2, 1, 2)
+        );
+    }
+
+    #[test]
+    fn render_draws_each_transform() {
+        for (transform, expected) in transformed().into_iter().enumerate() {
+            let surface = scene_surface(1, numbered_buffer(4, 2), 1, transform as i32);
+            let mut compositor = Compositor::new(4, 4, 1);
+            compositor.render(&[surface]);
+            for (y, row) in expected.iter().enumerate() {
+                for (x, index) in row.iter().enumerate() {
+                    assert_eq!(
+                        compositor.pixels()[y * 4 + x],
+                        0xff00_0000 | *index as u32,
+                        "transform {} at {},{}",
+                        transform,
+                        x,
+                        y
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn over_blends_premultiplied_colors() {
+        assert_eq!(over(0xff12_3456, 0xffab_cdef), 0xff12_3456);
+        assert_eq!(over(0x0000_0000, 0xffab_cdef), 0xffab_cdef);
+        // Half-transparent white over black is mid gray.
+        assert_eq!(over(0x8080_8080, 0xff00_0000), 0xff80_8080);
+        // Half-transparent black over white.
+        assert_eq!(over(0x8000_0000, 0xffff_ffff), 0xff7f_7f7f);
+        // Half-transparent red over blue keeps both.
+        assert_eq!(over(0x8080_0000, 0xff00_00ff), 0xff80_007f);
+        // Invalid premultiplied colors saturate.
+        assert_eq!(over(0x80ff_ffff, 0xffff_ffff), 0xffff_ffff);
+    }
+
+    // A 4x4 XRGB8888 buffer of one color.
+    fn solid_buffer(color: u32) -> Buffer {
+        Buffer {
+            width: 4,
+            height: 4,
+            stride: 16,
+            format: FORMAT_XRGB8888,
+            pixels: Arc::new(color.to_le_bytes().repeat(16)),
+        }
+    }
+
+    #[test]
+    fn render_damages_what_changed() {
+        let mut compositor = Compositor::new(8, 8, 2);
+        let mut surface = scene_surface(1, solid_buffer(0x11), 1, 0);
+        surface.x = 1;
+        surface.y = 1;
+        // A new surface is damaged whole, in output pixels.
+        assert_eq!(
+            compositor.render(&[surface.clone()]),
+            vec![Rect::new(2, 2, 6, 6)]
+        );
+        assert_eq!(compositor.render(&[surface.clone()]), vec![]);
+
+        // A recommit is damaged where the client said, clipped to the
+        // surface.
+        surface.buffer = Some(solid_buffer(0x22));
+        surface.damage = Some((1, vec![Rect::new(1, 0, 1, 1), Rect::new(3, 3, 9, 9)]));
+        surface.generation = 2;
+        assert_eq!(
+            compositor.render(&[surface.clone()]),
+            vec![Rect::new(4, 2, 2, 2)]
+        );
+        assert_eq!(compositor.pixels()[2 * 8 + 4], 0xff00_0022);
+        assert_eq!(compositor.pixels()[2 * 8 + 2], 0xff00_0011);
+
+        // Damage made on top of a generation the compositor never drew
+        // redraws the whole surface.
+        surface.damage = Some((3, vec![Rect::new(0, 0, 1, 1)]));
+        surface.generation = 4;
+        assert_eq!(
+            compositor.render(&[surface.clone()]),
+            vec![Rect::new(2, 2, 6, 6)]
+        );
+        assert_eq!(compositor.pixels()[2 * 8 + 2], 0xff00_0022);
+
+        // So does a recommit without damage information.
+        surface.damage = None;
+        surface.generation = 5;
+        assert_eq!(
+            compositor.render(&[surface.clone()]),
+            vec![Rect::new(2, 2, 6, 6)]
+        );
+
+        // A move damages where the surface was and where it is.
+        surface.x = 0;
+        assert_eq!(
+            compositor.render(&[surface.clone()]),
+            vec![Rect::new(2, 2, 6, 6), Rect::new(0, 2, 8, 6)]
+        );
+        assert_eq!(compositor.pixels()[7 * 8 + 7], 0xff00_0022);
+        assert_eq!(compositor.pixels()[7 * 8], 0xff00_0022);
+
+        // Unmapping damages where it was.
+        assert_eq!(compositor.render(&[]), vec![Rect::new(0, 2, 8, 6)]);
+        assert!(compositor.pixels().iter().all(|x| *x == BACKGROUND));
+    }
+
+    #[test]
+    fn new_bounds_the_output() {
+        let compositor = Compositor::new(i32::MAX, 2, 1);
+        assert_eq!(compositor.width(), MAX_OUTPUT_SIZE);
+        assert_eq!(compositor.pixels().len(), MAX_OUTPUT_SIZE as usize * 2);
+        let compositor = Compositor::new(-1, 3, 1);
+        assert_eq!(compositor.pixels().len(), 0);
+    }
+}
@@ -0,0 +1,208 @@
+use super::SceneSurface;
+
+// Finding the surface under a point, for pointer and touch focus.
+//
+// The scene is walked from the top: the last tree first, and in each tree
+// the subsurfaces above their parent, the parent, then the ones below it.
+// A surface takes the point if it lies within its size, which already
+// accounts for the buffer scale and transform, and within its input region.
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Hit {
+    pub client_id: u32,
+    pub surface_id: u32,
+    // Surface-local coordinates of the point.
+    pub x: f64,
+    pub y: f64,
+}
+
+// The topmost surface that accepts input at x, y in global coordinates.
+pub fn hit_test(scene: &[SceneSurface], x: f64, y: f64) -> Option<Hit> {
+    scene.iter().rev().find_map(|root| hit_tree(root, x, y))
+}
+
+// Where x, y in global coordinates is on a surface, whether or not the point
+// is within it. None if the surface is not in the scene.
+pub fn surface_local(
+    scene: &[SceneSurface],
+    client_id: u32,
+    surface_id: u32,
+    x: f64,
+    y: f64,
+) -> Option<(f64, f64)> {
+    scene
+        .iter()
+        .find_map(|root| find_tree(root, client_id, surface_id, x, y))
+}
+
+impl SceneSurface {
+    // Whether the surface takes input at a surface-local point.
+    pub fn accepts_input(&self, sx: f64, sy: f64) -> bool {
+        let (width, height) = self.size();
+        if sx < 0.0 || sy < 0.0 || sx >= f64::from(width) || sy >= f64::from(height) {
+            return false;
+        }
+        match &self.input_region {
+            Some(region) => region.contains(sx.floor() as i32, sy.floor() as i32),
+            None => true,
+        }
+    }
+}
+
+// x and y are in the coordinates of the surface's parent.
+fn hit_tree(surface: &SceneSurface, x: f64, y: f64) -> Option<Hit> {
+    let sx = x - f64::from(surface.x);
+    let sy = y - f64::from(surface.y);
+    if let Some(hit) = surface.above.iter().rev().find_map(|x| hit_tree(x, sx, sy)) {
+        return Some(hit);
+    }
+    if surface.accepts_input(sx, sy) {
+        return Some(Hit {
+            client_id: surface.client_id,
+            surface_id: surface.surface_id,
+            x: sx,
+            y: sy,
+        });
+    }
+    surface.below.iter().rev().find_map(|x| hit_tree(x, sx, sy))
+}
+
+fn find_tree(
+    surface: &SceneSurface,
+    client_id: u32,
+    surface_id: u32,
+    x: f64,
+    y: f64,
+) -> Option<(f64, f64)> {
+    let sx = x - f64::from(surface.x);
+    let sy = y - f64::from(surface.y);
+    if surface.client_id == client_id && surface.surface_id == surface_id {
+        return Some((sx, sy));
+    }
+    surface
+        .below
+        .iter()
+        .chain(&surface.above)
+        .find_map(|x| find_tree(x, client_id, surface_id, sx, sy))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compositor::region::Region;
+    use crate::compositor::{Buffer, Rect, FORMAT_ARGB8888};
+    use std::sync::Arc;
+
+    // A surface of client 1 at x, y in its parent, showing a width x height
+    // buffer at scale 1.
+    fn surface(surface_id: u32, x: i32, y: i32, width: i32, height: i32) -> SceneSurface {
+        SceneSurface {
+            client_id: 1,
+            surface_id,
+            x,
+            y,
+            buffer: Some(Buffer {
+                width,
+                height,
+                stride: width * 4,
+                format: FORMAT_ARGB8888,
+                pixels: Arc::new(vec![0; (width * height * 4) as usize]),
+            }),
+            scale: 1,
+            transform: 0,
+            input_region: None,
+            generation: 1,
+            damage: None,
+            below: Vec::new(),
+            above: Vec::new(),
+        }
+    }
+
+    fn hit(scene: &[SceneSurface], x: f64, y: f64) -> Option<(u32, f64, f64)> {
+        hit_test(scene, x, y).map(|hit| (hit.surface_id, hit.x, hit.y))
+    }
+
+    #[test]
+    fn later_trees_are_on_top() {
+        let scene = vec![surface(1, 0, 0, 4, 4), surface(2, 2, 2, 4, 4)];
+        assert_eq!(hit(&scene, 1.5, 1.5), Some((1, 1.5, 1.5)));
+        assert_eq!(hit(&scene, 2.5, 3.0), Some((2, 0.5, 1.0)));
+        assert_eq!(hit(&scene, 5.5, 5.5), Some((2, 3.5, 3.5)));
+        // The right and bottom edges are outside.
+        assert_eq!(hit(&scene, 6.0, 3.0), None);
+        assert_eq!(hit(&scene, -0.5, 0.0), None);
+        assert_eq!(hit(&[], 0.0, 0.0), None);
+    }
+
+    #[test]
+    fn subsurfaces_stack_around_their_parent() {
+        let mut root = surface(1, 10, 10, 4, 4);
+        root.below = vec![surface(2, -1, -1, 2, 2), surface(3, -2, -2, 2, 2)];
+        root.above = vec![surface(4, 2, 2, 4, 4), surface(5, 3, 3, 1, 1)];
+        let scene = vec![root];
+
+        // The parent hides what is below it, and those below stick out.
+        assert_eq!(hit(&scene, 10.5, 10.5), Some((1, 0.5, 0.5)));
+        assert_eq!(hit(&scene, 9.5, 9.5), Some((3, 1.5, 1.5)));
+        assert_eq!(hit(&scene, 8.5, 8.5), Some((3, 0.5, 0.5)));
+        // Those above it hide it, the later ones on top.
+        assert_eq!(hit(&scene, 12.5, 12.5), Some((4, 0.5, 0.5)));
+        assert_eq!(hit(&scene, 13.5, 13.5), Some((5, 0.5, 0.5)));
+        assert_eq!(hit(&scene, 15.5, 15.5), Some((4, 3.5, 3.5)));
+    }
+
+    #[test]
+    fn input_region_holes_let_the_point_through() {
+        let mut top = surface(2, 0, 0, 4, 4);
+        let whole = Region::from_rect(Rect::new(0, 0, 4, 4));
+        top.input_region = Some(whole.subtract(&Region::from_rect(Rect::new(1, 1, 2, 2))));
+        let mut child = surface(3, 0, 0, 4, 4);
+        child.input_region = Some(Region::new());
+        top.above.push(child);
+        let scene = vec![surface(1, 0, 0, 4, 4), top];
+
+        assert_eq!(hit(&scene, 0.5, 0.5), Some((2, 0.5, 0.5)));
+        assert_eq!(hit(&scene, 1.5, 2.9), Some((1, 1.5, 2.9)));
+        assert_eq!(hit(&scene, 3.0, 3.0), Some((2, 3.0, 3.0)));
+
+        // Input regions are clipped to the surface.
+        let mut surface = surface(1, 0, 0, 4, 4);
+        surface.input_region = Some(Region::from_rect(Rect::new(-4, -4, 16, 16)));
+        assert!(surface.accepts_input(3.5, 0.0));
+        assert!(!surface.accepts_input(4.5, 0.0));
+        assert!(!surface.accepts_input(-0.5, 0.0));
+    }
+
+    #[test]
+    fn size_follows_the_buffer_scale_and_transform() {
+        let mut surface = surface(1, 0, 0, 8, 4);
+        surface.scale = 2;
+        assert!(surface.accepts_input(3.5, 1.5));
+        assert!(!surface.accepts_input(4.5, 0.5));
+        assert!(!surface.accepts_input(0.5, 2.5));
+
+        // Rotated by 90 degrees, the 4x2 surface stands up.
+        surface.transform = 1;
+        assert!(surface.accepts_input(1.5, 3.5));
+        assert!(!surface.accepts_input(2.5, 0.5));
+
+        surface.buffer = None;
+        assert!(!surface.accepts_input(0.0, 0.0));
+    }
+
+    #[test]
+    fn surface_local_for_nested_subsurfaces() {
+        let mut child = surface(2, 1, 2, 4, 4);
+        child.below.push(surface(3, -3, 4, 4, 4));
+        let mut root = surface(1, 10, 20, 4, 4);
+        root.above.push(child);
+        let scene = vec![surface(4, 0, 0, 1, 1), root];
+
+        assert_eq!(surface_local(&scene, 1, 1, 0.0, 0.0), Some((-10.0, -20.0)));
+        assert_eq!(surface_local(&scene, 1, 2, 11.5, 22.5), Some((0.5, 0.5)));
+        // Outside the surface, and under another one.
+        assert_eq!(surface_local(&scene, 1, 3, 0.0, 0.0), Some((-8.0, -26.0)));
+        assert_eq!(surface_local(&scene, 2, 3, 0.0, 0.0), None);
+        assert_eq!(surface_local(&scene, 1, 5, 0.0, 0.0), None);
+    }
+}
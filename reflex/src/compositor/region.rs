@@ -0,0 +1,305 @@
+use super::Rect;
+
+// A set of pixels, as wl_region describes them.
+//
+// The set is kept y-x banded, the way pixman and X11 regions are: a list of
+// horizontal bands from top to bottom, each with the spans it covers from
+// left to right. Bands do not overlap, spans within a band do not touch, and
+// vertically adjacent bands with the same spans are merged, so that equal
+// sets have equal representations.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Region {
+    bands: Vec<Band>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Band {
+    top: i32,
+    bottom: i32,
+    // Left inclusive, right exclusive.
+    spans: Vec<(i32, i32)>,
+}
+
+#[derive(Clone, Copy)]
+enum Op {
+    Union,
+    Subtract,
+    Intersect,
+}
+
+impl Op {
+    fn apply(self, a: bool, b: bool) -> bool {
+        match self {
+            Op::Union => a || b,
+            Op::Subtract => a && !b,
+            Op::Intersect => a && b,
+        }
+    }
+}
+
+impl Region {
+    pub fn new() -> Region {
+        Region::default()
+    }
+
+    // Extents past i32::MAX are cut off there, which leaves nothing of a
+    // rectangle that starts at i32::MAX.
+    pub fn from_rect(rect: Rect) -> Region {
+        let bottom = rect.y.saturating_add(rect.height);
+        let right = rect.x.saturating_add(rect.width);
+        if rect.is_empty() || bottom <= rect.y || right <= rect.x {
+            return Region::new();
+        }
+        Region {
+            bands: vec![Band {
+                top: rect.y,
+                bottom,
+                spans: vec![(rect.x, right)],
+            }],
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bands.is_empty()
+    }
+
+    pub fn union(&self, other: &Region) -> Region {
+        self.combine(other, Op::Union)
+    }
+
+    pub fn subtract(&self, other: &Region) -> Region {
+        self.combine(other, Op::Subtract)
+    }
+
+    pub fn intersect(&self, other: &Region) -> Region {
+        self.combine(other, Op::Intersect)
+    }
+
+    pub fn translate(&mut self, dx: i32, dy: i32) {
+        for band in &mut self.bands {
+            band.top = band.top.saturating_add(dy);
+            band.bottom = band.bottom.saturating_add(dy);
+            for span in &mut band.spans {
+                span.0 = span.0.saturating_add(dx);
+                span.1 = span.1.saturating_add(dx);
+            }
+        }
+        // Whatever was pushed against the limits of i32 collapsed; combining
+        // drops it and merges the bands again.
+        *self = self.union(&Region::new());
+    }
+
+    pub fn contains(&self, x: i32, y: i32) -> bool {
+        self.spans_at(y)
+            .iter()
+            .any(|&(left, right)| x >= left && x < right)
+    }
+
+    // The smallest rectangle around the region.
+    pub fn extents(&self) -> Rect {
+        let (first, last) = match (self.bands.first(), self.bands.last()) {
+            (Some(x), Some(y)) => (x, y),
+            _ => return Rect::default(),
+        };
+        let left = self.bands.iter().map(|x| x.spans[0].0).min().unwrap_or(0);
+        let right = self
+            .bands
+            .iter()
+            .map(|x| x.spans[x.spans.len() - 1].1)
+            .max()
+            .unwrap_or(0);
+        Rect::new(
+            left,
+            first.top,
+            right.saturating_sub(left),
+            last.bottom.saturating_sub(first.top),
+        )
+    }
+
+    // The region as rectangles, band by band, top to bottom and left to
+    // right.
+    pub fn rects(&self) -> Vec<Rect> {
+        let mut rects = Vec::new();
+        for band in &self.bands {
+            for &(left, right) in &band.spans {
+                rects.push(Rect::new(
+                    left,
+                    band.top,
+                    right.saturating_sub(left),
+                    band.bottom.saturating_sub(band.top),
+                ));
+            }
+        }
+        rects
+    }
+
+    // Sweeps both regions top to bottom, combining the spans of each stretch
+    // of rows in which neither of them changes.
+    fn combine(&self, other: &Region, op: Op) -> Region {
+        let mut edges: Vec<i32> = self
+            .bands
+            .iter()
+            .chain(&other.bands)
+            .flat_map(|x| vec![x.top, x.bottom])
+            .collect();
+        edges.sort();
+        edges.dedup();
+
+        let mut result = Region::new();
+        for pair in edges.windows(2) {
+            let (top, bottom) = (pair[0], pair[1]);
+            let spans = combine_spans(self.spans_at(top), other.spans_at(top), op);
+            if spans.is_empty() {
+                continue;
+            }
+            if let Some(last) = result.bands.last_mut() {
+                if last.bottom == top && last.spans == spans {
+                    last.bottom = bottom;
+                    continue;
+                }
+            }
+            result.bands.push(Band { top, bottom, spans });
+        }
+        result
+    }
+
+    fn spans_at(&self, y: i32) -> &[(i32, i32)] {
+        self.bands
+            .iter()
+            .find(|band| y >= band.top && y < band.bottom)
+            .map_or(&[], |band| &band.spans)
+    }
+}
+
+fn combine_spans(a: &[(i32, i32)], b: &[(i32, i32)], op: Op) -> Vec<(i32, i32)> {
+    let mut edges: Vec<i32> = a
+        .iter()
+        .chain(b)
+        .flat_map(|&(left, right)| vec![left, right])
+        .collect();
+    edges.sort();
+    edges.dedup();
+
+    let inside = |spans: &[(i32, i32)], x: i32| spans.iter().any(|&(l, r)| x >= l && x < r);
+    let mut spans: Vec<(i32, i32)> = Vec::new();
+    for pair in edges.windows(2) {
+        let (left, right) = (pair[0], pair[1]);
+        if !op.apply(inside(a, left), inside(b, left)) {
+            continue;
+        }
+        match spans.last_mut() {
+            Some(last) if last.1 == left => last.1 = right,
+            _ => spans.push((left, right)),
+        }
+    }
+    spans
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rect(x: i32, y: i32, width: i32, height: i32) -> Region {
+        Region::from_rect(Rect::new(x, y, width, height))
+    }
+
+    #[test]
+    fn union_of_overlapping_rects() {
+        let region = rect(0, 0, 4, 4).union(&rect(2, 2, 4, 4));
+        assert_eq!(
+            region.rects(),
+            vec![
+                Rect::new(0, 0, 4, 2),
+                Rect::new(0, 2, 6, 2),
+                Rect::new(2, 4, 4, 2),
+            ]
+        );
+        assert_eq!(region.extents(), Rect::new(0, 0, 6, 6));
+    }
+
+    #[test]
+    fn union_of_adjacent_rects() {
+        assert_eq!(rect(0, 0, 2, 2).union(&rect(2, 0, 2, 2)), rect(0, 0, 4, 2));
+        assert_eq!(rect(0, 0, 2, 2).union(&rect(0, 2, 2, 2)), rect(0, 0, 2, 4));
+        // Diagonal neighbours only touch at a corner.
+        assert_eq!(rect(0, 0, 2, 2).union(&rect(2, 2, 2, 2)).rects().len(), 2);
+    }
+
+    #[test]
+    fn subtract_a_hole() {
+        let region = rect(0, 0, 4, 4).subtract(&rect(1, 1, 2, 2));
+        assert_eq!(
+            region.rects(),
+            vec![
+                Rect::new(0, 0, 4, 1),
+                Rect::new(0, 1, 1, 2),
+                Rect::new(3, 1, 1, 2),
+                Rect::new(0, 3, 4, 1),
+            ]
+        );
+        assert!(!region.contains(1, 1));
+        assert!(!region.contains(2, 2));
+        assert!(region.contains(0, 1));
+        assert!(region.contains(3, 2));
+        // Filling the hole again gives the rectangle back.
+        assert_eq!(region.union(&rect(1, 1, 2, 2)), rect(0, 0, 4, 4));
+        assert!(rect(1, 1, 2, 2).subtract(&rect(0, 0, 4, 4)).is_empty());
+    }
+
+    #[test]
+    fn equal_sets_compare_equal() {
+        let rows = (0..4).fold(Region::new(), |region, y| region.union(&rect(0, y, 3, 1)));
+        assert_eq!(rows, rect(0, 0, 3, 4));
+        let columns = (0..3).fold(Region::new(), |region, x| region.union(&rect(x, 0, 1, 4)));
+        assert_eq!(columns, rows);
+        let a = rect(0, 0, 4, 4).union(&rect(2, 2, 4, 4));
+        let b = rect(2, 2, 4, 4).union(&rect(0, 0, 4, 4));
+        assert_eq!(a, b);
+        assert_eq!(a.intersect(&rect(0, 0, 4, 4)), rect(0, 0, 4, 4));
+    }
+
+    #[test]
+    fn translate_and_contains_at_the_edges() {
+        let mut region = rect(0, 0, 2, 3);
+        region.translate(5, -1);
+        assert_eq!(region, rect(5, -1, 2, 3));
+        assert!(region.contains(5, -1));
+        assert!(region.contains(6, 1));
+        assert!(!region.contains(4, 0));
+        assert!(!region.contains(7, 0));
+        assert!(!region.contains(5, -2));
+        assert!(!region.contains(5, 2));
+
+        // Pushed past the limits of i32, the region collapses.
+        let mut region = rect(0, 0, 2, 2);
+        region.translate(i32::MAX, 0);
+        assert!(region.is_empty());
+        let mut region = rect(0, -2, 2, 2);
+        region.translate(0, i32::MIN);
+        assert!(region.is_empty());
+        let mut region = rect(-1, 0, 2, 2);
+        region.translate(i32::MAX, 0);
+        assert_eq!(region, rect(i32::MAX - 1, 0, 1, 2));
+    }
+
+    #[test]
+    fn from_rect_saturates() {
+        let region = rect(i32::MAX - 1, 0, 10, 1);
+        assert_eq!(region.extents(), Rect::new(i32::MAX - 1, 0, 1, 1));
+        assert!(region.contains(i32::MAX - 1, 0));
+        assert!(rect(0, i32::MAX, 4, 5).is_empty());
+        assert!(rect(i32::MAX, 0, 1, 1).is_empty());
+        assert!(rect(0, 0, 0, 5).is_empty());
+        assert!(rect(0, 0, 5, -1).is_empty());
+        assert_eq!(
+            rect(i32::MIN, i32::MIN, i32::MAX, 1).extents(),
+            Rect::new(i32::MIN, i32::MIN, i32::MAX, 1)
+        );
+        // Spans that together are wider than an i32 can hold.
+        let region = rect(i32::MIN, 0, i32::MAX, 1).union(&rect(-1, 0, 10, 1));
+        assert_eq!(region.extents(), Rect::new(i32::MIN, 0, i32::MAX, 1));
+        assert_eq!(region.rects(), vec![Rect::new(i32::MIN, 0, i32::MAX, 1)]);
+        let region = rect(0, i32::MIN, 1, i32::MAX).union(&rect(0, -1, 1, 10));
+        assert_eq!(region.extents(), Rect::new(0, i32::MIN, 1, i32::MAX));
+    }
+}
@@ -0,0 +1,598 @@
+use crate::compositor::MAX_OUTPUT_SIZE;
+use crate::lock::RwLockExt;
+use crate::protocol::quota::Quotas;
+use crate::protocol::wayland::wl_registry::WlRegistry;
+use futures::future::Future;
+use futures::stream::Stream;
+use serde::Deserialize;
+use std::fmt;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use tokio_signal::unix::{Signal, SIGHUP};
+
+// reflex.toml
+//
+// The file is looked up in this order: the path given with --config, the
+// REFLEX_CONFIG environment variable, $XDG_CONFIG_HOME/reflex/reflex.toml
+// (or ~/.config/reflex/reflex.toml). Without any of them the built-in
+// defaults are used. Every key is optional.
+//
+//   [server]
+//   socket = "/tmp/temp.unix"
+//   admin_socket = "/tmp/temp.unix.admin"
+//   lock_file = "/tmp/temp.unix.lock"
+//
+//   [backend]
+//   kind = "vision"                   # or "headless", "vnc", "browser"
+//   address = "/mnt/c/Temp/reflex.unix"
+//   clock = "realtime"                # or "manual": headless frame clock
+//                                     # moves on advance() only
+//   listen = "127.0.0.1:5900"         # where vnc or browser viewers connect;
+//                                     # 127.0.0.1:8080 for browser
+//
+//   [log]
+//   filter = "info"                   # REFLEX_LOG syntax
+//
+//   [[output]]
+//   name = "default"
+//   x = 0
+//   y = 0
+//   width = 1920
+//   height = 1080
+//   scale = 1
+//   refresh = 60000                   # mHz, paces frame callbacks
+//
+//   [seat]
+//   pointer = true
+//   keyboard = true
+//   touch = false
+//
+//   [keyboard]
+//   layout = "us"
+//   repeat_rate = 25                  # keys per second, 0 disables repeat
+//   repeat_delay = 600                # ms
+//
+//   [shm]
+//   formats = ["argb8888", "xrgb8888"]
+//
+//   [protocols]
+//   wl_data_device_manager = true
+//   wl_seat = true
+//   wl_subcompositor = true
+//   xdg_wm_base = true
+//
+//   [quotas]
+//   max_objects = 65536
+//   max_shm_bytes = 1073741824
+//   max_pending_events = 48000
+//   max_requests_per_second = 100000
+//
+// Outputs, seat, keyboard and shm describe what the backend presents to
+// clients. The headless, vnc and browser backends advertise the shm formats
+// and seat capabilities and compile the keymap from the keyboard layout.
+// vision takes all of them from the Windows host, so with vision only the
+// refresh rate of a single output may be set. Frame callbacks run at each
+// output's refresh rate with every backend.
+//
+// On SIGHUP the file is read again. The vision address, protocols and
+// quotas apply to clients that connect afterwards; the other sections need a
+// restart.
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub server: ServerConfig,
+    pub backend: BackendConfig,
+    pub log: LogConfig,
+    #[serde(rename = "output")]
+    pub outputs: Vec<OutputConfig>,
+    pub seat: SeatConfig,
+    pub keyboard: KeyboardConfig,
+    pub shm: ShmConfig,
+    pub protocols: ProtocolsConfig,
+    pub quotas: Quotas,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            server: ServerConfig::default(),
+            backend: BackendConfig::default(),
+            log: LogConfig::default(),
+            outputs: vec![OutputConfig::default()],
+            seat: SeatConfig::default(),
+            keyboard: KeyboardConfig::default(),
+            shm: ShmConfig::default(),
+            protocols: ProtocolsConfig::default(),
+            quotas: Quotas::default(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub socket: String,
+    pub admin_socket: String,
+    pub lock_file: String,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            socket: "/tmp/temp.unix".to_string(),
+            admin_socket: "/tmp/temp.unix.admin".to_string(),
+            lock_file: "/tmp/temp.unix.lock".to_string(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct BackendConfig {
+    pub kind: String,
+    pub address: String,
+    pub clock: String,
+    pub listen: Option<String>,
+}
+
+impl Default for BackendConfig {
+    fn default() -> Self {
+        BackendConfig {
+            kind: "vision".to_string(),
+            address: "/mnt/c/Temp/reflex.unix".to_string(),
+            clock: "realtime".to_string(),
+            listen: None,
+        }
+    }
+}
+
+const BACKENDS: &[&str] = &["vision", "headless", "vnc", "browser"];
+
+impl BackendConfig {
+    // The address a network backend listens on, by default its usual port
+    // on loopback.
+    pub fn listen_address(&self) -> String {
+        match &self.listen {
+            Some(x) => x.clone(),
+            None if self.kind == "browser" => "127.0.0.1:8080".to_string(),
+            None => "127.0.0.1:5900".to_string(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct LogConfig {
+    pub filter: String,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        LogConfig {
+            filter: "info".to_string(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct OutputConfig {
+    pub name: String,
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+    pub scale: i32,
+    pub refresh: i32,
+}
+
+impl Default for OutputConfig {
+    fn default() -> Self {
+        OutputConfig {
+            name: "default".to_string(),
+            x: 0,
+            y: 0,
+            width: 1920,
+            height: 1080,
+            scale: 1,
+            refresh: 60000,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct SeatConfig {
+    pub pointer: bool,
+    pub keyboard: bool,
+    pub touch: bool,
+}
+
+impl Default for SeatConfig {
+    fn default() -> Self {
+        SeatConfig {
+            pointer: true,
+            keyboard: true,
+            touch: false,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct KeyboardConfig {
+    pub layout: String,
+    pub repeat_rate: i32,
+    pub repeat_delay: i32,
+}
+
+impl Default for KeyboardConfig {
+    fn default() -> Self {
+        KeyboardConfig {
+            layout: "us".to_string(),
+            repeat_rate: 25,
+            repeat_delay: 600,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct ShmConfig {
+    pub formats: Vec<String>,
+}
+
+impl Default for ShmConfig {
+    fn default() -> Self {
+        ShmConfig {
+            formats: vec!["argb8888".to_string(), "xrgb8888".to_string()],
+        }
+    }
+}
+
+// wl_shm.format codes of the formats that can be enabled, with their
+// bytes per pixel.
+const SHM_FORMATS: &[(&str, u32, i32)] = &[
+    ("argb8888", 0, 4),
+    ("xrgb8888", 1, 4),
+    ("abgr8888", 0x3432_4241, 4),
+    ("xbgr8888", 0x3432_4258, 4),
+    ("rgb565", 0x3631_4752, 2),
+];
+
+// Bytes per pixel of a format that can be enabled.
+pub fn shm_bytes_per_pixel(format: u32) -> Option<i32> {
+    SHM_FORMATS
+        .iter()
+        .find(|(_, code, _)| *code == format)
+        .map(|(_, _, bytes)| *bytes)
+}
+
+impl ShmConfig {
+    pub fn format_codes(&self) -> Vec<u32> {
+        self.formats
+            .iter()
+            .filter_map(|name| {
+                SHM_FORMATS
+                    .iter()
+                    .find(|(x, _, _)| x == name)
+                    .map(|(_, code, _)| *code)
+            })
+            .collect()
+    }
+}
+
+// Optional globals. The core ones (wl_display, wl_registry, wl_compositor
+// and wl_shm) are always there.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProtocolsConfig {
+    pub wl_data_device_manager: bool,
+    pub wl_seat: bool,
+    pub wl_subcompositor: bool,
+    pub xdg_wm_base: bool,
+}
+
+impl Default for ProtocolsConfig {
+    fn default() -> Self {
+        ProtocolsConfig {
+            wl_data_device_manager: true,
+            wl_seat: true,
+            wl_subcompositor: true,
+            xdg_wm_base: true,
+        }
+    }
+}
+
+impl ProtocolsConfig {
+    // Global names of the disabled globals.
+    pub fn disabled_globals(&self) -> Vec<u32> {
+        let mut names = Vec::new();
+        if !self.wl_data_device_manager {
+            names.push(crate::protocol::wayland::wl_data_device_manager::GLOBAL_SINGLETON_NAME);
+        }
+        if !self.wl_seat {
+            names.push(crate::protocol::wayland::wl_seat::GLOBAL_NAME);
+        }
+        if !self.wl_subcompositor {
+            names.push(crate::protocol::wayland::wl_subcompositor::GLOBAL_NAME);
+        }
+        if !self.xdg_wm_base {
+            names.push(crate::protocol::xdg_shell::xdg_wm_base::GLOBAL_SINGLETON_NAME);
+        }
+        names
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(PathBuf, std::io::Error),
+    Parse(PathBuf, toml::de::Error),
+    Invalid(Option<PathBuf>, String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::Io(path, err) => write!(f, "{}: {}", path.display(), err),
+            ConfigError::Parse(path, err) => write!(f, "{}: {}", path.display(), err),
+            ConfigError::Invalid(Some(path), message) => {
+                write!(f, "{}: {}", path.display(), message)
+            }
+            ConfigError::Invalid(None, message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl Config {
+    // The config file to use, if any.
+    pub fn find(args: &[String]) -> Option<PathBuf> {
+        if let Some(i) = args.iter().position(|arg| arg == "--config") {
+            return args.get(i + 1).map(PathBuf::from);
+        }
+        if let Ok(path) = std::env::var("REFLEX_CONFIG") {
+            return Some(PathBuf::from(path));
+        }
+        let dir = std::env::var("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|_| std::env::var("HOME").map(|home| Path::new(&home).join(".config")))
+            .ok()?;
+        let path = dir.join("reflex").join("reflex.toml");
+        if path.exists() {
+            Some(path)
+        } else {
+            None
+        }
+    }
+
+    pub fn load(path: Option<&Path>) -> Result<Config, ConfigError> {
+        let config = match path {
+            Some(path) => {
+                let text = std::fs::read_to_string(path)
+                    .map_err(|err| ConfigError::Io(path.to_path_buf(), err))?;
+                toml::from_str(&text).map_err(|err| ConfigError::Parse(path.to_path_buf(), err))?
+            }
+            None => Config::default(),
+        };
+        config
+            .validate()
+            .map_err(|message| ConfigError::Invalid(path.map(Path::to_path_buf), message))?;
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        for (key, path) in &[
+            ("server.socket", &self.server.socket),
+            ("server.admin_socket", &self.server.admin_socket),
+            ("server.lock_file", &self.server.lock_file),
+            ("backend.address", &self.backend.address),
+        ] {
+            if path.is_empty() {
+                return Err(format!("{} must not be empty", key));
+            }
+        }
+        if !BACKENDS.contains(&self.backend.kind.as_str()) {
+            return Err(format!(
+                "backend.kind: unknown backend {:?}, expected one of {}",
+                self.backend.kind,
+                BACKENDS.join(", ")
+            ));
+        }
+        if let Err(err) = self.backend.listen_address().parse::<SocketAddr>() {
+            return Err(format!(
+                "backend.listen: {:?} is not an address and port: {}",
+                self.backend.listen_address(),
+                err
+            ));
+        }
+        if self.backend.clock != "realtime" && self.backend.clock != "manual" {
+            return Err(format!(
+                "backend.clock: unknown clock {:?}, expected realtime or manual",
+                self.backend.clock
+            ));
+        }
+        if self.server.socket == self.server.admin_socket {
+            return Err("server.socket and server.admin_socket must differ".to_string());
+        }
+
+        if self.outputs.is_empty() {
+            return Err("at least one [[output]] is required".to_string());
+        }
+        for (i, output) in self.outputs.iter().enumerate() {
+            let at = format!("output[{}] {:?}", i, output.name);
+            if output.width <= 0 || output.height <= 0 {
+                return Err(format!("{}: width and height must be positive", at));
+            }
+            if output.width > MAX_OUTPUT_SIZE || output.height > MAX_OUTPUT_SIZE {
+                return Err(format!(
+                    "{}: width and height must be at most {}",
+                    at, MAX_OUTPUT_SIZE
+                ));
+            }
+            if output.scale < 1 || output.scale > 4 {
+                return Err(format!("{}: scale must be between 1 and 4", at));
+            }
+            if output.refresh <= 0 {
+                return Err(format!("{}: refresh must be positive (mHz)", at));
+            }
+            if self.outputs[..i].iter().any(|x| x.name == output.name) {
+                return Err(format!("{}: duplicate output name", at));
+            }
+        }
+
+        if self.backend.kind == "vision" {
+            self.validate_vision()?;
+        }
+
+        if self.keyboard.layout.is_empty() {
+            return Err("keyboard.layout must not be empty".to_string());
+        }
+        if self.keyboard.repeat_rate < 0 || self.keyboard.repeat_rate > 1000 {
+            return Err("keyboard.repeat_rate must be between 0 and 1000".to_string());
+        }
+        if self.keyboard.repeat_delay < 0 {
+            return Err("keyboard.repeat_delay must not be negative".to_string());
+        }
+
+        for name in &self.shm.formats {
+            if !SHM_FORMATS.iter().any(|(x, _, _)| x == name) {
+                let known: Vec<&str> = SHM_FORMATS.iter().map(|(x, _, _)| *x).collect();
+                return Err(format!(
+                    "shm.formats: unknown format {:?}, expected one of {}",
+                    name,
+                    known.join(", ")
+                ));
+            }
+        }
+        for required in &["argb8888", "xrgb8888"] {
+            if !self.shm.formats.iter().any(|x| x == required) {
+                return Err(format!("shm.formats: {} is mandatory", required));
+            }
+        }
+
+        if self.quotas.max_objects == 0
+            || self.quotas.max_pending_events == 0
+            || self.quotas.max_requests_per_second == 0
+        {
+            return Err("quotas must be positive".to_string());
+        }
+        Ok(())
+    }
+
+    // Settings that vision would ignore are refused, rather than leaving
+    // the user to wonder why they have no effect.
+    fn validate_vision(&self) -> Result<(), String> {
+        let default = OutputConfig::default();
+        if self.outputs.len() > 1 {
+            return Err("vision takes its outputs from the Windows host: \
+                        only one [[output]] may be given"
+                .to_string());
+        }
+        for output in &self.outputs {
+            if (
+                output.x,
+                output.y,
+                output.width,
+                output.height,
+                output.scale,
+            ) != (
+                default.x,
+                default.y,
+                default.width,
+                default.height,
+                default.scale,
+            ) {
+                return Err(format!(
+                    "output[0] {:?}: vision takes the output layout from the Windows host, \
+                     only refresh may be set",
+                    output.name
+                ));
+            }
+        }
+        for (section, is_default) in &[
+            ("seat", self.seat == SeatConfig::default()),
+            ("keyboard", self.keyboard == KeyboardConfig::default()),
+            ("shm", self.shm == ShmConfig::default()),
+        ] {
+            if !is_default {
+                return Err(format!(
+                    "[{}] is not used by vision, which takes it from the Windows host",
+                    section
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    // Applies the parts of a reloaded config that are safe to change while
+    // running, and returns the sections that were left alone.
+    pub fn reload(&mut self, new: Config) -> Vec<&'static str> {
+        let mut needs_restart = Vec::new();
+        if new.server != self.server {
+            needs_restart.push("server");
+        }
+        if new.log != self.log {
+            needs_restart.push("log");
+        }
+        if new.outputs != self.outputs {
+            needs_restart.push("output");
+        }
+        if new.seat != self.seat {
+            needs_restart.push("seat");
+        }
+        if new.keyboard != self.keyboard {
+            needs_restart.push("keyboard");
+        }
+        if new.shm != self.shm {
+            needs_restart.push("shm");
+        }
+        if new.backend.kind != self.backend.kind
+            || new.backend.clock != self.backend.clock
+            || new.backend.listen != self.backend.listen
+        {
+            needs_restart.push("backend");
+        }
+        self.backend.address = new.backend.address;
+        self.protocols = new.protocols;
+        self.quotas = new.quotas;
+        needs_restart
+    }
+}
+
+// Reloads the config file on every SIGHUP. A file that fails to load or
+// validate is reported and the running config is kept.
+pub fn reload_on_hangup(
+    path: Option<PathBuf>,
+    config: Arc<RwLock<Config>>,
+    wl_registry: Arc<RwLock<WlRegistry>>,
+) -> Box<dyn Future<Item = (), Error = ()> + Send> {
+    Box::new(
+        Signal::new(SIGHUP)
+            .flatten_stream()
+            .map_err(|err| log::error!("signal handler failed: {:?}", err))
+            .for_each(move |_| {
+                let new = match Config::load(path.as_deref()) {
+                    Ok(x) => x,
+                    Err(err) => {
+                        log::error!("config not reloaded: {}", err);
+                        return Ok(());
+                    }
+                };
+                let mut config = config.write_locked();
+                let needs_restart = config.reload(new);
+                wl_registry.write_locked().disabled_globals = config.protocols.disabled_globals();
+                log::info!("config reloaded");
+                if !needs_restart.is_empty() {
+                    log::warn!(
+                        "changes to [{}] take effect after a restart",
+                        needs_restart.join("], [")
+                    );
+                }
+                Ok(())
+            }),
+    )
+}
@@ -0,0 +1,217 @@
+use crate::backend::{Backend, ClientEvents};
+use crate::config::Config;
+use crate::lock::{MutexExt, RwLockExt};
+use crate::protocol::wayland::{wl_callback, wl_display};
+use futures::stream::Stream;
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::{Arc, Mutex, RwLock, Weak};
+use std::time::{Duration, Instant};
+
+// Frame callbacks.
+//
+// wl_surface.frame callbacks are queued per surface when the surface commits
+// and done on the next refresh of the output the backend puts the surface
+// on, with the time of that refresh in milliseconds. Outputs refresh at
+// their configured rate, unless the backend drives the clock with vblank().
+//
+// Surfaces the backend reports as hidden, minimized or occluded are
+// throttled: their callbacks are done once a second instead, so that they
+// keep going without drawing for nobody at the full rate.
+
+// How long a throttled surface waits for its callbacks.
+const THROTTLED_INTERVAL_MS: u32 = 1000;
+
+#[derive(Clone)]
+pub struct FrameClock {
+    config: Arc<RwLock<Config>>,
+    inner: Arc<Mutex<Inner>>,
+}
+
+struct Inner {
+    epoch: Instant,
+    backend: Option<Weak<dyn Backend>>,
+    // Whether the backend sends vblanks. No timers run then.
+    driven: bool,
+    // Outputs whose timer is running.
+    ticking: BTreeSet<usize>,
+    // The time of the last vblank of any output.
+    last_vblank: u32,
+    clients: BTreeMap<u32, ClientEvents>,
+    // Committed callbacks by client and surface id.
+    surfaces: BTreeMap<(u32, u32), Pending>,
+}
+
+struct Pending {
+    // In the order they were committed.
+    callbacks: Vec<u32>,
+    // When the first of them was queued.
+    since: u32,
+}
+
+impl FrameClock {
+    pub fn new(config: Arc<RwLock<Config>>) -> FrameClock {
+        FrameClock {
+            config,
+            inner: Arc::new(Mutex::new(Inner {
+                epoch: Instant::now(),
+                backend: None,
+                driven: false,
+                ticking: BTreeSet::new(),
+                last_vblank: 0,
+                clients: BTreeMap::new(),
+                surfaces: BTreeMap::new(),
+            })),
+        }
+    }
+
+    // The backend that tells where surfaces are shown, and may drive the
+    // clock itself.
+    pub fn set_backend(&self, backend: &Arc<dyn Backend>) {
+        {
+            let mut inner = self.inner.locked();
+            inner.backend = Some(Arc::downgrade(backend));
+            inner.driven = false;
+        }
+        let driven = backend.drive_frame_clock(self);
+        self.inner.locked().driven = driven;
+    }
+
+    pub fn add_client(&self, client_id: u32, events: ClientEvents) {
+        self.inner.locked().clients.insert(client_id, events);
+    }
+
+    // Forgets a client that has disconnected, with its callbacks.
+    pub fn remove_client(&self, client_id: u32) {
+        let mut inner = self.inner.locked();
+        inner.clients.remove(&client_id);
+        inner.surfaces.retain(|(x, _), _| *x != client_id);
+    }
+
+    // Queues the callbacks of a wl_surface.commit.
+    pub fn queue(&self, client_id: u32, surface_id: u32, callbacks: Vec<u32>) {
+        if callbacks.is_empty() {
+            return;
+        }
+        let mut inner = self.inner.locked();
+        let since = inner.now();
+        inner
+            .surfaces
+            .entry((client_id, surface_id))
+            .or_insert_with(|| Pending {
+                callbacks: Vec::new(),
+                since,
+            })
+            .callbacks
+            .extend(callbacks);
+        if !inner.driven {
+            self.start_timers(&mut inner);
+        }
+    }
+
+    // Drops the callbacks of a destroyed surface without doing them, along
+    // with the ones it had not committed yet.
+    pub fn surface_destroyed(&self, client_id: u32, surface_id: u32, uncommitted: &[u32]) {
+        let mut inner = self.inner.locked();
+        let mut callbacks = inner
+            .surfaces
+            .remove(&(client_id, surface_id))
+            .map(|x| x.callbacks)
+            .unwrap_or_default();
+        callbacks.extend_from_slice(uncommitted);
+        if let Some(events) = inner.clients.get_mut(&client_id) {
+            for callback in callbacks {
+                events.send(Box::new(wl_display::events::DeleteId {
+                    sender_object_id: 1,
+                    id: callback,
+                }));
+            }
+        }
+    }
+
+    // An output refreshed at time, in milliseconds. Only for backends that
+    // drive the clock.
+    pub fn vblank(&self, output: usize, time: u32) {
+        self.inner.locked().last_vblank = time;
+        self.refresh(output, time);
+    }
+
+    fn refresh(&self, output: usize, time: u32) {
+        let mut inner = self.inner.locked();
+        let backend = inner.backend.as_ref().and_then(|x| x.upgrade());
+        let mut done = Vec::new();
+        for (&(client_id, surface_id), pending) in &inner.surfaces {
+            let due = match &backend {
+                Some(backend) => match backend.surface_output(client_id, surface_id) {
+                    Some(x) => x == output,
+                    None => time.wrapping_sub(pending.since) >= THROTTLED_INTERVAL_MS,
+                },
+                None => true,
+            };
+            if due {
+                done.push((client_id, surface_id));
+            }
+        }
+        for key in done {
+            let pending = match inner.surfaces.remove(&key) {
+                Some(x) => x,
+                None => continue,
+            };
+            let events = match inner.clients.get_mut(&key.0) {
+                Some(x) => x,
+                None => continue,
+            };
+            for callback in pending.callbacks {
+                events.send(Box::new(wl_callback::events::Done {
+                    sender_object_id: callback,
+                    callback_data: time,
+                }));
+                events.send(Box::new(wl_display::events::DeleteId {
+                    sender_object_id: 1,
+                    id: callback,
+                }));
+            }
+        }
+    }
+
+    // Every output ticks at its refresh rate while callbacks are queued, so
+    // that nothing runs once they are all done.
+    fn start_timers(&self, inner: &mut Inner) {
+        let config = self.config.read_locked();
+        for (output, x) in config.outputs.iter().enumerate() {
+            if !inner.ticking.insert(output) {
+                continue;
+            }
+            let interval = Duration::from_micros(1_000_000_000 / x.refresh.max(1) as u64);
+            let clock = self.clone();
+            let tick_clock = self.clone();
+            tokio::spawn(
+                tokio::timer::Interval::new(Instant::now() + interval, interval)
+                    .map_err(|err| log::error!("frame clock failed: {}", err))
+                    .take_while(move |_| {
+                        let mut inner = clock.inner.locked();
+                        let keep = !inner.surfaces.is_empty() && !inner.driven;
+                        if !keep {
+                            inner.ticking.remove(&output);
+                        }
+                        Ok(keep)
+                    })
+                    .for_each(move |_| {
+                        let time = tick_clock.inner.locked().now();
+                        tick_clock.refresh(output, time);
+                        Ok(())
+                    }),
+            );
+        }
+    }
+}
+
+impl Inner {
+    // Milliseconds on the clock of the refreshes.
+    fn now(&self) -> u32 {
+        if self.driven {
+            self.last_vblank
+        } else {
+            self.epoch.elapsed().as_millis() as u32
+        }
+    }
+}
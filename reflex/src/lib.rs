@@ -0,0 +1,21 @@
+// The reflex protocol core as a library.
+//
+// The ultimate-desktop-reflex binary is a thin wrapper around Server. Tools
+// and test harnesses can link this crate to run a server of their own, or
+// use the protocol module directly to read requests and write events.
+
+pub mod admin;
+pub mod backend;
+pub mod compositor;
+pub mod config;
+pub mod frame_clock;
+pub mod lock;
+pub mod protocol;
+pub mod recording;
+pub mod screenshot;
+pub mod server;
+pub mod shutdown;
+
+pub use backend::{Backend, BackendSession};
+pub use config::Config;
+pub use server::{Globals, Server, ServerError};
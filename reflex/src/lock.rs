@@ -0,0 +1,58 @@
+use std::sync::{Mutex, MutexGuard, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+// Locks on state shared by all clients.
+//
+// A panic in a client's handler is caught and only disconnects that client,
+// but it poisons any lock the handler held. The other clients keep using
+// the state behind it, so a poisoned lock is taken over instead of making
+// every later user panic as well.
+
+pub trait MutexExt<T> {
+    fn locked(&self) -> MutexGuard<'_, T>;
+}
+
+impl<T> MutexExt<T> for Mutex<T> {
+    fn locked(&self) -> MutexGuard<'_, T> {
+        self.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+pub trait RwLockExt<T> {
+    fn read_locked(&self) -> RwLockReadGuard<'_, T>;
+    fn write_locked(&self) -> RwLockWriteGuard<'_, T>;
+}
+
+impl<T> RwLockExt<T> for RwLock<T> {
+    fn read_locked(&self) -> RwLockReadGuard<'_, T> {
+        self.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn write_locked(&self) -> RwLockWriteGuard<'_, T> {
+        self.write().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[test]
+    fn poisoned_locks_are_taken_over() {
+        let mutex = Arc::new(Mutex::new(1));
+        let rw_lock = Arc::new(RwLock::new(1));
+        let (m, r) = (mutex.clone(), rw_lock.clone());
+        let _ = std::thread::spawn(move || {
+            let _m = m.lock().unwrap();
+            let _r = r.write().unwrap();
+            panic!("handler panic");
+        })
+        .join();
+        assert!(mutex.is_poisoned());
+        assert!(rw_lock.is_poisoned());
+        *mutex.locked() += 1;
+        *rw_lock.write_locked() += 1;
+        assert_eq!(*mutex.locked(), 2);
+        assert_eq!(*rw_lock.read_locked(), 2);
+    }
+}
@@ -1,11 +1,15 @@
 pub mod connection_stream;
+pub mod error;
 pub mod event;
 pub mod event_sink;
 pub mod fd_drop;
+pub mod interface;
+pub mod quota;
 pub mod raw_event;
 pub mod request;
 pub mod request_stream;
 pub mod resource;
 pub mod session;
+pub mod trace;
 pub mod wayland;
 pub mod xdg_shell;
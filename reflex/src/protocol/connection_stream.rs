@@ -1,21 +1,42 @@
 use futures::stream::Stream;
+use futures::Future;
+use nix::errno::Errno;
 use nix::sys::socket::*;
 use std::fs;
 use std::os::unix::fs::PermissionsExt;
 use std::os::unix::io::RawFd;
+use std::time::{Duration, Instant};
 use tokio::prelude::Async;
+use tokio::timer::Delay;
+
+// How long accepting waits when the process or the system is out of file
+// descriptors or memory. The client stays in the backlog meanwhile.
+const ACCEPT_RETRY: Duration = Duration::from_millis(100);
+
+// Process and user on the other end of a client socket, taken with
+// SO_PEERCRED when the client is accepted.
+#[derive(Clone, Copy, Debug)]
+pub struct Credentials {
+    pub pid: i32,
+    pub uid: u32,
+    pub gid: u32,
+}
+
+pub struct Connection {
+    pub fd: RawFd,
+    pub credentials: Credentials,
+}
 
 pub struct ConnectionStream {
     fd: RawFd,
     tokio_registration: tokio::reactor::Registration,
+    retry: Option<Delay>,
 }
 
 impl ConnectionStream {
     pub fn bind(path: String) -> ConnectionStream {
         let unix_addr = UnixAddr::new(path.as_bytes()).unwrap();
-        println!("unix_addr={:?}", unix_addr);
         let sock_addr = SockAddr::Unix(unix_addr);
-        println!("sa={:?} f={:?}", sock_addr, sock_addr.family());
 
         let fd = socket(
             AddressFamily::Unix,
@@ -40,49 +61,80 @@ impl ConnectionStream {
         ConnectionStream {
             fd,
             tokio_registration,
+            retry: None,
         }
     }
 }
 
+impl Drop for ConnectionStream {
+    fn drop(&mut self) {
+        let _ = nix::unistd::close(self.fd);
+    }
+}
+
 impl Stream for ConnectionStream {
-    type Item = RawFd;
+    type Item = Connection;
     type Error = std::io::Error;
 
     fn poll(&mut self) -> Result<Async<Option<Self::Item>>, Self::Error> {
-        println!("[Connection] poll");
-
-        match self.tokio_registration.poll_read_ready() {
-            Ok(Async::Ready(ready)) if ready.is_readable() => {
-                println!("[Connection] read ready");
-                ()
-            }
-            Ok(Async::Ready(_)) => {
-                println!("[Connection] read ready ERROR");
-                //return Ok(Async::NotReady);
-            }
-            Ok(Async::NotReady) => {
-                println!("[Connection] read not ready");
-                //return Ok(Async::NotReady);
-            }
-            Err(err) => {
-                println!("[Connection] err {:?}", err);
-                return Err(std::io::Error::new(std::io::ErrorKind::Other, err));
-            }
+        if let Err(err) = self.tokio_registration.poll_read_ready() {
+            log::error!("poll_read_ready failed: {:?}", err);
+            return Err(std::io::Error::new(std::io::ErrorKind::Other, err));
         }
 
-        match accept(self.fd) {
-            Ok(client_fd) => {
-                println!("[Connection] ready");
-                return Ok(Async::Ready(Some(client_fd)));
-            }
-            Err(nix::Error::Sys(nix::errno::Errno::EAGAIN)) => {
-                println!("[Connection] EAGAIN not ready");
-                return Ok(Async::NotReady);
+        loop {
+            if let Some(retry) = &mut self.retry {
+                match retry.poll() {
+                    Ok(Async::NotReady) => return Ok(Async::NotReady),
+                    _ => self.retry = None,
+                }
             }
-            Err(err) => {
-                println!("[Connection] err2: {:?}", err);
-                return Err(std::io::Error::new(std::io::ErrorKind::Other, err));
+            // Client sockets must not block: RequestStream and EventSink run
+            // on the shared runtime threads.
+            let client_fd = match accept4(self.fd, SockFlag::SOCK_NONBLOCK | SockFlag::SOCK_CLOEXEC)
+            {
+                Ok(x) => x,
+                Err(nix::Error::Sys(Errno::EAGAIN)) => {
+                    return Ok(Async::NotReady);
+                }
+                // The client went away before it was accepted.
+                Err(nix::Error::Sys(Errno::ECONNABORTED)) | Err(nix::Error::Sys(Errno::EINTR)) => {
+                    continue;
+                }
+                // Only the listening socket failing ends the stream: running
+                // short of resources, for instance because of clients at
+                // their quotas, must not take down the others.
+                Err(nix::Error::Sys(errno @ Errno::EMFILE))
+                | Err(nix::Error::Sys(errno @ Errno::ENFILE))
+                | Err(nix::Error::Sys(errno @ Errno::ENOBUFS))
+                | Err(nix::Error::Sys(errno @ Errno::ENOMEM)) => {
+                    log::warn!("accept failed, retrying in {:?}: {}", ACCEPT_RETRY, errno);
+                    self.retry = Some(Delay::new(Instant::now() + ACCEPT_RETRY));
+                    continue;
+                }
+                Err(err) => {
+                    log::error!("accept failed: {:?}", err);
+                    return Err(std::io::Error::new(std::io::ErrorKind::Other, err));
+                }
+            };
+            match getsockopt(client_fd, sockopt::PeerCredentials) {
+                Ok(cred) => {
+                    return Ok(Async::Ready(Some(Connection {
+                        fd: client_fd,
+                        credentials: Credentials {
+                            pid: cred.pid(),
+                            uid: cred.uid(),
+                            gid: cred.gid(),
+                        },
+                    })));
+                }
+                Err(err) => {
+                    // Without credentials the client cannot be told apart
+                    // from anyone else, so refuse it.
+                    log::error!("SO_PEERCRED failed: {:?}", err);
+                    let _ = nix::unistd::close(client_fd);
+                }
             }
-        };
+        }
     }
 }
@@ -0,0 +1,60 @@
+use std::fmt;
+
+// Why a client's session ended. Handlers still fail with (), after they have
+// reported the problem to the client themselves; that case is Aborted.
+#[derive(Debug)]
+pub enum ClientError {
+    Io(std::io::Error),
+    Backend(std::io::Error),
+    MalformedMessage(String),
+    Quota(String),
+    Killed(&'static str),
+    Shutdown,
+    Panic {
+        message: String,
+        last_request: Option<String>,
+    },
+    Aborted,
+}
+
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ClientError::Io(err) => write!(f, "client socket error: {}", err),
+            ClientError::Backend(err) => write!(f, "backend connection error: {}", err),
+            ClientError::MalformedMessage(message) => write!(f, "malformed message: {}", message),
+            ClientError::Quota(message) => write!(f, "quota exceeded: {}", message),
+            ClientError::Killed(reason) => write!(f, "killed: {}", reason),
+            ClientError::Shutdown => write!(f, "compositor is shutting down"),
+            ClientError::Panic {
+                message,
+                last_request,
+            } => write!(
+                f,
+                "panic: {} (last request: {})",
+                message,
+                last_request.as_deref().unwrap_or("none")
+            ),
+            ClientError::Aborted => write!(f, "aborted by a request handler"),
+        }
+    }
+}
+
+impl std::error::Error for ClientError {}
+
+impl From<std::io::Error> for ClientError {
+    fn from(err: std::io::Error) -> Self {
+        ClientError::Io(err)
+    }
+}
+
+// Extracts the message of a caught panic.
+pub fn panic_message(payload: &(dyn std::any::Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown panic payload".to_string()
+    }
+}
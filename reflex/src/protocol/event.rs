@@ -1,5 +1,13 @@
 use bytes::BytesMut;
+use std::os::unix::io::RawFd;
 
 pub trait Event {
     fn encode(&self, dst: &mut BytesMut) -> Result<(), std::io::Error>;
+
+    // File descriptors sent along with the message, like the keymap of
+    // wl_keyboard.keymap. They stay owned by the event's creator, and the
+    // client gets duplicates.
+    fn fds(&self) -> Vec<RawFd> {
+        Vec::new()
+    }
 }
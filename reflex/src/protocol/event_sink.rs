@@ -1,24 +1,65 @@
+use crate::lock::MutexExt;
 use crate::protocol::event::Event;
 use crate::protocol::fd_drop::FdDrop;
+use crate::protocol::trace::Tracer;
 use bytes::BytesMut;
 use futures::sink::Sink;
-use futures::AsyncSink;
-use nix::fcntl::fcntl;
-use nix::fcntl::FcntlArg::F_GETFD;
+use futures::task::AtomicTask;
+use futures::{AsyncSink, Future};
 use nix::sys::socket::*;
+use nix::sys::uio::IoVec;
 use std::os::unix::io::RawFd;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use tokio::prelude::Async;
+use tokio::timer::Delay;
+
+// How long a client that is being dropped gets to read its
+// wl_display.error before the socket is closed anyway.
+const FATAL_ERROR_TIMEOUT: Duration = Duration::from_secs(1);
+
+// A wl_display.error that replaces the events still queued for a client,
+// after which the client is disconnected. It is used when the queue itself
+// is full, so the error cannot wait in line.
+#[derive(Clone, Default)]
+pub struct FatalError {
+    error: Arc<Mutex<Option<Box<dyn Event + Send>>>>,
+    task: Arc<AtomicTask>,
+}
+
+impl FatalError {
+    pub fn set(&self, error: Box<dyn Event + Send>) {
+        let mut slot = self.error.locked();
+        if slot.is_none() {
+            *slot = Some(error);
+        }
+        self.task.notify();
+    }
+
+    fn is_set(&self) -> bool {
+        self.error.locked().is_some()
+    }
+
+    fn take(&self) -> Option<Box<dyn Event + Send>> {
+        self.error.locked().take()
+    }
+}
 
 pub struct EventSink {
     fd: RawFd,
     _fd_drop: Arc<FdDrop>,
     tokio_registration: Arc<tokio::reactor::Registration>,
+    client_id: u32,
+    tracer: Option<Arc<Tracer>>,
     pending_bytes: Vec<u8>,
     pending_fds: Vec<RawFd>,
     pending_events: Vec<Box<dyn Event + Send>>,
     //  _tokio_stream: Arc<UnixStream>,
     closed: bool,
+    fatal: FatalError,
+    // Set once a fatal error is pending: nothing else is sent from then on.
+    fatal_deadline: Option<Delay>,
+    fatal_sent: bool,
 }
 
 impl EventSink {
@@ -26,19 +67,48 @@ impl EventSink {
         fd: RawFd,
         fd_drop: Arc<FdDrop>,
         tokio_registration: Arc<tokio::reactor::Registration>,
+        client_id: u32,
+        tracer: Option<Arc<Tracer>>,
+        fatal: FatalError,
         //tokio_stream: Arc<UnixStream>,
     ) -> EventSink {
         EventSink {
             fd,
             _fd_drop: fd_drop,
             tokio_registration,
+            client_id,
+            tracer,
             pending_bytes: Vec::new(),
             pending_fds: Vec::new(),
             pending_events: Vec::new(),
             //_tokio_stream: tokio_stream,
             closed: false,
+            fatal,
+            fatal_deadline: None,
+            fatal_sent: false,
         }
     }
+
+    // Switches to sending only the fatal error once one is set, and gives
+    // up on the client if it does not take it in time.
+    fn poll_fatal(&mut self) -> Result<(), std::io::Error> {
+        self.fatal.task.register();
+        if self.fatal_deadline.is_none() && self.fatal.is_set() {
+            self.fatal_deadline = Some(Delay::new(Instant::now() + FATAL_ERROR_TIMEOUT));
+        }
+        if let Some(deadline) = &mut self.fatal_deadline {
+            match deadline.poll() {
+                Ok(Async::NotReady) => {}
+                _ => {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::TimedOut,
+                        "client did not read its wl_display.error",
+                    ))
+                }
+            }
+        }
+        Ok(())
+    }
 }
 
 impl Sink for EventSink {
@@ -49,53 +119,48 @@ impl Sink for EventSink {
         &mut self,
         item: Self::SinkItem,
     ) -> Result<AsyncSink<Self::SinkItem>, Self::SinkError> {
-        self.pending_events.push(item);
-        println!("[Sink] start_send events.len={}", self.pending_events.len());
+        if self.fatal_deadline.is_none() {
+            self.pending_events.push(item);
+        }
         Ok(AsyncSink::Ready)
     }
 
     fn poll_complete(&mut self) -> Result<Async<()>, Self::SinkError> {
         loop {
-            println!(
-                "[Sink] poll complete events.len={}",
-                self.pending_events.len()
-            );
-            match self.tokio_registration.poll_write_ready() {
-                Ok(Async::Ready(ready)) if ready.is_writable() => {
-                    println!("[Sink] write ready");
-                    ()
-                }
-                Ok(Async::Ready(_)) => {
-                    println!("[Sink] write ready ERROR");
-                    // return Ok(Async::NotReady);
-                }
-                Ok(Async::NotReady) => {
-                    println!("[Sink] write not ready");
-                    //return Ok(Async::NotReady);
-                }
-                Err(err) => {
-                    println!("[Sink] write ERROR {:?}", err);
-                    return Err(std::io::Error::new(std::io::ErrorKind::Other, err));
-                }
+            self.poll_fatal()?;
+            if let Err(err) = self.tokio_registration.poll_write_ready() {
+                log::error!(client_id = self.client_id; "poll_write_ready failed: {:?}", err);
+                return Err(std::io::Error::new(std::io::ErrorKind::Other, err));
             }
 
             let mut bytes = BytesMut::new();
             if self.pending_bytes.len() == 0 && self.pending_fds.len() == 0 {
-                println!("[Sink] pending bytes is empty");
+                if let Some(error) = self.fatal.take() {
+                    self.pending_events.clear();
+                    self.pending_events.push(error);
+                    self.fatal_sent = true;
+                } else if self.pending_events.len() == 0 && self.fatal_sent {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::ConnectionAborted,
+                        "client dropped after a wl_display.error",
+                    ));
+                }
                 if self.pending_events.len() == 0 {
-                    println!("[Sink] pending event is empty");
                     return Ok(Async::Ready(()));
                 } else {
                     let rest = self.pending_events.split_off(1);
                     let first = self.pending_events.pop().expect("pending events error");
                     self.pending_events = rest;
 
+                    self.pending_fds.extend(first.fds());
                     match first.encode(&mut bytes) {
                         Ok(()) => {
-                            println!("[Sink] encoded");
+                            if let Some(tracer) = &self.tracer {
+                                tracer.events(&bytes);
+                            }
                         }
                         Err(err) => {
-                            println!("[Sink] err3 {:?}", err);
+                            log::error!(client_id = self.client_id; "encode failed: {:?}", err);
                             return Err(std::io::Error::new(std::io::ErrorKind::Other, err));
                         }
                     }
@@ -103,29 +168,53 @@ impl Sink for EventSink {
             }
 
             self.pending_bytes.extend(bytes);
-            println!("[Sink] write {:?}", &self.pending_bytes);
-            let flags = fcntl(self.fd, F_GETFD);
-            println!("[Sink] flags={:?}", flags);
-            let sent_bytes = match send(self.fd, &self.pending_bytes[..], MsgFlags::empty()) {
+            let sent = if self.pending_fds.is_empty() {
+                send(self.fd, &self.pending_bytes[..], MsgFlags::empty())
+            } else {
+                // The fds go with the first byte of the message that carries
+                // them.
+                sendmsg(
+                    self.fd,
+                    &[IoVec::from_slice(&self.pending_bytes[..])],
+                    &[ControlMessage::ScmRights(&self.pending_fds)],
+                    MsgFlags::empty(),
+                    None,
+                )
+            };
+            let sent_bytes = match sent {
                 Ok(sent_bytes) => sent_bytes,
-                Err(nix::Error::Sys(nix::errno::Errno::EAGAIN)) => return Ok(Async::NotReady),
+                Err(nix::Error::Sys(nix::errno::Errno::EAGAIN)) => {
+                    log::trace!(
+                        client_id = self.client_id;
+                        "send would block, {} bytes pending",
+                        self.pending_bytes.len()
+                    );
+                    return Ok(Async::NotReady);
+                }
                 Err(err) => {
-                    println!("[Sink] err2 {:?}", err);
+                    log::error!(client_id = self.client_id; "send failed: {:?}", err);
                     return Err(std::io::Error::new(std::io::ErrorKind::Other, err));
                 }
             };
 
-            println!("[Sink] write {} bytes written", sent_bytes);
+            log::trace!(client_id = self.client_id; "sent {} bytes", sent_bytes);
+            if sent_bytes > 0 {
+                self.pending_fds.clear();
+            }
             if self.pending_bytes.len() <= sent_bytes {
                 self.pending_bytes.clear();
             } else if sent_bytes > 0 {
                 self.pending_bytes = self.pending_bytes.split_off(sent_bytes);
             }
-            println!("[Sink] new pending_bytes={:?}", self.pending_bytes);
         }
     }
 
     fn close(&mut self) -> Result<Async<()>, Self::SinkError> {
+        // Flush first, so that a wl_display.error queued right before the
+        // client is dropped still reaches it.
+        if let Async::NotReady = self.poll_complete()? {
+            return Ok(Async::NotReady);
+        }
         self.closed = true;
         Ok(Async::Ready(()))
     }
@@ -0,0 +1,43 @@
+// Wire-level description of an interface, emitted by scan/reflex.rb next to
+// each dispatch_request so that tools like the protocol tracer can decode
+// messages without knowing the concrete request/event types.
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ArgKind {
+    Int,
+    Uint,
+    Fixed,
+    String,
+    Object,
+    NewId,
+    Array,
+    Fd,
+}
+
+#[allow(dead_code)]
+pub struct Arg {
+    pub name: &'static str,
+    pub kind: ArgKind,
+    pub interface: Option<&'static str>,
+    pub nullable: bool,
+}
+
+pub struct Message {
+    pub name: &'static str,
+    pub args: &'static [Arg],
+}
+
+#[allow(dead_code)]
+pub struct Interface {
+    pub name: &'static str,
+    pub version: u32,
+    pub requests: &'static [Message],
+    pub events: &'static [Message],
+}
+
+pub fn find_interface(name: &str) -> Option<&'static Interface> {
+    crate::protocol::resource::INTERFACES
+        .iter()
+        .find(|interface| interface.name == name)
+        .cloned()
+}
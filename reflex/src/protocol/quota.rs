@@ -0,0 +1,200 @@
+use crate::protocol::error::ClientError;
+use crate::protocol::resource::Resource;
+use crate::protocol::session::Session;
+use crate::protocol::wayland::wl_display;
+use futures::future::Future;
+use futures::sink::Sink;
+use std::time::{Duration, Instant};
+
+// Per-client limits. A client that exceeds one of them gets a
+// wl_display.error with code no_memory and is disconnected.
+//
+// The limits are set in the [quotas] section of reflex.toml. Each one can be
+// overridden with an environment variable:
+//   REFLEX_MAX_OBJECTS, REFLEX_MAX_SHM_BYTES, REFLEX_MAX_PENDING_EVENTS,
+//   REFLEX_MAX_REQUESTS_PER_SECOND
+#[derive(Clone, Copy, Debug, serde::Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Quotas {
+    pub max_objects: usize,
+    pub max_shm_bytes: u64,
+    pub max_pending_events: usize,
+    pub max_requests_per_second: u32,
+}
+
+impl Default for Quotas {
+    fn default() -> Self {
+        Quotas {
+            max_objects: 65536,
+            max_shm_bytes: 1 << 30,
+            max_pending_events: 48000,
+            max_requests_per_second: 100_000,
+        }
+    }
+}
+
+fn env_or<T: std::str::FromStr>(name: &str, default: T) -> T {
+    match std::env::var(name) {
+        Ok(value) => match value.trim().parse() {
+            Ok(x) => x,
+            Err(_) => {
+                log::warn!("ignoring invalid {}={:?}", name, value);
+                default
+            }
+        },
+        Err(_) => default,
+    }
+}
+
+// Like env_or, for the quotas that must be positive: a zero would refuse
+// every client, or make its event queue panic. It falls back to the
+// configured value, and to the default if that is zero too.
+fn positive_env_or<T>(name: &str, configured: T, default: T) -> T
+where
+    T: std::str::FromStr + Default + PartialEq + Copy,
+{
+    let zero = T::default();
+    let value = env_or(name, configured);
+    if value != zero {
+        return value;
+    }
+    log::warn!("ignoring {} of 0: quotas must be positive", name);
+    if configured != zero {
+        configured
+    } else {
+        default
+    }
+}
+
+impl Quotas {
+    // Applies the environment overrides on top of self.
+    pub fn with_env(self) -> Quotas {
+        let configured = self;
+        let default = Quotas::default();
+        Quotas {
+            max_objects: positive_env_or(
+                "REFLEX_MAX_OBJECTS",
+                configured.max_objects,
+                default.max_objects,
+            ),
+            max_shm_bytes: env_or("REFLEX_MAX_SHM_BYTES", configured.max_shm_bytes),
+            max_pending_events: positive_env_or(
+                "REFLEX_MAX_PENDING_EVENTS",
+                configured.max_pending_events,
+                default.max_pending_events,
+            ),
+            max_requests_per_second: positive_env_or(
+                "REFLEX_MAX_REQUESTS_PER_SECOND",
+                configured.max_requests_per_second,
+                default.max_requests_per_second,
+            ),
+        }
+    }
+
+    // Checks the session after a request has been dispatched. The shm total
+    // is only recomputed for requests that can change it.
+    pub fn check(&self, session: &Session, interface: &str) -> Result<(), String> {
+        if session.resources.len() > self.max_objects {
+            return Err(format!(
+                "object quota exceeded: {} > {}",
+                session.resources.len(),
+                self.max_objects
+            ));
+        }
+        if interface == "wl_shm" || interface == "wl_shm_pool" {
+            let shm_bytes: u64 = session
+                .resources
+                .values()
+                .map(|resource| match resource {
+                    Resource::WlShmPool(pool) => pool.size.max(0) as u64,
+                    _ => 0,
+                })
+                .sum();
+            if shm_bytes > self.max_shm_bytes {
+                return Err(format!(
+                    "shm quota exceeded: {} > {} bytes",
+                    shm_bytes, self.max_shm_bytes
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+// Counts requests in one second windows.
+pub struct RateLimiter {
+    max_requests_per_second: u32,
+    window_start: Instant,
+    count: u32,
+}
+
+impl RateLimiter {
+    pub fn new(quotas: &Quotas) -> RateLimiter {
+        RateLimiter {
+            max_requests_per_second: quotas.max_requests_per_second,
+            window_start: Instant::now(),
+            count: 0,
+        }
+    }
+
+    pub fn check(&mut self) -> Result<(), String> {
+        let now = Instant::now();
+        if now.duration_since(self.window_start) >= Duration::from_secs(1) {
+            self.window_start = now;
+            self.count = 0;
+        }
+        self.count += 1;
+        if self.count > self.max_requests_per_second {
+            return Err(format!(
+                "request rate exceeded: more than {} requests per second",
+                self.max_requests_per_second
+            ));
+        }
+        Ok(())
+    }
+}
+
+// Reports the violation to the client and ends its request loop. The error
+// is still flushed, because the event queue is closed only after the
+// session is gone.
+pub fn disconnect(
+    session: Session,
+    object_id: u32,
+    message: String,
+) -> Box<dyn Future<Item = Session, Error = ClientError> + Send> {
+    let error = wl_display::events::Error {
+        sender_object_id: 1,
+        object_id,
+        code: wl_display::enums::Error::NoMemory as u32,
+        message: message.clone(),
+    };
+    Box::new(
+        session
+            .tx
+            .clone()
+            .send(Box::new(error))
+            .then(|_| Err(ClientError::Quota(message))),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quotas_stay_positive() {
+        std::env::set_var("REFLEX_MAX_PENDING_EVENTS", "0");
+        std::env::set_var("REFLEX_MAX_REQUESTS_PER_SECOND", "7");
+        let configured = Quotas {
+            max_objects: 0,
+            max_pending_events: 10,
+            ..Quotas::default()
+        };
+        let quotas = configured.with_env();
+        std::env::remove_var("REFLEX_MAX_PENDING_EVENTS");
+        std::env::remove_var("REFLEX_MAX_REQUESTS_PER_SECOND");
+        assert_eq!(quotas.max_objects, Quotas::default().max_objects);
+        assert_eq!(quotas.max_pending_events, 10);
+        assert_eq!(quotas.max_requests_per_second, 7);
+    }
+}
@@ -1,5 +1,7 @@
+use crate::protocol::error::ClientError;
 use crate::protocol::fd_drop::FdDrop;
 use crate::protocol::request::Request;
+use crate::protocol::trace::Tracer;
 use byteorder::{NativeEndian, ReadBytesExt};
 use futures::stream::Stream;
 use std::io::{Cursor, Read};
@@ -12,6 +14,8 @@ pub struct RequestStream {
     fd: RawFd,
     _fd_drop: Arc<FdDrop>,
     tokio_registration: Arc<tokio::reactor::Registration>,
+    client_id: u32,
+    tracer: Option<Arc<Tracer>>,
     //_tokio_stream: Arc<UnixStream>,
     pending_bytes: Vec<u8>,
     pending_fds: Vec<RawFd>,
@@ -23,11 +27,15 @@ impl RequestStream {
         fd: RawFd,
         fd_drop: Arc<FdDrop>,
         tokio_registration: Arc<Registration>,
+        client_id: u32,
+        tracer: Option<Arc<Tracer>>,
     ) -> RequestStream {
         RequestStream {
             fd,
             _fd_drop: fd_drop,
             tokio_registration,
+            client_id,
+            tracer,
             //_tokio_stream: tokio_stream,
             pending_bytes: Vec::new(),
             pending_fds: Vec::new(),
@@ -38,39 +46,17 @@ impl RequestStream {
 
 impl Stream for RequestStream {
     type Item = Request;
-    type Error = ();
+    type Error = ClientError;
 
     fn poll(&mut self) -> Result<Async<Option<Self::Item>>, Self::Error> {
-        println!(
-            "[Stream] poll: pending_requests={}",
-            self.pending_requests.len()
-        );
-
         if !self.pending_requests.is_empty() {
             let rest = self.pending_requests.split_off(1);
             let first = self.pending_requests.pop().expect("pending requests error");
             self.pending_requests = rest;
-            println!("[Stream] return pending request {:?}", first);
             return Ok(Async::Ready(Some(first)));
         }
 
-        match self.tokio_registration.poll_read_ready() {
-            Ok(Async::Ready(ready)) if ready.is_readable() => {
-                println!("[Stream] read ready");
-            }
-            Ok(Async::Ready(_)) => {
-                println!("[Stream] read ready ERROR");
-                //return Ok(Async::NotReady);
-            }
-            Ok(Async::NotReady) => {
-                println!("[Stream] read not ready");
-                //return Ok(Async::NotReady);
-            }
-            Err(e) => {
-                println!("[Stream] err {:?}", e);
-                return Err(());
-            }
-        }
+        self.tokio_registration.poll_read_ready()?;
 
         let mut received_fds: Vec<RawFd> = Vec::new();
         let mut buf: Vec<u8> = Vec::new();
@@ -102,14 +88,16 @@ impl Stream for RequestStream {
                         msg_flags: 0,
                     };
                     let read = libc::recvmsg(self.fd, &mut msg_hdr, libc::MSG_PEEK);
+                    if read == 0 {
+                        log::debug!(client_id = self.client_id; "end of stream");
+                        return Ok(Async::Ready(None));
+                    }
                     if read < 0 {
                         let errno = *libc::__errno_location();
                         if errno == libc::EAGAIN || errno == libc::EWOULDBLOCK {
-                            println!("[Stream] EAGAIN not ready");
                             return Ok(Async::NotReady);
                         }
-                        println!("[Stream] err {}", errno);
-                        return Err(());
+                        return Err(ClientError::Io(std::io::Error::from_raw_os_error(errno)));
                     }
                     if (msg_hdr.msg_flags & libc::MSG_TRUNC) != 0 {
                         buf.resize(buf.len() * 2, 0);
@@ -145,28 +133,24 @@ impl Stream for RequestStream {
                     if read < 0 {
                         let errno = *libc::__errno_location();
                         if errno == libc::EAGAIN || errno == libc::EWOULDBLOCK {
-                            println!("[Stream] EAGAIN not ready");
                             return Ok(Async::NotReady);
                         }
-                        println!("[Stream] err {}", errno);
-                        return Err(());
+                        return Err(ClientError::Io(std::io::Error::from_raw_os_error(errno)));
                     }
                     if (msg_hdr.msg_flags & libc::MSG_TRUNC) != 0 {
-                        println!("[Stream] Oops MSG_TRUNC");
+                        log::error!(client_id = self.client_id; "recvmsg: MSG_TRUNC");
                     }
                     if (msg_hdr.msg_flags & libc::MSG_CTRUNC) != 0 {
-                        println!("[Stream] Oops MSG_CTRUNC");
+                        log::error!(client_id = self.client_id; "recvmsg: MSG_CTRUNC");
                     }
 
                     buf.resize(read as usize, 0);
 
                     let mut cmsg_hdr = libc::CMSG_FIRSTHDR(&msg_hdr);
                     while !cmsg_hdr.is_null() {
-                        println!("[Stream] cmsg");
                         let cmsg_level = (*cmsg_hdr).cmsg_level;
                         let cmsg_type = (*cmsg_hdr).cmsg_type;
                         if cmsg_level == libc::SOL_SOCKET && cmsg_type == libc::SCM_RIGHTS {
-                            println!("[Stream] SCM_RIGHTS cmsg_len={}", (*cmsg_hdr).cmsg_len);
                             let received_fds_ptr =
                                 libc::CMSG_DATA(cmsg_hdr) as *mut std::os::raw::c_int;
                             let mut received_fds_len = 1;
@@ -175,24 +159,18 @@ impl Stream for RequestStream {
                                     * received_fds_len,
                             ) <= (*cmsg_hdr).cmsg_len as u32
                             {
-                                println!(
-                                    "[Stream] SCM_RIGHTS  vs {}",
-                                    libc::CMSG_LEN(
-                                        std::mem::size_of::<std::os::raw::c_int>() as u32
-                                            * received_fds_len
-                                    )
-                                );
                                 received_fds_len += 1;
                             }
                             received_fds_len -= 1;
-                            println!("[Stream] len={}", received_fds_len);
                             for offset in 0..received_fds_len {
                                 received_fds.push(*received_fds_ptr.offset(offset as isize));
                             }
                         } else {
-                            println!(
-                                "[Stream] UNHANDLED CMSG: level={} type={}",
-                                cmsg_level, cmsg_type
+                            log::warn!(
+                                client_id = self.client_id;
+                                "unhandled cmsg: level={} type={}",
+                                cmsg_level,
+                                cmsg_type
                             );
                         }
                         cmsg_hdr = libc::CMSG_NXTHDR(&msg_hdr, cmsg_hdr);
@@ -203,18 +181,12 @@ impl Stream for RequestStream {
             break;
         }
 
-        println!("[Stream] data={:?} fds={:?}", buf, received_fds);
         self.pending_bytes.extend(buf);
         self.pending_fds.extend(received_fds);
 
         let header_size = 8;
         loop {
             if self.pending_bytes.len() < header_size {
-                println!(
-                    "[Stream] break pending_bytes={} < header_size={}",
-                    self.pending_bytes.len(),
-                    header_size
-                );
                 break;
             }
 
@@ -224,38 +196,43 @@ impl Stream for RequestStream {
             let message_size_and_opcode = cursor.read_u32::<NativeEndian>().unwrap();
             let message_size = (message_size_and_opcode >> 16) as usize;
             if self.pending_bytes.len() < message_size {
-                println!(
-                    "[Stream] break pending_bytes={} < message_size={}",
-                    self.pending_bytes.len(),
-                    message_size
-                );
                 break;
             }
 
             let opcode = (0x0000_ffff & message_size_and_opcode) as u16;
             if message_size < header_size {
-                return Err(());
+                return Err(ClientError::MalformedMessage(format!(
+                    "object_id={} opcode={} size={}",
+                    sender_object_id, opcode, message_size
+                )));
             }
             let mut args = Vec::new();
             args.resize(message_size - header_size, 0);
             cursor.read_exact(&mut args).unwrap();
-            println!(
-                "[Stream] decode: id={} opcode={} args={:?} fds={:?}",
-                sender_object_id, opcode, &args, &self.pending_fds
-            );
             let fds = self.pending_fds.clone();
             self.pending_fds.clear();
             self.pending_bytes = self.pending_bytes.split_off(message_size);
-            self.pending_requests.push(Request {
+            let request = Request {
                 sender_object_id,
                 opcode,
                 args,
                 fds,
-            });
+            };
+            log::trace!(
+                client_id = self.client_id,
+                object_id = sender_object_id,
+                opcode = opcode;
+                "decoded request, {} bytes, {} fds",
+                message_size,
+                request.fds.len()
+            );
+            if let Some(tracer) = &self.tracer {
+                tracer.request(&request);
+            }
+            self.pending_requests.push(request);
         }
 
         if self.pending_requests.is_empty() {
-            println!("[Stream] pending request is empty");
             return Ok(Async::NotReady);
         }
 
@@ -263,7 +240,6 @@ impl Stream for RequestStream {
             let rest = self.pending_requests.split_off(1);
             let first = self.pending_requests.pop().expect("pending requests error");
             self.pending_requests = rest;
-            println!("[Stream] ok {:?}", first);
             return Ok(Async::Ready(Some(first)));
         }
     }
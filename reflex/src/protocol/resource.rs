@@ -30,6 +30,68 @@ pub enum Resource {
     XdgWmBase(Arc<RwLock<crate::protocol::xdg_shell::xdg_wm_base::XdgWmBase>>),
 }
 
+pub static INTERFACES: &[&crate::protocol::interface::Interface] = &[
+    &crate::protocol::wayland::wl_buffer::INTERFACE,
+    &crate::protocol::wayland::wl_callback::INTERFACE,
+    &crate::protocol::wayland::wl_compositor::INTERFACE,
+    &crate::protocol::wayland::wl_data_device::INTERFACE,
+    &crate::protocol::wayland::wl_data_device_manager::INTERFACE,
+    &crate::protocol::wayland::wl_data_offer::INTERFACE,
+    &crate::protocol::wayland::wl_data_source::INTERFACE,
+    &crate::protocol::wayland::wl_display::INTERFACE,
+    &crate::protocol::wayland::wl_keyboard::INTERFACE,
+    &crate::protocol::wayland::wl_output::INTERFACE,
+    &crate::protocol::wayland::wl_pointer::INTERFACE,
+    &crate::protocol::wayland::wl_region::INTERFACE,
+    &crate::protocol::wayland::wl_registry::INTERFACE,
+    &crate::protocol::wayland::wl_seat::INTERFACE,
+    &crate::protocol::wayland::wl_shm::INTERFACE,
+    &crate::protocol::wayland::wl_shm_pool::INTERFACE,
+    &crate::protocol::wayland::wl_subcompositor::INTERFACE,
+    &crate::protocol::wayland::wl_subsurface::INTERFACE,
+    &crate::protocol::wayland::wl_surface::INTERFACE,
+    &crate::protocol::wayland::wl_touch::INTERFACE,
+    &crate::protocol::xdg_shell::xdg_popup::INTERFACE,
+    &crate::protocol::xdg_shell::xdg_positioner::INTERFACE,
+    &crate::protocol::xdg_shell::xdg_surface::INTERFACE,
+    &crate::protocol::xdg_shell::xdg_toplevel::INTERFACE,
+    &crate::protocol::xdg_shell::xdg_wm_base::INTERFACE,
+];
+
+impl Resource {
+    pub fn interface(&self) -> &'static crate::protocol::interface::Interface {
+        match self {
+            Resource::WlBuffer(_) => &crate::protocol::wayland::wl_buffer::INTERFACE,
+            Resource::WlCallback(_) => &crate::protocol::wayland::wl_callback::INTERFACE,
+            Resource::WlCompositor(_) => &crate::protocol::wayland::wl_compositor::INTERFACE,
+            Resource::WlDataDevice(_) => &crate::protocol::wayland::wl_data_device::INTERFACE,
+            Resource::WlDataDeviceManager(_) => {
+                &crate::protocol::wayland::wl_data_device_manager::INTERFACE
+            }
+            Resource::WlDataOffer(_) => &crate::protocol::wayland::wl_data_offer::INTERFACE,
+            Resource::WlDataSource(_) => &crate::protocol::wayland::wl_data_source::INTERFACE,
+            Resource::WlDisplay(_) => &crate::protocol::wayland::wl_display::INTERFACE,
+            Resource::WlKeyboard(_) => &crate::protocol::wayland::wl_keyboard::INTERFACE,
+            Resource::WlOutput(_) => &crate::protocol::wayland::wl_output::INTERFACE,
+            Resource::WlPointer(_) => &crate::protocol::wayland::wl_pointer::INTERFACE,
+            Resource::WlRegion(_) => &crate::protocol::wayland::wl_region::INTERFACE,
+            Resource::WlRegistry(_) => &crate::protocol::wayland::wl_registry::INTERFACE,
+            Resource::WlSeat(_) => &crate::protocol::wayland::wl_seat::INTERFACE,
+            Resource::WlShm(_) => &crate::protocol::wayland::wl_shm::INTERFACE,
+            Resource::WlShmPool(_) => &crate::protocol::wayland::wl_shm_pool::INTERFACE,
+            Resource::WlSubcompositor(_) => &crate::protocol::wayland::wl_subcompositor::INTERFACE,
+            Resource::WlSubsurface(_) => &crate::protocol::wayland::wl_subsurface::INTERFACE,
+            Resource::WlSurface(_) => &crate::protocol::wayland::wl_surface::INTERFACE,
+            Resource::WlTouch(_) => &crate::protocol::wayland::wl_touch::INTERFACE,
+            Resource::XdgPopup(_) => &crate::protocol::xdg_shell::xdg_popup::INTERFACE,
+            Resource::XdgPositioner(_) => &crate::protocol::xdg_shell::xdg_positioner::INTERFACE,
+            Resource::XdgSurface(_) => &crate::protocol::xdg_shell::xdg_surface::INTERFACE,
+            Resource::XdgToplevel(_) => &crate::protocol::xdg_shell::xdg_toplevel::INTERFACE,
+            Resource::XdgWmBase(_) => &crate::protocol::xdg_shell::xdg_wm_base::INTERFACE,
+        }
+    }
+}
+
 pub fn dispatch_request(
     resource: Resource,
     session: crate::protocol::session::Session,
@@ -1,18 +1,22 @@
+use super::connection_stream::Credentials;
+use super::error::ClientError;
 use super::event::Event;
 use super::resource::Resource;
+use super::wayland::wl_buffer::{self, BufferState, WlBuffer};
 use super::wayland::wl_compositor::WlCompositor;
 use super::wayland::wl_display::WlDisplay;
 use super::wayland::wl_registry::WlRegistry;
 use super::wayland::wl_shm::WlShm;
 use super::xdg_shell::xdg_wm_base::XdgWmBase;
+use crate::backend::BackendSession;
+use crate::frame_clock::FrameClock;
 use crate::protocol::wayland::wl_data_device_manager::WlDataDeviceManager;
+use crate::protocol::xdg_shell::xdg_surface::ConfigureSerials;
 use futures::future::Future;
 use futures::sink::Sink;
 use std::collections::HashMap;
 use std::os::unix::io::RawFd;
 use std::sync::{Arc, RwLock};
-use tokio::io::WriteHalf;
-use tokio::net::UnixStream;
 use tokio::sync::mpsc::Sender;
 
 pub enum NextAction {
@@ -22,6 +26,8 @@ pub enum NextAction {
 }
 
 pub struct Session {
+    pub client_id: u32,
+    pub credentials: Credentials,
     pub resources: HashMap<u32, Resource>,
     pub wl_display: Arc<RwLock<WlDisplay>>,
     pub wl_compositor: Arc<RwLock<WlCompositor>>,
@@ -31,7 +37,12 @@ pub struct Session {
     pub xdg_wm_base: Arc<RwLock<XdgWmBase>>,
     pub tx: Sender<Box<dyn Event + Send>>,
     pub fds: Vec<RawFd>,
-    pub unix_stream: WriteHalf<UnixStream>,
+    // wl_buffers to release once the request being handled has been
+    // relayed, which is when a backend that copies buffers has its copy.
+    pub pending_releases: Vec<u32>,
+    pub frame_clock: FrameClock,
+    pub configure_serials: ConfigureSerials,
+    pub backend: Box<dyn BackendSession>,
 }
 
 pub struct Context<T>
@@ -41,6 +52,8 @@ where
     pub sender_object_id: u32,
     pub sender_object: T,
 
+    pub client_id: u32,
+    pub credentials: Credentials,
     pub resources: HashMap<u32, Resource>,
     pub wl_display: Arc<RwLock<WlDisplay>>,
     pub wl_compositor: Arc<RwLock<WlCompositor>>,
@@ -50,7 +63,10 @@ where
     pub xdg_wm_base: Arc<RwLock<XdgWmBase>>,
     pub tx: Sender<Box<dyn Event + Send>>,
     pub fds: Vec<RawFd>,
-    pub unix_stream: WriteHalf<UnixStream>,
+    pub pending_releases: Vec<u32>,
+    pub frame_clock: FrameClock,
+    pub configure_serials: ConfigureSerials,
+    pub backend: Box<dyn BackendSession>,
 }
 
 impl<T> Context<T>
@@ -59,6 +75,8 @@ where
 {
     pub fn new(session: Session, sender_object: T, sender_object_id: u32) -> Self {
         Self {
+            client_id: session.client_id,
+            credentials: session.credentials,
             resources: session.resources,
             wl_display: session.wl_display,
             wl_registry: session.wl_registry,
@@ -68,9 +86,12 @@ where
             xdg_wm_base: session.xdg_wm_base,
             tx: session.tx,
             fds: session.fds,
+            pending_releases: session.pending_releases,
+            frame_clock: session.frame_clock,
+            configure_serials: session.configure_serials,
             sender_object_id,
             sender_object,
-            unix_stream: session.unix_stream,
+            backend: session.backend,
         }
     }
 
@@ -80,24 +101,99 @@ where
         Box::new(futures::future::ok((self.into(), NextAction::Relay)))
     }
 
-    fn create_invalid_method_error(
+    // For requests that reflex handles entirely: they are not relayed, so
+    // backends never see them.
+    pub fn handled(
+        self,
+    ) -> Box<dyn futures::future::Future<Item = (Session, NextAction), Error = ()> + Send> {
+        Box::new(futures::future::ok((self.into(), NextAction::Nop)))
+    }
+
+    // Ends the sender object's lifetime in reflex. The request is still
+    // relayed, so that the backend can drop its own state for the object.
+    pub fn destroy(
+        self,
+    ) -> Box<dyn futures::future::Future<Item = (Session, NextAction), Error = ()> + Send> {
+        let object_id = self.sender_object_id;
+        let mut session: Session = self.into();
+        session.resources.remove(&object_id);
+        Box::new(futures::future::ok((session, NextAction::Relay)))
+    }
+
+    // Like destroy(), for objects that only reflex knows, whose ID reflex
+    // gives back to the client itself.
+    pub fn destroy_handled(
+        self,
+    ) -> Box<dyn futures::future::Future<Item = (Session, NextAction), Error = ()> + Send> {
+        let object_id = self.sender_object_id;
+        let tx = self.tx.clone();
+        let mut session: Session = self.into();
+        session.resources.remove(&object_id);
+        let delete_id = crate::protocol::wayland::wl_display::events::DeleteId {
+            sender_object_id: 1,
+            id: object_id,
+        };
+        Box::new(
+            tx.send(Box::new(delete_id))
+                .map_err(|_| ())
+                .map(|_| (session, NextAction::Nop)),
+        )
+    }
+
+    fn create_error(
         &self,
+        object_id: u32,
+        code: u32,
         message: String,
     ) -> crate::protocol::wayland::wl_display::events::Error {
         crate::protocol::wayland::wl_display::events::Error {
             sender_object_id: 1,
-            object_id: self.sender_object_id,
-            code: crate::protocol::wayland::wl_display::enums::Error::InvalidMethod as u32,
+            object_id,
+            code,
             message,
         }
     }
 
-    pub fn invalid_method(
+    fn create_invalid_method_error(
+        &self,
+        message: String,
+    ) -> crate::protocol::wayland::wl_display::events::Error {
+        self.create_error(
+            self.sender_object_id,
+            crate::protocol::wayland::wl_display::enums::Error::InvalidMethod as u32,
+            message,
+        )
+    }
+
+    // Reports a misuse of the sender object with one of the error codes of
+    // its own interface. The request is not relayed.
+    pub fn protocol_error(
+        self,
+        code: u32,
+        message: String,
+    ) -> Box<dyn futures::future::Future<Item = (Session, NextAction), Error = ()> + Send> {
+        let object_id = self.sender_object_id;
+        self.object_error(object_id, code, message)
+    }
+
+    // Like protocol_error(), for errors the protocol raises on another
+    // object than the sender, such as xdg_wm_base.role on a request to an
+    // xdg_surface.
+    pub fn object_error(
         self,
+        object_id: u32,
+        code: u32,
         message: String,
     ) -> Box<dyn futures::future::Future<Item = (Session, NextAction), Error = ()> + Send> {
+        log::warn!(
+            client_id = self.client_id,
+            object_id = object_id,
+            code = code;
+            "{}",
+            message
+        );
         let tx = self.tx.clone();
-        let error = self.create_invalid_method_error(message);
+        let error = self.create_error(object_id, code, message);
         let session: Session = self.into();
 
         Box::new(
@@ -107,10 +203,28 @@ where
         )
     }
 
+    pub fn invalid_method(
+        self,
+        message: String,
+    ) -> Box<dyn futures::future::Future<Item = (Session, NextAction), Error = ()> + Send> {
+        let object_id = self.sender_object_id;
+        self.object_error(
+            object_id,
+            crate::protocol::wayland::wl_display::enums::Error::InvalidMethod as u32,
+            message,
+        )
+    }
+
     pub fn invalid_method_dispatch(
         self,
         message: String,
     ) -> Box<dyn futures::future::Future<Item = Session, Error = ()> + Send> {
+        log::warn!(
+            client_id = self.client_id,
+            object_id = self.sender_object_id;
+            "{}",
+            message
+        );
         let tx = self.tx.clone();
         let error = self.create_invalid_method_error(message);
         let session: Session = self.into();
@@ -127,6 +241,8 @@ where
         self.resources
             .insert(self.sender_object_id, self.sender_object.into());
         Session {
+            client_id: self.client_id,
+            credentials: self.credentials,
             resources: self.resources,
             wl_display: self.wl_display,
             wl_registry: self.wl_registry,
@@ -136,85 +252,57 @@ where
             xdg_wm_base: self.xdg_wm_base,
             tx: self.tx,
             fds: self.fds,
-            unix_stream: self.unix_stream,
+            pending_releases: self.pending_releases,
+            frame_clock: self.frame_clock,
+            configure_serials: self.configure_serials,
+            backend: self.backend,
         }
     }
 }
 
 impl Session {
+    // Hands a request that reflex does not handle itself to the backend.
     pub fn relay(
-        self,
+        mut self,
         buf: Vec<u8>,
     ) -> Box<dyn futures::future::Future<Item = Session, Error = ()> + Send> {
-        let (relay_session, unix_stream) = self.into_relay_session();
+        log::trace!(client_id = self.client_id; "relay {} bytes", buf.len());
+        match self.backend.relay(buf) {
+            Ok(()) => self.release_buffers(),
+            Err(err) => {
+                log::error!(client_id = self.client_id; "{}", ClientError::Backend(err));
+                Box::new(futures::future::err(()))
+            }
+        }
+    }
+
+    fn release_buffers(
+        mut self,
+    ) -> Box<dyn futures::future::Future<Item = Session, Error = ()> + Send> {
+        if self.pending_releases.is_empty() {
+            return Box::new(futures::future::ok(self));
+        }
+        let mut events: Vec<Box<dyn Event + Send>> = Vec::new();
+        for buffer_id in std::mem::take(&mut self.pending_releases) {
+            if let Some(buffer) = WlBuffer::get_mut(&mut self.resources, buffer_id) {
+                buffer.state = BufferState::Released;
+                events.push(Box::new(wl_buffer::events::Release {
+                    sender_object_id: buffer_id,
+                }));
+            }
+        }
+        let tx = self.tx.clone();
         Box::new(
-            tokio::io::write_all(unix_stream, buf)
-                .map_err(|err| panic!("relay err: {:?}", err))
-                .and_then(|(u, _): (WriteHalf<UnixStream>, Vec<u8>)| {
-                    futures::future::ok(Session::from_relay_session(relay_session, u))
-                }),
+            tx.send_all(futures::stream::iter_ok(events))
+                .map_err(|_| ())
+                .map(|_| self),
         )
-        //Box::new(futures::future::ok(self))
     }
 
     pub fn relay_wait(
         self,
         buf: Vec<u8>,
     ) -> Box<dyn futures::future::Future<Item = Session, Error = ()> + Send> {
-        let (relay_session, unix_stream) = self.into_relay_session();
-        Box::new(
-            tokio::io::write_all(unix_stream, buf)
-                .map_err(|err| panic!("relay_wait err: {:?}", err))
-                .and_then(|(u, _): (WriteHalf<UnixStream>, Vec<u8>)| {
-                    futures::future::ok(Session::from_relay_session(relay_session, u))
-                }),
-        )
-        //Box::new(futures::future::ok(self))
-    }
-
-    fn from_relay_session(
-        relay_session: RelaySession,
-        unix_stream: WriteHalf<UnixStream>,
-    ) -> Session {
-        Session {
-            resources: relay_session.resources,
-            wl_display: relay_session.wl_display,
-            wl_registry: relay_session.wl_registry,
-            wl_compositor: relay_session.wl_compositor,
-            wl_shm: relay_session.wl_shm,
-            wl_data_device_manager: relay_session.wl_data_device_manager,
-            xdg_wm_base: relay_session.xdg_wm_base,
-            tx: relay_session.tx,
-            fds: relay_session.fds,
-            unix_stream,
-        }
-    }
-
-    fn into_relay_session(self) -> (RelaySession, WriteHalf<UnixStream>) {
-        let unix_stream = self.unix_stream;
-        let relay_session = RelaySession {
-            resources: self.resources,
-            wl_display: self.wl_display,
-            wl_registry: self.wl_registry,
-            wl_compositor: self.wl_compositor,
-            wl_shm: self.wl_shm,
-            wl_data_device_manager: self.wl_data_device_manager,
-            xdg_wm_base: self.xdg_wm_base,
-            tx: self.tx,
-            fds: self.fds,
-        };
-        (relay_session, unix_stream)
+        self.relay(buf)
     }
 }
-
-struct RelaySession {
-    pub resources: HashMap<u32, Resource>,
-    pub wl_display: Arc<RwLock<WlDisplay>>,
-    pub wl_compositor: Arc<RwLock<WlCompositor>>,
-    pub wl_shm: Arc<RwLock<WlShm>>,
-    pub wl_registry: Arc<RwLock<WlRegistry>>,
-    pub wl_data_device_manager: Arc<RwLock<WlDataDeviceManager>>,
-    pub xdg_wm_base: Arc<RwLock<XdgWmBase>>,
-    pub tx: Sender<Box<dyn Event + Send>>,
-    pub fds: Vec<RawFd>,
-}
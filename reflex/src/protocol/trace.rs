@@ -0,0 +1,306 @@
+use crate::lock::MutexExt;
+use crate::protocol::connection_stream::Credentials;
+use crate::protocol::interface::{find_interface, Arg, ArgKind, Interface, Message};
+use crate::protocol::request::Request;
+use byteorder::{NativeEndian, ReadBytesExt};
+use std::collections::HashMap;
+use std::io::{Cursor, Read};
+use std::os::unix::io::RawFd;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+// Decoded protocol tracing, in the spirit of WAYLAND_DEBUG=server.
+//
+// REFLEX_TRACE turns it on. Its value is either "1" / "all", or a comma
+// separated list of interface names such as "wl_surface,xdg_toplevel".
+// REFLEX_TRACE_CLIENTS optionally limits it to a comma separated list of
+// client ids.
+pub struct TraceFilter {
+    epoch: Instant,
+    interfaces: Option<Vec<String>>,
+    clients: Option<Vec<u32>>,
+}
+
+impl TraceFilter {
+    pub fn from_env() -> Option<TraceFilter> {
+        let interfaces = std::env::var("REFLEX_TRACE").ok()?;
+        let interfaces = match interfaces.trim() {
+            "" | "0" => return None,
+            "1" | "all" => None,
+            list => Some(
+                list.split(',')
+                    .map(|name| name.trim().to_string())
+                    .collect(),
+            ),
+        };
+        let clients = std::env::var("REFLEX_TRACE_CLIENTS").ok().map(|list| {
+            list.split(',')
+                .filter_map(|id| id.trim().parse().ok())
+                .collect()
+        });
+        Some(TraceFilter {
+            epoch: Instant::now(),
+            interfaces,
+            clients,
+        })
+    }
+
+    pub fn tracer(&self, client_id: u32, credentials: Credentials) -> Option<Arc<Tracer>> {
+        if let Some(clients) = &self.clients {
+            if !clients.contains(&client_id) {
+                return None;
+            }
+        }
+        let mut objects: HashMap<u32, &'static Interface> = HashMap::new();
+        objects.insert(1, &crate::protocol::wayland::wl_display::INTERFACE);
+        Some(Arc::new(Tracer {
+            client_id,
+            pid: credentials.pid,
+            epoch: self.epoch,
+            interfaces: self.interfaces.clone(),
+            objects: Mutex::new(objects),
+        }))
+    }
+}
+
+// Per-client tracer shared by the RequestStream and the EventSink of a
+// connection. It follows object creation and deletion on the wire by itself,
+// so it never has to look into the Session.
+pub struct Tracer {
+    client_id: u32,
+    pid: i32,
+    epoch: Instant,
+    interfaces: Option<Vec<String>>,
+    objects: Mutex<HashMap<u32, &'static Interface>>,
+}
+
+impl Tracer {
+    pub fn request(&self, request: &Request) {
+        let mut objects = self.objects.locked();
+        let line = self.format(
+            false,
+            &mut objects,
+            request.sender_object_id,
+            request.opcode,
+            &request.args,
+            &mut request.fds.iter(),
+        );
+        if let Some(line) = line {
+            eprintln!("{}", line);
+        }
+    }
+
+    pub fn events(&self, bytes: &[u8]) {
+        let mut objects = self.objects.locked();
+        let mut cursor = Cursor::new(bytes);
+        loop {
+            let sender_object_id = match cursor.read_u32::<NativeEndian>() {
+                Ok(x) => x,
+                Err(_) => return,
+            };
+            let message_size_and_opcode = match cursor.read_u32::<NativeEndian>() {
+                Ok(x) => x,
+                Err(_) => return,
+            };
+            let message_size = (message_size_and_opcode >> 16) as usize;
+            let opcode = (message_size_and_opcode & 0xffff) as u16;
+            if message_size < 8 {
+                return;
+            }
+            let mut args = vec![0; message_size - 8];
+            let _ = cursor.read(&mut args);
+
+            let interface = objects.get(&sender_object_id).cloned();
+            let line = self.format(
+                true,
+                &mut objects,
+                sender_object_id,
+                opcode,
+                &args,
+                &mut [].iter(),
+            );
+            if let Some(line) = line {
+                eprintln!("{}", line);
+            }
+
+            if interface.map(|x| x.name) == Some("wl_display") && opcode == 1 {
+                if let Ok(id) = Cursor::new(&args).read_u32::<NativeEndian>() {
+                    objects.remove(&id);
+                }
+            }
+        }
+    }
+
+    fn enabled(&self, interface: Option<&'static Interface>) -> bool {
+        match (&self.interfaces, interface) {
+            (None, _) => true,
+            (Some(names), Some(interface)) => names.iter().any(|name| name == interface.name),
+            (Some(_), None) => false,
+        }
+    }
+
+    // Decodes one message and records the objects it creates. Returns the
+    // trace line, or None if the filter excludes the sender's interface.
+    fn format(
+        &self,
+        is_event: bool,
+        objects: &mut HashMap<u32, &'static Interface>,
+        object_id: u32,
+        opcode: u16,
+        args: &[u8],
+        fds: &mut dyn Iterator<Item = &RawFd>,
+    ) -> Option<String> {
+        let interface = objects.get(&object_id).cloned();
+        let message: Option<&'static Message> = interface.and_then(|x| {
+            if is_event {
+                x.events.get(opcode as usize)
+            } else {
+                x.requests.get(opcode as usize)
+            }
+        });
+
+        let mut decoded = Vec::new();
+        let mut cursor = Cursor::new(args);
+        if let Some(message) = message {
+            for arg in message.args {
+                match decode_arg(arg, &mut cursor, objects, fds) {
+                    Some(x) => decoded.push(x),
+                    None => {
+                        decoded.push("<truncated>".to_string());
+                        break;
+                    }
+                }
+            }
+        } else {
+            decoded.push(format!("{} bytes", args.len()));
+        }
+
+        if !self.enabled(interface) {
+            return None;
+        }
+
+        let elapsed = self.epoch.elapsed();
+        Some(format!(
+            "[{:7}.{:03}] client#{}[{}] {}{}@{}.{}({})",
+            elapsed.as_secs(),
+            elapsed.subsec_millis(),
+            self.client_id,
+            self.pid,
+            if is_event { " -> " } else { "" },
+            interface.map(|x| x.name).unwrap_or("[unknown]"),
+            object_id,
+            message
+                .map(|x| x.name.to_string())
+                .unwrap_or_else(|| format!("opcode#{}", opcode)),
+            decoded.join(", ")
+        ))
+    }
+}
+
+fn decode_arg(
+    arg: &Arg,
+    cursor: &mut Cursor<&[u8]>,
+    objects: &mut HashMap<u32, &'static Interface>,
+    fds: &mut dyn Iterator<Item = &RawFd>,
+) -> Option<String> {
+    Some(match arg.kind {
+        ArgKind::Int => format!("{}", cursor.read_i32::<NativeEndian>().ok()?),
+        ArgKind::Uint => format!("{}", cursor.read_u32::<NativeEndian>().ok()?),
+        ArgKind::Fixed => format!(
+            "{}",
+            f64::from(cursor.read_i32::<NativeEndian>().ok()?) / 256.0
+        ),
+        ArgKind::String => match decode_string(cursor)? {
+            Some(x) => format!("\"{}\"", x),
+            None => "nil".to_string(),
+        },
+        ArgKind::Object => {
+            let id = cursor.read_u32::<NativeEndian>().ok()?;
+            if id == 0 {
+                "nil".to_string()
+            } else {
+                let name = objects.get(&id).map(|x| x.name).unwrap_or("[unknown]");
+                format!("{}@{}", name, id)
+            }
+        }
+        ArgKind::NewId => {
+            if let Some(name) = arg.interface {
+                let id = cursor.read_u32::<NativeEndian>().ok()?;
+                if let Some(interface) = find_interface(name) {
+                    objects.insert(id, interface);
+                }
+                format!("new id {}@{}", name, id)
+            } else {
+                // Untyped new_id (wl_registry.bind) is sent as interface
+                // name, version and id.
+                let name = decode_string(cursor)?.unwrap_or_default();
+                let version = cursor.read_u32::<NativeEndian>().ok()?;
+                let id = cursor.read_u32::<NativeEndian>().ok()?;
+                if let Some(interface) = find_interface(&name) {
+                    objects.insert(id, interface);
+                }
+                format!("\"{}\", {}, new id {}@{}", name, version, name, id)
+            }
+        }
+        ArgKind::Array => {
+            let len = cursor.read_u32::<NativeEndian>().ok()?;
+            let padded = padded_len(cursor, len)?;
+            cursor.set_position(cursor.position() + padded as u64);
+            format!("array[{}]", len)
+        }
+        ArgKind::Fd => match fds.next() {
+            Some(fd) => format!("fd {}", fd),
+            None => "fd".to_string(),
+        },
+    })
+}
+
+fn decode_string(cursor: &mut Cursor<&[u8]>) -> Option<Option<String>> {
+    let len = cursor.read_u32::<NativeEndian>().ok()?;
+    if len == 0 {
+        return Some(None);
+    }
+    let mut buf = vec![0; padded_len(cursor, len)?];
+    cursor.read_exact(&mut buf).ok()?;
+    buf.truncate(len as usize - 1);
+    Some(Some(String::from_utf8_lossy(&buf).into_owned()))
+}
+
+// The size of a string or array of len bytes with its padding, or None if
+// the rest of the message is shorter than that.
+pub fn padded_len(cursor: &Cursor<&[u8]>, len: u32) -> Option<usize> {
+    let padded = (len as usize).checked_add(3)? & !3;
+    let remaining = cursor
+        .get_ref()
+        .len()
+        .saturating_sub(cursor.position() as usize);
+    if padded > remaining {
+        return None;
+    }
+    Some(padded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lengths_must_fit_in_the_message() {
+        let mut body = vec![0xff, 0xff, 0xff, 0xff];
+        assert_eq!(decode_string(&mut Cursor::new(&body[..])), None);
+
+        body = vec![6, 0, 0, 0];
+        body.extend_from_slice(b"hello\0");
+        assert_eq!(decode_string(&mut Cursor::new(&body[..])), None);
+        body.extend_from_slice(&[0, 0]);
+        assert_eq!(
+            decode_string(&mut Cursor::new(&body[..])),
+            Some(Some("hello".to_string()))
+        );
+
+        let cursor = Cursor::new(&body[..]);
+        assert_eq!(padded_len(&cursor, u32::MAX), None);
+        assert_eq!(padded_len(&cursor, 9), Some(12));
+        assert_eq!(padded_len(&cursor, 13), None);
+    }
+}
@@ -23,12 +23,15 @@
 // CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
 // SOFTWARE.
 
+#[allow(unused_imports)]
+use crate::protocol::resource::Resource;
 #[allow(unused_imports)]
 use crate::protocol::session::{Context, NextAction, Session};
 #[allow(unused_imports)]
 use futures::future::{err, ok, Future};
 #[allow(unused_imports)]
 use futures::sink::Sink;
+use std::collections::HashMap;
 #[allow(unused_imports)]
 use std::sync::{Arc, RwLock};
 
@@ -49,9 +52,32 @@ pub struct WlBuffer {
     pub height: i32, // int: buffer height, in pixels
     pub stride: i32, // int: number of bytes from the beginning of one row to the beginning of the next row
     pub format: u32, // uint: buffer pixel format
+    pub state: BufferState,
+}
+
+// Where a buffer is in its round trip between the client and the backend.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BufferState {
+    // The client may draw into it: it was never committed, or it was
+    // released.
+    Released,
+    // Attached to a surface whose commit has not come yet.
+    Attached,
+    // Committed to a backend that copies buffers. It is released as soon as
+    // the commit has been relayed.
+    Committed,
+    // Committed to a backend that reads it until another commit replaces it.
+    InUse,
 }
 
 impl WlBuffer {
+    pub fn get_mut(resources: &mut HashMap<u32, Resource>, buffer_id: u32) -> Option<&mut Self> {
+        match resources.get_mut(&buffer_id) {
+            Some(Resource::WlBuffer(x)) => Some(x),
+            _ => None,
+        }
+    }
+
     // destroy a buffer
     //
     // Destroy a buffer. If and how you need to release the backing
@@ -59,8 +85,17 @@ impl WlBuffer {
     //
     // For possible side-effects to a surface, see wl_surface.attach.
     pub fn destroy(
-        context: Context<WlBuffer>,
+        mut context: Context<WlBuffer>,
     ) -> Box<dyn Future<Item = (Session, NextAction), Error = ()> + Send> {
-        context.invalid_method("wl_buffer::destroy is not implemented yet".to_string())
+        // Surfaces still showing the buffer keep whatever the backend made
+        // of it, but forget the id, which the client may reuse.
+        let buffer_id = context.sender_object_id;
+        for resource in context.resources.values_mut() {
+            if let Resource::WlSurface(surface) = resource {
+                surface.forget_buffer(buffer_id);
+            }
+        }
+        context.pending_releases.retain(|x| *x != buffer_id);
+        context.destroy()
     }
 }
@@ -23,6 +23,8 @@
 // CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
 // SOFTWARE.
 
+#[allow(unused_imports)]
+use crate::protocol::interface::{Arg, ArgKind, Interface, Message};
 #[allow(unused_imports)]
 use crate::protocol::session::NextAction;
 #[allow(unused_imports)]
@@ -41,6 +43,20 @@ use std::sync::{Arc, RwLock};
 #[allow(dead_code)]
 pub const VERSION: u32 = 1;
 
+#[allow(dead_code)]
+pub static INTERFACE: Interface = Interface {
+    name: "wl_buffer",
+    version: 1,
+    requests: &[Message {
+        name: "destroy",
+        args: &[],
+    }],
+    events: &[Message {
+        name: "release",
+        args: &[],
+    }],
+};
+
 #[allow(unused_variables)]
 #[allow(dead_code)]
 pub fn dispatch_request(
@@ -62,7 +78,7 @@ pub fn dispatch_request(
             let relay_buf = {
                 let total_len = 8;
                 if total_len > 0xffff {
-                    println!("Oops! total_len={}", total_len);
+                    log::error!("Oops! total_len={}", total_len);
                     return Box::new(futures::future::err(()));
                 }
 
@@ -23,6 +23,8 @@
 // CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
 // SOFTWARE.
 
+#[allow(unused_imports)]
+use crate::protocol::interface::{Arg, ArgKind, Interface, Message};
 #[allow(unused_imports)]
 use crate::protocol::session::NextAction;
 #[allow(unused_imports)]
@@ -41,6 +43,22 @@ use std::sync::{Arc, RwLock};
 #[allow(dead_code)]
 pub const VERSION: u32 = 1;
 
+#[allow(dead_code)]
+pub static INTERFACE: Interface = Interface {
+    name: "wl_callback",
+    version: 1,
+    requests: &[],
+    events: &[Message {
+        name: "done",
+        args: &[Arg {
+            name: "callback_data",
+            kind: ArgKind::Uint,
+            interface: None,
+            nullable: false,
+        }],
+    }],
+};
+
 #[allow(unused_variables)]
 #[allow(dead_code)]
 pub fn dispatch_request(
@@ -48,10 +48,13 @@ impl WlCompositor {
     //
     // Ask the compositor to create a new region.
     pub fn create_region(
-        context: Context<Arc<RwLock<WlCompositor>>>,
-        _id: u32, // new_id: the new region
+        mut context: Context<Arc<RwLock<WlCompositor>>>,
+        id: u32, // new_id: the new region
     ) -> Box<dyn Future<Item = (Session, NextAction), Error = ()> + Send> {
-        context.invalid_method("wl_compositor::create_region is not implemented yet".to_string())
+        context
+            .resources
+            .insert(id, wayland::wl_region::WlRegion::default().into());
+        context.handled()
     }
 
     // create new surface
@@ -63,7 +66,7 @@ impl WlCompositor {
     ) -> Box<dyn Future<Item = (Session, NextAction), Error = ()> + Send> {
         context
             .resources
-            .insert(id, wayland::wl_surface::WlSurface {}.into());
+            .insert(id, wayland::wl_surface::WlSurface::default().into());
         return context.ok();
     }
 }
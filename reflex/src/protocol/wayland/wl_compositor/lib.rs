@@ -23,6 +23,8 @@
 // CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
 // SOFTWARE.
 
+#[allow(unused_imports)]
+use crate::protocol::interface::{Arg, ArgKind, Interface, Message};
 #[allow(unused_imports)]
 use crate::protocol::session::NextAction;
 #[allow(unused_imports)]
@@ -43,6 +45,33 @@ pub const GLOBAL_SINGLETON_NAME: u32 = 2;
 #[allow(dead_code)]
 pub const VERSION: u32 = 4;
 
+#[allow(dead_code)]
+pub static INTERFACE: Interface = Interface {
+    name: "wl_compositor",
+    version: 4,
+    requests: &[
+        Message {
+            name: "create_surface",
+            args: &[Arg {
+                name: "id",
+                kind: ArgKind::NewId,
+                interface: Some("wl_surface"),
+                nullable: false,
+            }],
+        },
+        Message {
+            name: "create_region",
+            args: &[Arg {
+                name: "id",
+                kind: ArgKind::NewId,
+                interface: Some("wl_region"),
+                nullable: false,
+            }],
+        },
+    ],
+    events: &[],
+};
+
 #[allow(unused_variables)]
 #[allow(dead_code)]
 pub fn dispatch_request(
@@ -75,7 +104,7 @@ pub fn dispatch_request(
             let relay_buf = {
                 let total_len = 8 + 4;
                 if total_len > 0xffff {
-                    println!("Oops! total_len={}", total_len);
+                    log::error!("Oops! total_len={}", total_len);
                     return Box::new(futures::future::err(()));
                 }
 
@@ -132,7 +161,7 @@ pub fn dispatch_request(
             let relay_buf = {
                 let total_len = 8 + 4;
                 if total_len > 0xffff {
-                    println!("Oops! total_len={}", total_len);
+                    log::error!("Oops! total_len={}", total_len);
                     return Box::new(futures::future::err(()));
                 }
 
@@ -25,6 +25,7 @@
 
 #[allow(unused_imports)]
 use crate::protocol::session::{Context, NextAction, Session};
+use crate::protocol::wayland::wl_surface::{Role, WlSurface};
 #[allow(unused_imports)]
 use futures::future::{err, ok, Future};
 #[allow(unused_imports)]
@@ -100,12 +101,20 @@ impl WlDataDevice {
     // as an icon ends, the current and pending input regions become
     // undefined, and the wl_surface is unmapped.
     pub fn start_drag(
-        context: Context<WlDataDevice>,
+        mut context: Context<WlDataDevice>,
         _source: u32, // object: data source for the eventual transfer
         _origin: u32, // object: surface where the drag originates
-        _icon: u32,   // object: drag-and-drop icon surface
+        icon: u32,    // object: drag-and-drop icon surface
         _serial: u32, // uint: serial number of the implicit grab on the origin
     ) -> Box<dyn Future<Item = (Session, NextAction), Error = ()> + Send> {
+        // The icon takes its role even though the drag itself cannot start
+        // yet.
+        if let Some(x) = WlSurface::get_mut(&mut context.resources, icon) {
+            if let Err(message) = x.check_role(icon, Role::DragIcon) {
+                return context.protocol_error(enums::Error::Role as u32, message);
+            }
+            x.role = Some(Role::DragIcon);
+        }
         context.invalid_method("wl_data_device::start_drag is not implemented yet".to_string())
     }
 }
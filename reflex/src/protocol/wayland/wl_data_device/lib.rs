@@ -23,6 +23,8 @@
 // CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
 // SOFTWARE.
 
+#[allow(unused_imports)]
+use crate::protocol::interface::{Arg, ArgKind, Interface, Message};
 #[allow(unused_imports)]
 use crate::protocol::session::NextAction;
 #[allow(unused_imports)]
@@ -41,6 +43,150 @@ use std::sync::{Arc, RwLock};
 #[allow(dead_code)]
 pub const VERSION: u32 = 3;
 
+#[allow(dead_code)]
+pub static INTERFACE: Interface = Interface {
+    name: "wl_data_device",
+    version: 3,
+    requests: &[
+        Message {
+            name: "start_drag",
+            args: &[
+                Arg {
+                    name: "source",
+                    kind: ArgKind::Object,
+                    interface: Some("wl_data_source"),
+                    nullable: true,
+                },
+                Arg {
+                    name: "origin",
+                    kind: ArgKind::Object,
+                    interface: Some("wl_surface"),
+                    nullable: false,
+                },
+                Arg {
+                    name: "icon",
+                    kind: ArgKind::Object,
+                    interface: Some("wl_surface"),
+                    nullable: true,
+                },
+                Arg {
+                    name: "serial",
+                    kind: ArgKind::Uint,
+                    interface: None,
+                    nullable: false,
+                },
+            ],
+        },
+        Message {
+            name: "set_selection",
+            args: &[
+                Arg {
+                    name: "source",
+                    kind: ArgKind::Object,
+                    interface: Some("wl_data_source"),
+                    nullable: true,
+                },
+                Arg {
+                    name: "serial",
+                    kind: ArgKind::Uint,
+                    interface: None,
+                    nullable: false,
+                },
+            ],
+        },
+        Message {
+            name: "release",
+            args: &[],
+        },
+    ],
+    events: &[
+        Message {
+            name: "data_offer",
+            args: &[Arg {
+                name: "id",
+                kind: ArgKind::NewId,
+                interface: Some("wl_data_offer"),
+                nullable: false,
+            }],
+        },
+        Message {
+            name: "enter",
+            args: &[
+                Arg {
+                    name: "serial",
+                    kind: ArgKind::Uint,
+                    interface: None,
+                    nullable: false,
+                },
+                Arg {
+                    name: "surface",
+                    kind: ArgKind::Object,
+                    interface: Some("wl_surface"),
+                    nullable: false,
+                },
+                Arg {
+                    name: "x",
+                    kind: ArgKind::Fixed,
+                    interface: None,
+                    nullable: false,
+                },
+                Arg {
+                    name: "y",
+                    kind: ArgKind::Fixed,
+                    interface: None,
+                    nullable: false,
+                },
+                Arg {
+                    name: "id",
+                    kind: ArgKind::Object,
+                    interface: Some("wl_data_offer"),
+                    nullable: true,
+                },
+            ],
+        },
+        Message {
+            name: "leave",
+            args: &[],
+        },
+        Message {
+            name: "motion",
+            args: &[
+                Arg {
+                    name: "time",
+                    kind: ArgKind::Uint,
+                    interface: None,
+                    nullable: false,
+                },
+                Arg {
+                    name: "x",
+                    kind: ArgKind::Fixed,
+                    interface: None,
+                    nullable: false,
+                },
+                Arg {
+                    name: "y",
+                    kind: ArgKind::Fixed,
+                    interface: None,
+                    nullable: false,
+                },
+            ],
+        },
+        Message {
+            name: "drop",
+            args: &[],
+        },
+        Message {
+            name: "selection",
+            args: &[Arg {
+                name: "id",
+                kind: ArgKind::Object,
+                interface: Some("wl_data_offer"),
+                nullable: true,
+            }],
+        },
+    ],
+};
+
 #[allow(unused_variables)]
 #[allow(dead_code)]
 pub fn dispatch_request(
@@ -97,7 +243,7 @@ pub fn dispatch_request(
             let relay_buf = {
                 let total_len = 8 + 4 + 4 + 4 + 4;
                 if total_len > 0xffff {
-                    println!("Oops! total_len={}", total_len);
+                    log::error!("Oops! total_len={}", total_len);
                     return Box::new(futures::future::err(()));
                 }
 
@@ -171,7 +317,7 @@ pub fn dispatch_request(
             let relay_buf = {
                 let total_len = 8 + 4 + 4;
                 if total_len > 0xffff {
-                    println!("Oops! total_len={}", total_len);
+                    log::error!("Oops! total_len={}", total_len);
                     return Box::new(futures::future::err(()));
                 }
 
@@ -221,7 +367,7 @@ pub fn dispatch_request(
             let relay_buf = {
                 let total_len = 8;
                 if total_len > 0xffff {
-                    println!("Oops! total_len={}", total_len);
+                    log::error!("Oops! total_len={}", total_len);
                     return Box::new(futures::future::err(()));
                 }
 
@@ -23,6 +23,8 @@
 // CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
 // SOFTWARE.
 
+#[allow(unused_imports)]
+use crate::protocol::interface::{Arg, ArgKind, Interface, Message};
 #[allow(unused_imports)]
 use crate::protocol::session::NextAction;
 #[allow(unused_imports)]
@@ -43,6 +45,41 @@ pub const GLOBAL_SINGLETON_NAME: u32 = 5;
 #[allow(dead_code)]
 pub const VERSION: u32 = 3;
 
+#[allow(dead_code)]
+pub static INTERFACE: Interface = Interface {
+    name: "wl_data_device_manager",
+    version: 3,
+    requests: &[
+        Message {
+            name: "create_data_source",
+            args: &[Arg {
+                name: "id",
+                kind: ArgKind::NewId,
+                interface: Some("wl_data_source"),
+                nullable: false,
+            }],
+        },
+        Message {
+            name: "get_data_device",
+            args: &[
+                Arg {
+                    name: "id",
+                    kind: ArgKind::NewId,
+                    interface: Some("wl_data_device"),
+                    nullable: false,
+                },
+                Arg {
+                    name: "seat",
+                    kind: ArgKind::Object,
+                    interface: Some("wl_seat"),
+                    nullable: false,
+                },
+            ],
+        },
+    ],
+    events: &[],
+};
+
 #[allow(unused_variables)]
 #[allow(dead_code)]
 pub fn dispatch_request(
@@ -75,7 +112,7 @@ pub fn dispatch_request(
             let relay_buf = {
                 let total_len = 8 + 4;
                 if total_len > 0xffff {
-                    println!("Oops! total_len={}", total_len);
+                    log::error!("Oops! total_len={}", total_len);
                     return Box::new(futures::future::err(()));
                 }
 
@@ -140,7 +177,7 @@ pub fn dispatch_request(
             let relay_buf = {
                 let total_len = 8 + 4 + 4;
                 if total_len > 0xffff {
-                    println!("Oops! total_len={}", total_len);
+                    log::error!("Oops! total_len={}", total_len);
                     return Box::new(futures::future::err(()));
                 }
 
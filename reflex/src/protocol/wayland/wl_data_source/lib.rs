@@ -23,6 +23,8 @@
 // CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
 // SOFTWARE.
 
+#[allow(unused_imports)]
+use crate::protocol::interface::{Arg, ArgKind, Interface, Message};
 #[allow(unused_imports)]
 use crate::protocol::session::NextAction;
 #[allow(unused_imports)]
@@ -41,6 +43,85 @@ use std::sync::{Arc, RwLock};
 #[allow(dead_code)]
 pub const VERSION: u32 = 3;
 
+#[allow(dead_code)]
+pub static INTERFACE: Interface = Interface {
+    name: "wl_data_source",
+    version: 3,
+    requests: &[
+        Message {
+            name: "offer",
+            args: &[Arg {
+                name: "mime_type",
+                kind: ArgKind::String,
+                interface: None,
+                nullable: false,
+            }],
+        },
+        Message {
+            name: "destroy",
+            args: &[],
+        },
+        Message {
+            name: "set_actions",
+            args: &[Arg {
+                name: "dnd_actions",
+                kind: ArgKind::Uint,
+                interface: None,
+                nullable: false,
+            }],
+        },
+    ],
+    events: &[
+        Message {
+            name: "target",
+            args: &[Arg {
+                name: "mime_type",
+                kind: ArgKind::String,
+                interface: None,
+                nullable: true,
+            }],
+        },
+        Message {
+            name: "send",
+            args: &[
+                Arg {
+                    name: "mime_type",
+                    kind: ArgKind::String,
+                    interface: None,
+                    nullable: false,
+                },
+                Arg {
+                    name: "fd",
+                    kind: ArgKind::Fd,
+                    interface: None,
+                    nullable: false,
+                },
+            ],
+        },
+        Message {
+            name: "cancelled",
+            args: &[],
+        },
+        Message {
+            name: "dnd_drop_performed",
+            args: &[],
+        },
+        Message {
+            name: "dnd_finished",
+            args: &[],
+        },
+        Message {
+            name: "action",
+            args: &[Arg {
+                name: "dnd_action",
+                kind: ArgKind::Uint,
+                interface: None,
+                nullable: false,
+            }],
+        },
+    ],
+};
+
 #[allow(unused_variables)]
 #[allow(dead_code)]
 pub fn dispatch_request(
@@ -94,7 +175,7 @@ pub fn dispatch_request(
             let relay_buf = {
                 let total_len = 8 + { 4 + (arg_mime_type.len() + 1 + 3) / 4 * 4 };
                 if total_len > 0xffff {
-                    println!("Oops! total_len={}", total_len);
+                    log::error!("Oops! total_len={}", total_len);
                     return Box::new(futures::future::err(()));
                 }
 
@@ -153,7 +234,7 @@ pub fn dispatch_request(
             let relay_buf = {
                 let total_len = 8;
                 if total_len > 0xffff {
-                    println!("Oops! total_len={}", total_len);
+                    log::error!("Oops! total_len={}", total_len);
                     return Box::new(futures::future::err(()));
                 }
 
@@ -206,7 +287,7 @@ pub fn dispatch_request(
             let relay_buf = {
                 let total_len = 8 + 4;
                 if total_len > 0xffff {
-                    println!("Oops! total_len={}", total_len);
+                    log::error!("Oops! total_len={}", total_len);
                     return Box::new(futures::future::err(()));
                 }
 
@@ -23,6 +23,8 @@
 // CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
 // SOFTWARE.
 
+use crate::lock::RwLockExt;
+use crate::protocol::event::Event;
 #[allow(unused_imports)]
 use crate::protocol::session::{Context, NextAction, Session};
 #[allow(unused_imports)]
@@ -59,11 +61,40 @@ impl WlDisplay {
         mut context: Context<Arc<RwLock<WlDisplay>>>,
         registry: u32, // new_id: global registry object
     ) -> Box<dyn Future<Item = (Session, NextAction), Error = ()> + Send> {
-        println!("WlDisplay::get_registry({})", registry);
+        log::debug!(
+            client_id = context.client_id,
+            object_id = registry,
+            interface = "wl_registry";
+            "wl_display::get_registry"
+        );
         context
             .resources
             .insert(registry, context.wl_registry.clone().into());
-        context.ok()
+        // The backend announces its own globals when the request is relayed.
+        let globals: Vec<Box<dyn Event + Send>> = context
+            .wl_registry
+            .read_locked()
+            .registered
+            .iter()
+            .map(|global| {
+                Box::new(crate::protocol::wayland::wl_registry::events::Global {
+                    sender_object_id: registry,
+                    name: global.name,
+                    interface: global.interface.name.to_string(),
+                    version: global.version,
+                }) as Box<dyn Event + Send>
+            })
+            .collect();
+        if globals.is_empty() {
+            return context.ok();
+        }
+        let tx = context.tx.clone();
+        let session: Session = context.into();
+        Box::new(
+            tx.send_all(futures::stream::iter_ok(globals))
+                .map_err(|_| ())
+                .map(|_| (session, NextAction::Relay)),
+        )
     }
 
     // asynchronous roundtrip
@@ -83,7 +114,12 @@ impl WlDisplay {
         context: Context<Arc<RwLock<WlDisplay>>>,
         callback: u32, // new_id: callback object for the sync request
     ) -> Box<dyn Future<Item = (Session, NextAction), Error = ()> + Send> {
-        println!("WlDisplay::sync({})", callback);
+        log::debug!(
+            client_id = context.client_id,
+            object_id = callback,
+            interface = "wl_callback";
+            "wl_display::sync"
+        );
         context.ok()
     }
 }
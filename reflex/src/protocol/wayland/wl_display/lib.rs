@@ -23,6 +23,8 @@
 // CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
 // SOFTWARE.
 
+#[allow(unused_imports)]
+use crate::protocol::interface::{Arg, ArgKind, Interface, Message};
 #[allow(unused_imports)]
 use crate::protocol::session::NextAction;
 #[allow(unused_imports)]
@@ -43,6 +45,66 @@ pub const GLOBAL_SINGLETON_NAME: u32 = 1;
 #[allow(dead_code)]
 pub const VERSION: u32 = 1;
 
+#[allow(dead_code)]
+pub static INTERFACE: Interface = Interface {
+    name: "wl_display",
+    version: 1,
+    requests: &[
+        Message {
+            name: "sync",
+            args: &[Arg {
+                name: "callback",
+                kind: ArgKind::NewId,
+                interface: Some("wl_callback"),
+                nullable: false,
+            }],
+        },
+        Message {
+            name: "get_registry",
+            args: &[Arg {
+                name: "registry",
+                kind: ArgKind::NewId,
+                interface: Some("wl_registry"),
+                nullable: false,
+            }],
+        },
+    ],
+    events: &[
+        Message {
+            name: "error",
+            args: &[
+                Arg {
+                    name: "object_id",
+                    kind: ArgKind::Object,
+                    interface: None,
+                    nullable: false,
+                },
+                Arg {
+                    name: "code",
+                    kind: ArgKind::Uint,
+                    interface: None,
+                    nullable: false,
+                },
+                Arg {
+                    name: "message",
+                    kind: ArgKind::String,
+                    interface: None,
+                    nullable: false,
+                },
+            ],
+        },
+        Message {
+            name: "delete_id",
+            args: &[Arg {
+                name: "id",
+                kind: ArgKind::Uint,
+                interface: None,
+                nullable: false,
+            }],
+        },
+    ],
+};
+
 #[allow(unused_variables)]
 #[allow(dead_code)]
 pub fn dispatch_request(
@@ -75,7 +137,7 @@ pub fn dispatch_request(
             let relay_buf = {
                 let total_len = 8 + 4;
                 if total_len > 0xffff {
-                    println!("Oops! total_len={}", total_len);
+                    log::error!("Oops! total_len={}", total_len);
                     return Box::new(futures::future::err(()));
                 }
 
@@ -130,7 +192,7 @@ pub fn dispatch_request(
             let relay_buf = {
                 let total_len = 8 + 4;
                 if total_len > 0xffff {
-                    println!("Oops! total_len={}", total_len);
+                    log::error!("Oops! total_len={}", total_len);
                     return Box::new(futures::future::err(()));
                 }
 
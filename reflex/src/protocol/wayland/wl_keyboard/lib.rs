@@ -23,6 +23,8 @@
 // CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
 // SOFTWARE.
 
+#[allow(unused_imports)]
+use crate::protocol::interface::{Arg, ArgKind, Interface, Message};
 #[allow(unused_imports)]
 use crate::protocol::session::NextAction;
 #[allow(unused_imports)]
@@ -41,6 +43,162 @@ use std::sync::{Arc, RwLock};
 #[allow(dead_code)]
 pub const VERSION: u32 = 6;
 
+#[allow(dead_code)]
+pub static INTERFACE: Interface = Interface {
+    name: "wl_keyboard",
+    version: 6,
+    requests: &[Message {
+        name: "release",
+        args: &[],
+    }],
+    events: &[
+        Message {
+            name: "keymap",
+            args: &[
+                Arg {
+                    name: "format",
+                    kind: ArgKind::Uint,
+                    interface: None,
+                    nullable: false,
+                },
+                Arg {
+                    name: "fd",
+                    kind: ArgKind::Fd,
+                    interface: None,
+                    nullable: false,
+                },
+                Arg {
+                    name: "size",
+                    kind: ArgKind::Uint,
+                    interface: None,
+                    nullable: false,
+                },
+            ],
+        },
+        Message {
+            name: "enter",
+            args: &[
+                Arg {
+                    name: "serial",
+                    kind: ArgKind::Uint,
+                    interface: None,
+                    nullable: false,
+                },
+                Arg {
+                    name: "surface",
+                    kind: ArgKind::Object,
+                    interface: Some("wl_surface"),
+                    nullable: false,
+                },
+                Arg {
+                    name: "keys",
+                    kind: ArgKind::Array,
+                    interface: None,
+                    nullable: false,
+                },
+            ],
+        },
+        Message {
+            name: "leave",
+            args: &[
+                Arg {
+                    name: "serial",
+                    kind: ArgKind::Uint,
+                    interface: None,
+                    nullable: false,
+                },
+                Arg {
+                    name: "surface",
+                    kind: ArgKind::Object,
+                    interface: Some("wl_surface"),
+                    nullable: false,
+                },
+            ],
+        },
+        Message {
+            name: "key",
+            args: &[
+                Arg {
+                    name: "serial",
+                    kind: ArgKind::Uint,
+                    interface: None,
+                    nullable: false,
+                },
+                Arg {
+                    name: "time",
+                    kind: ArgKind::Uint,
+                    interface: None,
+                    nullable: false,
+                },
+                Arg {
+                    name: "key",
+                    kind: ArgKind::Uint,
+                    interface: None,
+                    nullable: false,
+                },
+                Arg {
+                    name: "state",
+                    kind: ArgKind::Uint,
+                    interface: None,
+                    nullable: false,
+                },
+            ],
+        },
+        Message {
+            name: "modifiers",
+            args: &[
+                Arg {
+                    name: "serial",
+                    kind: ArgKind::Uint,
+                    interface: None,
+                    nullable: false,
+                },
+                Arg {
+                    name: "mods_depressed",
+                    kind: ArgKind::Uint,
+                    interface: None,
+                    nullable: false,
+                },
+                Arg {
+                    name: "mods_latched",
+                    kind: ArgKind::Uint,
+                    interface: None,
+                    nullable: false,
+                },
+                Arg {
+                    name: "mods_locked",
+                    kind: ArgKind::Uint,
+                    interface: None,
+                    nullable: false,
+                },
+                Arg {
+                    name: "group",
+                    kind: ArgKind::Uint,
+                    interface: None,
+                    nullable: false,
+                },
+            ],
+        },
+        Message {
+            name: "repeat_info",
+            args: &[
+                Arg {
+                    name: "rate",
+                    kind: ArgKind::Int,
+                    interface: None,
+                    nullable: false,
+                },
+                Arg {
+                    name: "delay",
+                    kind: ArgKind::Int,
+                    interface: None,
+                    nullable: false,
+                },
+            ],
+        },
+    ],
+};
+
 #[allow(unused_variables)]
 #[allow(dead_code)]
 pub fn dispatch_request(
@@ -62,7 +220,7 @@ pub fn dispatch_request(
             let relay_buf = {
                 let total_len = 8;
                 if total_len > 0xffff {
-                    println!("Oops! total_len={}", total_len);
+                    log::error!("Oops! total_len={}", total_len);
                     return Box::new(futures::future::err(()));
                 }
 
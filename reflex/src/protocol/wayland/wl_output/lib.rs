@@ -23,6 +23,8 @@
 // CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
 // SOFTWARE.
 
+#[allow(unused_imports)]
+use crate::protocol::interface::{Arg, ArgKind, Interface, Message};
 #[allow(unused_imports)]
 use crate::protocol::session::NextAction;
 #[allow(unused_imports)]
@@ -41,6 +43,113 @@ use std::sync::{Arc, RwLock};
 #[allow(dead_code)]
 pub const VERSION: u32 = 3;
 
+#[allow(dead_code)]
+pub static INTERFACE: Interface = Interface {
+    name: "wl_output",
+    version: 3,
+    requests: &[Message {
+        name: "release",
+        args: &[],
+    }],
+    events: &[
+        Message {
+            name: "geometry",
+            args: &[
+                Arg {
+                    name: "x",
+                    kind: ArgKind::Int,
+                    interface: None,
+                    nullable: false,
+                },
+                Arg {
+                    name: "y",
+                    kind: ArgKind::Int,
+                    interface: None,
+                    nullable: false,
+                },
+                Arg {
+                    name: "physical_width",
+                    kind: ArgKind::Int,
+                    interface: None,
+                    nullable: false,
+                },
+                Arg {
+                    name: "physical_height",
+                    kind: ArgKind::Int,
+                    interface: None,
+                    nullable: false,
+                },
+                Arg {
+                    name: "subpixel",
+                    kind: ArgKind::Int,
+                    interface: None,
+                    nullable: false,
+                },
+                Arg {
+                    name: "make",
+                    kind: ArgKind::String,
+                    interface: None,
+                    nullable: false,
+                },
+                Arg {
+                    name: "model",
+                    kind: ArgKind::String,
+                    interface: None,
+                    nullable: false,
+                },
+                Arg {
+                    name: "transform",
+                    kind: ArgKind::Int,
+                    interface: None,
+                    nullable: false,
+                },
+            ],
+        },
+        Message {
+            name: "mode",
+            args: &[
+                Arg {
+                    name: "flags",
+                    kind: ArgKind::Uint,
+                    interface: None,
+                    nullable: false,
+                },
+                Arg {
+                    name: "width",
+                    kind: ArgKind::Int,
+                    interface: None,
+                    nullable: false,
+                },
+                Arg {
+                    name: "height",
+                    kind: ArgKind::Int,
+                    interface: None,
+                    nullable: false,
+                },
+                Arg {
+                    name: "refresh",
+                    kind: ArgKind::Int,
+                    interface: None,
+                    nullable: false,
+                },
+            ],
+        },
+        Message {
+            name: "done",
+            args: &[],
+        },
+        Message {
+            name: "scale",
+            args: &[Arg {
+                name: "factor",
+                kind: ArgKind::Int,
+                interface: None,
+                nullable: false,
+            }],
+        },
+    ],
+};
+
 #[allow(unused_variables)]
 #[allow(dead_code)]
 pub fn dispatch_request(
@@ -62,7 +171,7 @@ pub fn dispatch_request(
             let relay_buf = {
                 let total_len = 8;
                 if total_len > 0xffff {
-                    println!("Oops! total_len={}", total_len);
+                    log::error!("Oops! total_len={}", total_len);
                     return Box::new(futures::future::err(()));
                 }
 
@@ -25,6 +25,7 @@
 
 #[allow(unused_imports)]
 use crate::protocol::session::{Context, NextAction, Session};
+use crate::protocol::wayland::wl_surface::{Role, WlSurface};
 #[allow(unused_imports)]
 use futures::future::{err, ok, Future};
 #[allow(unused_imports)]
@@ -60,7 +61,7 @@ impl WlPointer {
     pub fn release(
         context: Context<WlPointer>,
     ) -> Box<dyn Future<Item = (Session, NextAction), Error = ()> + Send> {
-        context.invalid_method("wl_pointer::release is not implemented yet".to_string())
+        context.destroy()
     }
 
     // set the pointer surface
@@ -97,12 +98,18 @@ impl WlPointer {
     // cursor ends, the current and pending input regions become
     // undefined, and the wl_surface is unmapped.
     pub fn set_cursor(
-        context: Context<WlPointer>,
+        mut context: Context<WlPointer>,
         _serial: u32,    // uint: serial number of the enter event
-        _surface: u32,   // object: pointer surface
+        surface: u32,    // object: pointer surface
         _hotspot_x: i32, // int: surface-local x coordinate
         _hotspot_y: i32, // int: surface-local y coordinate
     ) -> Box<dyn Future<Item = (Session, NextAction), Error = ()> + Send> {
-        context.invalid_method("wl_pointer::set_cursor is not implemented yet".to_string())
+        if let Some(x) = WlSurface::get_mut(&mut context.resources, surface) {
+            if let Err(message) = x.check_role(surface, Role::Cursor) {
+                return context.protocol_error(enums::Error::Role as u32, message);
+            }
+            x.role = Some(Role::Cursor);
+        }
+        context.ok()
     }
 }
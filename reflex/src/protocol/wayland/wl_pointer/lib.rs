@@ -23,6 +23,8 @@
 // CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
 // SOFTWARE.
 
+#[allow(unused_imports)]
+use crate::protocol::interface::{Arg, ArgKind, Interface, Message};
 #[allow(unused_imports)]
 use crate::protocol::session::NextAction;
 #[allow(unused_imports)]
@@ -41,6 +43,217 @@ use std::sync::{Arc, RwLock};
 #[allow(dead_code)]
 pub const VERSION: u32 = 6;
 
+#[allow(dead_code)]
+pub static INTERFACE: Interface = Interface {
+    name: "wl_pointer",
+    version: 6,
+    requests: &[
+        Message {
+            name: "set_cursor",
+            args: &[
+                Arg {
+                    name: "serial",
+                    kind: ArgKind::Uint,
+                    interface: None,
+                    nullable: false,
+                },
+                Arg {
+                    name: "surface",
+                    kind: ArgKind::Object,
+                    interface: Some("wl_surface"),
+                    nullable: true,
+                },
+                Arg {
+                    name: "hotspot_x",
+                    kind: ArgKind::Int,
+                    interface: None,
+                    nullable: false,
+                },
+                Arg {
+                    name: "hotspot_y",
+                    kind: ArgKind::Int,
+                    interface: None,
+                    nullable: false,
+                },
+            ],
+        },
+        Message {
+            name: "release",
+            args: &[],
+        },
+    ],
+    events: &[
+        Message {
+            name: "enter",
+            args: &[
+                Arg {
+                    name: "serial",
+                    kind: ArgKind::Uint,
+                    interface: None,
+                    nullable: false,
+                },
+                Arg {
+                    name: "surface",
+                    kind: ArgKind::Object,
+                    interface: Some("wl_surface"),
+                    nullable: false,
+                },
+                Arg {
+                    name: "surface_x",
+                    kind: ArgKind::Fixed,
+                    interface: None,
+                    nullable: false,
+                },
+                Arg {
+                    name: "surface_y",
+                    kind: ArgKind::Fixed,
+                    interface: None,
+                    nullable: false,
+                },
+            ],
+        },
+        Message {
+            name: "leave",
+            args: &[
+                Arg {
+                    name: "serial",
+                    kind: ArgKind::Uint,
+                    interface: None,
+                    nullable: false,
+                },
+                Arg {
+                    name: "surface",
+                    kind: ArgKind::Object,
+                    interface: Some("wl_surface"),
+                    nullable: false,
+                },
+            ],
+        },
+        Message {
+            name: "motion",
+            args: &[
+                Arg {
+                    name: "time",
+                    kind: ArgKind::Uint,
+                    interface: None,
+                    nullable: false,
+                },
+                Arg {
+                    name: "surface_x",
+                    kind: ArgKind::Fixed,
+                    interface: None,
+                    nullable: false,
+                },
+                Arg {
+                    name: "surface_y",
+                    kind: ArgKind::Fixed,
+                    interface: None,
+                    nullable: false,
+                },
+            ],
+        },
+        Message {
+            name: "button",
+            args: &[
+                Arg {
+                    name: "serial",
+                    kind: ArgKind::Uint,
+                    interface: None,
+                    nullable: false,
+                },
+                Arg {
+                    name: "time",
+                    kind: ArgKind::Uint,
+                    interface: None,
+                    nullable: false,
+                },
+                Arg {
+                    name: "button",
+                    kind: ArgKind::Uint,
+                    interface: None,
+                    nullable: false,
+                },
+                Arg {
+                    name: "state",
+                    kind: ArgKind::Uint,
+                    interface: None,
+                    nullable: false,
+                },
+            ],
+        },
+        Message {
+            name: "axis",
+            args: &[
+                Arg {
+                    name: "time",
+                    kind: ArgKind::Uint,
+                    interface: None,
+                    nullable: false,
+                },
+                Arg {
+                    name: "axis",
+                    kind: ArgKind::Uint,
+                    interface: None,
+                    nullable: false,
+                },
+                Arg {
+                    name: "value",
+                    kind: ArgKind::Fixed,
+                    interface: None,
+                    nullable: false,
+                },
+            ],
+        },
+        Message {
+            name: "frame",
+            args: &[],
+        },
+        Message {
+            name: "axis_source",
+            args: &[Arg {
+                name: "axis_source",
+                kind: ArgKind::Uint,
+                interface: None,
+                nullable: false,
+            }],
+        },
+        Message {
+            name: "axis_stop",
+            args: &[
+                Arg {
+                    name: "time",
+                    kind: ArgKind::Uint,
+                    interface: None,
+                    nullable: false,
+                },
+                Arg {
+                    name: "axis",
+                    kind: ArgKind::Uint,
+                    interface: None,
+                    nullable: false,
+                },
+            ],
+        },
+        Message {
+            name: "axis_discrete",
+            args: &[
+                Arg {
+                    name: "axis",
+                    kind: ArgKind::Uint,
+                    interface: None,
+                    nullable: false,
+                },
+                Arg {
+                    name: "discrete",
+                    kind: ArgKind::Int,
+                    interface: None,
+                    nullable: false,
+                },
+            ],
+        },
+    ],
+};
+
 #[allow(unused_variables)]
 #[allow(dead_code)]
 pub fn dispatch_request(
@@ -95,7 +308,7 @@ pub fn dispatch_request(
             let relay_buf = {
                 let total_len = 8 + 4 + 4 + 4 + 4;
                 if total_len > 0xffff {
-                    println!("Oops! total_len={}", total_len);
+                    log::error!("Oops! total_len={}", total_len);
                     return Box::new(futures::future::err(()));
                 }
 
@@ -156,7 +369,7 @@ pub fn dispatch_request(
             let relay_buf = {
                 let total_len = 8;
                 if total_len > 0xffff {
-                    println!("Oops! total_len={}", total_len);
+                    log::error!("Oops! total_len={}", total_len);
                     return Box::new(futures::future::err(()));
                 }
 
@@ -23,6 +23,8 @@
 // CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
 // SOFTWARE.
 
+use crate::compositor::region::Region;
+use crate::compositor::Rect;
 #[allow(unused_imports)]
 use crate::protocol::session::{Context, NextAction, Session};
 #[allow(unused_imports)]
@@ -41,20 +43,28 @@ pub use lib::*;
 //
 // Region objects are used to describe the opaque and input
 // regions of a surface.
-pub struct WlRegion {}
+//
+// Regions only exist in reflex: none of their requests are relayed, and
+// backends get them resolved in the committed surface state.
+#[derive(Default)]
+pub struct WlRegion {
+    pub region: Region,
+}
 
 impl WlRegion {
     // add rectangle to region
     //
     // Add the specified rectangle to the region.
     pub fn add(
-        context: Context<WlRegion>,
-        _x: i32,      // int: region-local x coordinate
-        _y: i32,      // int: region-local y coordinate
-        _width: i32,  // int: rectangle width
-        _height: i32, // int: rectangle height
+        mut context: Context<WlRegion>,
+        x: i32,      // int: region-local x coordinate
+        y: i32,      // int: region-local y coordinate
+        width: i32,  // int: rectangle width
+        height: i32, // int: rectangle height
     ) -> Box<dyn Future<Item = (Session, NextAction), Error = ()> + Send> {
-        context.invalid_method("wl_region::add is not implemented yet".to_string())
+        let rect = Region::from_rect(Rect::new(x, y, width, height));
+        context.sender_object.region = context.sender_object.region.union(&rect);
+        context.handled()
     }
 
     // destroy region
@@ -63,19 +73,21 @@ impl WlRegion {
     pub fn destroy(
         context: Context<WlRegion>,
     ) -> Box<dyn Future<Item = (Session, NextAction), Error = ()> + Send> {
-        context.invalid_method("wl_region::destroy is not implemented yet".to_string())
+        context.destroy_handled()
     }
 
     // subtract rectangle from region
     //
     // Subtract the specified rectangle from the region.
     pub fn subtract(
-        context: Context<WlRegion>,
-        _x: i32,      // int: region-local x coordinate
-        _y: i32,      // int: region-local y coordinate
-        _width: i32,  // int: rectangle width
-        _height: i32, // int: rectangle height
+        mut context: Context<WlRegion>,
+        x: i32,      // int: region-local x coordinate
+        y: i32,      // int: region-local y coordinate
+        width: i32,  // int: rectangle width
+        height: i32, // int: rectangle height
     ) -> Box<dyn Future<Item = (Session, NextAction), Error = ()> + Send> {
-        context.invalid_method("wl_region::subtract is not implemented yet".to_string())
+        let rect = Region::from_rect(Rect::new(x, y, width, height));
+        context.sender_object.region = context.sender_object.region.subtract(&rect);
+        context.handled()
     }
 }
@@ -23,6 +23,9 @@
 // CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
 // SOFTWARE.
 
+use crate::lock::RwLockExt;
+use crate::protocol::interface::Interface;
+use crate::protocol::resource::Resource;
 use crate::protocol::session::{Context, NextAction, Session};
 use byteorder::{ByteOrder, NativeEndian, ReadBytesExt};
 use futures::future::{ok, Future};
@@ -33,7 +36,7 @@ use std::sync::{Arc, RwLock};
 
 pub mod events;
 mod lib;
-pub use lib::{GLOBAL_SINGLETON_NAME, VERSION};
+pub use lib::{GLOBAL_SINGLETON_NAME, INTERFACE, VERSION};
 
 pub fn dispatch_request(
     context: crate::protocol::session::Context<
@@ -64,7 +67,7 @@ pub fn dispatch_request(
     name_buf.resize(name_buf_len, 0);
     cursor.read_exact(&mut name_buf).unwrap();
     cursor.set_position(cursor.position() + (name_buf_len_with_pad - name_buf_len) as u64);
-    let _version = if let Ok(x) = cursor.read_u32::<NativeEndian>() {
+    let version = if let Ok(x) = cursor.read_u32::<NativeEndian>() {
         x
     } else {
         return context
@@ -86,7 +89,7 @@ pub fn dispatch_request(
     let relay_buf = {
         let total_len = 8 + 4 + 4;
         if total_len > 0xffff {
-            println!("Oops! total_len={}", total_len);
+            log::error!("Oops! total_len={}", total_len);
             return Box::new(futures::future::err(()));
         }
 
@@ -107,21 +110,20 @@ pub fn dispatch_request(
         dst
     };
 
-    Box::new(WlRegistry::bind(context, name, id).and_then(
-        |(session, next_action)| -> Box<
-            dyn futures::future::Future<Item = crate::protocol::session::Session, Error = ()>
-                + Send,
-        > {
-            match next_action {
-                NextAction::Nop => Box::new(futures::future::ok(session)),
-                NextAction::Relay => {
-                    println!("[WlRegistry Relay]: {:?}", &relay_buf);
-                    session.relay(relay_buf)
+    Box::new(
+        WlRegistry::bind_version(context, name, version, id).and_then(
+            |(session, next_action)| -> Box<
+                dyn futures::future::Future<Item = crate::protocol::session::Session, Error = ()>
+                    + Send,
+            > {
+                match next_action {
+                    NextAction::Nop => Box::new(futures::future::ok(session)),
+                    NextAction::Relay => session.relay(relay_buf),
+                    NextAction::RelayWait => session.relay_wait(relay_buf),
                 }
-                NextAction::RelayWait => session.relay_wait(relay_buf),
-            }
-        },
-    ))
+            },
+        ),
+    )
 }
 
 // global registry object
@@ -146,7 +148,32 @@ pub fn dispatch_request(
 // request.  This creates a client-side handle that lets the object
 // emit events to the client and lets the client invoke requests on
 // the object.
-pub struct WlRegistry {}
+#[derive(Clone)]
+pub struct WlRegistry {
+    // Names of the globals turned off in the [protocols] section of the
+    // config. Each client gets a copy when it connects, so that globals
+    // advertised to it stay bindable after a reload.
+    pub disabled_globals: Vec<u32>,
+    // Globals added through Globals::register, which reflex advertises
+    // itself next to those of the backend.
+    pub registered: Vec<RegisteredGlobal>,
+}
+
+// Names of registered globals start here, well clear of the names reflex
+// and its backends use.
+pub const FIRST_REGISTERED_NAME: u32 = 1000;
+
+// A global added by an embedder. Binding it inserts the resource that
+// `bind` makes for the bound version; the bind is then relayed like any
+// other, so requests to the object reach backends that know the interface
+// and are ignored by the headless ones.
+#[derive(Clone)]
+pub struct RegisteredGlobal {
+    pub name: u32,
+    pub interface: &'static Interface,
+    pub version: u32,
+    pub bind: Arc<dyn Fn(u32) -> Resource + Send + Sync>,
+}
 
 impl WlRegistry {
     // bind an object to the display
@@ -154,11 +181,41 @@ impl WlRegistry {
     // Binds a new, client-created object to the server using the
     // specified name as the identifier.
     pub fn bind(
-        mut context: Context<Arc<RwLock<WlRegistry>>>,
+        context: Context<Arc<RwLock<WlRegistry>>>,
         name: u32, // uint: unique numeric name of the object
         id: u32,   // new_id: bounded object
     ) -> Box<dyn Future<Item = (Session, NextAction), Error = ()> + Send> {
-        println!("WlRegistry::bind(name: {}, id: {})", name, id);
+        WlRegistry::bind_version(context, name, 1, id)
+    }
+
+    // bind as it is sent on the wire, where the new_id has no fixed
+    // interface and so comes with the interface name and version.
+    pub fn bind_version(
+        mut context: Context<Arc<RwLock<WlRegistry>>>,
+        name: u32,    // uint: unique numeric name of the object
+        version: u32, // uint: the version the client binds
+        id: u32,      // new_id: bounded object
+    ) -> Box<dyn Future<Item = (Session, NextAction), Error = ()> + Send> {
+        log::debug!(
+            client_id = context.client_id,
+            object_id = id,
+            name = name;
+            "wl_registry::bind"
+        );
+
+        if context
+            .wl_registry
+            .read_locked()
+            .disabled_globals
+            .contains(&name)
+        {
+            // Disabled globals are not advertised, so only a client that
+            // makes up names gets here.
+            return context.protocol_error(
+                crate::protocol::wayland::wl_display::enums::Error::InvalidObject as u32,
+                format!("invalid global {}", name),
+            );
+        }
 
         match name {
             crate::protocol::wayland::wl_registry::GLOBAL_SINGLETON_NAME => {
@@ -195,9 +252,46 @@ impl WlRegistry {
                 context.resources.insert(id, context.wl_shm.clone().into());
                 return context.ok();
             }
+            crate::protocol::wayland::wl_seat::GLOBAL_NAME => {
+                context.resources.insert(
+                    id,
+                    crate::protocol::wayland::wl_seat::WlSeat { version }.into(),
+                );
+                context.backend.global_bound(name, id, version);
+                return context.ok();
+            }
+            crate::protocol::wayland::wl_subcompositor::GLOBAL_NAME => {
+                context.resources.insert(
+                    id,
+                    crate::protocol::wayland::wl_subcompositor::WlSubcompositor {}.into(),
+                );
+                context.backend.global_bound(name, id, version);
+                return context.ok();
+            }
             _ => {}
         }
 
+        let registered = context
+            .wl_registry
+            .read_locked()
+            .registered
+            .iter()
+            .find(|x| x.name == name)
+            .cloned();
+        if let Some(global) = registered {
+            if version == 0 || version > global.version {
+                return context.protocol_error(
+                    crate::protocol::wayland::wl_display::enums::Error::InvalidObject as u32,
+                    format!(
+                        "{} v{} is not supported, up to v{} is",
+                        global.interface.name, version, global.version
+                    ),
+                );
+            }
+            context.resources.insert(id, (global.bind)(version));
+            context.backend.global_bound(name, id, version);
+        }
+
         context.ok()
     }
 }
@@ -23,6 +23,8 @@
 // CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
 // SOFTWARE.
 
+#[allow(unused_imports)]
+use crate::protocol::interface::{Arg, ArgKind, Interface, Message};
 #[allow(unused_imports)]
 use crate::protocol::session::NextAction;
 #[allow(unused_imports)]
@@ -43,6 +45,63 @@ pub const GLOBAL_SINGLETON_NAME: u32 = 4;
 #[allow(dead_code)]
 pub const VERSION: u32 = 1;
 
+#[allow(dead_code)]
+pub static INTERFACE: Interface = Interface {
+    name: "wl_registry",
+    version: 1,
+    requests: &[Message {
+        name: "bind",
+        args: &[
+            Arg {
+                name: "name",
+                kind: ArgKind::Uint,
+                interface: None,
+                nullable: false,
+            },
+            Arg {
+                name: "id",
+                kind: ArgKind::NewId,
+                interface: None,
+                nullable: false,
+            },
+        ],
+    }],
+    events: &[
+        Message {
+            name: "global",
+            args: &[
+                Arg {
+                    name: "name",
+                    kind: ArgKind::Uint,
+                    interface: None,
+                    nullable: false,
+                },
+                Arg {
+                    name: "interface",
+                    kind: ArgKind::String,
+                    interface: None,
+                    nullable: false,
+                },
+                Arg {
+                    name: "version",
+                    kind: ArgKind::Uint,
+                    interface: None,
+                    nullable: false,
+                },
+            ],
+        },
+        Message {
+            name: "global_remove",
+            args: &[Arg {
+                name: "name",
+                kind: ArgKind::Uint,
+                interface: None,
+                nullable: false,
+            }],
+        },
+    ],
+};
+
 #[allow(unused_variables)]
 #[allow(dead_code)]
 pub fn dispatch_request(
@@ -83,7 +142,7 @@ pub fn dispatch_request(
             let relay_buf = {
                 let total_len = 8 + 4 + 4;
                 if total_len > 0xffff {
-                    println!("Oops! total_len={}", total_len);
+                    log::error!("Oops! total_len={}", total_len);
                     return Box::new(futures::future::err(()));
                 }
 
@@ -43,7 +43,13 @@ pub use lib::*;
 // object is published as a global during start up, or when such a
 // device is hot plugged.  A seat typically has a pointer and
 // maintains a keyboard focus and a pointer focus.
-pub struct WlSeat {}
+pub struct WlSeat {
+    pub version: u32,
+}
+
+// Unlike the singletons, every bind of wl_seat gets its own WlSeat, but the
+// seat is still advertised under a fixed name next to them.
+pub const GLOBAL_NAME: u32 = 7;
 
 impl WlSeat {
     // return keyboard object
@@ -56,10 +62,20 @@ impl WlSeat {
     // It is a protocol violation to issue this request on a seat that has
     // never had the keyboard capability.
     pub fn get_keyboard(
-        context: Context<WlSeat>,
-        _id: u32, // new_id: seat keyboard
+        mut context: Context<WlSeat>,
+        id: u32, // new_id: seat keyboard
     ) -> Box<dyn Future<Item = (Session, NextAction), Error = ()> + Send> {
-        context.invalid_method("wl_seat::get_keyboard is not implemented yet".to_string())
+        log::debug!(
+            client_id = context.client_id,
+            object_id = id,
+            interface = "wl_keyboard";
+            "wl_seat::get_keyboard"
+        );
+        context.resources.insert(
+            id,
+            crate::protocol::wayland::wl_keyboard::WlKeyboard {}.into(),
+        );
+        context.ok()
     }
 
     // return pointer object
@@ -72,10 +88,20 @@ impl WlSeat {
     // It is a protocol violation to issue this request on a seat that has
     // never had the pointer capability.
     pub fn get_pointer(
-        context: Context<WlSeat>,
-        _id: u32, // new_id: seat pointer
+        mut context: Context<WlSeat>,
+        id: u32, // new_id: seat pointer
     ) -> Box<dyn Future<Item = (Session, NextAction), Error = ()> + Send> {
-        context.invalid_method("wl_seat::get_pointer is not implemented yet".to_string())
+        log::debug!(
+            client_id = context.client_id,
+            object_id = id,
+            interface = "wl_pointer";
+            "wl_seat::get_pointer"
+        );
+        context.resources.insert(
+            id,
+            crate::protocol::wayland::wl_pointer::WlPointer {}.into(),
+        );
+        context.ok()
     }
 
     // return touch object
@@ -101,6 +127,6 @@ impl WlSeat {
     pub fn release(
         context: Context<WlSeat>,
     ) -> Box<dyn Future<Item = (Session, NextAction), Error = ()> + Send> {
-        context.invalid_method("wl_seat::release is not implemented yet".to_string())
+        context.destroy()
     }
 }
@@ -23,6 +23,8 @@
 // CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
 // SOFTWARE.
 
+#[allow(unused_imports)]
+use crate::protocol::interface::{Arg, ArgKind, Interface, Message};
 #[allow(unused_imports)]
 use crate::protocol::session::NextAction;
 #[allow(unused_imports)]
@@ -41,6 +43,65 @@ use std::sync::{Arc, RwLock};
 #[allow(dead_code)]
 pub const VERSION: u32 = 6;
 
+#[allow(dead_code)]
+pub static INTERFACE: Interface = Interface {
+    name: "wl_seat",
+    version: 6,
+    requests: &[
+        Message {
+            name: "get_pointer",
+            args: &[Arg {
+                name: "id",
+                kind: ArgKind::NewId,
+                interface: Some("wl_pointer"),
+                nullable: false,
+            }],
+        },
+        Message {
+            name: "get_keyboard",
+            args: &[Arg {
+                name: "id",
+                kind: ArgKind::NewId,
+                interface: Some("wl_keyboard"),
+                nullable: false,
+            }],
+        },
+        Message {
+            name: "get_touch",
+            args: &[Arg {
+                name: "id",
+                kind: ArgKind::NewId,
+                interface: Some("wl_touch"),
+                nullable: false,
+            }],
+        },
+        Message {
+            name: "release",
+            args: &[],
+        },
+    ],
+    events: &[
+        Message {
+            name: "capabilities",
+            args: &[Arg {
+                name: "capabilities",
+                kind: ArgKind::Uint,
+                interface: None,
+                nullable: false,
+            }],
+        },
+        Message {
+            name: "name",
+            args: &[Arg {
+                name: "name",
+                kind: ArgKind::String,
+                interface: None,
+                nullable: false,
+            }],
+        },
+    ],
+};
+
 #[allow(unused_variables)]
 #[allow(dead_code)]
 pub fn dispatch_request(
@@ -71,7 +132,7 @@ pub fn dispatch_request(
             let relay_buf = {
                 let total_len = 8 + 4;
                 if total_len > 0xffff {
-                    println!("Oops! total_len={}", total_len);
+                    log::error!("Oops! total_len={}", total_len);
                     return Box::new(futures::future::err(()));
                 }
 
@@ -126,7 +187,7 @@ pub fn dispatch_request(
             let relay_buf = {
                 let total_len = 8 + 4;
                 if total_len > 0xffff {
-                    println!("Oops! total_len={}", total_len);
+                    log::error!("Oops! total_len={}", total_len);
                     return Box::new(futures::future::err(()));
                 }
 
@@ -181,7 +242,7 @@ pub fn dispatch_request(
             let relay_buf = {
                 let total_len = 8 + 4;
                 if total_len > 0xffff {
-                    println!("Oops! total_len={}", total_len);
+                    log::error!("Oops! total_len={}", total_len);
                     return Box::new(futures::future::err(()));
                 }
 
@@ -227,7 +288,7 @@ pub fn dispatch_request(
             let relay_buf = {
                 let total_len = 8;
                 if total_len > 0xffff {
-                    println!("Oops! total_len={}", total_len);
+                    log::error!("Oops! total_len={}", total_len);
                     return Box::new(futures::future::err(()));
                 }
 
@@ -48,7 +48,11 @@ pub use lib::*;
 // At connection setup time, the wl_shm object emits one or more
 // format events to inform clients about the valid pixel formats
 // that can be used for buffers.
-pub struct WlShm {}
+pub struct WlShm {
+    // The format codes enabled in the [shm] section of the config. With
+    // vision they are the two every compositor supports.
+    pub formats: Vec<u32>,
+}
 
 impl WlShm {
     // create a shm pool
@@ -23,6 +23,8 @@
 // CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
 // SOFTWARE.
 
+#[allow(unused_imports)]
+use crate::protocol::interface::{Arg, ArgKind, Interface, Message};
 #[allow(unused_imports)]
 use crate::protocol::session::NextAction;
 #[allow(unused_imports)]
@@ -43,6 +45,44 @@ pub const GLOBAL_SINGLETON_NAME: u32 = 3;
 #[allow(dead_code)]
 pub const VERSION: u32 = 1;
 
+#[allow(dead_code)]
+pub static INTERFACE: Interface = Interface {
+    name: "wl_shm",
+    version: 1,
+    requests: &[Message {
+        name: "create_pool",
+        args: &[
+            Arg {
+                name: "id",
+                kind: ArgKind::NewId,
+                interface: Some("wl_shm_pool"),
+                nullable: false,
+            },
+            Arg {
+                name: "fd",
+                kind: ArgKind::Fd,
+                interface: None,
+                nullable: false,
+            },
+            Arg {
+                name: "size",
+                kind: ArgKind::Int,
+                interface: None,
+                nullable: false,
+            },
+        ],
+    }],
+    events: &[Message {
+        name: "format",
+        args: &[Arg {
+            name: "format",
+            kind: ArgKind::Uint,
+            interface: None,
+            nullable: false,
+        }],
+    }],
+};
+
 #[allow(unused_variables)]
 #[allow(dead_code)]
 pub fn dispatch_request(
@@ -95,7 +135,7 @@ pub fn dispatch_request(
             let relay_buf = {
                 let total_len = 8 + 4 + 4 + 4;
                 if total_len > 0xffff {
-                    println!("Oops! total_len={}", total_len);
+                    log::error!("Oops! total_len={}", total_len);
                     return Box::new(futures::future::err(()));
                 }
 
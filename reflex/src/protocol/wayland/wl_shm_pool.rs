@@ -23,8 +23,11 @@
 // CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
 // SOFTWARE.
 
+use crate::config::shm_bytes_per_pixel;
+use crate::lock::RwLockExt;
 #[allow(unused_imports)]
 use crate::protocol::session::{Context, NextAction, Session};
+use crate::protocol::wayland::wl_shm;
 #[allow(unused_imports)]
 use futures::future::{err, ok, Future};
 #[allow(unused_imports)]
@@ -72,6 +75,31 @@ impl WlShmPool {
         stride: i32, // int: number of bytes from the beginning of one row to the beginning of the next row
         format: u32, // uint: buffer pixel format
     ) -> Box<dyn Future<Item = (Session, NextAction), Error = ()> + Send> {
+        let bytes_per_pixel = if context.wl_shm.read_locked().formats.contains(&format) {
+            shm_bytes_per_pixel(format)
+        } else {
+            None
+        };
+        let bytes_per_pixel = match bytes_per_pixel {
+            Some(x) => i64::from(x),
+            None => {
+                let message = format!("unsupported format 0x{:x}", format);
+                return context.protocol_error(wl_shm::enums::Error::InvalidFormat as u32, message);
+            }
+        };
+        let end = i64::from(offset) + i64::from(stride) * i64::from(height);
+        if offset < 0
+            || width <= 0
+            || height <= 0
+            || i64::from(stride) < i64::from(width) * bytes_per_pixel
+            || end > i64::from(context.sender_object.size)
+        {
+            let message = format!(
+                "invalid width, height or stride ({}x{}, {}) at {} in a pool of {} bytes",
+                width, height, stride, offset, context.sender_object.size
+            );
+            return context.protocol_error(wl_shm::enums::Error::InvalidStride as u32, message);
+        }
         context.resources.insert(
             id,
             crate::protocol::wayland::wl_buffer::WlBuffer {
@@ -80,6 +108,7 @@ impl WlShmPool {
                 height,
                 stride,
                 format,
+                state: crate::protocol::wayland::wl_buffer::BufferState::Released,
             }
             .into(),
         );
@@ -96,7 +125,7 @@ impl WlShmPool {
     pub fn destroy(
         context: Context<WlShmPool>,
     ) -> Box<dyn Future<Item = (Session, NextAction), Error = ()> + Send> {
-        context.invalid_method("wl_shm_pool::destroy is not implemented yet".to_string())
+        context.destroy()
     }
 
     // change the size of the pool mapping
@@ -106,9 +135,17 @@ impl WlShmPool {
     // created, but using the new size.  This request can only be
     // used to make the pool bigger.
     pub fn resize(
-        context: Context<WlShmPool>,
-        _size: i32, // int: new size of the pool, in bytes
+        mut context: Context<WlShmPool>,
+        size: i32, // int: new size of the pool, in bytes
     ) -> Box<dyn Future<Item = (Session, NextAction), Error = ()> + Send> {
-        context.invalid_method("wl_shm_pool::resize is not implemented yet".to_string())
+        if size < context.sender_object.size {
+            let message = format!(
+                "wl_shm_pool can only grow, not shrink from {} to {} bytes",
+                context.sender_object.size, size
+            );
+            return context.protocol_error(wl_shm::enums::Error::InvalidFd as u32, message);
+        }
+        context.sender_object.size = size;
+        context.ok()
     }
 }
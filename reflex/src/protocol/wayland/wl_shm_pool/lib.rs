@@ -23,6 +23,8 @@
 // CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
 // SOFTWARE.
 
+#[allow(unused_imports)]
+use crate::protocol::interface::{Arg, ArgKind, Interface, Message};
 #[allow(unused_imports)]
 use crate::protocol::session::NextAction;
 #[allow(unused_imports)]
@@ -41,6 +43,69 @@ use std::sync::{Arc, RwLock};
 #[allow(dead_code)]
 pub const VERSION: u32 = 1;
 
+#[allow(dead_code)]
+pub static INTERFACE: Interface = Interface {
+    name: "wl_shm_pool",
+    version: 1,
+    requests: &[
+        Message {
+            name: "create_buffer",
+            args: &[
+                Arg {
+                    name: "id",
+                    kind: ArgKind::NewId,
+                    interface: Some("wl_buffer"),
+                    nullable: false,
+                },
+                Arg {
+                    name: "offset",
+                    kind: ArgKind::Int,
+                    interface: None,
+                    nullable: false,
+                },
+                Arg {
+                    name: "width",
+                    kind: ArgKind::Int,
+                    interface: None,
+                    nullable: false,
+                },
+                Arg {
+                    name: "height",
+                    kind: ArgKind::Int,
+                    interface: None,
+                    nullable: false,
+                },
+                Arg {
+                    name: "stride",
+                    kind: ArgKind::Int,
+                    interface: None,
+                    nullable: false,
+                },
+                Arg {
+                    name: "format",
+                    kind: ArgKind::Uint,
+                    interface: None,
+                    nullable: false,
+                },
+            ],
+        },
+        Message {
+            name: "destroy",
+            args: &[],
+        },
+        Message {
+            name: "resize",
+            args: &[Arg {
+                name: "size",
+                kind: ArgKind::Int,
+                interface: None,
+                nullable: false,
+            }],
+        },
+    ],
+    events: &[],
+};
+
 #[allow(unused_variables)]
 #[allow(dead_code)]
 pub fn dispatch_request(
@@ -111,7 +176,7 @@ pub fn dispatch_request(
             let relay_buf = {
                 let total_len = 8 + 4 + 4 + 4 + 4 + 4 + 4;
                 if total_len > 0xffff {
-                    println!("Oops! total_len={}", total_len);
+                    log::error!("Oops! total_len={}", total_len);
                     return Box::new(futures::future::err(()));
                 }
 
@@ -172,7 +237,7 @@ pub fn dispatch_request(
             let relay_buf = {
                 let total_len = 8;
                 if total_len > 0xffff {
-                    println!("Oops! total_len={}", total_len);
+                    log::error!("Oops! total_len={}", total_len);
                     return Box::new(futures::future::err(()));
                 }
 
@@ -225,7 +290,7 @@ pub fn dispatch_request(
             let relay_buf = {
                 let total_len = 8 + 4;
                 if total_len > 0xffff {
-                    println!("Oops! total_len={}", total_len);
+                    log::error!("Oops! total_len={}", total_len);
                     return Box::new(futures::future::err(()));
                 }
 
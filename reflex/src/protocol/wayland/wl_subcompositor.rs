@@ -23,12 +23,17 @@
 // CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
 // SOFTWARE.
 
+#[allow(unused_imports)]
+use crate::protocol::resource::Resource;
 #[allow(unused_imports)]
 use crate::protocol::session::{Context, NextAction, Session};
+use crate::protocol::wayland::wl_subsurface::WlSubsurface;
+use crate::protocol::wayland::wl_surface::{Placement, Role, SubsurfaceState, WlSurface};
 #[allow(unused_imports)]
 use futures::future::{err, ok, Future};
 #[allow(unused_imports)]
 use futures::sink::Sink;
+use std::collections::HashMap;
 #[allow(unused_imports)]
 use std::sync::{Arc, RwLock};
 
@@ -59,6 +64,10 @@ pub use lib::*;
 // processing to dedicated overlay hardware when possible.
 pub struct WlSubcompositor {}
 
+// Like wl_seat, wl_subcompositor is not a singleton but is advertised under
+// a fixed name next to them.
+pub const GLOBAL_NAME: u32 = 8;
+
 impl WlSubcompositor {
     // unbind from the subcompositor interface
     //
@@ -68,7 +77,7 @@ impl WlSubcompositor {
     pub fn destroy(
         context: Context<WlSubcompositor>,
     ) -> Box<dyn Future<Item = (Session, NextAction), Error = ()> + Send> {
-        context.invalid_method("wl_subcompositor::destroy is not implemented yet".to_string())
+        context.destroy()
     }
 
     // give a surface the role sub-surface
@@ -89,12 +98,119 @@ impl WlSubcompositor {
     // This request modifies the behaviour of wl_surface.commit request on
     // the sub-surface, see the documentation on wl_subsurface interface.
     pub fn get_subsurface(
-        context: Context<WlSubcompositor>,
-        _id: u32,      // new_id: the new sub-surface object ID
-        _surface: u32, // object: the surface to be turned into a sub-surface
-        _parent: u32,  // object: the parent surface
+        mut context: Context<WlSubcompositor>,
+        id: u32,      // new_id: the new sub-surface object ID
+        surface: u32, // object: the surface to be turned into a sub-surface
+        parent: u32,  // object: the parent surface
     ) -> Box<dyn Future<Item = (Session, NextAction), Error = ()> + Send> {
-        context
-            .invalid_method("wl_subcompositor::get_subsurface is not implemented yet".to_string())
+        if let Err(message) = check_subsurface(&context.resources, surface, parent) {
+            return context.protocol_error(enums::Error::BadSurface as u32, message);
+        }
+        if let Some(x) = WlSurface::get_mut(&mut context.resources, surface) {
+            x.role = Some(Role::Subsurface);
+            x.role_object = Some(id);
+            x.subsurface = Some(SubsurfaceState {
+                parent: Some(parent),
+                sync: true,
+                cached: None,
+            });
+        }
+        if let Some(x) = WlSurface::get_mut(&mut context.resources, parent) {
+            x.pending.subsurfaces.push(Placement {
+                surface_id: surface,
+                x: 0,
+                y: 0,
+            });
+        }
+        context.resources.insert(
+            id,
+            WlSubsurface {
+                surface_id: surface,
+            }
+            .into(),
+        );
+        context.ok()
+    }
+}
+
+// Whether surface can become a sub-surface of parent: both are surfaces,
+// surface can take the role, and parent is not surface or one of its
+// descendants.
+fn check_subsurface(
+    resources: &HashMap<u32, Resource>,
+    surface: u32,
+    parent: u32,
+) -> Result<(), String> {
+    match WlSurface::get(resources, surface) {
+        Some(x) => {
+            x.check_role(surface, Role::Subsurface)?;
+            x.check_role_object(surface)?;
+        }
+        None => return Err(format!("{} is not a wl_surface", surface)),
+    }
+    let mut ancestor = Some(parent);
+    while let Some(x) = ancestor {
+        if x == surface {
+            return Err(format!(
+                "wl_surface@{} cannot be a sub-surface of itself or of its descendant wl_surface@{}",
+                surface, parent
+            ));
+        }
+        ancestor = match resources.get(&x) {
+            Some(Resource::WlSurface(x)) => x.subsurface.as_ref().and_then(|x| x.parent),
+            _ if x == parent => return Err(format!("{} is not a wl_surface", parent)),
+            _ => None,
+        };
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Surfaces 1 to 4, where 2 is a sub-surface of 1 and 3 of 2.
+    fn tree() -> HashMap<u32, Resource> {
+        let mut resources = HashMap::new();
+        for (surface_id, parent) in &[(1, None), (2, Some(1)), (3, Some(2)), (4, None)] {
+            let mut surface = WlSurface::default();
+            if let Some(parent) = parent {
+                surface.role = Some(Role::Subsurface);
+                surface.subsurface = Some(SubsurfaceState {
+                    parent: Some(*parent),
+                    sync: true,
+                    cached: None,
+                });
+            }
+            resources.insert(*surface_id, surface.into());
+        }
+        resources
+    }
+
+    #[test]
+    fn no_surface_is_its_own_ancestor() {
+        let resources = tree();
+        assert!(check_subsurface(&resources, 4, 3).is_ok());
+        assert!(check_subsurface(&resources, 4, 1).is_ok());
+        assert!(check_subsurface(&resources, 4, 4).is_err());
+        // 1 is an ancestor of 3, directly or not.
+        assert!(check_subsurface(&resources, 1, 2).is_err());
+        assert!(check_subsurface(&resources, 1, 3).is_err());
+        // Not a surface.
+        assert!(check_subsurface(&resources, 4, 9).is_err());
+        assert!(check_subsurface(&resources, 9, 4).is_err());
+    }
+
+    #[test]
+    fn an_orphaned_subtree_has_no_ancestors() {
+        let mut resources = tree();
+        resources.remove(&1);
+        if let Some(x) = WlSurface::get_mut(&mut resources, 2).and_then(|x| x.subsurface.as_mut()) {
+            x.parent = None;
+        }
+        let mut surface = WlSurface::default();
+        surface.role = Some(Role::Subsurface);
+        resources.insert(5, surface.into());
+        assert!(check_subsurface(&resources, 5, 3).is_ok());
     }
 }
@@ -23,6 +23,8 @@
 // CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
 // SOFTWARE.
 
+#[allow(unused_imports)]
+use crate::protocol::interface::{Arg, ArgKind, Interface, Message};
 #[allow(unused_imports)]
 use crate::protocol::session::NextAction;
 #[allow(unused_imports)]
@@ -41,6 +43,42 @@ use std::sync::{Arc, RwLock};
 #[allow(dead_code)]
 pub const VERSION: u32 = 1;
 
+#[allow(dead_code)]
+pub static INTERFACE: Interface = Interface {
+    name: "wl_subcompositor",
+    version: 1,
+    requests: &[
+        Message {
+            name: "destroy",
+            args: &[],
+        },
+        Message {
+            name: "get_subsurface",
+            args: &[
+                Arg {
+                    name: "id",
+                    kind: ArgKind::NewId,
+                    interface: Some("wl_subsurface"),
+                    nullable: false,
+                },
+                Arg {
+                    name: "surface",
+                    kind: ArgKind::Object,
+                    interface: Some("wl_surface"),
+                    nullable: false,
+                },
+                Arg {
+                    name: "parent",
+                    kind: ArgKind::Object,
+                    interface: Some("wl_surface"),
+                    nullable: false,
+                },
+            ],
+        },
+    ],
+    events: &[],
+};
+
 #[allow(unused_variables)]
 #[allow(dead_code)]
 pub fn dispatch_request(
@@ -64,7 +102,7 @@ pub fn dispatch_request(
             let relay_buf = {
                 let total_len = 8;
                 if total_len > 0xffff {
-                    println!("Oops! total_len={}", total_len);
+                    log::error!("Oops! total_len={}", total_len);
                     return Box::new(futures::future::err(()));
                 }
 
@@ -133,7 +171,7 @@ pub fn dispatch_request(
             let relay_buf = {
                 let total_len = 8 + 4 + 4 + 4;
                 if total_len > 0xffff {
-                    println!("Oops! total_len={}", total_len);
+                    log::error!("Oops! total_len={}", total_len);
                     return Box::new(futures::future::err(()));
                 }
 
@@ -23,8 +23,11 @@
 // CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
 // SOFTWARE.
 
+#[allow(unused_imports)]
+use crate::protocol::resource::Resource;
 #[allow(unused_imports)]
 use crate::protocol::session::{Context, NextAction, Session};
+use crate::protocol::wayland::wl_surface::{Placement, WlSurface};
 #[allow(unused_imports)]
 use futures::future::{err, ok, Future};
 #[allow(unused_imports)]
@@ -87,9 +90,60 @@ pub use lib::*;
 //
 // If the parent wl_surface object is destroyed, the sub-surface is
 // unmapped.
-pub struct WlSubsurface {}
+//
+// Reflex keeps the sub-surface state in the WlSurface objects, so that it
+// goes with the surface: the mode and cached commits in the sub-surface
+// itself, and the position and stacking order in the parent.
+pub struct WlSubsurface {
+    // 0 once the surface is destroyed, which leaves the sub-surface inert.
+    pub surface_id: u32,
+}
 
 impl WlSubsurface {
+    // The parent of the sub-surface, None once the sub-surface or its parent
+    // is destroyed.
+    fn parent(context: &Context<WlSubsurface>) -> Option<u32> {
+        match context.resources.get(&context.sender_object.surface_id) {
+            Some(Resource::WlSurface(x)) => x.subsurface.as_ref().and_then(|x| x.parent),
+            _ => None,
+        }
+    }
+
+    // Moves the sub-surface next to a sibling or the parent in the pending
+    // stacking order, above it or in its place.
+    fn place(
+        mut context: Context<WlSubsurface>,
+        sibling: u32,
+        above: bool,
+    ) -> Box<dyn Future<Item = (Session, NextAction), Error = ()> + Send> {
+        let surface_id = context.sender_object.surface_id;
+        let parent_id = match Self::parent(&context) {
+            Some(x) => x,
+            None => return context.ok(),
+        };
+        // The parent stands for itself as 0 in its stacking order.
+        let reference = if sibling == parent_id { 0 } else { sibling };
+        let parent = match WlSurface::get_mut(&mut context.resources, parent_id) {
+            Some(x) => x,
+            None => return context.ok(),
+        };
+        if !restack(
+            &mut parent.pending.subsurfaces,
+            surface_id,
+            reference,
+            above,
+        ) {
+            return context.protocol_error(
+                enums::Error::BadSurface as u32,
+                format!(
+                    "wl_surface@{} is not a sibling or the parent of wl_surface@{}",
+                    sibling, surface_id
+                ),
+            );
+        }
+        context.ok()
+    }
+
     // remove sub-surface interface
     //
     // The sub-surface interface is removed from the wl_surface object
@@ -98,9 +152,27 @@ impl WlSubsurface {
     // to the parent is deleted, and the wl_surface loses its role as
     // a sub-surface. The wl_surface is unmapped immediately.
     pub fn destroy(
-        context: Context<WlSubsurface>,
+        mut context: Context<WlSubsurface>,
     ) -> Box<dyn Future<Item = (Session, NextAction), Error = ()> + Send> {
-        context.invalid_method("wl_subsurface::destroy is not implemented yet".to_string())
+        let surface_id = context.sender_object.surface_id;
+        let parent_id = Self::parent(&context);
+        if let Some(parent) = parent_id.and_then(|x| WlSurface::get_mut(&mut context.resources, x))
+        {
+            parent.remove_subsurface(surface_id);
+        }
+        // The surface keeps the role, to be given a wl_subsurface again.
+        let subsurface = WlSurface::get_mut(&mut context.resources, surface_id).and_then(|x| {
+            x.role_object = None;
+            x.subsurface.take()
+        });
+        // The frame callbacks of the commits that were never applied still
+        // get done, without the rest of them.
+        if let Some(cached) = subsurface.and_then(|x| x.cached) {
+            context
+                .frame_clock
+                .queue(context.client_id, surface_id, cached.frame_callbacks);
+        }
+        context.destroy()
     }
 
     // restack the sub-surface
@@ -122,9 +194,9 @@ impl WlSubsurface {
     // of its siblings and parent.
     pub fn place_above(
         context: Context<WlSubsurface>,
-        _sibling: u32, // object: the reference surface
+        sibling: u32, // object: the reference surface
     ) -> Box<dyn Future<Item = (Session, NextAction), Error = ()> + Send> {
-        context.invalid_method("wl_subsurface::place_above is not implemented yet".to_string())
+        Self::place(context, sibling, true)
     }
 
     // restack the sub-surface
@@ -133,9 +205,9 @@ impl WlSubsurface {
     // See wl_subsurface.place_above.
     pub fn place_below(
         context: Context<WlSubsurface>,
-        _sibling: u32, // object: the reference surface
+        sibling: u32, // object: the reference surface
     ) -> Box<dyn Future<Item = (Session, NextAction), Error = ()> + Send> {
-        context.invalid_method("wl_subsurface::place_below is not implemented yet".to_string())
+        Self::place(context, sibling, false)
     }
 
     // set sub-surface to desynchronized mode
@@ -160,9 +232,29 @@ impl WlSubsurface {
     // If a surface's parent surface behaves as desynchronized, then
     // the cached state is applied on set_desync.
     pub fn set_desync(
-        context: Context<WlSubsurface>,
+        mut context: Context<WlSubsurface>,
     ) -> Box<dyn Future<Item = (Session, NextAction), Error = ()> + Send> {
-        context.invalid_method("wl_subsurface::set_desync is not implemented yet".to_string())
+        let surface_id = context.sender_object.surface_id;
+        let surface = match WlSurface::get_mut(&mut context.resources, surface_id) {
+            Some(x) => x,
+            None => return context.handled(),
+        };
+        if let Some(x) = surface.subsurface.as_mut() {
+            x.sync = false;
+        }
+        let surface = match context.resources.get(&surface_id) {
+            Some(Resource::WlSurface(x)) => x,
+            _ => return context.handled(),
+        };
+        if !surface.synchronized(&context.resources) {
+            let cached = WlSurface::get_mut(&mut context.resources, surface_id)
+                .and_then(|x| x.subsurface.as_mut())
+                .and_then(|x| x.cached.take());
+            if let Some(cached) = cached {
+                WlSurface::apply_resource(&mut context, surface_id, cached);
+            }
+        }
+        context.handled()
     }
 
     // reposition the sub-surface
@@ -184,11 +276,24 @@ impl WlSubsurface {
     //
     // The initial position is 0, 0.
     pub fn set_position(
-        context: Context<WlSubsurface>,
-        _x: i32, // int: x coordinate in the parent surface
-        _y: i32, // int: y coordinate in the parent surface
+        mut context: Context<WlSubsurface>,
+        x: i32, // int: x coordinate in the parent surface
+        y: i32, // int: y coordinate in the parent surface
     ) -> Box<dyn Future<Item = (Session, NextAction), Error = ()> + Send> {
-        context.invalid_method("wl_subsurface::set_position is not implemented yet".to_string())
+        let surface_id = context.sender_object.surface_id;
+        let parent =
+            Self::parent(&context).and_then(|x| WlSurface::get_mut(&mut context.resources, x));
+        if let Some(placement) = parent.and_then(|parent| {
+            parent
+                .pending
+                .subsurfaces
+                .iter_mut()
+                .find(|x| x.surface_id == surface_id)
+        }) {
+            placement.x = x;
+            placement.y = y;
+        }
+        context.ok()
     }
 
     // set sub-surface to synchronized mode
@@ -207,8 +312,102 @@ impl WlSubsurface {
     //
     // See wl_subsurface for the recursive effect of this mode.
     pub fn set_sync(
-        context: Context<WlSubsurface>,
+        mut context: Context<WlSubsurface>,
     ) -> Box<dyn Future<Item = (Session, NextAction), Error = ()> + Send> {
-        context.invalid_method("wl_subsurface::set_sync is not implemented yet".to_string())
+        let surface_id = context.sender_object.surface_id;
+        if let Some(x) = WlSurface::get_mut(&mut context.resources, surface_id)
+            .and_then(|x| x.subsurface.as_mut())
+        {
+            x.sync = true;
+        }
+        // Only reflex holds back the state of synchronized sub-surfaces, so
+        // neither mode is relayed.
+        context.handled()
+    }
+}
+
+// Moves surface_id right above or below reference in a stacking order.
+// Returns false if either is not in it, or they are the same surface.
+fn restack(stack: &mut Vec<Placement>, surface_id: u32, reference: u32, above: bool) -> bool {
+    let (index, reference_index) = match (
+        stack.iter().position(|x| x.surface_id == surface_id),
+        stack.iter().position(|x| x.surface_id == reference),
+    ) {
+        (Some(x), Some(y)) if x != y => (x, y),
+        _ => return false,
+    };
+    let placement = stack.remove(index);
+    let reference_index = if index < reference_index {
+        reference_index - 1
+    } else {
+        reference_index
+    };
+    let index = if above {
+        reference_index + 1
+    } else {
+        reference_index
+    };
+    stack.insert(index, placement);
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stack(ids: &[u32]) -> Vec<Placement> {
+        ids.iter()
+            .map(|&surface_id| Placement {
+                surface_id,
+                x: 0,
+                y: 0,
+            })
+            .collect()
+    }
+
+    fn ids(stack: &[Placement]) -> Vec<u32> {
+        stack.iter().map(|x| x.surface_id).collect()
+    }
+
+    #[test]
+    fn place_above_and_below() {
+        let cases: &[(u32, u32, bool, &[u32])] = &[
+            // Down past the reference, which shifts it.
+            (5, 3, true, &[3, 5, 0, 4]),
+            (5, 3, false, &[5, 3, 0, 4]),
+            // Up past the reference.
+            (3, 4, true, &[0, 4, 3, 5]),
+            (3, 4, false, &[0, 3, 4, 5]),
+            // Next to the parent, which is 0.
+            (5, 0, false, &[3, 5, 0, 4]),
+            (3, 0, true, &[0, 3, 4, 5]),
+            // To the top and bottom.
+            (3, 5, true, &[0, 4, 5, 3]),
+            (4, 3, false, &[4, 3, 0, 5]),
+            // Where it already is.
+            (3, 0, false, &[3, 0, 4, 5]),
+            (4, 0, true, &[3, 0, 4, 5]),
+        ];
+        for &(surface_id, reference, above, expected) in cases {
+            let mut order = stack(&[3, 0, 4, 5]);
+            assert!(restack(&mut order, surface_id, reference, above));
+            assert_eq!(
+                ids(&order),
+                expected,
+                "{} {} {}",
+                surface_id,
+                if above { "above" } else { "below" },
+                reference
+            );
+        }
+    }
+
+    #[test]
+    fn place_needs_a_sibling_or_the_parent() {
+        let mut order = stack(&[0, 4, 5]);
+        assert!(!restack(&mut order, 4, 4, true));
+        assert!(!restack(&mut order, 4, 9, false));
+        assert!(!restack(&mut order, 9, 4, true));
+        assert_eq!(ids(&order), vec![0, 4, 5]);
     }
 }
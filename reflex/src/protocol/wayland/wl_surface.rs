@@ -23,12 +23,20 @@
 // CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
 // SOFTWARE.
 
+use crate::backend::WindowChange;
+use crate::compositor::region::Region;
+use crate::compositor::{buffer_rect_to_surface, surface_size, Rect};
+#[allow(unused_imports)]
+use crate::protocol::resource::Resource;
 #[allow(unused_imports)]
 use crate::protocol::session::{Context, NextAction, Session};
+use crate::protocol::wayland::wl_buffer::{BufferState, WlBuffer};
+use crate::protocol::xdg_shell::xdg_surface::{self, XdgSurface};
 #[allow(unused_imports)]
 use futures::future::{err, ok, Future};
 #[allow(unused_imports)]
 use futures::sink::Sink;
+use std::collections::HashMap;
 #[allow(unused_imports)]
 use std::sync::{Arc, RwLock};
 
@@ -78,9 +86,470 @@ pub use lib::*;
 // wl_surface again, but it is not allowed to use the wl_surface as
 // a cursor (cursor is a different role than sub-surface, and role
 // switching is not allowed).
-pub struct WlSurface {}
+#[derive(Default)]
+pub struct WlSurface {
+    pub pending: SurfaceState,
+    pub current: SurfaceState,
+    // The size of the current buffer in pixels, kept from the commit that
+    // made it current.
+    pub buffer_size: (i32, i32),
+    // The size of the surface in surface-local coordinates: the buffer size
+    // with the current scale and transform undone.
+    pub size: (i32, i32),
+    // Given by the first role-granting request and kept until the surface
+    // is destroyed.
+    pub role: Option<Role>,
+    // The object that extends the surface for its role, while there is one:
+    // the xdg_surface or the wl_subsurface.
+    pub role_object: Option<u32>,
+    // Set while the surface is a sub-surface.
+    pub subsurface: Option<SubsurfaceState>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Role {
+    XdgToplevel,
+    XdgPopup,
+    Subsurface,
+    Cursor,
+    DragIcon,
+}
+
+impl Role {
+    pub fn name(self) -> &'static str {
+        match self {
+            Role::XdgToplevel => "xdg_toplevel",
+            Role::XdgPopup => "xdg_popup",
+            Role::Subsurface => "sub-surface",
+            Role::Cursor => "cursor",
+            Role::DragIcon => "drag-and-drop icon",
+        }
+    }
+}
+
+// wl_surface.error.defunct_role_object, from a later version of wl_surface
+// than the one reflex implements.
+const ERROR_DEFUNCT_ROLE_OBJECT: u32 = 4;
+
+// What a surface has as a sub-surface. Its position and its place in the
+// stacking order belong to the parent's state instead, because they are
+// applied with the parent's commits.
+#[derive(Clone, Debug)]
+pub struct SubsurfaceState {
+    // None once the parent is destroyed, which unmaps the surface.
+    pub parent: Option<u32>,
+    pub sync: bool,
+    // The commits made while the surface was effectively synchronized,
+    // merged into one state that is applied right after the parent's.
+    pub cached: Option<SurfaceState>,
+}
+
+// A sub-surface in its parent's stacking order.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Placement {
+    // 0 for the parent itself.
+    pub surface_id: u32,
+    // In the parent's surface-local coordinates.
+    pub x: i32,
+    pub y: i32,
+}
+
+// Double-buffered surface state. Requests change the pending state and
+// commit applies it to the current state, which is what the surface shows.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SurfaceState {
+    // Whether attach was sent since the last commit. Only then does commit
+    // change the buffer. In the current state, whether the commit that made
+    // it current did.
+    pub attached: bool,
+    // The wl_buffer of the contents, None for no contents.
+    pub buffer: Option<u32>,
+    // Where the new buffer goes relative to the old one, in surface-local
+    // coordinates. Reset to 0, 0 by each commit.
+    pub dx: i32,
+    pub dy: i32,
+    // Damage in surface-local and in buffer coordinates, kept apart until
+    // commit because the scale and transform may still change. Commit turns
+    // the buffer damage into surface damage.
+    pub damage: Vec<Rect>,
+    pub buffer_damage: Vec<Rect>,
+    pub scale: i32,
+    pub transform: i32,
+    // Empty unless set.
+    pub opaque_region: Region,
+    // None means infinite, the whole surface.
+    pub input_region: Option<Region>,
+    // wl_callback objects, in the order they were requested. Commit hands
+    // them to the frame clock.
+    pub frame_callbacks: Vec<u32>,
+    // The sub-surfaces and the surface itself, bottom to top. New
+    // sub-surfaces go on top.
+    pub subsurfaces: Vec<Placement>,
+}
+
+impl Default for SurfaceState {
+    fn default() -> Self {
+        SurfaceState {
+            attached: false,
+            buffer: None,
+            dx: 0,
+            dy: 0,
+            damage: Vec::new(),
+            buffer_damage: Vec::new(),
+            scale: 1,
+            transform: 0,
+            opaque_region: Region::new(),
+            input_region: None,
+            frame_callbacks: Vec::new(),
+            subsurfaces: vec![Placement {
+                surface_id: 0,
+                x: 0,
+                y: 0,
+            }],
+        }
+    }
+}
+
+impl SurfaceState {
+    // Adds a later commit to a cached one, as if the two had been a single
+    // commit.
+    fn merge(&mut self, later: SurfaceState) {
+        if later.attached {
+            self.attached = true;
+            self.buffer = later.buffer;
+        }
+        self.dx += later.dx;
+        self.dy += later.dy;
+        self.damage.extend(later.damage);
+        self.buffer_damage.extend(later.buffer_damage);
+        self.scale = later.scale;
+        self.transform = later.transform;
+        self.opaque_region = later.opaque_region;
+        self.input_region = later.input_region;
+        self.frame_callbacks.extend(later.frame_callbacks);
+        self.subsurfaces = later.subsurfaces;
+    }
+}
 
 impl WlSurface {
+    pub fn get(resources: &HashMap<u32, Resource>, surface_id: u32) -> Option<&Self> {
+        match resources.get(&surface_id) {
+            Some(Resource::WlSurface(x)) => Some(x),
+            _ => None,
+        }
+    }
+
+    pub fn get_mut(resources: &mut HashMap<u32, Resource>, surface_id: u32) -> Option<&mut Self> {
+        match resources.get_mut(&surface_id) {
+            Some(Resource::WlSurface(x)) => Some(x),
+            _ => None,
+        }
+    }
+
+    // Takes what a commit applies from the pending state. The buffer, its
+    // offset, the damage and the frame callbacks are taken; the scale, the
+    // transform, the regions and the stacking order stay pending as they
+    // were.
+    pub fn take_pending(&mut self) -> SurfaceState {
+        let state = self.pending.clone();
+        let pending = &mut self.pending;
+        pending.attached = false;
+        pending.buffer = None;
+        pending.dx = 0;
+        pending.dy = 0;
+        pending.damage.clear();
+        pending.buffer_damage.clear();
+        pending.frame_callbacks.clear();
+        state
+    }
+
+    // Makes a committed state current. buffer_size must already be that of
+    // a newly attached buffer.
+    pub fn apply_state(&mut self, state: SurfaceState) {
+        let current = &mut self.current;
+        if state.attached {
+            current.buffer = state.buffer;
+            current.dx = state.dx;
+            current.dy = state.dy;
+        } else {
+            current.dx = 0;
+            current.dy = 0;
+        }
+        current.attached = state.attached;
+        current.scale = state.scale;
+        current.transform = state.transform;
+        current.opaque_region = state.opaque_region;
+        current.input_region = state.input_region;
+        current.frame_callbacks = state.frame_callbacks;
+        current.subsurfaces = state.subsurfaces;
+
+        let (width, height) = self.buffer_size;
+        self.size = surface_size(width, height, current.scale, current.transform);
+        current.damage = state.damage;
+        current.buffer_damage.clear();
+        for rect in state.buffer_damage {
+            current.damage.push(buffer_rect_to_surface(
+                &rect,
+                current.scale,
+                current.transform,
+                self.size,
+            ));
+        }
+    }
+
+    // Drops the references to a destroyed wl_buffer. A pending one still
+    // counts as attached, so the next commit removes the contents.
+    pub fn forget_buffer(&mut self, buffer_id: u32) {
+        let cached = self.subsurface.as_mut().and_then(|x| x.cached.as_mut());
+        for state in std::iter::once(&mut self.pending)
+            .chain(std::iter::once(&mut self.current))
+            .chain(cached)
+        {
+            if state.buffer == Some(buffer_id) {
+                state.buffer = None;
+            }
+        }
+    }
+
+    // Whether the surface can be given a role, which it cannot if it has
+    // had another one. Errors name the surface as surface_id.
+    pub fn check_role(&self, surface_id: u32, role: Role) -> Result<(), String> {
+        match self.role {
+            Some(x) if x != role => Err(format!(
+                "wl_surface@{} already has the {} role",
+                surface_id,
+                x.name()
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    // Whether the surface can be given a new role object, which it cannot
+    // while it has one.
+    pub fn check_role_object(&self, surface_id: u32) -> Result<(), String> {
+        match self.role_object {
+            Some(x) => Err(format!(
+                "wl_surface@{} already has role object {}",
+                surface_id, x
+            )),
+            None => Ok(()),
+        }
+    }
+
+    // Whether commits of the surface are cached instead of applied: it is a
+    // synchronized sub-surface, or one of its ancestors is.
+    pub fn synchronized(&self, resources: &HashMap<u32, Resource>) -> bool {
+        let mut subsurface = self.subsurface.as_ref();
+        while let Some(x) = subsurface {
+            if x.sync {
+                return true;
+            }
+            subsurface = match x.parent.and_then(|x| resources.get(&x)) {
+                Some(Resource::WlSurface(parent)) => parent.subsurface.as_ref(),
+                _ => None,
+            };
+        }
+        false
+    }
+
+    // The extents of the surface and its mapped sub-surfaces, in
+    // surface-local coordinates.
+    pub fn bounds(&self, resources: &HashMap<u32, Resource>) -> Rect {
+        let mut bounds = Rect::new(0, 0, self.size.0, self.size.1);
+        for placement in &self.current.subsurfaces {
+            let child = match placement.surface_id {
+                0 => continue,
+                x => match WlSurface::get(resources, x) {
+                    Some(x) => x.bounds(resources),
+                    None => continue,
+                },
+            };
+            if child.is_empty() {
+                continue;
+            }
+            if bounds.is_empty() {
+                bounds = Rect::new(
+                    child.x + placement.x,
+                    child.y + placement.y,
+                    child.width,
+                    child.height,
+                );
+                continue;
+            }
+            let x = bounds.x.min(child.x + placement.x);
+            let y = bounds.y.min(child.y + placement.y);
+            let right = bounds.right().max(child.right() + placement.x);
+            let bottom = bounds.bottom().max(child.bottom() + placement.y);
+            bounds = Rect::new(x, y, right - x, bottom - y);
+        }
+        bounds
+    }
+
+    // The xdg_surface of the surface if the pending state attaches a buffer
+    // before the xdg_surface acked a configure, which must not be committed.
+    fn unconfigured_xdg_surface(&self, resources: &HashMap<u32, Resource>) -> Option<u32> {
+        if !self.pending.attached || self.pending.buffer.is_none() {
+            return None;
+        }
+        let xdg_surface_id = self.role_object?;
+        match XdgSurface::get(resources, xdg_surface_id) {
+            Some(x) if !x.configured => Some(xdg_surface_id),
+            _ => None,
+        }
+    }
+
+    // Takes a sub-surface out of the stacking order, right away rather than
+    // on the next commit.
+    pub fn remove_subsurface(&mut self, surface_id: u32) {
+        let cached = self.subsurface.as_mut().and_then(|x| x.cached.as_mut());
+        for state in std::iter::once(&mut self.pending)
+            .chain(std::iter::once(&mut self.current))
+            .chain(cached)
+        {
+            state.subsurfaces.retain(|x| x.surface_id != surface_id);
+        }
+    }
+
+    // Applies a committed state, then the cached states of the sub-surfaces
+    // it stacks, and theirs in turn.
+    pub fn apply<T>(
+        context: &mut Context<T>,
+        surface_id: u32,
+        surface: &mut WlSurface,
+        state: SurfaceState,
+    ) where
+        T: Into<Resource>,
+    {
+        Self::commit_buffers(context, surface, &state);
+        surface.apply_state(state);
+        if let Some(xdg_surface_id) = surface.role_object {
+            let bounds = surface.bounds(&context.resources);
+            let geometry = match XdgSurface::get_mut(&mut context.resources, xdg_surface_id) {
+                Some(x) => x.apply_geometry(bounds).map(|g| (x.role_object, g)),
+                None => None,
+            };
+            if let (Some((Some(toplevel_id), geometry)), Some(Role::XdgToplevel)) =
+                (geometry, surface.role)
+            {
+                context
+                    .backend
+                    .window_changed(toplevel_id, &WindowChange::Geometry(geometry));
+            }
+        }
+        let callbacks = std::mem::take(&mut surface.current.frame_callbacks);
+        context
+            .frame_clock
+            .queue(context.client_id, surface_id, callbacks);
+        context.backend.surface_commit(surface_id, &surface.current);
+
+        for placement in &surface.current.subsurfaces {
+            let child_id = placement.surface_id;
+            let cached = WlSurface::get_mut(&mut context.resources, child_id)
+                .and_then(|x| x.subsurface.as_mut())
+                .and_then(|x| x.cached.take());
+            if let Some(cached) = cached {
+                Self::apply_resource(context, child_id, cached);
+            }
+        }
+    }
+
+    // Applies a state to a surface other than the sender of the request.
+    pub fn apply_resource<T>(context: &mut Context<T>, surface_id: u32, state: SurfaceState)
+    where
+        T: Into<Resource>,
+    {
+        let mut surface = match context.resources.remove(&surface_id) {
+            Some(Resource::WlSurface(x)) => x,
+            Some(x) => {
+                context.resources.insert(surface_id, x);
+                return;
+            }
+            None => return,
+        };
+        Self::apply(context, surface_id, &mut surface, state);
+        context.resources.insert(surface_id, surface.into());
+    }
+
+    // Keeps the commit of an effectively synchronized sub-surface until its
+    // parent's state is applied.
+    fn cache(context: &mut Context<WlSurface>, state: SurfaceState) {
+        let subsurface = match context.sender_object.subsurface.as_mut() {
+            Some(x) => x,
+            None => return,
+        };
+        let cached = match &mut subsurface.cached {
+            Some(x) => x,
+            None => {
+                subsurface.cached = Some(state);
+                return;
+            }
+        };
+        // A cached buffer that is replaced before it was ever applied goes
+        // back to the client without having been used.
+        if let Some(old) = cached
+            .buffer
+            .filter(|x| state.attached && Some(*x) != state.buffer)
+        {
+            if let Some(x) = WlBuffer::get_mut(&mut context.resources, old) {
+                if x.state == BufferState::Attached {
+                    x.state = BufferState::Released;
+                }
+            }
+        }
+        cached.merge(state);
+    }
+
+    // Moves the buffers through their states for a state about to be
+    // applied: the new buffer is handed to the backend and the one it
+    // replaces is released. Also takes the size of the new buffer.
+    fn commit_buffers<T>(context: &mut Context<T>, surface: &mut WlSurface, state: &SurfaceState)
+    where
+        T: Into<Resource>,
+    {
+        if !state.attached {
+            return;
+        }
+        let (old, new) = (surface.current.buffer, state.buffer);
+        if let Some(old) = old.filter(|x| Some(*x) != new) {
+            Self::release_buffer(context, old);
+        }
+        surface.buffer_size = (0, 0);
+        if let Some(new) = new {
+            let copies = context.backend.copies_buffers();
+            if let Some(buffer) = WlBuffer::get_mut(&mut context.resources, new) {
+                surface.buffer_size = (buffer.width, buffer.height);
+                if copies {
+                    buffer.state = BufferState::Committed;
+                    context.pending_releases.push(new);
+                } else {
+                    buffer.state = BufferState::InUse;
+                }
+            }
+        }
+    }
+
+    // Releases a buffer the backend may still be reading, once the request
+    // that stops it from doing so has been relayed.
+    fn release_buffer<T>(context: &mut Context<T>, buffer_id: u32)
+    where
+        T: Into<Resource>,
+    {
+        if let Some(buffer) = WlBuffer::get_mut(&mut context.resources, buffer_id) {
+            if buffer.state == BufferState::InUse {
+                context.pending_releases.push(buffer_id);
+            }
+        }
+    }
+
+    // A copy of a wl_region, or None for a NULL or unknown region. Later
+    // changes to the wl_region do not affect the copy.
+    fn region(context: &Context<WlSurface>, region: u32) -> Option<Region> {
+        match context.resources.get(&region) {
+            Some(Resource::WlRegion(x)) => Some(x.region.clone()),
+            _ => None,
+        }
+    }
+
     // set the surface contents
     //
     // Set a buffer as the content of this surface.
@@ -123,12 +592,31 @@ impl WlSurface {
     // If wl_surface.attach is sent with a NULL wl_buffer, the
     // following wl_surface.commit will remove the surface content.
     pub fn attach(
-        context: Context<WlSurface>,
-        _buffer: u32, // object: buffer of surface contents
-        _x: i32,      // int: surface-local x coordinate
-        _y: i32,      // int: surface-local y coordinate
+        mut context: Context<WlSurface>,
+        buffer: u32, // object: buffer of surface contents
+        x: i32,      // int: surface-local x coordinate
+        y: i32,      // int: surface-local y coordinate
     ) -> Box<dyn Future<Item = (Session, NextAction), Error = ()> + Send> {
-        context.invalid_method("wl_surface::attach is not implemented yet".to_string())
+        let buffer = if buffer == 0 { None } else { Some(buffer) };
+        // A buffer replaced by another attach before the commit is not used.
+        if let Some(old) = context.sender_object.pending.buffer {
+            if let Some(x) = WlBuffer::get_mut(&mut context.resources, old) {
+                if x.state == BufferState::Attached && Some(old) != buffer {
+                    x.state = BufferState::Released;
+                }
+            }
+        }
+        if let Some(x) = buffer.and_then(|x| WlBuffer::get_mut(&mut context.resources, x)) {
+            if x.state == BufferState::Released {
+                x.state = BufferState::Attached;
+            }
+        }
+        let pending = &mut context.sender_object.pending;
+        pending.attached = true;
+        pending.buffer = buffer;
+        pending.dx = x;
+        pending.dy = y;
+        context.ok()
     }
 
     // commit pending surface state
@@ -151,9 +639,50 @@ impl WlSurface {
     //
     // Other interfaces may add further double-buffered surface state.
     pub fn commit(
-        context: Context<WlSurface>,
+        mut context: Context<WlSurface>,
     ) -> Box<dyn Future<Item = (Session, NextAction), Error = ()> + Send> {
-        println!("WlSurface::commit");
+        log::trace!(
+            client_id = context.client_id,
+            object_id = context.sender_object_id;
+            "wl_surface::commit"
+        );
+        if let Some(xdg_surface_id) = context
+            .sender_object
+            .unconfigured_xdg_surface(&context.resources)
+        {
+            let message = format!(
+                "wl_surface@{} committed a buffer before acking a configure",
+                context.sender_object_id
+            );
+            return context.object_error(
+                xdg_surface_id,
+                xdg_surface::enums::Error::UnconfiguredBuffer as u32,
+                message,
+            );
+        }
+        let state = context.sender_object.take_pending();
+        if context.sender_object.synchronized(&context.resources) {
+            Self::cache(&mut context, state);
+            return context.ok();
+        }
+        // A desynchronized sub-surface applies what it cached while its
+        // parent was synchronized along with the new commit.
+        let cached = context
+            .sender_object
+            .subsurface
+            .as_mut()
+            .and_then(|x| x.cached.take());
+        let state = match cached {
+            Some(mut cached) => {
+                cached.merge(state);
+                cached
+            }
+            None => state,
+        };
+        let surface_id = context.sender_object_id;
+        let mut surface = std::mem::take(&mut context.sender_object);
+        Self::apply(&mut context, surface_id, &mut surface, state);
+        context.sender_object = surface;
         context.ok()
     }
 
@@ -181,13 +710,17 @@ impl WlSurface {
     // which uses buffer coordinates instead of surface coordinates,
     // and is probably the preferred and intuitive way of doing this.
     pub fn damage(
-        context: Context<WlSurface>,
-        _x: i32,      // int: surface-local x coordinate
-        _y: i32,      // int: surface-local y coordinate
-        _width: i32,  // int: width of damage rectangle
-        _height: i32, // int: height of damage rectangle
+        mut context: Context<WlSurface>,
+        x: i32,      // int: surface-local x coordinate
+        y: i32,      // int: surface-local y coordinate
+        width: i32,  // int: width of damage rectangle
+        height: i32, // int: height of damage rectangle
     ) -> Box<dyn Future<Item = (Session, NextAction), Error = ()> + Send> {
-        context.invalid_method("wl_surface::damage is not implemented yet".to_string())
+        let rect = Rect::new(x, y, width, height);
+        if !rect.is_empty() {
+            context.sender_object.pending.damage.push(rect);
+        }
+        context.ok()
     }
 
     // mark part of the surface damaged using buffer coordinates
@@ -225,22 +758,93 @@ impl WlSurface {
     // two requests separately and only transform from one to the other
     // after receiving the wl_surface.commit.
     pub fn damage_buffer(
-        context: Context<WlSurface>,
-        _x: i32,      // int: buffer-local x coordinate
-        _y: i32,      // int: buffer-local y coordinate
-        _width: i32,  // int: width of damage rectangle
-        _height: i32, // int: height of damage rectangle
+        mut context: Context<WlSurface>,
+        x: i32,      // int: buffer-local x coordinate
+        y: i32,      // int: buffer-local y coordinate
+        width: i32,  // int: width of damage rectangle
+        height: i32, // int: height of damage rectangle
     ) -> Box<dyn Future<Item = (Session, NextAction), Error = ()> + Send> {
-        context.invalid_method("wl_surface::damage_buffer is not implemented yet".to_string())
+        let rect = Rect::new(x, y, width, height);
+        if !rect.is_empty() {
+            context.sender_object.pending.buffer_damage.push(rect);
+        }
+        context.ok()
     }
 
     // delete surface
     //
     // Deletes the surface and invalidates its object ID.
     pub fn destroy(
-        context: Context<WlSurface>,
+        mut context: Context<WlSurface>,
     ) -> Box<dyn Future<Item = (Session, NextAction), Error = ()> + Send> {
-        context.invalid_method("wl_surface::destroy is not implemented yet".to_string())
+        let surface_id = context.sender_object_id;
+        match (
+            context.sender_object.role,
+            context.sender_object.role_object,
+        ) {
+            // A wl_subsurface outlives its surface: the surface leaves its
+            // parent right away and the wl_subsurface becomes inert.
+            (Some(Role::Subsurface), Some(subsurface_id)) => {
+                let parent_id = context
+                    .sender_object
+                    .subsurface
+                    .as_ref()
+                    .and_then(|x| x.parent);
+                if let Some(parent) =
+                    parent_id.and_then(|x| WlSurface::get_mut(&mut context.resources, x))
+                {
+                    parent.remove_subsurface(surface_id);
+                }
+                if let Some(Resource::WlSubsurface(x)) = context.resources.get_mut(&subsurface_id) {
+                    x.surface_id = 0;
+                }
+            }
+            (_, Some(x)) => {
+                let message = format!(
+                    "object {} must be destroyed before wl_surface@{}",
+                    x, surface_id
+                );
+                return context.protocol_error(ERROR_DEFUNCT_ROLE_OBJECT, message);
+            }
+            (_, None) => {}
+        }
+        if let Some(buffer_id) = context.sender_object.current.buffer {
+            Self::release_buffer(&mut context, buffer_id);
+        }
+        let surface = &context.sender_object;
+        let resources = &mut context.resources;
+
+        // The sub-surfaces of the surface are unmapped.
+        for placement in surface
+            .pending
+            .subsurfaces
+            .iter()
+            .chain(&surface.current.subsurfaces)
+        {
+            if let Some(x) = WlSurface::get_mut(resources, placement.surface_id)
+                .and_then(|x| x.subsurface.as_mut())
+                .filter(|x| x.parent == Some(surface_id))
+            {
+                x.parent = None;
+            }
+        }
+
+        // The callbacks of commits a synchronized sub-surface cached come
+        // before the pending ones.
+        let mut uncommitted = match &context.sender_object.subsurface {
+            Some(SubsurfaceState {
+                cached: Some(cached),
+                ..
+            }) => cached.frame_callbacks.clone(),
+            _ => Vec::new(),
+        };
+        uncommitted.extend_from_slice(&context.sender_object.pending.frame_callbacks);
+        context.sender_object.subsurface = None;
+        context.sender_object.role_object = None;
+        context
+            .frame_clock
+            .surface_destroyed(context.client_id, surface_id, &uncommitted);
+        context.destroy()
     }
 
     // request a frame throttling hint
@@ -278,10 +882,12 @@ impl WlSurface {
     // The callback_data passed in the callback is the current time, in
     // milliseconds, with an undefined base.
     pub fn frame(
-        context: Context<WlSurface>,
-        _callback: u32, // new_id: callback object for the frame request
+        mut context: Context<WlSurface>,
+        callback: u32, // new_id: callback object for the frame request
     ) -> Box<dyn Future<Item = (Session, NextAction), Error = ()> + Send> {
-        context.invalid_method("wl_surface::frame is not implemented yet".to_string())
+        // The frame clock fires the callback, without the backend.
+        context.sender_object.pending.frame_callbacks.push(callback);
+        context.handled()
     }
 
     // sets the buffer scaling factor
@@ -310,10 +916,17 @@ impl WlSurface {
     // If scale is not positive the invalid_scale protocol error is
     // raised.
     pub fn set_buffer_scale(
-        context: Context<WlSurface>,
-        _scale: i32, // int: positive scale for interpreting buffer contents
+        mut context: Context<WlSurface>,
+        scale: i32, // int: positive scale for interpreting buffer contents
     ) -> Box<dyn Future<Item = (Session, NextAction), Error = ()> + Send> {
-        context.invalid_method("wl_surface::set_buffer_scale is not implemented yet".to_string())
+        if scale < 1 {
+            return context.protocol_error(
+                enums::Error::InvalidScale as u32,
+                format!("buffer scale {} is not positive", scale),
+            );
+        }
+        context.sender_object.pending.scale = scale;
+        context.ok()
     }
 
     // sets the buffer transformation
@@ -348,11 +961,20 @@ impl WlSurface {
     // wl_output.transform enum the invalid_transform protocol error
     // is raised.
     pub fn set_buffer_transform(
-        context: Context<WlSurface>,
-        _transform: i32, // int: transform for interpreting buffer contents
+        mut context: Context<WlSurface>,
+        transform: i32, // int: transform for interpreting buffer contents
     ) -> Box<dyn Future<Item = (Session, NextAction), Error = ()> + Send> {
-        context
-            .invalid_method("wl_surface::set_buffer_transform is not implemented yet".to_string())
+        if !(0..=7).contains(&transform) {
+            return context.protocol_error(
+                enums::Error::InvalidTransform as u32,
+                format!(
+                    "buffer transform {} is not a wl_output.transform",
+                    transform
+                ),
+            );
+        }
+        context.sender_object.pending.transform = transform;
+        context.ok()
     }
 
     // set input region
@@ -380,10 +1002,11 @@ impl WlSurface {
     // immediately. A NULL wl_region causes the input region to be set
     // to infinite.
     pub fn set_input_region(
-        context: Context<WlSurface>,
-        _region: u32, // object: input region of the surface
+        mut context: Context<WlSurface>,
+        region: u32, // object: input region of the surface
     ) -> Box<dyn Future<Item = (Session, NextAction), Error = ()> + Send> {
-        context.invalid_method("wl_surface::set_input_region is not implemented yet".to_string())
+        context.sender_object.pending.input_region = WlSurface::region(&context, region);
+        context.handled()
     }
 
     // set opaque region
@@ -413,9 +1036,205 @@ impl WlSurface {
     // destroyed immediately. A NULL wl_region causes the pending opaque
     // region to be set to empty.
     pub fn set_opaque_region(
-        context: Context<WlSurface>,
-        _region: u32, // object: opaque region of the surface
+        mut context: Context<WlSurface>,
+        region: u32, // object: opaque region of the surface
     ) -> Box<dyn Future<Item = (Session, NextAction), Error = ()> + Send> {
-        context.invalid_method("wl_surface::set_opaque_region is not implemented yet".to_string())
+        context.sender_object.pending.opaque_region =
+            WlSurface::region(&context, region).unwrap_or_default();
+        context.handled()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Commits state on a surface whose current buffer is width x height.
+    fn commit(width: i32, height: i32, state: SurfaceState) -> WlSurface {
+        let mut surface = WlSurface {
+            buffer_size: (width, height),
+            ..WlSurface::default()
+        };
+        surface.apply_state(state);
+        surface
+    }
+
+    // A surface and, for each given parent, a sub-surface of the one before,
+    // synchronized or not.
+    fn subsurfaces(sync: &[bool]) -> HashMap<u32, Resource> {
+        let mut resources = HashMap::new();
+        resources.insert(1, WlSurface::default().into());
+        for (i, sync) in sync.iter().enumerate() {
+            let surface_id = i as u32 + 2;
+            let surface = WlSurface {
+                role: Some(Role::Subsurface),
+                subsurface: Some(SubsurfaceState {
+                    parent: Some(surface_id - 1),
+                    sync: *sync,
+                    cached: None,
+                }),
+                ..WlSurface::default()
+            };
+            resources.insert(surface_id, surface.into());
+        }
+        resources
+    }
+
+    fn synchronized(resources: &HashMap<u32, Resource>, surface_id: u32) -> bool {
+        WlSurface::get(resources, surface_id)
+            .unwrap()
+            .synchronized(resources)
+    }
+
+    #[test]
+    fn synchronized_is_inherited() {
+        let resources = subsurfaces(&[false, true, false]);
+        assert!(!synchronized(&resources, 1));
+        assert!(!synchronized(&resources, 2));
+        assert!(synchronized(&resources, 3));
+        // Desynchronized, but below a synchronized one.
+        assert!(synchronized(&resources, 4));
+
+        let resources = subsurfaces(&[false, false]);
+        assert!(!synchronized(&resources, 3));
+    }
+
+    #[test]
+    fn an_orphan_follows_only_its_own_mode() {
+        let mut resources = subsurfaces(&[true, false]);
+        resources.remove(&2);
+        if let Some(x) = WlSurface::get_mut(&mut resources, 3).and_then(|x| x.subsurface.as_mut()) {
+            x.parent = None;
+        }
+        assert!(!synchronized(&resources, 3));
+    }
+
+    #[test]
+    fn cached_commits_merge() {
+        let mut cached = SurfaceState {
+            attached: true,
+            buffer: Some(10),
+            dx: 1,
+            damage: vec![Rect::new(0, 0, 1, 1)],
+            frame_callbacks: vec![20],
+            ..SurfaceState::default()
+        };
+        // A commit without attach keeps the cached buffer.
+        cached.merge(SurfaceState {
+            scale: 2,
+            damage: vec![Rect::new(1, 1, 1, 1)],
+            frame_callbacks: vec![21],
+            ..SurfaceState::default()
+        });
+        assert_eq!(cached.buffer, Some(10));
+        assert_eq!(cached.scale, 2);
+        // A later attach replaces it, even with no buffer.
+        cached.merge(SurfaceState {
+            attached: true,
+            buffer: None,
+            dx: 2,
+            frame_callbacks: vec![22],
+            ..SurfaceState::default()
+        });
+        assert!(cached.attached);
+        assert_eq!(cached.buffer, None);
+        assert_eq!(cached.dx, 3);
+        assert_eq!(
+            cached.damage,
+            vec![Rect::new(0, 0, 1, 1), Rect::new(1, 1, 1, 1)]
+        );
+        assert_eq!(cached.frame_callbacks, vec![20, 21, 22]);
+        // Later state that is not accumulated wins.
+        assert_eq!(cached.scale, 1);
+    }
+
+    #[test]
+    fn buffer_damage_becomes_surface_damage() {
+        let surface = commit(
+            8,
+            4,
+            SurfaceState {
+                scale: 2,
+                damage: vec![Rect::new(0, 0, 1, 1)],
+                buffer_damage: vec![Rect::new(2, 0, 2, 2), Rect::new(5, 3, 1, 1)],
+                ..SurfaceState::default()
+            },
+        );
+        assert_eq!(surface.size, (4, 2));
+        // Surface damage is kept; partly covered surface pixels count.
+        assert_eq!(
+            surface.current.damage,
+            vec![
+                Rect::new(0, 0, 1, 1),
+                Rect::new(1, 0, 1, 1),
+                Rect::new(2, 1, 1, 1),
+            ]
+        );
+        assert!(surface.current.buffer_damage.is_empty());
+    }
+
+    #[test]
+    fn buffer_damage_follows_the_transform() {
+        // Rotated by 90 degrees, the 8x4 buffer at scale 2 is a 2x4 surface
+        // whose first column shows the bottom row of the buffer.
+        let state = SurfaceState {
+            scale: 2,
+            transform: 1,
+            buffer_damage: vec![Rect::new(0, 0, 2, 2), Rect::new(6, 2, 2, 2)],
+            ..SurfaceState::default()
+        };
+        let surface = commit(8, 4, state.clone());
+        assert_eq!(surface.size, (2, 4));
+        assert_eq!(
+            surface.current.damage,
+            vec![Rect::new(1, 0, 1, 1), Rect::new(0, 3, 1, 1)]
+        );
+
+        // Flipped and rotated by 270 degrees.
+        let surface = commit(
+            8,
+            4,
+            SurfaceState {
+                transform: 7,
+                ..state
+            },
+        );
+        assert_eq!(
+            surface.current.damage,
+            vec![Rect::new(1, 3, 1, 1), Rect::new(0, 0, 1, 1)]
+        );
+    }
+
+    #[test]
+    fn no_buffer_before_the_first_ack() {
+        let mut resources: HashMap<u32, Resource> = HashMap::new();
+        resources.insert(
+            2,
+            XdgSurface {
+                wl_surface_id: 1,
+                xdg_wm_base_id: 3,
+                role_object: None,
+                configured: false,
+                pending_geometry: None,
+                geometry: None,
+            }
+            .into(),
+        );
+        let mut surface = WlSurface {
+            role_object: Some(2),
+            ..WlSurface::default()
+        };
+        // The initial commit, without a buffer, asks for the first configure.
+        assert_eq!(surface.unconfigured_xdg_surface(&resources), None);
+        surface.pending.attached = true;
+        assert_eq!(surface.unconfigured_xdg_surface(&resources), None);
+
+        surface.pending.buffer = Some(4);
+        assert_eq!(surface.unconfigured_xdg_surface(&resources), Some(2));
+
+        if let Some(x) = XdgSurface::get_mut(&mut resources, 2) {
+            x.configured = true;
+        }
+        assert_eq!(surface.unconfigured_xdg_surface(&resources), None);
     }
 }
@@ -26,6 +26,7 @@
 
 #[allow(unused_imports)]
 use crate::protocol::session::{Context, NextAction, Session};
+use crate::protocol::xdg_shell::xdg_surface::XdgSurface;
 #[allow(unused_imports)]
 use futures::future::{err, ok, Future};
 #[allow(unused_imports)]
@@ -70,7 +71,9 @@ pub use lib::*;
 //
 // The client must call wl_surface.commit on the corresponding wl_surface
 // for the xdg_popup state to take effect.
-pub struct XdgPopup {}
+pub struct XdgPopup {
+    pub xdg_surface_id: u32,
+}
 
 impl XdgPopup {
     // remove xdg_popup interface
@@ -81,9 +84,13 @@ impl XdgPopup {
     // If this xdg_popup is not the "topmost" popup, a protocol error
     // will be sent.
     pub fn destroy(
-        context: Context<XdgPopup>,
+        mut context: Context<XdgPopup>,
     ) -> Box<dyn Future<Item = (Session, NextAction), Error = ()> + Send> {
-        context.invalid_method("xdg_popup::destroy is not implemented yet".to_string())
+        let xdg_surface_id = context.sender_object.xdg_surface_id;
+        if let Some(x) = XdgSurface::get_mut(&mut context.resources, xdg_surface_id) {
+            x.role_object = None;
+        }
+        context.destroy()
     }
 
     // make the popup take an explicit grab
@@ -24,6 +24,7 @@
 // FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
 // DEALINGS IN THE SOFTWARE.
 
+use crate::compositor::Rect;
 #[allow(unused_imports)]
 use crate::protocol::session::{Context, NextAction, Session};
 #[allow(unused_imports)]
@@ -58,16 +59,57 @@ pub use lib::*;
 // non-zero size set by set_size, and a non-zero anchor rectangle set by
 // set_anchor_rect. Passing an incomplete xdg_positioner object when
 // positioning a surface raises an error.
-pub struct XdgPositioner {}
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct XdgPositioner {
+    pub size: Option<(i32, i32)>,
+    pub anchor_rect: Option<Rect>,
+    pub anchor: u32,
+    pub gravity: u32,
+    pub offset: (i32, i32),
+}
+
+// The horizontal and vertical direction of an anchor or a gravity, which
+// share their values: -1 for left or top, 1 for right or bottom.
+fn direction(value: u32) -> (i32, i32) {
+    match value {
+        1 => (0, -1),
+        2 => (0, 1),
+        3 => (-1, 0),
+        4 => (1, 0),
+        5 => (-1, -1),
+        6 => (-1, 1),
+        7 => (1, -1),
+        8 => (1, 1),
+        _ => (0, 0),
+    }
+}
 
 impl XdgPositioner {
+    // The positioned rectangle relative to the parent's window geometry, or
+    // None while the positioner is incomplete. Constraint adjustments are
+    // not applied, so the rectangle may reach outside the output.
+    pub fn place(&self) -> Option<Rect> {
+        let (width, height) = self.size?;
+        let rect = self.anchor_rect?;
+        let (anchor_x, anchor_y) = direction(self.anchor);
+        let (gravity_x, gravity_y) = direction(self.gravity);
+        let x = rect.x + rect.width * (anchor_x + 1) / 2;
+        let y = rect.y + rect.height * (anchor_y + 1) / 2;
+        Some(Rect::new(
+            x + self.offset.0 - width * (1 - gravity_x) / 2,
+            y + self.offset.1 - height * (1 - gravity_y) / 2,
+            width,
+            height,
+        ))
+    }
+
     // destroy the xdg_positioner object
     //
     // Notify the compositor that the xdg_positioner will no longer be used.
     pub fn destroy(
         context: Context<XdgPositioner>,
     ) -> Box<dyn Future<Item = (Session, NextAction), Error = ()> + Send> {
-        context.invalid_method("xdg_positioner::destroy is not implemented yet".to_string())
+        context.destroy()
     }
 
     // set anchor rectangle anchor
@@ -79,10 +121,15 @@ impl XdgPositioner {
     // otherwise, the derived anchor point will be centered on the specified
     // edge, or in the center of the anchor rectangle if no edge is specified.
     pub fn set_anchor(
-        context: Context<XdgPositioner>,
-        _anchor: u32, // uint: anchor
+        mut context: Context<XdgPositioner>,
+        anchor: u32, // uint: anchor
     ) -> Box<dyn Future<Item = (Session, NextAction), Error = ()> + Send> {
-        context.invalid_method("xdg_positioner::set_anchor is not implemented yet".to_string())
+        if anchor > enums::Anchor::BottomRight as u32 {
+            let message = format!("invalid anchor {}", anchor);
+            return context.protocol_error(enums::Error::InvalidInput as u32, message);
+        }
+        context.sender_object.anchor = anchor;
+        context.ok()
     }
 
     // set the anchor rectangle within the parent surface
@@ -98,13 +145,18 @@ impl XdgPositioner {
     //
     // If a negative size is set the invalid_input error is raised.
     pub fn set_anchor_rect(
-        context: Context<XdgPositioner>,
-        _x: i32,      // int: x position of anchor rectangle
-        _y: i32,      // int: y position of anchor rectangle
-        _width: i32,  // int: width of anchor rectangle
-        _height: i32, // int: height of anchor rectangle
+        mut context: Context<XdgPositioner>,
+        x: i32,      // int: x position of anchor rectangle
+        y: i32,      // int: y position of anchor rectangle
+        width: i32,  // int: width of anchor rectangle
+        height: i32, // int: height of anchor rectangle
     ) -> Box<dyn Future<Item = (Session, NextAction), Error = ()> + Send> {
-        context.invalid_method("xdg_positioner::set_anchor_rect is not implemented yet".to_string())
+        if width < 0 || height < 0 {
+            let message = format!("invalid anchor rect size {}x{}", width, height);
+            return context.protocol_error(enums::Error::InvalidInput as u32, message);
+        }
+        context.sender_object.anchor_rect = Some(Rect::new(x, y, width, height));
+        context.ok()
     }
 
     // set the adjustment to be done when constrained
@@ -122,13 +174,13 @@ impl XdgPositioner {
     // are applied is specified in the corresponding adjustment descriptions.
     //
     // The default adjustment is none.
+    //
+    // Reflex does not constrain popups, so the adjustment is only relayed.
     pub fn set_constraint_adjustment(
         context: Context<XdgPositioner>,
         _constraint_adjustment: u32, // uint: bit mask of constraint adjustments
     ) -> Box<dyn Future<Item = (Session, NextAction), Error = ()> + Send> {
-        context.invalid_method(
-            "xdg_positioner::set_constraint_adjustment is not implemented yet".to_string(),
-        )
+        context.ok()
     }
 
     // set child surface gravity
@@ -140,10 +192,15 @@ impl XdgPositioner {
     // surface will be centered over the anchor point on any axis that had no
     // gravity specified.
     pub fn set_gravity(
-        context: Context<XdgPositioner>,
-        _gravity: u32, // uint: gravity direction
+        mut context: Context<XdgPositioner>,
+        gravity: u32, // uint: gravity direction
     ) -> Box<dyn Future<Item = (Session, NextAction), Error = ()> + Send> {
-        context.invalid_method("xdg_positioner::set_gravity is not implemented yet".to_string())
+        if gravity > enums::Gravity::BottomRight as u32 {
+            let message = format!("invalid gravity {}", gravity);
+            return context.protocol_error(enums::Error::InvalidInput as u32, message);
+        }
+        context.sender_object.gravity = gravity;
+        context.ok()
     }
 
     // set surface position offset
@@ -160,11 +217,12 @@ impl XdgPositioner {
     // element, while aligning the user interface element of the parent surface
     // with some user interface element placed somewhere in the popup surface.
     pub fn set_offset(
-        context: Context<XdgPositioner>,
-        _x: i32, // int: surface position x offset
-        _y: i32, // int: surface position y offset
+        mut context: Context<XdgPositioner>,
+        x: i32, // int: surface position x offset
+        y: i32, // int: surface position y offset
     ) -> Box<dyn Future<Item = (Session, NextAction), Error = ()> + Send> {
-        context.invalid_method("xdg_positioner::set_offset is not implemented yet".to_string())
+        context.sender_object.offset = (x, y);
+        context.ok()
     }
 
     // set the size of the to-be positioned rectangle
@@ -175,10 +233,15 @@ impl XdgPositioner {
     //
     // If a zero or negative size is set the invalid_input error is raised.
     pub fn set_size(
-        context: Context<XdgPositioner>,
-        _width: i32,  // int: width of positioned rectangle
-        _height: i32, // int: height of positioned rectangle
+        mut context: Context<XdgPositioner>,
+        width: i32,  // int: width of positioned rectangle
+        height: i32, // int: height of positioned rectangle
     ) -> Box<dyn Future<Item = (Session, NextAction), Error = ()> + Send> {
-        context.invalid_method("xdg_positioner::set_size is not implemented yet".to_string())
+        if width <= 0 || height <= 0 {
+            let message = format!("invalid size {}x{}", width, height);
+            return context.protocol_error(enums::Error::InvalidInput as u32, message);
+        }
+        context.sender_object.size = Some((width, height));
+        context.ok()
     }
 }
@@ -24,13 +24,22 @@
 // FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
 // DEALINGS IN THE SOFTWARE.
 
+use crate::backend::WindowChange;
+use crate::compositor::Rect;
+use crate::lock::MutexExt;
+use crate::protocol::resource::Resource;
 #[allow(unused_imports)]
 use crate::protocol::session::{Context, NextAction, Session};
+use crate::protocol::wayland::wl_surface::{Role, WlSurface};
+use crate::protocol::xdg_shell::xdg_wm_base;
 #[allow(unused_imports)]
 use futures::future::{err, ok, Future};
 #[allow(unused_imports)]
 use futures::sink::Sink;
 #[allow(unused_imports)]
+use std::collections::HashMap;
+use std::sync::Mutex;
+#[allow(unused_imports)]
 use std::sync::{Arc, RwLock};
 
 pub mod enums;
@@ -81,9 +90,148 @@ pub use lib::*;
 //    has not been destroyed.
 pub struct XdgSurface {
     pub wl_surface_id: u32,
+    // The xdg_wm_base that made it, which role errors are raised on.
+    pub xdg_wm_base_id: u32,
+    // The xdg_toplevel or xdg_popup, while there is one.
+    pub role_object: Option<u32>,
+    // Whether the client has acked a configure. Until then it must not
+    // commit a buffer.
+    pub configured: bool,
+    // The window geometry as set and as last committed, in surface-local
+    // coordinates. None until set_window_geometry, which means the bounds
+    // of the surface and its sub-surfaces.
+    pub pending_geometry: Option<Rect>,
+    pub geometry: Option<Rect>,
+}
+
+// Error codes from a later version of xdg_surface than the one reflex
+// implements.
+const ERROR_INVALID_SERIAL: u32 = 4;
+const ERROR_INVALID_SIZE: u32 = 5;
+const ERROR_DEFUNCT_ROLE_OBJECT: u32 = 6;
+
+// The serials of the configures sent to each xdg_surface of a client that
+// it has not acked yet, oldest first. The session checks the acks against
+// them, and the backend, which sends the configures, adds to them through
+// ClientEvents.
+#[derive(Clone, Default)]
+pub struct ConfigureSerials {
+    inner: Arc<Mutex<HashMap<u32, Vec<u32>>>>,
+}
+
+impl ConfigureSerials {
+    pub fn add_surface(&self, xdg_surface_id: u32) {
+        self.inner.locked().insert(xdg_surface_id, Vec::new());
+    }
+
+    pub fn remove_surface(&self, xdg_surface_id: u32) {
+        self.inner.locked().remove(&xdg_surface_id);
+    }
+
+    // Objects that are not xdg_surfaces of the client are ignored, so that
+    // relays can pass any event that looks like a configure.
+    pub fn sent(&self, xdg_surface_id: u32, serial: u32) {
+        if let Some(x) = self.inner.locked().get_mut(&xdg_surface_id) {
+            x.push(serial);
+        }
+    }
+
+    // Returns false if the serial was not sent to the xdg_surface or has
+    // been acked already. Acking a configure acks the older ones with it.
+    pub fn ack(&self, xdg_surface_id: u32, serial: u32) -> bool {
+        let mut inner = self.inner.locked();
+        let serials = match inner.get_mut(&xdg_surface_id) {
+            Some(x) => x,
+            None => return false,
+        };
+        match serials.iter().position(|x| *x == serial) {
+            Some(x) => {
+                serials.drain(..=x);
+                true
+            }
+            None => false,
+        }
+    }
 }
 
 impl XdgSurface {
+    pub fn get(resources: &HashMap<u32, Resource>, xdg_surface_id: u32) -> Option<&Self> {
+        match resources.get(&xdg_surface_id) {
+            Some(Resource::XdgSurface(x)) => Some(x),
+            _ => None,
+        }
+    }
+
+    // Applies the pending window geometry with the rest of a wl_surface
+    // commit, clamped to bounds, those of the surface and its sub-surfaces.
+    // Returns the new geometry if it changed.
+    pub fn apply_geometry(&mut self, bounds: Rect) -> Option<Rect> {
+        let geometry = self.pending_geometry.map(|x| {
+            let clamped = x.intersect(&bounds);
+            if clamped.is_empty() {
+                x
+            } else {
+                clamped
+            }
+        });
+        if geometry == self.geometry {
+            return None;
+        }
+        self.geometry = geometry;
+        geometry
+    }
+
+    pub fn get_mut(
+        resources: &mut HashMap<u32, Resource>,
+        xdg_surface_id: u32,
+    ) -> Option<&mut Self> {
+        match resources.get_mut(&xdg_surface_id) {
+            Some(Resource::XdgSurface(x)) => Some(x),
+            _ => None,
+        }
+    }
+
+    // Gives the wl_surface an xdg_surface-based role, with id as its role
+    // object. Otherwise returns the object to raise the error on, the error
+    // code and the message.
+    fn set_role(
+        context: &mut Context<XdgSurface>,
+        id: u32,
+        role: Role,
+    ) -> Result<(), (u32, u32, String)> {
+        if let Some(x) = context.sender_object.role_object {
+            return Err((
+                context.sender_object_id,
+                enums::Error::AlreadyConstructed as u32,
+                format!(
+                    "xdg_surface@{} already has role object {}",
+                    context.sender_object_id, x
+                ),
+            ));
+        }
+        let surface_id = context.sender_object.wl_surface_id;
+        let surface = match WlSurface::get_mut(&mut context.resources, surface_id) {
+            Some(x) => x,
+            None => {
+                return Err((
+                    context.sender_object.xdg_wm_base_id,
+                    xdg_wm_base::enums::Error::Role as u32,
+                    format!("{} is not a wl_surface", surface_id),
+                ))
+            }
+        };
+        if let Err(message) = surface.check_role(surface_id, role) {
+            return Err((
+                context.sender_object.xdg_wm_base_id,
+                xdg_wm_base::enums::Error::Role as u32,
+                message,
+            ));
+        }
+        surface.role = Some(role);
+        context.sender_object.role_object = Some(id);
+        Ok(())
+    }
+
     // ack a configure event
     //
     // When a configure event is received, if a client commits the
@@ -106,10 +254,21 @@ impl XdgSurface {
     // only the last request sent before a commit indicates which configure
     // event the client really is responding to.
     pub fn ack_configure(
-        context: Context<XdgSurface>,
-        _serial: u32, // uint: the serial from the configure event
+        mut context: Context<XdgSurface>,
+        serial: u32, // uint: the serial from the configure event
     ) -> Box<dyn Future<Item = (Session, NextAction), Error = ()> + Send> {
-        context.invalid_method("xdg_surface::ack_configure is not implemented yet".to_string())
+        if !context
+            .configure_serials
+            .ack(context.sender_object_id, serial)
+        {
+            return context.protocol_error(
+                ERROR_INVALID_SERIAL,
+                format!("serial {} is not that of a configure to ack", serial),
+            );
+        }
+        context.sender_object.configured = true;
+        // Only reflex tracks serials, so acks are not relayed.
+        context.handled()
     }
 
     // destroy the xdg_surface
@@ -117,9 +276,24 @@ impl XdgSurface {
     // Destroy the xdg_surface object. An xdg_surface must only be destroyed
     // after its role object has been destroyed.
     pub fn destroy(
-        context: Context<XdgSurface>,
+        mut context: Context<XdgSurface>,
     ) -> Box<dyn Future<Item = (Session, NextAction), Error = ()> + Send> {
-        context.invalid_method("xdg_surface::destroy is not implemented yet".to_string())
+        if let Some(x) = context.sender_object.role_object {
+            let message = format!(
+                "object {} must be destroyed before xdg_surface@{}",
+                x, context.sender_object_id
+            );
+            return context.protocol_error(ERROR_DEFUNCT_ROLE_OBJECT, message);
+        }
+        if let Some(x) =
+            WlSurface::get_mut(&mut context.resources, context.sender_object.wl_surface_id)
+        {
+            x.role_object = None;
+        }
+        context
+            .configure_serials
+            .remove_surface(context.sender_object_id);
+        context.destroy()
     }
 
     // assign the xdg_popup surface role
@@ -133,12 +307,47 @@ impl XdgSurface {
     // See the documentation of xdg_popup for more details about what an
     // xdg_popup is and how it is used.
     pub fn get_popup(
-        context: Context<XdgSurface>,
-        _id: u32,         // new_id:
-        _parent: u32,     // object:
-        _positioner: u32, // object:
+        mut context: Context<XdgSurface>,
+        id: u32,         // new_id:
+        parent: u32,     // object:
+        positioner: u32, // object:
     ) -> Box<dyn Future<Item = (Session, NextAction), Error = ()> + Send> {
-        context.invalid_method("xdg_surface::get_popup is not implemented yet".to_string())
+        let xdg_wm_base_id = context.sender_object.xdg_wm_base_id;
+        match context.resources.get(&positioner) {
+            Some(Resource::XdgPositioner(x)) if x.place().is_some() => {}
+            _ => {
+                let message = format!("xdg_positioner@{} is not complete", positioner);
+                return context.object_error(
+                    xdg_wm_base_id,
+                    xdg_wm_base::enums::Error::InvalidPositioner as u32,
+                    message,
+                );
+            }
+        }
+        // A null parent is left to be set through another protocol.
+        match context.resources.get(&parent) {
+            Some(Resource::XdgSurface(_)) => {}
+            _ if parent == 0 => {}
+            _ => {
+                let message = format!("{} is not an xdg_surface", parent);
+                return context.object_error(
+                    xdg_wm_base_id,
+                    xdg_wm_base::enums::Error::InvalidPopupParent as u32,
+                    message,
+                );
+            }
+        }
+        if let Err((object_id, code, message)) = Self::set_role(&mut context, id, Role::XdgPopup) {
+            return context.object_error(object_id, code, message);
+        }
+        context.resources.insert(
+            id,
+            crate::protocol::xdg_shell::xdg_popup::XdgPopup {
+                xdg_surface_id: context.sender_object_id,
+            }
+            .into(),
+        );
+        context.ok()
     }
 
     // assign the xdg_toplevel surface role
@@ -152,14 +361,31 @@ impl XdgSurface {
         mut context: Context<XdgSurface>,
         id: u32, // new_id:
     ) -> Box<dyn Future<Item = (Session, NextAction), Error = ()> + Send> {
-        println!("XdgSurface::get_toplevel(id={})", id);
+        log::debug!(
+            client_id = context.client_id,
+            object_id = id,
+            interface = "xdg_toplevel";
+            "xdg_surface::get_toplevel"
+        );
+        if let Err((object_id, code, message)) = Self::set_role(&mut context, id, Role::XdgToplevel)
+        {
+            return context.object_error(object_id, code, message);
+        }
         context.resources.insert(
             id,
             crate::protocol::xdg_shell::xdg_toplevel::XdgToplevel {
                 xdg_surface_id: context.sender_object_id,
+                title: None,
+                app_id: None,
             }
             .into(),
         );
+        context.backend.window_changed(
+            id,
+            &WindowChange::Created {
+                surface_id: context.sender_object.wl_surface_id,
+            },
+        );
         context.ok()
     }
 
@@ -195,13 +421,94 @@ impl XdgSurface {
     // combined geometry of the surface of the xdg_surface and the associated
     // subsurfaces.
     pub fn set_window_geometry(
-        context: Context<XdgSurface>,
-        _x: i32,      // int:
-        _y: i32,      // int:
-        _width: i32,  // int:
-        _height: i32, // int:
+        mut context: Context<XdgSurface>,
+        x: i32,      // int:
+        y: i32,      // int:
+        width: i32,  // int:
+        height: i32, // int:
     ) -> Box<dyn Future<Item = (Session, NextAction), Error = ()> + Send> {
-        context
-            .invalid_method("xdg_surface::set_window_geometry is not implemented yet".to_string())
+        if width <= 0 || height <= 0 {
+            return context.protocol_error(
+                ERROR_INVALID_SIZE,
+                format!("window geometry {}x{} is empty", width, height),
+            );
+        }
+        context.sender_object.pending_geometry = Some(Rect::new(x, y, width, height));
+        // Backends get the geometry with the committed state.
+        context.handled()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_sent_serials_are_acked() {
+        let serials = ConfigureSerials::default();
+        serials.add_surface(1);
+        serials.sent(1, 10);
+        // Neither a serial that was not sent nor one sent to another
+        // xdg_surface can be acked.
+        assert!(!serials.ack(1, 11));
+        assert!(!serials.ack(2, 10));
+        assert!(serials.ack(1, 10));
+        // Nor can a serial be acked twice.
+        assert!(!serials.ack(1, 10));
+
+        serials.remove_surface(1);
+        serials.sent(1, 12);
+        assert!(!serials.ack(1, 12));
+    }
+
+    #[test]
+    fn acking_a_configure_acks_the_older_ones() {
+        let serials = ConfigureSerials::default();
+        serials.add_surface(1);
+        for serial in &[10, 11, 12] {
+            serials.sent(1, *serial);
+        }
+        assert!(serials.ack(1, 11));
+        assert!(!serials.ack(1, 10));
+        assert!(serials.ack(1, 12));
+    }
+
+    fn xdg_surface(pending_geometry: Option<Rect>) -> XdgSurface {
+        XdgSurface {
+            wl_surface_id: 1,
+            xdg_wm_base_id: 2,
+            role_object: None,
+            configured: true,
+            pending_geometry,
+            geometry: None,
+        }
+    }
+
+    #[test]
+    fn geometry_is_clamped_to_the_bounds() {
+        let bounds = Rect::new(-2, 0, 10, 10);
+        let mut surface = xdg_surface(None);
+        assert_eq!(surface.apply_geometry(bounds), None);
+
+        surface.pending_geometry = Some(Rect::new(-5, 2, 10, 20));
+        assert_eq!(surface.apply_geometry(bounds), Some(Rect::new(-2, 2, 7, 8)));
+        // Unchanged, even once the pending geometry is committed again.
+        assert_eq!(surface.apply_geometry(bounds), None);
+        assert_eq!(surface.geometry, Some(Rect::new(-2, 2, 7, 8)));
+
+        // Set apart from the bounds, the geometry is kept as it is.
+        surface.pending_geometry = Some(Rect::new(20, 20, 4, 4));
+        assert_eq!(
+            surface.apply_geometry(bounds),
+            Some(Rect::new(20, 20, 4, 4))
+        );
+
+        // Bounds that grow can let more of the set geometry through.
+        let mut surface = xdg_surface(Some(Rect::new(0, 0, 20, 20)));
+        assert_eq!(surface.apply_geometry(bounds), Some(Rect::new(0, 0, 8, 10)));
+        assert_eq!(
+            surface.apply_geometry(Rect::new(0, 0, 30, 30)),
+            Some(Rect::new(0, 0, 20, 20))
+        );
     }
 }
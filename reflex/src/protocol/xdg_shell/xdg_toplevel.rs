@@ -24,8 +24,10 @@
 // FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
 // DEALINGS IN THE SOFTWARE.
 
+use crate::backend::WindowChange;
 #[allow(unused_imports)]
 use crate::protocol::session::{Context, NextAction, Session};
+use crate::protocol::xdg_shell::xdg_surface::XdgSurface;
 #[allow(unused_imports)]
 use futures::future::{err, ok, Future};
 #[allow(unused_imports)]
@@ -55,6 +57,8 @@ pub use lib::*;
 // Attaching a null buffer to a toplevel unmaps the surface.
 pub struct XdgToplevel {
     pub xdg_surface_id: u32,
+    pub title: Option<String>,
+    pub app_id: Option<String>,
 }
 
 impl XdgToplevel {
@@ -63,9 +67,13 @@ impl XdgToplevel {
     // This request destroys the role surface and unmaps the surface;
     // see "Unmapping" behavior in interface section for details.
     pub fn destroy(
-        context: Context<XdgToplevel>,
+        mut context: Context<XdgToplevel>,
     ) -> Box<dyn Future<Item = (Session, NextAction), Error = ()> + Send> {
-        context.invalid_method("xdg_toplevel::destroy is not implemented yet".to_string())
+        let xdg_surface_id = context.sender_object.xdg_surface_id;
+        if let Some(x) = XdgSurface::get_mut(&mut context.resources, xdg_surface_id) {
+            x.role_object = None;
+        }
+        context.destroy()
     }
 
     // start an interactive move
@@ -158,10 +166,16 @@ impl XdgToplevel {
     //
     // [0] http://standards.freedesktop.org/desktop-entry-spec/
     pub fn set_app_id(
-        context: Context<XdgToplevel>,
-        _app_id: String, // string:
+        mut context: Context<XdgToplevel>,
+        app_id: String, // string:
     ) -> Box<dyn Future<Item = (Session, NextAction), Error = ()> + Send> {
-        context.invalid_method("xdg_toplevel::set_app_id is not implemented yet".to_string())
+        let app_id = app_id.trim_end_matches('\0').to_string();
+        context.backend.window_changed(
+            context.sender_object_id,
+            &WindowChange::AppId(app_id.clone()),
+        );
+        context.sender_object.app_id = Some(app_id);
+        context.ok()
     }
 
     // set the window as fullscreen on an output
@@ -322,9 +336,12 @@ impl XdgToplevel {
     // also work with live previews on windows in Alt-Tab, Expose or
     // similar compositor features.
     pub fn set_minimized(
-        context: Context<XdgToplevel>,
+        mut context: Context<XdgToplevel>,
     ) -> Box<dyn Future<Item = (Session, NextAction), Error = ()> + Send> {
-        context.invalid_method("xdg_toplevel::set_minimized is not implemented yet".to_string())
+        context
+            .backend
+            .window_changed(context.sender_object_id, &WindowChange::Minimized);
+        context.ok()
     }
 
     // set the parent of this surface
@@ -362,10 +379,16 @@ impl XdgToplevel {
     //
     // The string must be encoded in UTF-8.
     pub fn set_title(
-        context: Context<XdgToplevel>,
-        _title: String, // string:
+        mut context: Context<XdgToplevel>,
+        title: String, // string:
     ) -> Box<dyn Future<Item = (Session, NextAction), Error = ()> + Send> {
-        context.invalid_method("xdg_toplevel::set_title is not implemented yet".to_string())
+        let title = title.trim_end_matches('\0').to_string();
+        context.backend.window_changed(
+            context.sender_object_id,
+            &WindowChange::Title(title.clone()),
+        );
+        context.sender_object.title = Some(title);
+        context.ok()
     }
 
     // show the window menu
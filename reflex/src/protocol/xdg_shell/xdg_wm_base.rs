@@ -26,6 +26,7 @@
 
 #[allow(unused_imports)]
 use crate::protocol::session::{Context, NextAction, Session};
+use crate::protocol::wayland::wl_surface::{Role, WlSurface};
 #[allow(unused_imports)]
 use futures::future::{err, ok, Future};
 #[allow(unused_imports)]
@@ -54,10 +55,14 @@ impl XdgWmBase {
     // surfaces relative to some parent surface. See the interface description
     // and xdg_surface.get_popup for details.
     pub fn create_positioner(
-        context: Context<Arc<RwLock<XdgWmBase>>>,
-        _id: u32, // new_id:
+        mut context: Context<Arc<RwLock<XdgWmBase>>>,
+        id: u32, // new_id:
     ) -> Box<dyn Future<Item = (Session, NextAction), Error = ()> + Send> {
-        context.invalid_method("xdg_wm_base::create_positioner is not implemented yet".to_string())
+        context.resources.insert(
+            id,
+            crate::protocol::xdg_shell::xdg_positioner::XdgPositioner::default().into(),
+        );
+        context.ok()
     }
 
     // destroy xdg_wm_base
@@ -91,14 +96,45 @@ impl XdgWmBase {
         id: u32,      // new_id:
         surface: u32, // object:
     ) -> Box<dyn Future<Item = (Session, NextAction), Error = ()> + Send> {
-        println!("XdgWmBase::get_xdg_surface(id={}, surface={})", id, surface);
+        log::debug!(
+            client_id = context.client_id,
+            object_id = id,
+            interface = "xdg_surface",
+            surface = surface;
+            "xdg_wm_base::get_xdg_surface"
+        );
+        let check = match WlSurface::get(&context.resources, surface) {
+            Some(x) => match x.role {
+                None | Some(Role::XdgToplevel) | Some(Role::XdgPopup) => {
+                    x.check_role_object(surface)
+                }
+                Some(role) => Err(format!(
+                    "wl_surface@{} already has the {} role, which is not an xdg_surface role",
+                    surface,
+                    role.name()
+                )),
+            },
+            None => Err(format!("{} is not a wl_surface", surface)),
+        };
+        if let Err(message) = check {
+            return context.protocol_error(enums::Error::Role as u32, message);
+        }
+        if let Some(x) = WlSurface::get_mut(&mut context.resources, surface) {
+            x.role_object = Some(id);
+        }
         context.resources.insert(
             id,
             crate::protocol::xdg_shell::xdg_surface::XdgSurface {
                 wl_surface_id: surface,
+                xdg_wm_base_id: context.sender_object_id,
+                role_object: None,
+                configured: false,
+                pending_geometry: None,
+                geometry: None,
             }
             .into(),
         );
+        context.configure_serials.add_surface(id);
         context.ok()
     }
 
@@ -110,6 +146,6 @@ impl XdgWmBase {
         context: Context<Arc<RwLock<XdgWmBase>>>,
         _serial: u32, // uint: serial of the ping event
     ) -> Box<dyn Future<Item = (Session, NextAction), Error = ()> + Send> {
-        context.invalid_method("xdg_wm_base::pong is not implemented yet".to_string())
+        context.ok()
     }
 }
@@ -0,0 +1,274 @@
+// Recordings of the composited output or of one toplevel, as a sequence of
+// uncompressed frames.
+//
+// A file ending in .y4m is written as YUV4MPEG2 with 4:4:4 BT.601 samples,
+// which ffmpeg and most players read directly. Any other file gets raw
+// frames of little-endian 0xAARRGGBB pixels, for example
+//
+//   ffmpeg -f rawvideo -pix_fmt bgra -s 640x480 -r 30 -i out.raw out.mp4
+//
+// Frames arrive whenever a surface commits. Each frame of the recording
+// shows the last commit before it ends, so commits are duplicated or dropped
+// to hit the frame rate. The size of the first frame is the size of the
+// recording; later frames of a different size are cropped or padded.
+// Frames are encoded and written on a thread of their own, so that a slow
+// disk never holds up the compositor.
+//
+// Start and stop a recording through the admin socket:
+//
+//   record /tmp/out.y4m                          the whole first output
+//   record /tmp/out.raw fps 60 app_id org.foo    one toplevel at 60 fps
+//   record stop
+
+use crate::screenshot::{Image, ScreenshotTarget};
+use std::ffi::OsStr;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread::JoinHandle;
+
+pub const DEFAULT_FPS: u32 = 30;
+
+pub struct Recorder {
+    target: ScreenshotTarget,
+    tx: Sender<Message>,
+    writer: Option<JoinHandle<io::Result<RecordingSummary>>>,
+}
+
+enum Message {
+    Frame(Image, u64),
+    Finish(u64),
+}
+
+// The state of the writer thread.
+struct Writer {
+    fps: u32,
+    y4m: bool,
+    w: BufWriter<File>,
+    size: Option<(u32, u32)>,
+    start: u64,
+    current: Option<Image>,
+    frames: u64,
+    commits: u64,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct RecordingSummary {
+    pub width: u32,
+    pub height: u32,
+    pub frames: u64,
+    pub commits: u64,
+}
+
+impl Recorder {
+    pub fn create(path: &Path, target: ScreenshotTarget, fps: u32) -> io::Result<Recorder> {
+        if fps == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "the frame rate must be positive",
+            ));
+        }
+        let writer = Writer {
+            fps,
+            y4m: path.extension() == Some(OsStr::new("y4m")),
+            w: BufWriter::new(File::create(path)?),
+            size: None,
+            start: 0,
+            current: None,
+            frames: 0,
+            commits: 0,
+        };
+        let (tx, rx) = mpsc::channel();
+        let writer = std::thread::Builder::new()
+            .name("recording".to_string())
+            .spawn(move || writer.run(rx))?;
+        Ok(Recorder {
+            target,
+            tx,
+            writer: Some(writer),
+        })
+    }
+
+    pub fn target(&self) -> &ScreenshotTarget {
+        &self.target
+    }
+
+    // Adds what the target showed at time ms, in milliseconds of any clock
+    // that does not go backwards. This only queues the image; an error is
+    // one the writer ran into with earlier frames.
+    pub fn frame(&mut self, image: Image, ms: u64) -> io::Result<()> {
+        if self.tx.send(Message::Frame(image, ms)).is_ok() {
+            return Ok(());
+        }
+        // The writer only stops early when it cannot write.
+        self.join()?;
+        Err(writer_error("the recording writer stopped"))
+    }
+
+    // Writes the frames up to time ms and closes the file, waiting for the
+    // writer to catch up.
+    pub fn finish(mut self, ms: u64) -> io::Result<RecordingSummary> {
+        let _ = self.tx.send(Message::Finish(ms));
+        self.join()
+    }
+
+    fn join(&mut self) -> io::Result<RecordingSummary> {
+        match self.writer.take().map(JoinHandle::join) {
+            Some(Ok(result)) => result,
+            Some(Err(_)) => Err(writer_error("the recording writer panicked")),
+            None => Err(writer_error("the recording writer stopped")),
+        }
+    }
+}
+
+fn writer_error(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::Other, message)
+}
+
+impl Writer {
+    // Writes frames until told to finish. A dropped Recorder finishes the
+    // recording at the last commit.
+    fn run(mut self, rx: Receiver<Message>) -> io::Result<RecordingSummary> {
+        let mut last = 0;
+        for message in rx {
+            match message {
+                Message::Frame(image, ms) => {
+                    self.frame(image, ms)?;
+                    last = ms;
+                }
+                Message::Finish(ms) => return self.finish(ms),
+            }
+        }
+        self.finish(last)
+    }
+
+    fn frame(&mut self, image: Image, ms: u64) -> io::Result<()> {
+        if self.current.is_none() {
+            self.start = ms;
+        } else {
+            // Every frame that ended before this commit shows the previous
+            // one.
+            while self.frame_start(self.frames + 1) <= ms {
+                self.write_current()?;
+            }
+        }
+        self.current = Some(image);
+        self.commits += 1;
+        Ok(())
+    }
+
+    fn finish(mut self, ms: u64) -> io::Result<RecordingSummary> {
+        if self.current.is_some() {
+            while self.frames == 0 || self.frame_start(self.frames) < ms {
+                self.write_current()?;
+            }
+        }
+        self.w.flush()?;
+        let (width, height) = self.size.unwrap_or((0, 0));
+        Ok(RecordingSummary {
+            width,
+            height,
+            frames: self.frames,
+            commits: self.commits,
+        })
+    }
+
+    fn frame_start(&self, frame: u64) -> u64 {
+        self.start + frame * 1000 / u64::from(self.fps)
+    }
+
+    fn write_current(&mut self) -> io::Result<()> {
+        let image = match &self.current {
+            Some(x) => x,
+            None => return Ok(()),
+        };
+        let (width, height) = match self.size {
+            Some(x) => x,
+            None => {
+                if self.y4m {
+                    writeln!(
+                        self.w,
+                        "YUV4MPEG2 W{} H{} F{}:1 Ip A1:1 C444",
+                        image.width, image.height, self.fps
+                    )?;
+                }
+                self.size = Some((image.width, image.height));
+                (image.width, image.height)
+            }
+        };
+        // Crop or pad to the size of the recording.
+        let mut pixels = Vec::with_capacity((width * height) as usize);
+        for y in 0..height {
+            for x in 0..width {
+                pixels.push(if x < image.width && y < image.height {
+                    image.pixels[(y * image.width + x) as usize]
+                } else {
+                    0xff00_0000
+                });
+            }
+        }
+        if self.y4m {
+            let mut planes = vec![0; pixels.len() * 3];
+            let (y_plane, uv) = planes.split_at_mut(pixels.len());
+            let (u_plane, v_plane) = uv.split_at_mut(pixels.len());
+            for (i, pixel) in pixels.iter().enumerate() {
+                let (y, u, v) = yuv(*pixel);
+                y_plane[i] = y;
+                u_plane[i] = u;
+                v_plane[i] = v;
+            }
+            self.w.write_all(b"FRAME\n")?;
+            self.w.write_all(&planes)?;
+        } else {
+            for pixel in pixels {
+                self.w.write_all(&pixel.to_le_bytes())?;
+            }
+        }
+        self.frames += 1;
+        Ok(())
+    }
+}
+
+// BT.601 limited range, from straight alpha composited over black.
+fn yuv(pixel: u32) -> (u8, u8, u8) {
+    let alpha = (pixel >> 24) as i32;
+    let channel = |shift: u32| ((pixel >> shift) & 0xff) as i32 * alpha / 255;
+    let (r, g, b) = (channel(16), channel(8), channel(0));
+    let y = ((66 * r + 129 * g + 25 * b + 128) >> 8) + 16;
+    let u = ((-38 * r - 74 * g + 112 * b + 128) >> 8) + 128;
+    let v = ((112 * r - 94 * g - 18 * b + 128) >> 8) + 128;
+    (y as u8, u as u8, v as u8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn image(color: u32) -> Image {
+        Image {
+            width: 2,
+            height: 1,
+            alpha: false,
+            pixels: vec![color; 2],
+        }
+    }
+
+    #[test]
+    fn commits_are_repeated_to_the_frame_rate() {
+        let path = std::env::temp_dir().join(format!("reflex-test-{}.raw", std::process::id()));
+        let mut recorder = Recorder::create(&path, ScreenshotTarget::Output, 10).unwrap();
+        recorder.frame(image(0xff00_0001), 0).unwrap();
+        // Dropped: the next commit comes before its frame ends.
+        recorder.frame(image(0xff00_0002), 50).unwrap();
+        recorder.frame(image(0xff00_0003), 250).unwrap();
+        let summary = recorder.finish(400).unwrap();
+        let data = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!((summary.width, summary.height), (2, 1));
+        assert_eq!((summary.frames, summary.commits), (4, 3));
+        let frames: Vec<u8> = data.chunks(8).map(|x| x[0]).collect();
+        assert_eq!(frames, vec![2, 2, 3, 3]);
+    }
+}
@@ -0,0 +1,146 @@
+// Screenshots of the composited output or of a single surface, written as
+// PNG or PPM.
+//
+// Only backends that keep buffer contents in reflex can take them. Take one
+// through the admin socket:
+//
+//   screenshot /tmp/out.png                   the whole first output
+//   screenshot /tmp/out.ppm surface 1 7       wl_surface 7 of client#1
+//   screenshot /tmp/out.png app_id org.foo    the toplevel with that app_id
+//
+// or from the command line with --screenshot <path>, optionally followed by
+// --surface <client> <id> or --app-id <app_id>. PPM has no alpha channel, so
+// translucent ARGB8888 surfaces are written as if over black.
+
+use crate::compositor::{Buffer, FORMAT_ARGB8888, FORMAT_XRGB8888};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ScreenshotTarget {
+    Output,
+    Surface { client_id: u32, surface_id: u32 },
+    AppId(String),
+}
+
+// Pixels as 0xAARRGGBB with straight, not premultiplied, alpha.
+#[derive(Clone, Debug)]
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    pub alpha: bool,
+    pub pixels: Vec<u32>,
+}
+
+impl Image {
+    // An XRGB8888 framebuffer, as the compositor renders it.
+    pub fn from_frame(width: i32, height: i32, pixels: &[u32]) -> Image {
+        Image {
+            width: width as u32,
+            height: height as u32,
+            alpha: false,
+            pixels: pixels.iter().map(|x| x | 0xff00_0000).collect(),
+        }
+    }
+
+    // A buffer exactly as the client committed it, before any scale or
+    // transform is applied.
+    pub fn from_buffer(buffer: &Buffer) -> Result<Image, String> {
+        let alpha = match buffer.format {
+            FORMAT_ARGB8888 => true,
+            FORMAT_XRGB8888 => false,
+            format => return Err(format!("unsupported buffer format {}", format)),
+        };
+        let mut pixels = Vec::with_capacity((buffer.width * buffer.height) as usize);
+        for y in 0..buffer.height as usize {
+            let row = &buffer.pixels[y * buffer.stride as usize..];
+            for x in 0..buffer.width as usize {
+                let pixel = u32::from_le_bytes([
+                    row[x * 4],
+                    row[x * 4 + 1],
+                    row[x * 4 + 2],
+                    row[x * 4 + 3],
+                ]);
+                pixels.push(if alpha {
+                    unpremultiply(pixel)
+                } else {
+                    pixel | 0xff00_0000
+                });
+            }
+        }
+        Ok(Image {
+            width: buffer.width as u32,
+            height: buffer.height as u32,
+            alpha,
+            pixels,
+        })
+    }
+
+    pub fn write_png<W: Write>(&self, w: W) -> io::Result<()> {
+        let mut encoder = png::Encoder::new(w, self.width, self.height);
+        encoder.set_color(if self.alpha {
+            png::ColorType::Rgba
+        } else {
+            png::ColorType::Rgb
+        });
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut data = Vec::with_capacity(self.pixels.len() * 4);
+        for pixel in &self.pixels {
+            data.extend_from_slice(&[(pixel >> 16) as u8, (pixel >> 8) as u8, *pixel as u8]);
+            if self.alpha {
+                data.push((pixel >> 24) as u8);
+            }
+        }
+        encoder
+            .write_header()
+            .and_then(|mut writer| writer.write_image_data(&data))
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+
+    pub fn write_ppm<W: Write>(&self, mut w: W) -> io::Result<()> {
+        write!(w, "P6\n{} {}\n255\n", self.width, self.height)?;
+        let mut data = Vec::with_capacity(self.pixels.len() * 3);
+        for pixel in &self.pixels {
+            let alpha = pixel >> 24;
+            for shift in &[16, 8, 0] {
+                data.push((((pixel >> shift) & 0xff) * alpha / 255) as u8);
+            }
+        }
+        w.write_all(&data)
+    }
+
+    // Writes a .png or .ppm file, by the extension of path.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let extension = path
+            .extension()
+            .and_then(|x| x.to_str())
+            .map(|x| x.to_ascii_lowercase());
+        let write: fn(&Image, &mut BufWriter<File>) -> io::Result<()> = match extension.as_deref() {
+            Some("png") => |image, w| image.write_png(w),
+            Some("ppm") => |image, w| image.write_ppm(w),
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "the file name must end in .png or .ppm",
+                ))
+            }
+        };
+        let mut w = BufWriter::new(File::create(path)?);
+        write(self, &mut w)?;
+        w.flush()
+    }
+}
+
+fn unpremultiply(pixel: u32) -> u32 {
+    let alpha = pixel >> 24;
+    if alpha == 0 || alpha == 0xff {
+        return pixel;
+    }
+    let mut out = alpha << 24;
+    for shift in &[0, 8, 16] {
+        let c = (pixel >> shift) & 0xff;
+        out |= ((c * 255 + alpha / 2) / alpha).min(0xff) << shift;
+    }
+    out
+}
@@ -0,0 +1,580 @@
+use crate::admin;
+use crate::backend::browser;
+use crate::backend::headless::HeadlessBackend;
+use crate::backend::vision::VisionBackend;
+use crate::backend::vnc;
+use crate::backend::{Backend, BackendClient, ClientEvents};
+use crate::config::{self, Config};
+use crate::frame_clock::FrameClock;
+use crate::lock::{MutexExt, RwLockExt};
+use crate::protocol::connection_stream::{Connection, ConnectionStream, Credentials};
+use crate::protocol::error::{panic_message, ClientError};
+use crate::protocol::event::Event;
+use crate::protocol::event_sink::{EventSink, FatalError};
+use crate::protocol::fd_drop::FdDrop;
+use crate::protocol::interface::Interface;
+use crate::protocol::quota;
+use crate::protocol::quota::{Quotas, RateLimiter};
+use crate::protocol::request::Request;
+use crate::protocol::request_stream::RequestStream;
+use crate::protocol::resource::Resource;
+use crate::protocol::session::Session;
+use crate::protocol::trace::{TraceFilter, Tracer};
+use crate::protocol::wayland::wl_compositor::WlCompositor;
+use crate::protocol::wayland::wl_data_device_manager::WlDataDeviceManager;
+use crate::protocol::wayland::wl_display;
+use crate::protocol::wayland::wl_display::WlDisplay;
+use crate::protocol::wayland::wl_registry::{RegisteredGlobal, WlRegistry, FIRST_REGISTERED_NAME};
+use crate::protocol::wayland::wl_shm::WlShm;
+use crate::protocol::xdg_shell::xdg_surface::ConfigureSerials;
+use crate::protocol::xdg_shell::xdg_wm_base::XdgWmBase;
+use crate::shutdown::{self, LockFile, Shutdown};
+use futures::future::Future;
+use futures::sink::Sink;
+use futures::stream::Stream;
+use std::collections::HashMap;
+use std::fmt;
+use std::panic::AssertUnwindSafe;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+
+// Input of a client's request loop: a Wayland request, or a command from the
+// admin socket. Closed marks the end of the request stream.
+enum Input {
+    Request(Request),
+    Control(admin::Control),
+    Closed,
+}
+
+// The global objects shared by every client. An embedder that needs
+// different initial state for them can build its own and pass it to
+// Server::with_globals, and can add globals of its own with register().
+#[derive(Clone)]
+pub struct Globals {
+    pub wl_display: Arc<RwLock<WlDisplay>>,
+    pub wl_compositor: Arc<RwLock<WlCompositor>>,
+    pub wl_registry: Arc<RwLock<WlRegistry>>,
+    pub wl_shm: Arc<RwLock<WlShm>>,
+    pub wl_data_device_manager: Arc<RwLock<WlDataDeviceManager>>,
+    pub xdg_wm_base: Arc<RwLock<XdgWmBase>>,
+}
+
+impl Globals {
+    pub fn new(config: &Config) -> Globals {
+        Globals {
+            wl_display: Arc::new(RwLock::new(WlDisplay {})),
+            wl_compositor: Arc::new(RwLock::new(WlCompositor {})),
+            wl_registry: Arc::new(RwLock::new(WlRegistry {
+                disabled_globals: config.protocols.disabled_globals(),
+                registered: Vec::new(),
+            })),
+            wl_shm: Arc::new(RwLock::new(WlShm {
+                formats: config.shm.format_codes(),
+            })),
+            wl_data_device_manager: Arc::new(RwLock::new(WlDataDeviceManager {})),
+            xdg_wm_base: Arc::new(RwLock::new(XdgWmBase {})),
+        }
+    }
+
+    // Adds a global of one of the interfaces reflex implements, up to
+    // `version`, and returns its name. Clients that connect from here on
+    // see it in their registry; binding it calls `bind` with the version
+    // the client asked for.
+    pub fn register<F>(&self, interface: &'static Interface, version: u32, bind: F) -> u32
+    where
+        F: Fn(u32) -> Resource + Send + Sync + 'static,
+    {
+        let mut registry = self.wl_registry.write_locked();
+        let name = FIRST_REGISTERED_NAME + registry.registered.len() as u32;
+        registry.registered.push(RegisteredGlobal {
+            name,
+            interface,
+            version,
+            bind: Arc::new(bind),
+        });
+        name
+    }
+}
+
+#[derive(Clone)]
+struct Global {
+    clients: admin::Clients,
+    config: Arc<RwLock<Config>>,
+    frame_clock: FrameClock,
+    globals: Globals,
+}
+
+fn handle_client_input(
+    mut session: Session,
+    request: Request,
+) -> Box<dyn Future<Item = Session, Error = ()> + Send> {
+    session.fds.extend(request.fds);
+    let res = if let Some(x) = session.resources.remove(&request.sender_object_id) {
+        x
+    } else {
+        log::warn!(
+            client_id = session.client_id,
+            object_id = request.sender_object_id,
+            opcode = request.opcode;
+            "request to unknown object"
+        );
+        let tx = session.tx.clone();
+        let error = wl_display::events::Error {
+            sender_object_id: 1,
+            object_id: 1,
+            code: wl_display::enums::Error::InvalidObject as u32,
+            message: format!(
+                "object_id={} opcode={} args={:?} not found",
+                request.sender_object_id, request.opcode, request.args
+            ),
+        };
+        let f: Box<dyn Future<Item = Session, Error = ()> + Send> =
+            Box::new(tx.send(Box::new(error)).map(|_| session).map_err(|_| ()));
+        return f;
+    };
+
+    log::trace!(
+        client_id = session.client_id,
+        object_id = request.sender_object_id,
+        interface = res.interface().name,
+        opcode = request.opcode;
+        "dispatch"
+    );
+    let f: Box<dyn Future<Item = Session, Error = ()> + Send> =
+        crate::protocol::resource::dispatch_request(
+            res,
+            session,
+            request.sender_object_id,
+            request.opcode,
+            request.args,
+        );
+    f
+}
+
+fn handle_client(
+    global: Global,
+    backend: Arc<dyn Backend>,
+    fd: i32,
+    client_id: u32,
+    credentials: Credentials,
+    tracer: Option<Arc<Tracer>>,
+) -> Box<dyn Future<Item = (), Error = std::io::Error> + Send> {
+    let fd_drop = Arc::new(FdDrop::new(fd));
+    let tokio_registration = Arc::new(tokio::reactor::Registration::new());
+    tokio_registration
+        .register(&mio::unix::EventedFd(&fd))
+        .expect("register request fd");
+    let reader0 = RequestStream::new(
+        fd,
+        fd_drop.clone(),
+        tokio_registration.clone(),
+        client_id,
+        tracer.clone(),
+    );
+    let fatal = FatalError::default();
+    let writer0 = EventSink::new(
+        fd,
+        fd_drop.clone(),
+        tokio_registration.clone(),
+        client_id,
+        tracer,
+        fatal.clone(),
+    );
+    let quotas = global.config.read_locked().quotas.with_env();
+    let clients = global.clients.clone();
+    let (tx0, rx0) = tokio::sync::mpsc::channel::<Box<dyn Event + Send>>(quotas.max_pending_events);
+    let output_session = rx0
+        .map_err(|_| std::io::Error::new(std::io::ErrorKind::Other, "Oops!"))
+        .forward(writer0)
+        .map_err(|_| ())
+        .and_then(|_| Ok(()));
+    tokio::spawn(output_session);
+
+    // The client's event queue ends once both the session and the backend
+    // have let go of it, and the client socket is closed after the remaining
+    // events have been flushed.
+    let configure_serials = ConfigureSerials::default();
+    let events = ClientEvents::new(
+        client_id,
+        tx0.clone(),
+        clients.clone(),
+        configure_serials.clone(),
+        fatal,
+    );
+    global.frame_clock.add_client(client_id, events.clone());
+    let frame_clock = global.frame_clock.clone();
+    Box::new(
+        backend
+            .connect(BackendClient {
+                client_id,
+                credentials,
+                events,
+            })
+            .then(move |result| {
+                let backend = match result {
+                    Ok(x) => x,
+                    Err(err) => {
+                        // Only this client goes; its socket is closed once
+                        // the last handle on its event queue is dropped.
+                        frame_clock.remove_client(client_id);
+                        log::error!(
+                            client_id = client_id,
+                            pid = credentials.pid;
+                            "client dropped: {}",
+                            ClientError::Backend(err)
+                        );
+                        return Ok(());
+                    }
+                };
+                let session0 = Session {
+                    client_id,
+                    credentials,
+                    wl_display: global.globals.wl_display,
+                    wl_registry: Arc::new(RwLock::new(
+                        global.globals.wl_registry.read_locked().clone(),
+                    )),
+                    wl_compositor: global.globals.wl_compositor,
+                    wl_shm: global.globals.wl_shm,
+                    wl_data_device_manager: global.globals.wl_data_device_manager,
+                    xdg_wm_base: global.globals.xdg_wm_base,
+                    resources: HashMap::new(),
+                    tx: tx0,
+                    fds: Vec::new(),
+                    pending_releases: Vec::new(),
+                    frame_clock: global.frame_clock,
+                    configure_serials,
+                    backend,
+                };
+                serve_session(session0, reader0, clients, fd, quotas);
+                Ok(())
+            }),
+    )
+}
+
+// The interface, object id and opcode of the request being dispatched.
+type LastRequest = (&'static Interface, u32, u16);
+
+fn serve_session(
+    mut session0: Session,
+    reader0: RequestStream,
+    clients: admin::Clients,
+    fd: i32,
+    quotas: Quotas,
+) {
+    let client_id = session0.client_id;
+    let credentials = session0.credentials;
+    let frame_clock = session0.frame_clock.clone();
+    session0
+        .resources
+        .insert(1, Resource::WlDisplay(session0.wl_display.clone()));
+    let control = clients
+        .register(client_id, fd, credentials)
+        .map(Input::Control)
+        .map_err(|()| ClientError::Aborted);
+    let mut rate_limiter = RateLimiter::new(&quotas);
+    // Kept outside the session, so that a crash report can still name the
+    // request that was being dispatched.
+    let last_request: Arc<Mutex<Option<LastRequest>>> = Arc::new(Mutex::new(None));
+    let crash_last_request = last_request.clone();
+    let requests = reader0
+        .map(Input::Request)
+        .chain(futures::stream::once(Ok(Input::Closed)))
+        .select(control)
+        .take_while(|input| match input {
+            Input::Closed => Ok(false),
+            _ => Ok(true),
+        })
+        .fold(session0, move |session, input| {
+            let f: Box<dyn Future<Item = Session, Error = ClientError> + Send> = match input {
+                Input::Request(request) => {
+                    let object_id = request.sender_object_id;
+                    if let Err(message) = rate_limiter.check() {
+                        return quota::disconnect(session, object_id, message);
+                    }
+                    let interface = session.resources.get(&object_id).map(|res| res.interface());
+                    *last_request.locked() =
+                        interface.map(|interface| (interface, object_id, request.opcode));
+                    let interface_name = interface.map(|x| x.name).unwrap_or("");
+                    Box::new(
+                        handle_client_input(session, request)
+                            .map_err(|()| ClientError::Aborted)
+                            .and_then(move |session| {
+                                match quotas.check(&session, interface_name) {
+                                    Ok(()) => Box::new(futures::future::ok(session)),
+                                    Err(message) => quota::disconnect(session, object_id, message),
+                                }
+                            }),
+                    )
+                }
+                Input::Control(control) => admin::handle_control(session, fd, control),
+                Input::Closed => Box::new(futures::future::ok(session)),
+            };
+            f
+        });
+    // A panic in a handler only takes this client down.
+    let input_session0: Box<dyn Future<Item = (), Error = ()> + Send> = Box::new(
+        AssertUnwindSafe(requests)
+            .catch_unwind()
+            .then(move |result| {
+                let result = match result {
+                    Ok(result) => result.map(|_| ()),
+                    Err(payload) => Err(ClientError::Panic {
+                        message: panic_message(&*payload),
+                        last_request: crash_last_request.locked().map(
+                            |(interface, object_id, opcode)| {
+                                let name = interface
+                                    .requests
+                                    .get(opcode as usize)
+                                    .map(|x| x.name.to_string())
+                                    .unwrap_or_else(|| format!("opcode#{}", opcode));
+                                format!("{}@{}.{}", interface.name, object_id, name)
+                            },
+                        ),
+                    }),
+                };
+                clients.unregister(client_id);
+                frame_clock.remove_client(client_id);
+                match result {
+                    Ok(()) => log::info!(
+                        client_id = client_id,
+                        pid = credentials.pid;
+                        "client disconnected"
+                    ),
+                    Err(ClientError::Shutdown) => log::info!(
+                        client_id = client_id,
+                        pid = credentials.pid;
+                        "client closed for shutdown"
+                    ),
+                    Err(ClientError::Aborted) => log::info!(
+                        client_id = client_id,
+                        pid = credentials.pid;
+                        "client disconnected by a request handler"
+                    ),
+                    Err(err @ ClientError::Panic { .. }) => log::error!(
+                        client_id = client_id,
+                        pid = credentials.pid,
+                        uid = credentials.uid;
+                        "client crashed: {}",
+                        err
+                    ),
+                    Err(err) => log::warn!(
+                        client_id = client_id,
+                        pid = credentials.pid;
+                        "client disconnected: {}",
+                        err
+                    ),
+                }
+                futures::future::ok(())
+            }),
+    );
+
+    tokio::spawn(input_session0);
+}
+
+#[derive(Debug)]
+pub enum ServerError {
+    Locked(String, std::io::Error),
+    Runtime(std::io::Error),
+    Backend(std::io::Error),
+}
+
+impl fmt::Display for ServerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ServerError::Locked(path, err) => {
+                write!(f, "{} is held by another reflex: {}", path, err)
+            }
+            ServerError::Runtime(err) => write!(f, "cannot start the runtime: {}", err),
+            ServerError::Backend(err) => write!(f, "cannot start the backend: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for ServerError {}
+
+// A reflex server: the global objects, the connected clients and the
+// configuration they were started with.
+//
+// run() serves the configured socket until SIGINT or SIGTERM. Embedders that
+// bring their own runtime and sockets call accept() for each connection
+// instead.
+pub struct Server {
+    global: Global,
+    config_path: Option<PathBuf>,
+    backend: Arc<dyn Backend>,
+    trace_filter: Option<Arc<TraceFilter>>,
+    next_client_id: Arc<AtomicU32>,
+    shutdown: Shutdown,
+}
+
+impl Server {
+    pub fn new(config: Config) -> Server {
+        let globals = Globals::new(&config);
+        Server::with_globals(config, globals)
+    }
+
+    pub fn with_globals(config: Config, globals: Globals) -> Server {
+        let kind = config.backend.kind.clone();
+        let config = Arc::new(RwLock::new(config));
+        let backend: Arc<dyn Backend> = match kind.as_str() {
+            "headless" => Arc::new(HeadlessBackend::new(config.clone())),
+            "vnc" => Arc::new(vnc::backend(config.clone())),
+            "browser" => Arc::new(browser::backend(config.clone())),
+            _ => Arc::new(VisionBackend::new(config.clone())),
+        };
+        let frame_clock = FrameClock::new(config.clone());
+        frame_clock.set_backend(&backend);
+        Server {
+            global: Global {
+                clients: admin::Clients::default(),
+                config: config.clone(),
+                frame_clock,
+                globals,
+            },
+            config_path: None,
+            backend,
+            trace_filter: TraceFilter::from_env().map(Arc::new),
+            next_client_id: Arc::new(AtomicU32::new(0)),
+            shutdown: Shutdown::new(),
+        }
+    }
+
+    // The file that is reloaded on SIGHUP.
+    pub fn config_path(mut self, path: Option<PathBuf>) -> Server {
+        self.config_path = path;
+        self
+    }
+
+    // Where clients' windows go; the vision relay unless set.
+    pub fn backend(mut self, backend: Arc<dyn Backend>) -> Server {
+        self.global.frame_clock.set_backend(&backend);
+        self.backend = backend;
+        self
+    }
+
+    pub fn config(&self) -> Arc<RwLock<Config>> {
+        self.global.config.clone()
+    }
+
+    pub fn globals(&self) -> &Globals {
+        &self.global.globals
+    }
+
+    pub fn clients(&self) -> admin::Clients {
+        self.global.clients.clone()
+    }
+
+    // Stops run() when triggered.
+    pub fn shutdown(&self) -> Shutdown {
+        self.shutdown.clone()
+    }
+
+    // Starts serving a client that has connected to reflex: opens its
+    // backend connection and spawns its request loop. Must be called from
+    // within a tokio runtime.
+    pub fn accept(
+        &self,
+        connection: Connection,
+    ) -> Box<dyn Future<Item = (), Error = std::io::Error> + Send> {
+        let inner_global = self.global.clone();
+        let client_id = self.next_client_id.fetch_add(1, Ordering::SeqCst) + 1;
+        let fd = connection.fd;
+        let credentials = connection.credentials;
+        log::info!(
+            client_id = client_id,
+            fd = fd,
+            pid = credentials.pid,
+            uid = credentials.uid,
+            gid = credentials.gid;
+            "client connected"
+        );
+        let tracer = self
+            .trace_filter
+            .as_ref()
+            .and_then(|filter| filter.tracer(client_id, credentials));
+        handle_client(
+            inner_global,
+            self.backend.clone(),
+            fd,
+            client_id,
+            credentials,
+            tracer,
+        )
+    }
+
+    // Serves the configured socket and the admin socket until shutdown, then
+    // waits for the clients to close and removes the files it created.
+    pub fn run(self) -> Result<(), ServerError> {
+        let (socket_path, admin_socket_path, lock_path) = {
+            let config = self.global.config.read_locked();
+            (
+                config.server.socket.clone(),
+                config.server.admin_socket.clone(),
+                config.server.lock_file.clone(),
+            )
+        };
+        let lock_file = LockFile::acquire(&lock_path)
+            .map_err(|err| ServerError::Locked(lock_path.clone(), err))?;
+        let _ = std::fs::remove_file(&socket_path);
+        self.backend.start().map_err(ServerError::Backend)?;
+
+        let shutdown = self.shutdown.clone();
+        let mut runtime = tokio::runtime::Runtime::new().map_err(ServerError::Runtime)?;
+        {
+            let shutdown = shutdown.clone();
+            let admin_socket_path = admin_socket_path.clone();
+            let socket_path = socket_path.clone();
+            runtime.spawn(futures::future::lazy(move || {
+                let clients = self.clients();
+                tokio::spawn(shutdown.until(config::reload_on_hangup(
+                    self.config_path.clone(),
+                    self.global.config.clone(),
+                    self.global.globals.wl_registry.clone(),
+                )));
+                tokio::spawn(admin::serve(
+                    &admin_socket_path,
+                    clients.clone(),
+                    self.backend.clone(),
+                    shutdown.clone(),
+                ));
+                let on_signal = shutdown.clone();
+                tokio::spawn(shutdown.until(shutdown::signals().map(move |signal| {
+                    log::info!("signal {}, shutting down", signal);
+                    on_signal.trigger();
+                })));
+                let listener = ConnectionStream::bind(socket_path)
+                    .for_each(move |connection| self.accept(connection));
+                // Dropping the listener closes the socket, so new clients are
+                // refused from here on.
+                let on_error = shutdown.clone();
+                shutdown
+                    .until(listener.map_err(|err| log::error!("listener failed: {:?}", err)))
+                    .then(move |_| {
+                        on_error.trigger();
+                        clients.shutdown_all();
+                        Ok(())
+                    })
+            }));
+        }
+        let _ = runtime.block_on(shutdown.wait());
+
+        // Clients flush their pending events before their sockets are closed.
+        // One that does not read them is not waited for forever.
+        let (idle_tx, idle_rx) = std::sync::mpsc::channel();
+        std::thread::spawn(move || {
+            let _ = runtime.shutdown_on_idle().wait();
+            let _ = idle_tx.send(());
+        });
+        if idle_rx.recv_timeout(Duration::from_secs(5)).is_err() {
+            log::warn!("clients did not close in time");
+        }
+
+        let _ = std::fs::remove_file(&socket_path);
+        let _ = std::fs::remove_file(&admin_socket_path);
+        let _ = std::fs::remove_file(&lock_path);
+        drop(lock_file);
+        Ok(())
+    }
+}
@@ -0,0 +1,89 @@
+use crate::lock::MutexExt;
+use futures::future::{Future, Shared};
+use futures::stream::Stream;
+use futures::sync::oneshot;
+use nix::fcntl::{flock, FlockArg};
+use std::fs::{File, OpenOptions};
+use std::os::unix::io::AsRawFd;
+use std::sync::{Arc, Mutex};
+use tokio_signal::unix::{Signal, SIGINT, SIGTERM};
+
+// Graceful shutdown.
+//
+// On SIGINT or SIGTERM reflex stops accepting clients, asks every client's
+// request loop to say goodbye to the backend and end, and waits for the
+// event queues to drain before it removes its socket and lock files.
+
+#[derive(Clone)]
+pub struct Shutdown {
+    trigger: Arc<Mutex<Option<oneshot::Sender<()>>>>,
+    signal: Shared<oneshot::Receiver<()>>,
+}
+
+impl Shutdown {
+    pub fn new() -> Shutdown {
+        let (trigger, signal) = oneshot::channel();
+        Shutdown {
+            trigger: Arc::new(Mutex::new(Some(trigger))),
+            signal: signal.shared(),
+        }
+    }
+
+    pub fn trigger(&self) {
+        if let Some(trigger) = self.trigger.locked().take() {
+            let _ = trigger.send(());
+        }
+    }
+
+    // Resolves once shutdown has been triggered.
+    pub fn wait(&self) -> Box<dyn Future<Item = (), Error = ()> + Send> {
+        Box::new(self.signal.clone().then(|_| Ok(())))
+    }
+
+    // Runs f until it completes or shutdown is triggered, whichever is first.
+    pub fn until<F>(&self, f: F) -> Box<dyn Future<Item = (), Error = ()> + Send>
+    where
+        F: Future<Item = (), Error = ()> + Send + 'static,
+    {
+        Box::new(f.select2(self.wait()).then(|_| Ok(())))
+    }
+}
+
+impl Default for Shutdown {
+    fn default() -> Self {
+        Shutdown::new()
+    }
+}
+
+// Resolves on the first SIGINT or SIGTERM.
+pub fn signals() -> Box<dyn Future<Item = i32, Error = ()> + Send> {
+    let sigint = Signal::new(SIGINT).flatten_stream();
+    let sigterm = Signal::new(SIGTERM).flatten_stream();
+    Box::new(
+        sigint
+            .select(sigterm)
+            .into_future()
+            .map_err(|(err, _)| log::error!("signal handler failed: {:?}", err))
+            .and_then(|(signal, _)| signal.ok_or(())),
+    )
+}
+
+// Held for the lifetime of the process, so that a second reflex does not
+// remove the socket of a running one.
+pub struct LockFile {
+    _file: File,
+}
+
+impl LockFile {
+    pub fn acquire(path: &str) -> std::io::Result<LockFile> {
+        // Not truncated: the file may be held by a running reflex.
+        let file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(false)
+            .open(path)?;
+        flock(file.as_raw_fd(), FlockArg::LockExclusiveNonblock)
+            .map_err(|err| std::io::Error::new(std::io::ErrorKind::Other, err))?;
+        Ok(LockFile { _file: file })
+    }
+}
@@ -0,0 +1,138 @@
+// Builds a server through the library API and talks to it over a socket
+// pair, the way an embedding test harness does.
+
+use byteorder::{ByteOrder, NativeEndian};
+use futures::future::Future;
+use std::io::{Read, Write};
+use std::os::unix::io::IntoRawFd;
+use std::os::unix::net::UnixStream;
+use std::time::Duration;
+use ultimate_desktop_reflex::protocol::connection_stream::{Connection, Credentials};
+use ultimate_desktop_reflex::protocol::wayland::wl_output;
+use ultimate_desktop_reflex::{Config, Server};
+
+struct Client {
+    stream: UnixStream,
+    buf: Vec<u8>,
+}
+
+impl Client {
+    fn request(&mut self, object_id: u32, opcode: u16, args: &[u32]) {
+        let size = 8 + args.len() * 4;
+        let mut buf = vec![0; size];
+        NativeEndian::write_u32(&mut buf[0..4], object_id);
+        NativeEndian::write_u32(&mut buf[4..8], ((size as u32) << 16) | opcode as u32);
+        NativeEndian::write_u32_into(args, &mut buf[8..]);
+        self.stream.write_all(&buf).unwrap();
+    }
+
+    // Requests a wl_display.sync and returns the events that came before
+    // its done, as (object id, opcode, body).
+    fn roundtrip(&mut self, callback: u32) -> Vec<(u32, u16, Vec<u8>)> {
+        self.request(1, 0, &[callback]);
+        let mut events = Vec::new();
+        loop {
+            while self.buf.len() >= 8 {
+                let size = (NativeEndian::read_u32(&self.buf[4..8]) >> 16) as usize;
+                if self.buf.len() < size {
+                    break;
+                }
+                let event: Vec<u8> = self.buf.drain(..size).collect();
+                let object_id = NativeEndian::read_u32(&event[0..4]);
+                let opcode = NativeEndian::read_u32(&event[4..8]) as u16;
+                if object_id == callback && opcode == 0 {
+                    return events;
+                }
+                events.push((object_id, opcode, event[8..].to_vec()));
+            }
+            let mut chunk = [0; 4096];
+            let len = self.stream.read(&mut chunk).unwrap();
+            assert!(len > 0, "the server closed the connection");
+            self.buf.extend_from_slice(&chunk[..len]);
+        }
+    }
+}
+
+fn string_arg(body: &[u8]) -> String {
+    let len = NativeEndian::read_u32(&body[0..4]) as usize;
+    String::from_utf8(body[4..4 + len - 1].to_vec()).unwrap()
+}
+
+#[test]
+fn registered_globals_are_advertised_and_bound() {
+    let mut config = Config::default();
+    config.backend.kind = "headless".to_string();
+    let server = Server::new(config);
+    let name = server
+        .globals()
+        .register(&wl_output::INTERFACE, 2, |_version| {
+            wl_output::WlOutput {}.into()
+        });
+
+    let (theirs, ours) = UnixStream::pair().unwrap();
+    theirs.set_nonblocking(true).unwrap();
+    let connection = Connection {
+        fd: theirs.into_raw_fd(),
+        credentials: Credentials {
+            pid: std::process::id() as i32,
+            uid: 0,
+            gid: 0,
+        },
+    };
+    let mut runtime = tokio::runtime::Runtime::new().unwrap();
+    runtime.spawn(futures::future::lazy(move || {
+        server.accept(connection).map_err(|err| panic!("{}", err))
+    }));
+
+    ours.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    let mut client = Client {
+        stream: ours,
+        buf: Vec::new(),
+    };
+    // wl_display.get_registry
+    client.request(1, 1, &[2]);
+    let globals: Vec<(u32, String, u32)> = client
+        .roundtrip(3)
+        .into_iter()
+        .filter(|(object_id, opcode, _)| *object_id == 2 && *opcode == 0)
+        .map(|(_, _, body)| {
+            let interface = string_arg(&body[4..]);
+            let version = NativeEndian::read_u32(&body[body.len() - 4..]);
+            (NativeEndian::read_u32(&body[0..4]), interface, version)
+        })
+        .collect();
+    assert!(globals.contains(&(name, "wl_output".to_string(), 2)));
+    assert!(globals.iter().any(|(_, x, _)| x == "wl_compositor"));
+
+    // wl_registry.bind(name, "wl_output", 2, 4)
+    let mut args = vec![name, 10];
+    args.extend_from_slice(&[
+        u32::from_ne_bytes(*b"wl_o"),
+        u32::from_ne_bytes(*b"utpu"),
+        u32::from_ne_bytes(*b"t\0\0\0"),
+    ]);
+    args.extend_from_slice(&[2, 4]);
+    client.request(2, 0, &args);
+    let events = client.roundtrip(5);
+    assert!(
+        events
+            .iter()
+            .all(|(object_id, opcode, _)| (*object_id, *opcode) != (1, 0)),
+        "unexpected error: {:?}",
+        events
+    );
+
+    // Binding a version above the registered one is an error.
+    let mut args = vec![name, 10];
+    args.extend_from_slice(&[
+        u32::from_ne_bytes(*b"wl_o"),
+        u32::from_ne_bytes(*b"utpu"),
+        u32::from_ne_bytes(*b"t\0\0\0"),
+    ]);
+    args.extend_from_slice(&[3, 6]);
+    client.request(2, 0, &args);
+    let events = client.roundtrip(7);
+    assert!(events
+        .iter()
+        .any(|(object_id, opcode, _)| (*object_id, *opcode) == (1, 0)));
+}
@@ -11,10 +11,12 @@ def generate_reflex(base_dir, protocols)
       pub mod event;
       pub mod event_sink;
       pub mod fd_drop;
+      pub mod interface;
       pub mod request;
       pub mod request_stream;
       pub mod resource;
       pub mod session;
+      pub mod trace;
     MOD
   end
 
@@ -31,6 +33,26 @@ def generate_reflex(base_dir, protocols)
     end
     f.puts('}')
     f.puts('')
+    f.puts('pub static INTERFACES: &[&crate::protocol::interface::Interface] = &[')
+    protocols.each do |protocol|
+      protocol.interfaces.each do |interface|
+        f.puts("    &crate::protocol::#{protocol.name}::#{interface.name}::INTERFACE,")
+      end
+    end
+    f.puts('];')
+    f.puts('')
+    f.puts('impl Resource {')
+    f.puts("    pub fn interface(&self) -> &'static crate::protocol::interface::Interface {")
+    f.puts('        match self {')
+    protocols.each do |protocol|
+      protocol.interfaces.each do |interface|
+        f.puts("            Resource::#{camel_case(interface.name)}(_) => &crate::protocol::#{protocol.name}::#{interface.name}::INTERFACE,")
+      end
+    end
+    f.puts('        }')
+    f.puts('    }')
+    f.puts('}')
+    f.puts('')
     f.puts(<<~DISPATCH_REQUEST)
       pub fn dispatch_request(resource: Resource, session: crate::protocol::session::Session, sender_object_id: u32, opcode: u16, args: Vec<u8>) -> Box<dyn futures::future::Future<Item = crate::protocol::session::Session, Error = ()> + Send> {
           match resource {
@@ -117,6 +139,7 @@ def generate_reflex(base_dir, protocols)
           #[allow(unused_imports)] use std::convert::TryInto;
           #[allow(unused_imports)] use std::io::{Cursor, Read};
           #[allow(unused_imports)] use std::sync::{Arc, RwLock};
+          #[allow(unused_imports)] use crate::protocol::interface::{Arg, ArgKind, Interface, Message};
           #[allow(unused_imports)] use crate::protocol::session::NextAction;
 
         USE
@@ -129,6 +152,9 @@ def generate_reflex(base_dir, protocols)
           #[allow(dead_code)]
           pub const VERSION: u32 = #{interface.version};
 
+          #[allow(dead_code)]
+          #{interface.metadata}
+
           #[allow(unused_variables)]
           #[allow(dead_code)]
         CODE
@@ -129,6 +129,13 @@ class Interface
     end
   end
 
+  def metadata
+    requests = (@requests || []).sort_by(&:index).map(&:metadata)
+    events = (@events || []).sort_by(&:index).map(&:metadata)
+    "pub static INTERFACE: Interface = Interface { name: \"#{@name}\", version: #{@version}, " \
+      "requests: &[#{requests.join(', ')}], events: &[#{events.join(', ')}] };"
+  end
+
   def decode
     result = ''
     error = <<-ERROR
@@ -246,6 +253,10 @@ class Request
     end
   end
 
+  def metadata
+    "Message { name: \"#{@name}\", args: &[#{@args.map(&:metadata).join(', ')}] }"
+  end
+
   def encode_vision
     result = <<FN_ENCODE
     {
@@ -257,7 +268,7 @@ FN_ENCODE
     result += ";\n"
     result += <<HEADER
         if total_len > 0xffff {
-            println!("Oops! total_len={}", total_len);
+            log::error!("Oops! total_len={}", total_len);
             return Box::new(futures::future::err(()));
         }
 
@@ -332,31 +343,41 @@ end
 
 class Arg
   attr_reader :name, :summary, :serialize_len, :type, :rust_type, :dynamic_len, :interface_name
+  attr_accessor :target_interface, :allow_null
 
   def self.create(elem, interface_name)
     name = elem.attributes['name']
     summary = elem.attributes['summary']
     type = elem.attributes['type']
-    case type
-    when 'uint'
-      UintArg.new(name, summary, type, interface_name)
-    when 'int'
-      IntArg.new(name, summary, type, interface_name)
-    when 'object'
-      ObjectArg.new(name, summary, type, interface_name)
-    when 'string'
-      StringArg.new(name, summary, type, interface_name)
-    when 'fd'
-      FdArg.new(name, summary, type, interface_name)
-    when 'new_id'
-      NewIdArg.new(name, summary, type, interface_name)
-    when 'fixed'
-      FixedArg.new(name, summary, type, interface_name)
-    when 'array'
-      ArrayArg.new(name, summary, type, interface_name)
-    else
-      raise "unhandled type: #{@type}"
-    end
+    arg =
+      case type
+      when 'uint'
+        UintArg.new(name, summary, type, interface_name)
+      when 'int'
+        IntArg.new(name, summary, type, interface_name)
+      when 'object'
+        ObjectArg.new(name, summary, type, interface_name)
+      when 'string'
+        StringArg.new(name, summary, type, interface_name)
+      when 'fd'
+        FdArg.new(name, summary, type, interface_name)
+      when 'new_id'
+        NewIdArg.new(name, summary, type, interface_name)
+      when 'fixed'
+        FixedArg.new(name, summary, type, interface_name)
+      when 'array'
+        ArrayArg.new(name, summary, type, interface_name)
+      else
+        raise "unhandled type: #{@type}"
+      end
+    arg.target_interface = elem.attributes['interface']
+    arg.allow_null = elem.attributes['allow-null'] == 'true'
+    arg
+  end
+
+  def metadata
+    interface = @target_interface ? "Some(\"#{@target_interface}\")" : 'None'
+    "Arg { name: \"#{@name}\", kind: ArgKind::#{camel_case(@type)}, interface: #{interface}, nullable: #{@allow_null} }"
   end
 
   def deserialize_return_error
@@ -535,7 +556,7 @@ class FdArg < Arg
   def serialize(_prefix = 'self.')
     # "NativeEndian::write_i32(&mut dst[encode_offset..], self.#{name});"
     '// unimplemented!();'
-    'println!("UNIMPLEMENTED!!!!!");'
+    'log::error!("UNIMPLEMENTED!!!!!");'
   end
 
   def deserialize
@@ -686,6 +707,10 @@ class Event
     end
   end
 
+  def metadata
+    "Message { name: \"#{@name}\", args: &[#{@args.map(&:metadata).join(', ')}] }"
+  end
+
   def encode
     result = <<FN_ENCODE
     fn encode(&self, dst: &mut bytes::BytesMut) -> Result<(), std::io::Error> {
@@ -1,3 +1,4 @@
+use byteorder::{NativeEndian, ReadBytesExt};
 use futures::future::Future;
 use futures::sink::Sink;
 use futures::stream::Stream;
@@ -5,7 +6,7 @@ use protocol::codec::Codec;
 use protocol::event::Event;
 use protocol::request::Request;
 use protocol::resource::Resource;
-use protocol::session::Session;
+use protocol::session::{Credentials, Session};
 use protocol::wayland::wl_compositor::WlCompositor;
 use protocol::wayland::wl_data_device_manager::WlDataDeviceManager;
 use protocol::wayland::wl_display;
@@ -75,10 +76,41 @@ unsafe fn gui_thread(gui_thread_init: tokio::sync::oneshot::Sender<()>) {
     }
 }
 
+// Object id 0 is never used by Wayland clients; reflex sends its own
+// messages with it. Opcode 0 carries the client's pid, uid and gid, and
+// opcode 1 says goodbye.
+fn handle_reflex_message(mut session: Session, req: Request) -> Session {
+    let mut cursor = std::io::Cursor::new(&req.args);
+    match req.opcode {
+        0 => {
+            let pid = cursor.read_i32::<NativeEndian>();
+            let uid = cursor.read_u32::<NativeEndian>();
+            let gid = cursor.read_u32::<NativeEndian>();
+            if let (Ok(pid), Ok(uid), Ok(gid)) = (pid, uid, gid) {
+                log::info!(pid = pid, uid = uid, gid = gid; "client credentials");
+                session.credentials = Some(Credentials { pid, uid, gid });
+            } else {
+                log::warn!("malformed credentials from reflex");
+            }
+        }
+        1 => {
+            // The client is going away; drop its windows without waiting
+            // for the connection to close.
+            log::info!(objects = session.resources.len(); "client goodbye");
+            session.resources.retain(|id, _| *id == 1);
+        }
+        opcode => log::warn!(opcode = opcode; "unknown message from reflex"),
+    }
+    session
+}
+
 fn handle_client_input(
     mut session: Session,
     req: Request,
 ) -> Box<dyn Future<Item = Session, Error = std::io::Error> + Send> {
+    if req.sender_object_id == 0 {
+        return Box::new(futures::future::ok(handle_reflex_message(session, req)));
+    }
     let res = if let Some(x) = session.resources.remove(&req.sender_object_id) {
         x
     } else {
@@ -92,9 +124,10 @@ fn handle_client_input(
                 req.sender_object_id, req.opcode, req.args
             ),
         };
-        println!(
-            "object_id={} opcode={} args={:?} not found",
-            req.sender_object_id, req.opcode, req.args
+        log::warn!(
+            object_id = req.sender_object_id,
+            opcode = req.opcode;
+            "request to unknown object"
         );
         let f: Box<dyn Future<Item = Session, Error = std::io::Error> + Send> = Box::new(
             tx.send(Box::new(error))
@@ -123,11 +156,11 @@ fn handle_client(runtime: &mut Runtime, stream: tokio::net::TcpStream, global: G
 
     let output_session = rx0
         .map_err(|err| {
-            println!("err={:?}", err);
+            log::error!("event channel failed: {:?}", err);
             std::io::Error::new(std::io::ErrorKind::Other, "Oops!")
         })
         .forward(writer0)
-        .map_err(|err| println!("err={:?}", err))
+        .map_err(|err| log::error!("event write failed: {:?}", err))
         .and_then(|_| Ok(()));
     runtime.spawn(output_session);
 
@@ -143,6 +176,7 @@ fn handle_client(runtime: &mut Runtime, stream: tokio::net::TcpStream, global: G
         resources: HashMap::new(),
         tx: tx0,
         callback_data: 0,
+        credentials: None,
     };
 
     session0
@@ -155,14 +189,15 @@ fn handle_client(runtime: &mut Runtime, stream: tokio::net::TcpStream, global: G
         .and_then(|_| {
             reader0
                 .fold(session0, handle_client_input)
-                .map_err(|err| println!("err: {:?}", err))
+                .map_err(|err| log::error!("request read failed: {:?}", err))
                 .then(|_| futures::future::ok(()))
         })
-        .map_err(|err| println!("err: {:?}", err));
+        .map_err(|err| log::error!("session failed: {:?}", err));
     runtime.spawn(input_session0);
 }
 
 fn main() {
+    env_logger::Builder::from_env(env_logger::Env::new().filter_or("VISION_LOG", "info")).init();
     let mut runtime = Runtime::new().unwrap();
     let global = Global {
         wl_display: Arc::new(RwLock::new(WlDisplay {})),
@@ -178,7 +213,7 @@ fn main() {
         if let Some(stream) = server_socket.accept() {
             handle_client(&mut runtime, stream, global.clone());
         } else {
-            eprintln!("Oops!");
+            log::error!("accept failed");
         }
     }
 }
@@ -55,9 +55,11 @@ impl tokio::codec::Decoder for Codec {
             let mut args = Vec::new();
             args.resize(message_size - header_size, 0);
             cursor.read_exact(&mut args).unwrap();
-            println!(
-                "decode: id={} opcode={} args={:?}",
-                sender_object_id, opcode, &args
+            log::trace!(
+                object_id = sender_object_id,
+                opcode = opcode;
+                "decoded request, {} bytes",
+                message_size
             );
             (
                 Request {
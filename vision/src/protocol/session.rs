@@ -18,6 +18,15 @@ pub enum NextAction {
     RelayWait,
 }
 
+// Process and user of the Wayland client behind this connection, as
+// announced by reflex before it relays any request.
+#[derive(Clone, Copy, Debug)]
+pub struct Credentials {
+    pub pid: i32,
+    pub uid: u32,
+    pub gid: u32,
+}
+
 pub struct Session {
     pub resources: HashMap<u32, Resource>,
     pub wl_display: Arc<RwLock<WlDisplay>>,
@@ -28,6 +37,7 @@ pub struct Session {
     pub xdg_wm_base: Arc<RwLock<XdgWmBase>>,
     pub tx: Sender<Box<dyn Event + Send>>,
     pub callback_data: u32,
+    pub credentials: Option<Credentials>,
 }
 
 pub struct Context<T>
@@ -46,6 +56,7 @@ where
     pub xdg_wm_base: Arc<RwLock<XdgWmBase>>,
     pub tx: Sender<Box<dyn Event + Send>>,
     pub callback_data: u32,
+    pub credentials: Option<Credentials>,
 }
 
 impl<T> Context<T>
@@ -63,6 +74,7 @@ where
             xdg_wm_base: session.xdg_wm_base,
             tx: session.tx,
             callback_data: session.callback_data,
+            credentials: session.credentials,
             sender_object_id,
             sender_object,
         }
@@ -71,10 +83,29 @@ where
     pub fn ok(
         self,
     ) -> Box<dyn futures::future::Future<Item = (Session, NextAction), Error = ()> + Send> {
-        println!("ok");
         Box::new(futures::future::ok((self.into(), NextAction::Relay)))
     }
 
+    // Ends the sender object's lifetime and gives its ID back to the client,
+    // as vision is the last to see the request.
+    pub fn destroy(
+        self,
+    ) -> Box<dyn futures::future::Future<Item = (Session, NextAction), Error = ()> + Send> {
+        let object_id = self.sender_object_id;
+        let tx = self.tx.clone();
+        let mut session: Session = self.into();
+        session.resources.remove(&object_id);
+        let delete_id = crate::protocol::wayland::wl_display::events::DeleteId {
+            sender_object_id: 1,
+            id: object_id,
+        };
+        Box::new(
+            tx.send(Box::new(delete_id))
+                .map_err(|_| ())
+                .map(|_| (session, NextAction::Nop)),
+        )
+    }
+
     fn create_invalid_method_error(
         &self,
         message: String,
@@ -91,7 +122,7 @@ where
         self,
         message: String,
     ) -> Box<dyn futures::future::Future<Item = (Session, NextAction), Error = ()> + Send> {
-        println!("invalid method {}", &message);
+        log::warn!(object_id = self.sender_object_id; "{}", message);
         let tx = self.tx.clone();
         let error = self.create_invalid_method_error(message);
         let session: Session = self.into();
@@ -106,7 +137,7 @@ where
         self,
         message: String,
     ) -> Box<dyn futures::future::Future<Item = Session, Error = ()> + Send> {
-        println!("invalid method {}", &message);
+        log::warn!(object_id = self.sender_object_id; "{}", message);
         let tx = self.tx.clone();
         let error = self.create_invalid_method_error(message);
         let session: Session = self.into();
@@ -131,6 +162,7 @@ where
             xdg_wm_base: self.xdg_wm_base,
             tx: self.tx,
             callback_data: self.callback_data,
+            credentials: self.credentials,
         }
     }
 }
@@ -250,7 +250,7 @@ impl super::super::super::event::Event for Send {
         }
 
         encode_offset += { 4 + (self.mime_type.len() + 1 + 3) / 4 * 4 };
-        println!("UNIMPLEMENTED!!!!!");
+        log::error!("UNIMPLEMENTED!!!!!");
         encode_offset += 0;
         let _ = encode_offset;
         Ok(())
@@ -59,7 +59,7 @@ impl WlDisplay {
         mut context: Context<Arc<RwLock<WlDisplay>>>,
         registry: u32, // new_id: global registry object
     ) -> Box<dyn Future<Item = (Session, NextAction), Error = ()> + Send> {
-        println!("WlDisplay::get_registry({})", registry);
+        log::debug!(object_id = registry, interface = "wl_registry"; "wl_display::get_registry");
         context
             .resources
             .insert(registry, context.wl_registry.clone().into());
@@ -75,7 +75,7 @@ impl WlDisplay {
                             version: crate::protocol::wayland::wl_compositor::VERSION,
                         },
                     ))
-                    .map_err(|err| println!("err={:?}", err))
+                    .map_err(|err| log::error!("send failed: {:?}", err))
                 })
                 .and_then(move |tx| {
                     tx.send(Box::new(
@@ -86,7 +86,7 @@ impl WlDisplay {
                             version: crate::protocol::wayland::wl_shm::VERSION,
                         },
                     ))
-                    .map_err(|err| println!("err={:?}", err))
+                    .map_err(|err| log::error!("send failed: {:?}", err))
                 })
                 .and_then(move |tx| {
                     tx.send(Box::new(
@@ -97,7 +97,7 @@ impl WlDisplay {
                             version: crate::protocol::xdg_shell::xdg_wm_base::VERSION,
                         },
                     ))
-                    .map_err(|err| println!("err={:?}", err))
+                    .map_err(|err| log::error!("send failed: {:?}", err))
                 })
                 .and_then(|_| context.ok()),
         )
@@ -120,7 +120,7 @@ impl WlDisplay {
         mut context: Context<Arc<RwLock<WlDisplay>>>,
         callback: u32, // new_id: callback object for the sync request
     ) -> Box<dyn Future<Item = (Session, NextAction), Error = ()> + Send> {
-        println!("WlDisplay::sync({})", callback);
+        log::debug!(object_id = callback, interface = "wl_callback"; "wl_display::sync");
         context.callback_data += 1;
         let tx = context.tx.clone();
         Box::new(
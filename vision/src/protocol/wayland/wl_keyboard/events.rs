@@ -153,7 +153,7 @@ impl super::super::super::event::Event for Keymap {
         encode_offset += 8;
         NativeEndian::write_u32(&mut dst[encode_offset..], self.format);
         encode_offset += 4;
-        println!("UNIMPLEMENTED!!!!!");
+        log::error!("UNIMPLEMENTED!!!!!");
         encode_offset += 0;
         NativeEndian::write_u32(&mut dst[encode_offset..], self.size);
         encode_offset += 4;
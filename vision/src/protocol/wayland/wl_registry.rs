@@ -137,7 +137,7 @@ impl WlRegistry {
         name: u32, // uint: unique numeric name of the object
         id: u32,   // new_id: bounded object
     ) -> Box<dyn Future<Item = (Session, NextAction), Error = ()> + Send> {
-        println!("WlRegistry::bind(name: {}, id: {})", name, id);
+        log::debug!(object_id = id, name = name; "wl_registry::bind");
         let tx = context.tx.clone();
 
         match name {
@@ -171,6 +171,19 @@ impl WlRegistry {
                     .insert(id, context.xdg_wm_base.clone().into());
                 return context.ok();
             }
+            crate::protocol::wayland::wl_seat::GLOBAL_NAME => {
+                context
+                    .resources
+                    .insert(id, crate::protocol::wayland::wl_seat::WlSeat {}.into());
+                return context.ok();
+            }
+            crate::protocol::wayland::wl_subcompositor::GLOBAL_NAME => {
+                context.resources.insert(
+                    id,
+                    crate::protocol::wayland::wl_subcompositor::WlSubcompositor {}.into(),
+                );
+                return context.ok();
+            }
             crate::protocol::wayland::wl_shm::GLOBAL_SINGLETON_NAME => {
                 context.resources.insert(id, context.wl_shm.clone().into());
                 return Box::new(
@@ -45,6 +45,9 @@ pub use lib::*;
 // maintains a keyboard focus and a pointer focus.
 pub struct WlSeat {}
 
+// The name reflex advertises wl_seat under.
+pub const GLOBAL_NAME: u32 = 7;
+
 impl WlSeat {
     // return keyboard object
     //
@@ -56,10 +59,14 @@ impl WlSeat {
     // It is a protocol violation to issue this request on a seat that has
     // never had the keyboard capability.
     pub fn get_keyboard(
-        context: Context<WlSeat>,
-        _id: u32, // new_id: seat keyboard
+        mut context: Context<WlSeat>,
+        id: u32, // new_id: seat keyboard
     ) -> Box<dyn Future<Item = (Session, NextAction), Error = ()> + Send> {
-        context.invalid_method("wl_seat::get_keyboard is not implemented yet".to_string())
+        context.resources.insert(
+            id,
+            crate::protocol::wayland::wl_keyboard::WlKeyboard {}.into(),
+        );
+        context.ok()
     }
 
     // return pointer object
@@ -72,10 +79,14 @@ impl WlSeat {
     // It is a protocol violation to issue this request on a seat that has
     // never had the pointer capability.
     pub fn get_pointer(
-        context: Context<WlSeat>,
-        _id: u32, // new_id: seat pointer
+        mut context: Context<WlSeat>,
+        id: u32, // new_id: seat pointer
     ) -> Box<dyn Future<Item = (Session, NextAction), Error = ()> + Send> {
-        context.invalid_method("wl_seat::get_pointer is not implemented yet".to_string())
+        context.resources.insert(
+            id,
+            crate::protocol::wayland::wl_pointer::WlPointer {}.into(),
+        );
+        context.ok()
     }
 
     // return touch object
@@ -101,6 +112,6 @@ impl WlSeat {
     pub fn release(
         context: Context<WlSeat>,
     ) -> Box<dyn Future<Item = (Session, NextAction), Error = ()> + Send> {
-        context.invalid_method("wl_seat::release is not implemented yet".to_string())
+        context.destroy()
     }
 }
@@ -72,9 +72,15 @@ impl WlShmPool {
         stride: i32, // int: number of bytes from the beginning of one row to the beginning of the next row
         format: u32, // uint: buffer pixel format
     ) -> Box<dyn Future<Item = (Session, NextAction), Error = ()> + Send> {
-        println!(
-            "WlShmPool::create_buffer({}, {}, {}, {}, {}, {})",
-            id, offset, width, height, stride, format
+        log::debug!(
+            object_id = id,
+            interface = "wl_buffer",
+            offset = offset,
+            width = width,
+            height = height,
+            stride = stride,
+            format = format;
+            "wl_shm_pool::create_buffer"
         );
         context.resources.insert(
             id,
@@ -100,7 +106,7 @@ impl WlShmPool {
     pub fn destroy(
         context: Context<WlShmPool>,
     ) -> Box<dyn Future<Item = (Session, NextAction), Error = ()> + Send> {
-        context.invalid_method("wl_shm_pool::destroy is not implemented yet".to_string())
+        context.destroy()
     }
 
     // change the size of the pool mapping
@@ -110,9 +116,10 @@ impl WlShmPool {
     // created, but using the new size.  This request can only be
     // used to make the pool bigger.
     pub fn resize(
-        context: Context<WlShmPool>,
-        _size: i32, // int: new size of the pool, in bytes
+        mut context: Context<WlShmPool>,
+        size: i32, // int: new size of the pool, in bytes
     ) -> Box<dyn Future<Item = (Session, NextAction), Error = ()> + Send> {
-        context.invalid_method("wl_shm_pool::resize is not implemented yet".to_string())
+        context.sender_object.size = size;
+        context.ok()
     }
 }
@@ -25,6 +25,7 @@
 
 #[allow(unused_imports)]
 use crate::protocol::session::{Context, NextAction, Session};
+use crate::protocol::wayland::wl_subsurface::WlSubsurface;
 #[allow(unused_imports)]
 use futures::future::{err, ok, Future};
 #[allow(unused_imports)]
@@ -59,6 +60,9 @@ pub use lib::*;
 // processing to dedicated overlay hardware when possible.
 pub struct WlSubcompositor {}
 
+// The name reflex advertises wl_subcompositor under.
+pub const GLOBAL_NAME: u32 = 8;
+
 impl WlSubcompositor {
     // unbind from the subcompositor interface
     //
@@ -68,7 +72,7 @@ impl WlSubcompositor {
     pub fn destroy(
         context: Context<WlSubcompositor>,
     ) -> Box<dyn Future<Item = (Session, NextAction), Error = ()> + Send> {
-        context.invalid_method("wl_subcompositor::destroy is not implemented yet".to_string())
+        context.destroy()
     }
 
     // give a surface the role sub-surface
@@ -89,12 +93,12 @@ impl WlSubcompositor {
     // This request modifies the behaviour of wl_surface.commit request on
     // the sub-surface, see the documentation on wl_subsurface interface.
     pub fn get_subsurface(
-        context: Context<WlSubcompositor>,
-        _id: u32,      // new_id: the new sub-surface object ID
+        mut context: Context<WlSubcompositor>,
+        id: u32,       // new_id: the new sub-surface object ID
         _surface: u32, // object: the surface to be turned into a sub-surface
         _parent: u32,  // object: the parent surface
     ) -> Box<dyn Future<Item = (Session, NextAction), Error = ()> + Send> {
-        context
-            .invalid_method("wl_subcompositor::get_subsurface is not implemented yet".to_string())
+        context.resources.insert(id, WlSubsurface {}.into());
+        context.ok()
     }
 }
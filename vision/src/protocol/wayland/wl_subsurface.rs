@@ -100,7 +100,7 @@ impl WlSubsurface {
     pub fn destroy(
         context: Context<WlSubsurface>,
     ) -> Box<dyn Future<Item = (Session, NextAction), Error = ()> + Send> {
-        context.invalid_method("wl_subsurface::destroy is not implemented yet".to_string())
+        context.destroy()
     }
 
     // restack the sub-surface
@@ -124,7 +124,7 @@ impl WlSubsurface {
         context: Context<WlSubsurface>,
         _sibling: u32, // object: the reference surface
     ) -> Box<dyn Future<Item = (Session, NextAction), Error = ()> + Send> {
-        context.invalid_method("wl_subsurface::place_above is not implemented yet".to_string())
+        context.ok()
     }
 
     // restack the sub-surface
@@ -135,7 +135,7 @@ impl WlSubsurface {
         context: Context<WlSubsurface>,
         _sibling: u32, // object: the reference surface
     ) -> Box<dyn Future<Item = (Session, NextAction), Error = ()> + Send> {
-        context.invalid_method("wl_subsurface::place_below is not implemented yet".to_string())
+        context.ok()
     }
 
     // set sub-surface to desynchronized mode
@@ -188,7 +188,7 @@ impl WlSubsurface {
         _x: i32, // int: x coordinate in the parent surface
         _y: i32, // int: y coordinate in the parent surface
     ) -> Box<dyn Future<Item = (Session, NextAction), Error = ()> + Send> {
-        context.invalid_method("wl_subsurface::set_position is not implemented yet".to_string())
+        context.ok()
     }
 
     // set sub-surface to synchronized mode
@@ -130,7 +130,7 @@ impl WlSurface {
         _x: i32,      // int: surface-local x coordinate
         _y: i32,      // int: surface-local y coordinate
     ) -> Box<dyn Future<Item = (Session, NextAction), Error = ()> + Send> {
-        context.invalid_method("wl_surface::attach is not implemented yet".to_string())
+        context.ok()
     }
 
     // commit pending surface state
@@ -155,7 +155,7 @@ impl WlSurface {
     pub fn commit(
         context: Context<WlSurface>,
     ) -> Box<dyn Future<Item = (Session, NextAction), Error = ()> + Send> {
-        println!("WlSurface::commit");
+        log::trace!(object_id = context.sender_object_id; "wl_surface::commit");
         context.ok()
     }
 
@@ -189,7 +189,7 @@ impl WlSurface {
         _width: i32,  // int: width of damage rectangle
         _height: i32, // int: height of damage rectangle
     ) -> Box<dyn Future<Item = (Session, NextAction), Error = ()> + Send> {
-        context.invalid_method("wl_surface::damage is not implemented yet".to_string())
+        context.ok()
     }
 
     // mark part of the surface damaged using buffer coordinates
@@ -233,7 +233,7 @@ impl WlSurface {
         _width: i32,  // int: width of damage rectangle
         _height: i32, // int: height of damage rectangle
     ) -> Box<dyn Future<Item = (Session, NextAction), Error = ()> + Send> {
-        context.invalid_method("wl_surface::damage_buffer is not implemented yet".to_string())
+        context.ok()
     }
 
     // delete surface
@@ -242,7 +242,7 @@ impl WlSurface {
     pub fn destroy(
         context: Context<WlSurface>,
     ) -> Box<dyn Future<Item = (Session, NextAction), Error = ()> + Send> {
-        context.invalid_method("wl_surface::destroy is not implemented yet".to_string())
+        context.destroy()
     }
 
     // request a frame throttling hint
@@ -315,7 +315,7 @@ impl WlSurface {
         context: Context<WlSurface>,
         _scale: i32, // int: positive scale for interpreting buffer contents
     ) -> Box<dyn Future<Item = (Session, NextAction), Error = ()> + Send> {
-        context.invalid_method("wl_surface::set_buffer_scale is not implemented yet".to_string())
+        context.ok()
     }
 
     // sets the buffer transformation
@@ -353,8 +353,7 @@ impl WlSurface {
         context: Context<WlSurface>,
         _transform: i32, // int: transform for interpreting buffer contents
     ) -> Box<dyn Future<Item = (Session, NextAction), Error = ()> + Send> {
-        context
-            .invalid_method("wl_surface::set_buffer_transform is not implemented yet".to_string())
+        context.ok()
     }
 
     // set input region
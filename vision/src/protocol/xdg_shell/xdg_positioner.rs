@@ -67,7 +67,7 @@ impl XdgPositioner {
     pub fn destroy(
         context: Context<XdgPositioner>,
     ) -> Box<dyn Future<Item = (Session, NextAction), Error = ()> + Send> {
-        context.invalid_method("xdg_positioner::destroy is not implemented yet".to_string())
+        context.destroy()
     }
 
     // set anchor rectangle anchor
@@ -82,7 +82,7 @@ impl XdgPositioner {
         context: Context<XdgPositioner>,
         _anchor: u32, // uint: anchor
     ) -> Box<dyn Future<Item = (Session, NextAction), Error = ()> + Send> {
-        context.invalid_method("xdg_positioner::set_anchor is not implemented yet".to_string())
+        context.ok()
     }
 
     // set the anchor rectangle within the parent surface
@@ -104,7 +104,7 @@ impl XdgPositioner {
         _width: i32,  // int: width of anchor rectangle
         _height: i32, // int: height of anchor rectangle
     ) -> Box<dyn Future<Item = (Session, NextAction), Error = ()> + Send> {
-        context.invalid_method("xdg_positioner::set_anchor_rect is not implemented yet".to_string())
+        context.ok()
     }
 
     // set the adjustment to be done when constrained
@@ -126,9 +126,7 @@ impl XdgPositioner {
         context: Context<XdgPositioner>,
         _constraint_adjustment: u32, // uint: bit mask of constraint adjustments
     ) -> Box<dyn Future<Item = (Session, NextAction), Error = ()> + Send> {
-        context.invalid_method(
-            "xdg_positioner::set_constraint_adjustment is not implemented yet".to_string(),
-        )
+        context.ok()
     }
 
     // set child surface gravity
@@ -143,7 +141,7 @@ impl XdgPositioner {
         context: Context<XdgPositioner>,
         _gravity: u32, // uint: gravity direction
     ) -> Box<dyn Future<Item = (Session, NextAction), Error = ()> + Send> {
-        context.invalid_method("xdg_positioner::set_gravity is not implemented yet".to_string())
+        context.ok()
     }
 
     // set surface position offset
@@ -164,7 +162,7 @@ impl XdgPositioner {
         _x: i32, // int: surface position x offset
         _y: i32, // int: surface position y offset
     ) -> Box<dyn Future<Item = (Session, NextAction), Error = ()> + Send> {
-        context.invalid_method("xdg_positioner::set_offset is not implemented yet".to_string())
+        context.ok()
     }
 
     // set the size of the to-be positioned rectangle
@@ -179,6 +177,6 @@ impl XdgPositioner {
         _width: i32,  // int: width of positioned rectangle
         _height: i32, // int: height of positioned rectangle
     ) -> Box<dyn Future<Item = (Session, NextAction), Error = ()> + Send> {
-        context.invalid_method("xdg_positioner::set_size is not implemented yet".to_string())
+        context.ok()
     }
 }
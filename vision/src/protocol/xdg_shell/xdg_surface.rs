@@ -119,7 +119,7 @@ impl XdgSurface {
     pub fn destroy(
         context: Context<XdgSurface>,
     ) -> Box<dyn Future<Item = (Session, NextAction), Error = ()> + Send> {
-        context.invalid_method("xdg_surface::destroy is not implemented yet".to_string())
+        context.destroy()
     }
 
     // assign the xdg_popup surface role
@@ -133,12 +133,16 @@ impl XdgSurface {
     // See the documentation of xdg_popup for more details about what an
     // xdg_popup is and how it is used.
     pub fn get_popup(
-        context: Context<XdgSurface>,
-        _id: u32,         // new_id:
+        mut context: Context<XdgSurface>,
+        id: u32,          // new_id:
         _parent: u32,     // object:
         _positioner: u32, // object:
     ) -> Box<dyn Future<Item = (Session, NextAction), Error = ()> + Send> {
-        context.invalid_method("xdg_surface::get_popup is not implemented yet".to_string())
+        context.resources.insert(
+            id,
+            crate::protocol::xdg_shell::xdg_popup::XdgPopup {}.into(),
+        );
+        context.ok()
     }
 
     // assign the xdg_toplevel surface role
@@ -152,7 +156,7 @@ impl XdgSurface {
         mut context: Context<XdgSurface>,
         id: u32, // new_id:
     ) -> Box<dyn Future<Item = (Session, NextAction), Error = ()> + Send> {
-        println!("XdgSurface::get_toplevel(id={})", id);
+        log::debug!(object_id = id, interface = "xdg_toplevel"; "xdg_surface::get_toplevel");
         context.resources.insert(
             id,
             crate::protocol::xdg_shell::xdg_toplevel::XdgToplevel {
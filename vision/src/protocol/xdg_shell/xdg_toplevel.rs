@@ -65,7 +65,7 @@ impl XdgToplevel {
     pub fn destroy(
         context: Context<XdgToplevel>,
     ) -> Box<dyn Future<Item = (Session, NextAction), Error = ()> + Send> {
-        context.invalid_method("xdg_toplevel::destroy is not implemented yet".to_string())
+        context.destroy()
     }
 
     // start an interactive move
@@ -161,7 +161,7 @@ impl XdgToplevel {
         context: Context<XdgToplevel>,
         _app_id: String, // string:
     ) -> Box<dyn Future<Item = (Session, NextAction), Error = ()> + Send> {
-        context.invalid_method("xdg_toplevel::set_app_id is not implemented yet".to_string())
+        context.ok()
     }
 
     // set the window as fullscreen on an output
@@ -324,7 +324,7 @@ impl XdgToplevel {
     pub fn set_minimized(
         context: Context<XdgToplevel>,
     ) -> Box<dyn Future<Item = (Session, NextAction), Error = ()> + Send> {
-        context.invalid_method("xdg_toplevel::set_minimized is not implemented yet".to_string())
+        context.ok()
     }
 
     // set the parent of this surface
@@ -365,7 +365,7 @@ impl XdgToplevel {
         context: Context<XdgToplevel>,
         _title: String, // string:
     ) -> Box<dyn Future<Item = (Session, NextAction), Error = ()> + Send> {
-        context.invalid_method("xdg_toplevel::set_title is not implemented yet".to_string())
+        context.ok()
     }
 
     // show the window menu
@@ -55,10 +55,14 @@ impl XdgWmBase {
     // surfaces relative to some parent surface. See the interface description
     // and xdg_surface.get_popup for details.
     pub fn create_positioner(
-        context: Context<Arc<RwLock<XdgWmBase>>>,
-        _id: u32, // new_id:
+        mut context: Context<Arc<RwLock<XdgWmBase>>>,
+        id: u32, // new_id:
     ) -> Box<dyn Future<Item = (Session, NextAction), Error = ()> + Send> {
-        context.invalid_method("xdg_wm_base::create_positioner is not implemented yet".to_string())
+        context.resources.insert(
+            id,
+            crate::protocol::xdg_shell::xdg_positioner::XdgPositioner {}.into(),
+        );
+        context.ok()
     }
 
     // destroy xdg_wm_base
@@ -92,7 +96,12 @@ impl XdgWmBase {
         id: u32,      // new_id:
         surface: u32, // object:
     ) -> Box<dyn Future<Item = (Session, NextAction), Error = ()> + Send> {
-        println!("XdgWmBase::get_xdg_surface(id={}, surface={})", id, surface);
+        log::debug!(
+            object_id = id,
+            interface = "xdg_surface",
+            surface = surface;
+            "xdg_wm_base::get_xdg_surface"
+        );
         if let Some(Resource::WlSurface(wl_surface)) = context.resources.get_mut(&surface) {
             wl_surface.xdg_surface_id = Some(id);
         } else {
@@ -116,6 +125,6 @@ impl XdgWmBase {
         context: Context<Arc<RwLock<XdgWmBase>>>,
         _serial: u32, // uint: serial of the ping event
     ) -> Box<dyn Future<Item = (Session, NextAction), Error = ()> + Send> {
-        context.invalid_method("xdg_wm_base::pong is not implemented yet".to_string())
+        context.ok()
     }
 }